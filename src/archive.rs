@@ -0,0 +1,511 @@
+use std::collections::BTreeMap;
+use std::fmt::{Display, Formatter};
+use std::fs::File;
+use std::io::{BufReader, Read, Seek, SeekFrom, Write};
+use std::str::FromStr;
+use std::sync::{Mutex, MutexGuard};
+
+use iocore::Path;
+use zip::write::SimpleFileOptions;
+use zip::{CompressionMethod, DateTime, ZipArchive, ZipWriter};
+
+use crate::file::{read_unfold_index, validate_chunk};
+use crate::index::{UnfoldIndex, INDEX_NAME};
+use crate::maintenance::copy_store;
+use crate::store::{ChunkStore, DirStore};
+use crate::{Action, Error, Progress, Result};
+
+const TAR_MAGIC_OFFSET: usize = 257;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ArchiveFormat {
+    Tar,
+    Zip,
+}
+
+impl ArchiveFormat {
+    /// detects the format of the archive at `path` from its first
+    /// bytes, returning `None` for anything else
+    pub fn detect(path: &Path) -> Result<Option<ArchiveFormat>> {
+        let mut head = Vec::<u8>::new();
+        File::open(path.to_string())?
+            .take(TAR_MAGIC_OFFSET as u64 + 5)
+            .read_to_end(&mut head)?;
+        if head.starts_with(b"PK\x03\x04") || head.starts_with(b"PK\x05\x06") {
+            Ok(Some(ArchiveFormat::Zip))
+        } else if head.get(TAR_MAGIC_OFFSET..) == Some(b"ustar") {
+            Ok(Some(ArchiveFormat::Tar))
+        } else {
+            Ok(None)
+        }
+    }
+
+    /// format matching the extension of `path`, defaulting to tar
+    pub fn from_extension(path: &Path) -> ArchiveFormat {
+        if path.to_string().to_lowercase().ends_with(".zip") {
+            ArchiveFormat::Zip
+        } else {
+            ArchiveFormat::Tar
+        }
+    }
+}
+
+impl Display for ArchiveFormat {
+    fn fmt(&self, f: &mut Formatter) -> std::fmt::Result {
+        write!(
+            f,
+            "{}",
+            match self {
+                Self::Tar => "tar",
+                Self::Zip => "zip",
+            }
+        )
+    }
+}
+
+impl FromStr for ArchiveFormat {
+    type Err = Error;
+
+    fn from_str(value: &str) -> Result<ArchiveFormat> {
+        match value.to_lowercase().as_str() {
+            "tar" => Ok(ArchiveFormat::Tar),
+            "zip" => Ok(ArchiveFormat::Zip),
+            _ => Err(Error::ArchiveError(format!(
+                "unsupported archive format '{value}', expected tar or zip"
+            ))),
+        }
+    }
+}
+
+/// writes the unfolded folder at `input_path` into a new archive at
+/// `output_path`, see [`export_store`]
+pub fn export<C: FnMut(Progress)>(
+    input_path: &Path,
+    format: ArchiveFormat,
+    output_path: &Path,
+    progress: C,
+) -> Result<Path> {
+    if !input_path.is_dir() {
+        return Err(Error::ArchiveError(format!(
+            "{input_path} is not a directory"
+        )));
+    }
+    export_to_file(&DirStore::new(input_path), format, output_path, progress)
+}
+
+/// writes the index and chunks of `store` into a new archive at
+/// `output_path`, removing it if the export fails
+pub fn export_to_file<S: ChunkStore + ?Sized, C: FnMut(Progress)>(
+    store: &S,
+    format: ArchiveFormat,
+    output_path: &Path,
+    progress: C,
+) -> Result<Path> {
+    if output_path.exists() {
+        return Err(Error::ArchiveError(format!(
+            "{output_path} already exists"
+        )));
+    }
+    let file = File::create(output_path.mkdir_parents()?.to_string())?;
+    match export_store(store, format, file, progress) {
+        Ok(_) => Ok(output_path.clone()),
+        Err(error) => {
+            output_path.delete()?;
+            Err(error)
+        },
+    }
+}
+
+/// writes the index and the chunks it points at of `store` into
+/// `writer` as a deterministic archive: the index first followed by
+/// every chunk once sorted by name, with fixed modification times and
+/// permissions, and zip entries stored uncompressed
+pub fn export_store<S, W, C>(
+    store: &S,
+    format: ArchiveFormat,
+    writer: W,
+    mut progress: C,
+) -> Result<UnfoldIndex>
+where
+    S: ChunkStore + ?Sized,
+    W: Write + Seek,
+    C: FnMut(Progress),
+{
+    progress(Progress::Start(Action::Export));
+    let index = read_unfold_index(store)?;
+    let files = index.chunk_files()?;
+    let index_bytes = index.to_bytes()?;
+    let location = store.location();
+    let chunk_count = files.len();
+    let mut archive = ArchiveWriter::new(format, writer);
+    archive.append(INDEX_NAME, &index_bytes)?;
+    for (idx, (name, sha256)) in files.iter().enumerate() {
+        let bytes = store.get(name)?;
+        validate_chunk(sha256, &bytes).map_err(|error| {
+            Error::CorruptedDataError(format!("in {location}: {error}"))
+        })?;
+        archive.append(name, &bytes)?;
+        progress(Progress::Chunk {
+            index: idx + 1,
+            count: chunk_count,
+            action: Action::Export,
+        });
+    }
+    archive.finish()?;
+    progress(Progress::End(Action::Export));
+    Ok(index)
+}
+
+enum ArchiveWriter<W: Write + Seek> {
+    Tar(tar::Builder<W>),
+    Zip(Box<ZipWriter<W>>),
+}
+
+impl<W: Write + Seek> ArchiveWriter<W> {
+    fn new(format: ArchiveFormat, writer: W) -> ArchiveWriter<W> {
+        match format {
+            ArchiveFormat::Tar => ArchiveWriter::Tar(tar::Builder::new(writer)),
+            ArchiveFormat::Zip =>
+                ArchiveWriter::Zip(Box::new(ZipWriter::new(writer))),
+        }
+    }
+
+    fn append(&mut self, name: &str, bytes: &[u8]) -> Result<()> {
+        match self {
+            ArchiveWriter::Tar(builder) => {
+                let mut header = tar::Header::new_ustar();
+                header.set_entry_type(tar::EntryType::Regular);
+                header.set_size(bytes.len() as u64);
+                header.set_mode(0o644);
+                header.set_mtime(0);
+                header.set_uid(0);
+                header.set_gid(0);
+                builder.append_data(&mut header, name, bytes)?;
+            },
+            ArchiveWriter::Zip(writer) => {
+                let options = SimpleFileOptions::default()
+                    .compression_method(CompressionMethod::Stored)
+                    .last_modified_time(DateTime::default())
+                    .unix_permissions(0o644);
+                writer
+                    .start_file(name, options)
+                    .map_err(|error| {
+                        Error::ArchiveError(format!(
+                            "failed to add {name}: {error}"
+                        ))
+                    })?;
+                writer.write_all(bytes)?;
+            },
+        }
+        Ok(())
+    }
+
+    fn finish(self) -> Result<()> {
+        match self {
+            ArchiveWriter::Tar(builder) => {
+                builder.into_inner()?.flush()?;
+            },
+            ArchiveWriter::Zip(writer) => {
+                writer
+                    .finish()
+                    .map_err(|error| {
+                        Error::ArchiveError(format!(
+                            "failed to finish zip archive: {error}"
+                        ))
+                    })?
+                    .flush()?;
+            },
+        }
+        Ok(())
+    }
+}
+
+/// extracts the archive at `input_path`, written by [`export`] or by
+/// archiving an unfolded folder, into a new unfolded folder at
+/// `output_path`, verifying every chunk. Nothing is left at
+/// `output_path` if the import fails.
+pub fn import<C: FnMut(Progress)>(
+    input_path: &Path,
+    output_path: &Path,
+    progress: C,
+) -> Result<Path> {
+    if output_path.exists() {
+        return Err(Error::ArchiveError(format!(
+            "{output_path} already exists"
+        )));
+    }
+    let archive = ArchiveStore::open(input_path)?;
+    let staging_path = output_path.with_filename(format!(
+        ".{}.importing-{}",
+        output_path.name(),
+        std::process::id()
+    ));
+    if staging_path.exists() {
+        staging_path.delete()?;
+    }
+    let result = staging_path
+        .mkdir()
+        .map_err(Error::from)
+        .and_then(|_| {
+            copy_store(
+                &archive,
+                &DirStore::new(&staging_path),
+                Action::Import,
+                progress,
+            )
+        });
+    match result {
+        Ok(_) => {
+            staging_path.rename(output_path, true)?;
+            Ok(output_path.clone())
+        },
+        Err(error) => {
+            staging_path.delete()?;
+            Err(error)
+        },
+    }
+}
+
+/// read-only [`ChunkStore`] over a tar or zip archive of an unfolded
+/// folder. Entries are looked up by name relative to the folder holding
+/// the index, and read without extracting the archive: tar entries by
+/// seeking to their offset, zip entries through the central directory.
+pub struct ArchiveStore {
+    path: Path,
+    format: ArchiveFormat,
+    reader: Mutex<ArchiveReader>,
+}
+
+enum ArchiveReader {
+    /// offset and size of each entry
+    Tar(File, BTreeMap<String, (u64, u64)>),
+    /// full name of each entry in the archive
+    Zip(ZipArchive<BufReader<File>>, BTreeMap<String, String>),
+}
+
+impl ArchiveStore {
+    pub fn open(path: &Path) -> Result<ArchiveStore> {
+        if !path.is_file() {
+            return Err(Error::ArchiveError(format!("{path} is not a file")));
+        }
+        let format = ArchiveFormat::detect(path)?.ok_or_else(|| {
+            Error::ArchiveError(format!("{path} is not a tar or zip archive"))
+        })?;
+        let file = File::open(path.to_string())?;
+        let invalid = |error: &dyn std::fmt::Display| {
+            Error::ArchiveError(format!(
+                "invalid {format} archive {path}: {error}"
+            ))
+        };
+        let reader = match format {
+            ArchiveFormat::Tar => {
+                let mut full_entries = Vec::<(String, (u64, u64))>::new();
+                let mut archive = tar::Archive::new(&file);
+                for entry in archive
+                    .entries_with_seek()
+                    .map_err(|error| invalid(&error))?
+                {
+                    let entry = entry.map_err(|error| invalid(&error))?;
+                    if entry.header().entry_type() != tar::EntryType::Regular {
+                        continue;
+                    }
+                    let full_name = entry
+                        .path()
+                        .map_err(|error| invalid(&error))?
+                        .to_string_lossy()
+                        .to_string();
+                    full_entries.push((
+                        full_name,
+                        (entry.raw_file_position(), entry.size()),
+                    ));
+                }
+                let root = index_folder(
+                    full_entries
+                        .iter()
+                        .map(|(full_name, _)| full_name.as_str()),
+                );
+                let entries = full_entries
+                    .into_iter()
+                    .filter_map(|(full_name, entry)| {
+                        Some((entry_name(&full_name, &root)?, entry))
+                    })
+                    .collect::<BTreeMap<String, (u64, u64)>>();
+                ArchiveReader::Tar(file, entries)
+            },
+            ArchiveFormat::Zip => {
+                let archive = ZipArchive::new(BufReader::new(file))
+                    .map_err(|error| invalid(&error))?;
+                let root = index_folder(archive.file_names());
+                let mut names = BTreeMap::<String, String>::new();
+                for full_name in archive.file_names() {
+                    if full_name.ends_with('/') {
+                        continue;
+                    }
+                    if let Some(name) = entry_name(full_name, &root) {
+                        names.insert(name, full_name.to_string());
+                    }
+                }
+                ArchiveReader::Zip(archive, names)
+            },
+        };
+        Ok(ArchiveStore {
+            path: path.clone(),
+            format,
+            reader: Mutex::new(reader),
+        })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn format(&self) -> ArchiveFormat {
+        self.format
+    }
+
+    fn reader(&self) -> Result<MutexGuard<'_, ArchiveReader>> {
+        self.reader.lock().map_err(|error| {
+            Error::StoreError(format!(
+                "poisoned reader of {}: {error}",
+                self.path
+            ))
+        })
+    }
+
+    fn read_entry(&self, name: &str) -> Result<Option<Vec<u8>>> {
+        let mut reader = self.reader()?;
+        let mut bytes = Vec::<u8>::new();
+        match &mut *reader {
+            ArchiveReader::Tar(file, entries) => {
+                let (offset, size) = match entries.get(name) {
+                    Some(entry) => *entry,
+                    None => return Ok(None),
+                };
+                let mut file: &File = file;
+                file.seek(SeekFrom::Start(offset))?;
+                file.take(size).read_to_end(&mut bytes)?;
+            },
+            ArchiveReader::Zip(archive, names) => {
+                let full_name = match names.get(name) {
+                    Some(full_name) => full_name.clone(),
+                    None => return Ok(None),
+                };
+                archive
+                    .by_name(&full_name)
+                    .map_err(|error| {
+                        Error::ArchiveError(format!(
+                            "failed to read {full_name} from {}: {error}",
+                            self.path
+                        ))
+                    })?
+                    .read_to_end(&mut bytes)?;
+            },
+        }
+        Ok(Some(bytes))
+    }
+
+    fn names(&self) -> Result<Vec<String>> {
+        Ok(match &*self.reader()? {
+            ArchiveReader::Tar(_, entries) => entries.keys().cloned().collect(),
+            ArchiveReader::Zip(_, names) => names.keys().cloned().collect(),
+        })
+    }
+
+    fn contains(&self, name: &str) -> Result<bool> {
+        Ok(match &*self.reader()? {
+            ArchiveReader::Tar(_, entries) => entries.contains_key(name),
+            ArchiveReader::Zip(_, names) => names.contains_key(name),
+        })
+    }
+
+    fn read_only(&self) -> Error {
+        Error::ArchiveError(format!("{} is a read-only archive", self.path))
+    }
+}
+
+/// folder of the shallowest index entry, e.g.: `backup/` for
+/// `backup/index`, which chunk names are relative to
+fn index_folder<'a>(full_names: impl Iterator<Item = &'a str>) -> String {
+    full_names
+        .map(|full_name| full_name.trim_start_matches("./"))
+        .filter_map(|full_name| {
+            if full_name == INDEX_NAME {
+                return Some(String::new());
+            }
+            full_name
+                .strip_suffix(INDEX_NAME)
+                .filter(|folder| folder.ends_with('/'))
+                .map(String::from)
+        })
+        .min_by_key(|folder| folder.matches('/').count())
+        .unwrap_or_default()
+}
+
+/// name of an archive entry relative to `root`, keeping the fan-out
+/// folders chunks may be nested in, or `None` for entries outside it
+fn entry_name(full_name: &str, root: &str) -> Option<String> {
+    full_name
+        .trim_start_matches("./")
+        .strip_prefix(root)
+        .map(String::from)
+}
+
+impl std::fmt::Debug for ArchiveStore {
+    fn fmt(&self, f: &mut Formatter) -> std::fmt::Result {
+        f.debug_struct("ArchiveStore")
+            .field("path", &self.path)
+            .field("format", &self.format)
+            .finish()
+    }
+}
+
+impl Display for ArchiveStore {
+    fn fmt(&self, f: &mut Formatter) -> std::fmt::Result {
+        write!(f, "{}", self.path)
+    }
+}
+
+impl ChunkStore for ArchiveStore {
+    fn put(&self, _name: &str, _bytes: &[u8]) -> Result<()> {
+        Err(self.read_only())
+    }
+
+    fn get(&self, name: &str) -> Result<Vec<u8>> {
+        self.read_entry(name)?.ok_or_else(|| {
+            Error::MissingChunkError(format!(
+                "'{name}' is not in {}",
+                self.path
+            ))
+        })
+    }
+
+    fn has(&self, name: &str) -> Result<bool> {
+        self.contains(name)
+    }
+
+    fn list(&self) -> Result<Vec<String>> {
+        Ok(self
+            .names()?
+            .into_iter()
+            .filter(|name| name != INDEX_NAME && !name.starts_with('.'))
+            .collect())
+    }
+
+    fn delete(&self, _name: &str) -> Result<()> {
+        Err(self.read_only())
+    }
+
+    fn put_index(&self, _bytes: &[u8]) -> Result<()> {
+        Err(self.read_only())
+    }
+
+    fn get_index(&self) -> Result<Vec<u8>> {
+        self.read_entry(INDEX_NAME)?.ok_or_else(|| {
+            Error::MissingIndexError(format!("{} has no index", self.path))
+        })
+    }
+
+    fn location(&self) -> String {
+        self.to_string()
+    }
+}
@@ -0,0 +1,354 @@
+use std::future::Future;
+use std::sync::Arc;
+
+use iocore::Size;
+use sha2::{Digest, Sha256};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use tokio::sync::mpsc::UnboundedSender;
+
+use crate::cancel::CancelToken;
+use crate::file::{index_chunks, validate_chunk, CHUNK_SIZE, MAX_FILE_SIZE};
+use crate::index::{ChunkLayout, ParsedIndex, UnfoldIndex, INDEX_NAME};
+use crate::store::ChunkStore;
+use crate::{Action, Error, Progress, Result};
+
+/// Asynchronous counterpart of [`ChunkStore`], for backends which are
+/// async themselves, such as the clients of object stores in async
+/// services. [`BlockingStore`] turns any [`ChunkStore`] into one.
+pub trait AsyncChunkStore: Send + Sync {
+    /// stores `bytes` under `name`, replacing any previous contents
+    fn put(
+        &self,
+        name: &str,
+        bytes: &[u8],
+    ) -> impl Future<Output = Result<()>> + Send;
+
+    /// returns the contents stored under `name`
+    fn get(&self, name: &str) -> impl Future<Output = Result<Vec<u8>>> + Send;
+
+    fn has(&self, name: &str) -> impl Future<Output = Result<bool>> + Send;
+
+    /// names of every chunk in the store, excluding the index, sorted
+    fn list(&self) -> impl Future<Output = Result<Vec<String>>> + Send;
+
+    fn delete(&self, name: &str) -> impl Future<Output = Result<()>> + Send;
+
+    /// stores the serialized index
+    fn put_index(
+        &self,
+        bytes: &[u8],
+    ) -> impl Future<Output = Result<()>> + Send;
+
+    /// returns the serialized index, or
+    /// [`Error::MissingIndexError`] if there is none
+    fn get_index(&self) -> impl Future<Output = Result<Vec<u8>>> + Send;
+
+    /// human-readable location of the store used in messages
+    fn location(&self) -> String;
+
+    /// reads the index, along with the sub-indexes of a hierarchical
+    /// manifest
+    fn read_index(&self) -> impl Future<Output = Result<UnfoldIndex>> + Send {
+        async {
+            let bytes = self.get_index().await?;
+            let location = self.location();
+            let origin = format!("{location}/{INDEX_NAME}");
+            let manifest = match UnfoldIndex::decode(&bytes, &origin)? {
+                ParsedIndex::Index(index, Some(_)) => return Ok(index),
+                ParsedIndex::Index(index, None) => {
+                    // indexes written before the `size` field existed
+                    let count = index.chunk_count();
+                    let last = self.get(&index.chunk_name(count - 1)).await?;
+                    let size = ((count - 1) * CHUNK_SIZE + last.len()) as u64;
+                    return Ok(index.with_size(size));
+                },
+                ParsedIndex::Manifest(manifest) => manifest,
+            };
+            // grown as each sub-index is verified, see
+            // ManifestIndex::resolve
+            let mut chunks = Vec::<String>::new();
+            for idx in 0..manifest.subindexes().len() {
+                let bytes = self.get(&manifest.subindex_name(idx)).await?;
+                chunks.extend(manifest.parse_subindex(idx, &bytes, &location)?);
+            }
+            manifest
+                .header()
+                .clone()
+                .with_chunks(chunks, origin)
+        }
+    }
+
+    fn write_index(
+        &self,
+        index: &UnfoldIndex,
+    ) -> impl Future<Output = Result<()>> + Send {
+        async move { self.put_index(&index.to_bytes()?).await }
+    }
+}
+
+/// [`AsyncChunkStore`] running every call of a blocking [`ChunkStore`]
+/// on the blocking threads of tokio, e.g.: to use a
+/// [`DirStore`](crate::DirStore) or an `S3Store` from async code.
+#[derive(Debug)]
+pub struct BlockingStore<S: ChunkStore + 'static> {
+    store: Arc<S>,
+}
+
+impl<S: ChunkStore + 'static> BlockingStore<S> {
+    pub fn new(store: S) -> BlockingStore<S> {
+        BlockingStore {
+            store: Arc::new(store),
+        }
+    }
+
+    pub fn store(&self) -> &S {
+        &self.store
+    }
+
+    async fn call<T, F>(&self, call: F) -> Result<T>
+    where
+        T: Send + 'static,
+        F: FnOnce(&S) -> Result<T> + Send + 'static,
+    {
+        let store = self.store.clone();
+        tokio::task::spawn_blocking(move || call(&store))
+            .await
+            .map_err(|error| {
+                Error::StoreError(format!(
+                    "call to {} did not complete: {error}",
+                    self.store.location()
+                ))
+            })?
+    }
+}
+
+impl<S: ChunkStore + 'static> Clone for BlockingStore<S> {
+    fn clone(&self) -> BlockingStore<S> {
+        BlockingStore {
+            store: self.store.clone(),
+        }
+    }
+}
+
+impl<S: ChunkStore + 'static> AsyncChunkStore for BlockingStore<S> {
+    async fn put(&self, name: &str, bytes: &[u8]) -> Result<()> {
+        let (name, bytes) = (name.to_string(), bytes.to_vec());
+        self.call(move |store| store.put(&name, &bytes))
+            .await
+    }
+
+    async fn get(&self, name: &str) -> Result<Vec<u8>> {
+        let name = name.to_string();
+        self.call(move |store| store.get(&name)).await
+    }
+
+    async fn has(&self, name: &str) -> Result<bool> {
+        let name = name.to_string();
+        self.call(move |store| store.has(&name)).await
+    }
+
+    async fn list(&self) -> Result<Vec<String>> {
+        self.call(|store| store.list()).await
+    }
+
+    async fn delete(&self, name: &str) -> Result<()> {
+        let name = name.to_string();
+        self.call(move |store| store.delete(&name)).await
+    }
+
+    async fn put_index(&self, bytes: &[u8]) -> Result<()> {
+        let bytes = bytes.to_vec();
+        self.call(move |store| store.put_index(&bytes))
+            .await
+    }
+
+    async fn get_index(&self) -> Result<Vec<u8>> {
+        self.call(|store| store.get_index()).await
+    }
+
+    fn location(&self) -> String {
+        self.store.location()
+    }
+
+    /// reads the index the way the blocking store does, e.g.: deriving
+    /// the size of old indexes of a `DirStore` from metadata
+    async fn read_index(&self) -> Result<UnfoldIndex> {
+        self.call(|store| store.read_index()).await
+    }
+}
+
+fn report(progress: &Option<UnboundedSender<Progress>>, event: Progress) {
+    if let Some(progress) = progress {
+        // nobody listening is no reason to stop
+        let _ = progress.send(event);
+    }
+}
+
+/// unfolds everything `reader` yields into `store` laid out as
+/// `layout`, sending progress to `progress`, and returns the index.
+/// Chunks are hashed on the blocking threads of tokio and the index is
+/// written last, so that dropping the future never leaves an index
+/// pointing at missing chunks.
+pub async fn unfold_async<R, S>(
+    mut reader: R,
+    store: &S,
+    layout: ChunkLayout,
+    progress: Option<UnboundedSender<Progress>>,
+) -> Result<UnfoldIndex>
+where
+    R: AsyncRead + Unpin,
+    S: AsyncChunkStore + ?Sized,
+{
+    report(&progress, Progress::Start(Action::Unfold));
+    let mut bytes = Vec::<u8>::new();
+    (&mut reader)
+        .take(MAX_FILE_SIZE + 1)
+        .read_to_end(&mut bytes)
+        .await
+        .map_err(|error| {
+            Error::FlattenFileInputError(format!(
+                "failed to read input: {error}"
+            ))
+        })?;
+    if bytes.len() as u64 > MAX_FILE_SIZE {
+        let max_file_size = Size::from(MAX_FILE_SIZE);
+        return Err(Error::FlattenFileInputError(format!(
+            "input is too large (max = {max_file_size})"
+        )));
+    }
+    let (bytes, index) = tokio::task::spawn_blocking(move || {
+        let index =
+            index_chunks(&bytes, Some(&[]), layout, 1, &CancelToken::new());
+        (bytes, index)
+    })
+    .await
+    .map_err(|error| {
+        Error::FlattenFileInputError(format!("failed to hash input: {error}"))
+    })?;
+    let index = index?;
+    let chunk_count = index.chunk_count();
+    let location = store.location();
+    for (idx, chunk) in bytes.chunks(CHUNK_SIZE).enumerate() {
+        let chunk_index = idx + 1;
+        report(
+            &progress,
+            Progress::Chunk {
+                index: chunk_index,
+                count: chunk_count,
+                action: Action::Unfold,
+            },
+        );
+        if !index.is_zero(idx) {
+            store.put(&index.chunk_name(idx), chunk).await.map_err(|error| {
+                Error::FlattenFileOutputError(format!(
+                    "failed to write chunk {chunk_index}/{chunk_count} to {location}: {error}"
+                ))
+            })?;
+        }
+        // each sub-index goes out right after the last chunk it lists
+        let Some(span) = layout.span else {
+            continue;
+        };
+        if chunk_index % span != 0 && chunk_index != chunk_count {
+            continue;
+        }
+        let subindex = index.subindex(idx / span)?;
+        store.put(&subindex.name, &subindex.bytes).await?;
+    }
+    store.write_index(&index).await?;
+    report(&progress, Progress::End(Action::Unfold));
+    Ok(index)
+}
+
+/// folds the chunks of `store` into `writer`, verifying every chunk as
+/// well as the whole file, sending progress to `progress`, and returns
+/// the index. Chunks are written as soon as they are verified, so that
+/// `writer` may have received some of them when an error is returned.
+pub async fn fold_async<S, W>(
+    store: &S,
+    mut writer: W,
+    progress: Option<UnboundedSender<Progress>>,
+) -> Result<UnfoldIndex>
+where
+    S: AsyncChunkStore + ?Sized,
+    W: AsyncWrite + Unpin,
+{
+    report(&progress, Progress::Start(Action::Fold));
+    let index = store.read_index().await?;
+    read_chunks(store, &index, Action::Fold, &progress, &mut writer).await?;
+    writer.shutdown().await.map_err(|error| {
+        Error::UnflattenFileOutputError(format!(
+            "failed to write output: {error}"
+        ))
+    })?;
+    report(&progress, Progress::End(Action::Fold));
+    Ok(index)
+}
+
+/// checks, without writing anything, that every chunk the index of
+/// `store` points at is present and matches, and that together they
+/// match the whole-file checksum, sending progress to `progress`
+pub async fn verify_async<S: AsyncChunkStore + ?Sized>(
+    store: &S,
+    progress: Option<UnboundedSender<Progress>>,
+) -> Result<UnfoldIndex> {
+    report(&progress, Progress::Start(Action::Verify));
+    let index = store.read_index().await?;
+    let mut sink = tokio::io::sink();
+    read_chunks(store, &index, Action::Verify, &progress, &mut sink).await?;
+    report(&progress, Progress::End(Action::Verify));
+    Ok(index)
+}
+
+/// reads and verifies the chunks `index` points at from `store` in
+/// order into `writer`, then checks the whole file
+async fn read_chunks<S, W>(
+    store: &S,
+    index: &UnfoldIndex,
+    action: Action,
+    progress: &Option<UnboundedSender<Progress>>,
+    writer: &mut W,
+) -> Result<()>
+where
+    S: AsyncChunkStore + ?Sized,
+    W: AsyncWrite + Unpin,
+{
+    let location = store.location();
+    let chunk_count = index.chunk_count();
+    let mut hasher = Sha256::new();
+    for (idx, sha256) in index.chunks().iter().enumerate() {
+        let bytes = if index.is_zero(idx) {
+            let range = index.chunk_range(idx);
+            vec![0; (range.end - range.start) as usize]
+        } else {
+            store.get(&index.chunk_name(idx)).await?
+        };
+        validate_chunk(sha256, &bytes).map_err(|error| {
+            Error::CorruptedDataError(format!("in {location}: {error}"))
+        })?;
+        report(
+            progress,
+            Progress::Chunk {
+                index: idx + 1,
+                count: chunk_count,
+                action,
+            },
+        );
+        hasher.update(&bytes);
+        writer.write_all(&bytes).await.map_err(|error| {
+            Error::UnflattenFileOutputError(format!(
+                "failed to write chunk {}/{chunk_count}: {error}",
+                idx + 1
+            ))
+        })?;
+    }
+    let actual = hasher.finalize();
+    if actual.as_slice() != index.sha256() {
+        return Err(Error::CorruptedDataError(format!(
+            "invalid checksum at {location}: expected: {} actual: {}",
+            hex::encode(index.sha256()),
+            hex::encode(actual)
+        )));
+    }
+    Ok(())
+}
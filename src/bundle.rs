@@ -0,0 +1,210 @@
+use std::collections::BTreeSet;
+use std::fs::File;
+use std::io::{BufReader, BufWriter};
+
+use iocore::Path;
+
+use crate::index::{ParsedIndex, UnfoldIndex};
+use crate::pack::{missing_chunk, unpack_into, PackWriter};
+use crate::store::{ChunkStore, DirStore};
+use crate::{Action, Error, Progress, Result};
+
+/// reads the set of chunk names a receiver already has from either an
+/// unfolded folder, an `index` file or a text file with one chunk name
+/// per line.
+pub fn read_inventory(path: &Path) -> Result<BTreeSet<String>> {
+    if path.is_dir() {
+        let index = UnfoldIndex::read(path)?;
+        return Ok(index.chunk_files()?.into_values().collect());
+    }
+    if !path.is_file() {
+        return Err(Error::BundleInputError(format!(
+            "inventory {path} is not a file or folder"
+        )));
+    }
+    let bytes = path.read_bytes()?;
+    match UnfoldIndex::decode(&bytes, path) {
+        Ok(ParsedIndex::Index(index, _)) =>
+            return Ok(index.chunk_files()?.into_values().collect()),
+        // the sub-indexes of a manifest sit next to its top-level index
+        Ok(ParsedIndex::Manifest(manifest)) => {
+            let folder = path.parent().unwrap_or_else(|| Path::new("."));
+            let index = manifest.resolve(&DirStore::new(&folder))?;
+            return Ok(index.chunk_files()?.into_values().collect());
+        },
+        Err(_) => {},
+    }
+    let contents = String::from_utf8(bytes).map_err(|error| {
+        Error::BundleInputError(format!(
+            "invalid utf-8 in inventory {path}: {error}"
+        ))
+    })?;
+    let mut inventory = BTreeSet::<String>::new();
+    for line in contents.lines().map(|line| line.trim()) {
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        if line.len() != 64 || hex::decode(line).is_err() {
+            return Err(Error::BundleInputError(format!(
+                "invalid chunk name '{line}' in inventory {path}"
+            )));
+        }
+        inventory.insert(line.to_string());
+    }
+    Ok(inventory)
+}
+
+/// writes to `output_path` a pack stream containing the index of the
+/// unfolded folder at `input_path` followed by the chunks whose names
+/// are not in `have`.
+pub fn bundle<C: FnMut(Progress)>(
+    input_path: &Path,
+    have: &BTreeSet<String>,
+    output_path: &Path,
+    mut progress: C,
+) -> Result<Path> {
+    if !input_path.is_dir() {
+        return Err(Error::BundleInputError(format!(
+            "{input_path} is not a directory"
+        )));
+    }
+    if output_path.exists() {
+        return Err(Error::BundleOutputError(format!(
+            "{output_path} already exists"
+        )));
+    }
+    progress(Progress::Start(Action::Bundle));
+    let index = UnfoldIndex::read(input_path)?;
+    let missing = index
+        .chunk_names_by_sha256()?
+        .into_iter()
+        .filter(|(sha256, _)| !have.contains(sha256))
+        .collect::<Vec<_>>();
+    let store = DirStore::new(input_path);
+    let file = output_path.mkdir_parents()?.create()?;
+    let mut writer = PackWriter::new(BufWriter::new(file))?;
+    let chunk_count = missing.len();
+    writer.write_index(&index, chunk_count)?;
+    for (idx, (sha256, names)) in missing.into_iter().enumerate() {
+        let name = &names[0];
+        let bytes = store.get(name).map_err(|error| {
+            Error::BundleInputError(format!(
+                "failed to read chunk {name} from {input_path}: {error}"
+            ))
+        })?;
+        writer.write_chunk(&sha256, &bytes)?;
+        progress(Progress::Chunk {
+            index: idx + 1,
+            count: chunk_count,
+            action: Action::Bundle,
+        });
+    }
+    writer.finish()?;
+    progress(Progress::End(Action::Bundle));
+    Ok(output_path.clone())
+}
+
+/// verifies every chunk of the bundle at `input_path` and merges them
+/// into the unfolded folder at `output_path`, which may or may not
+/// exist, rewriting chunks that it holds with corrupted contents. The
+/// index is written last, and only once every chunk it
+/// points at is present, so that an interrupted unbundle never leaves
+/// behind an index with missing chunks. A new folder is staged in a
+/// sibling folder which is only renamed to `output_path` once complete.
+pub fn unbundle<C: FnMut(Progress)>(
+    input_path: &Path,
+    output_path: &Path,
+    mut progress: C,
+) -> Result<Path> {
+    if !input_path.is_file() {
+        return Err(Error::BundleInputError(format!(
+            "{input_path} is not a file"
+        )));
+    }
+    if output_path.exists() && !output_path.is_dir() {
+        return Err(Error::BundleOutputError(format!(
+            "{output_path} is not a directory"
+        )));
+    }
+    let staging_path = if output_path.exists() {
+        None
+    } else {
+        let staging_path = output_path.with_filename(format!(
+            ".{}.unbundling-{}",
+            output_path.name(),
+            std::process::id()
+        ));
+        if staging_path.exists() {
+            staging_path.delete()?;
+        }
+        Some(staging_path)
+    };
+    let store_path = staging_path.as_ref().unwrap_or(output_path);
+    progress(Progress::Start(Action::Unbundle));
+    let result =
+        store_path
+            .mkdir()
+            .map_err(Error::from)
+            .and_then(|store_path| {
+                unbundle_into(
+                    input_path,
+                    &output_path.to_string(),
+                    &DirStore::new(&store_path),
+                    &mut progress,
+                )
+            });
+    if let Some(staging_path) = staging_path {
+        match &result {
+            Ok(_) => staging_path.rename(output_path, true)?,
+            Err(_) => staging_path.delete()?,
+        };
+    }
+    result?;
+    progress(Progress::End(Action::Unbundle));
+    Ok(output_path.clone())
+}
+
+/// verifies every chunk of the bundle at `input_path` and merges them
+/// into `store`, rewriting chunks that `store` holds with corrupted
+/// contents. The index is written last, once every chunk it points at
+/// is present.
+pub fn unbundle_to_store<S: ChunkStore + ?Sized, C: FnMut(Progress)>(
+    input_path: &Path,
+    store: &S,
+    mut progress: C,
+) -> Result<UnfoldIndex> {
+    if !input_path.is_file() {
+        return Err(Error::BundleInputError(format!(
+            "{input_path} is not a file"
+        )));
+    }
+    progress(Progress::Start(Action::Unbundle));
+    let index =
+        unbundle_into(input_path, &store.location(), store, &mut progress)?;
+    progress(Progress::End(Action::Unbundle));
+    Ok(index)
+}
+
+/// merges the bundle at `input_path` into `store`, found at `location`
+fn unbundle_into<S: ChunkStore + ?Sized, C: FnMut(Progress)>(
+    input_path: &Path,
+    location: &str,
+    store: &S,
+    progress: &mut C,
+) -> Result<UnfoldIndex> {
+    let reader = BufReader::new(File::open(input_path)?);
+    let index = unpack_into(reader, store, Action::Unbundle, progress)
+        .map_err(|error| match error {
+            Error::CorruptedDataError(error) => Error::CorruptedDataError(
+                format!("in bundle {input_path}: {error}"),
+            ),
+            error => error,
+        })?;
+    if let Some(name) = missing_chunk(&index, store)? {
+        return Err(Error::BundleOutputError(format!(
+            "chunk {name} is neither in {input_path} nor in {location}"
+        )));
+    }
+    store.write_index(&index)?;
+    Ok(index)
+}
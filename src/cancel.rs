@@ -0,0 +1,69 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+use crate::{Error, Result};
+
+/// Token which stops a running unfold or fold from another thread, e.g.:
+/// a Ctrl-C handler. Clones share the same state, so that cancelling
+/// any of them cancels all of them.
+///
+/// Operations check the token between chunks and fail with
+/// [`Error::Cancelled`], removing the output they created unless the
+/// token keeps partial output.
+#[derive(Debug, Clone, Default)]
+pub struct CancelToken {
+    cancelled: Arc<AtomicBool>,
+    keep_partial: bool,
+}
+
+impl CancelToken {
+    pub fn new() -> CancelToken {
+        CancelToken::default()
+    }
+
+    /// the same token, which leaves the partial output of a cancelled
+    /// operation in place rather than removing it, e.g.: to resume it
+    pub fn keeping_partial_output(self) -> CancelToken {
+        CancelToken {
+            keep_partial: true,
+            ..self
+        }
+    }
+
+    pub fn keeps_partial_output(&self) -> bool {
+        self.keep_partial
+    }
+
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::SeqCst);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::SeqCst)
+    }
+
+    /// fails with [`Error::Cancelled`] once the token is cancelled
+    pub(crate) fn check(&self) -> Result<()> {
+        if self.is_cancelled() {
+            return Err(Error::Cancelled("operation cancelled".to_string()));
+        }
+        Ok(())
+    }
+
+    /// removes `path`, created by the operation which returned
+    /// `result`, when the operation was cancelled and the token does
+    /// not keep partial output
+    pub(crate) fn roll_back<T>(
+        &self,
+        result: Result<T>,
+        path: &iocore::Path,
+    ) -> Result<T> {
+        if matches!(result, Err(Error::Cancelled(_)))
+            && !self.keep_partial
+            && path.exists()
+        {
+            path.delete()?;
+        }
+        result
+    }
+}
@@ -4,7 +4,7 @@ where
 {
     fn dispatch(&self) -> Result<(), E>;
     fn dispatch_cargo(&self) -> Result<(), E> {
-        Ok(self.dispatch()?)
+        self.dispatch()
     }
     fn run() -> Result<(), E> {
         let (args, is_cargo) = Self::args();
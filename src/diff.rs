@@ -0,0 +1,268 @@
+use std::collections::{BTreeSet, HashMap};
+use std::fmt::{Display, Formatter};
+use std::ops::Range;
+
+use iocore::Path;
+use serde::{Deserialize, Serialize};
+
+use crate::index::UnfoldIndex;
+use crate::Result;
+
+#[derive(
+    Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize,
+)]
+#[serde(rename_all = "lowercase")]
+pub enum ChangeKind {
+    Identical,
+    Changed,
+    Inserted,
+    Removed,
+}
+impl Display for ChangeKind {
+    fn fmt(&self, f: &mut Formatter) -> std::fmt::Result {
+        write!(
+            f,
+            "{}",
+            match self {
+                Self::Identical => "identical",
+                Self::Changed => "changed",
+                Self::Inserted => "inserted",
+                Self::Removed => "removed",
+            }
+        )
+    }
+}
+
+/// a run of consecutive chunks with the same [`ChangeKind`] along with
+/// the chunk and byte ranges it covers on each side. Ranges on the side
+/// that does not take part in the change (e.g. `a` for
+/// [`ChangeKind::Inserted`]) are empty and point at the offset where
+/// the change happens.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DiffRange {
+    pub kind: ChangeKind,
+    pub a_chunks: Range<usize>,
+    pub b_chunks: Range<usize>,
+    pub a_bytes: Range<u64>,
+    pub b_bytes: Range<u64>,
+}
+impl Display for DiffRange {
+    fn fmt(&self, f: &mut Formatter) -> std::fmt::Result {
+        let a = format!("{}..{}", self.a_bytes.start, self.a_bytes.end);
+        let b = format!("{}..{}", self.b_bytes.start, self.b_bytes.end);
+        let kind = self.kind.to_string();
+        match self.kind {
+            ChangeKind::Inserted => write!(f, "{kind:<9} b[{b}]"),
+            ChangeKind::Removed => write!(f, "{kind:<9} a[{a}]"),
+            _ => write!(f, "{kind:<9} a[{a}] b[{b}]"),
+        }
+    }
+}
+
+/// chunk-level comparison of two unfolded files computed from their
+/// indexes alone.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Diff {
+    pub identical: bool,
+    pub a_size: u64,
+    pub b_size: u64,
+    pub a_chunks: usize,
+    pub b_chunks: usize,
+    /// number of chunks of `b` whose content is present anywhere in `a`
+    pub shared_chunks: usize,
+    pub ranges: Vec<DiffRange>,
+}
+impl Diff {
+    /// fraction of the chunks of `b` whose content is already present
+    /// in `a`, i.e.: the fraction that does not need to be transferred
+    /// to turn `a` into `b`.
+    pub fn shared_ratio(&self) -> f64 {
+        if self.b_chunks == 0 {
+            return 1.0;
+        }
+        self.shared_chunks as f64 / self.b_chunks as f64
+    }
+}
+impl Display for Diff {
+    fn fmt(&self, f: &mut Formatter) -> std::fmt::Result {
+        for range in &self.ranges {
+            writeln!(f, "{range}")?;
+        }
+        write!(
+            f,
+            "shared {}/{} chunks ({:.2}%)",
+            self.shared_chunks,
+            self.b_chunks,
+            self.shared_ratio() * 100.0
+        )
+    }
+}
+
+/// compares the unfolded folders `a` and `b` by reading their indexes
+pub fn diff_unfolded(a: &Path, b: &Path) -> Result<Diff> {
+    let a = UnfoldIndex::read(a)?;
+    let b = UnfoldIndex::read(b)?;
+    Ok(diff_indexes(&a, &b))
+}
+
+pub fn diff_indexes(a: &UnfoldIndex, b: &UnfoldIndex) -> Diff {
+    let a_hashes = a.chunks();
+    let b_hashes = b.chunks();
+    let present = a_hashes.iter().collect::<BTreeSet<_>>();
+    let shared_chunks = b_hashes
+        .iter()
+        .filter(|hash| present.contains(hash))
+        .count();
+
+    let mut matches = Vec::<(usize, usize)>::new();
+    align(
+        a_hashes,
+        b_hashes,
+        0..a_hashes.len(),
+        0..b_hashes.len(),
+        &mut matches,
+    );
+
+    let mut ranges = Vec::<DiffRange>::new();
+    let mut push = |kind: ChangeKind, ac: Range<usize>, bc: Range<usize>| {
+        if ac.is_empty() && bc.is_empty() {
+            return;
+        }
+        if let Some(last) = ranges.last_mut() {
+            if last.kind == kind {
+                last.a_chunks.end = ac.end;
+                last.b_chunks.end = bc.end;
+                last.a_bytes = a.byte_range(last.a_chunks.clone());
+                last.b_bytes = b.byte_range(last.b_chunks.clone());
+                return;
+            }
+        }
+        ranges.push(DiffRange {
+            kind,
+            a_bytes: a.byte_range(ac.clone()),
+            b_bytes: b.byte_range(bc.clone()),
+            a_chunks: ac,
+            b_chunks: bc,
+        });
+    };
+    let (mut ai, mut bi) = (0usize, 0usize);
+    for (am, bm) in matches
+        .into_iter()
+        .chain(std::iter::once((a_hashes.len(), b_hashes.len())))
+    {
+        let kind = match (am > ai, bm > bi) {
+            (true, true) => ChangeKind::Changed,
+            (true, false) => ChangeKind::Removed,
+            (false, true) => ChangeKind::Inserted,
+            (false, false) => ChangeKind::Identical,
+        };
+        push(kind, ai..am, bi..bm);
+        if am < a_hashes.len() {
+            push(ChangeKind::Identical, am..am + 1, bm..bm + 1);
+        }
+        (ai, bi) = (am + 1, bm + 1);
+    }
+    Diff {
+        identical: a.sha256() == b.sha256(),
+        a_size: a.size(),
+        b_size: b.size(),
+        a_chunks: a_hashes.len(),
+        b_chunks: b_hashes.len(),
+        shared_chunks,
+        ranges,
+    }
+}
+
+/// appends to `matches` the pairs of positions of equal chunks between
+/// `a[ar]` and `b[br]` in increasing order using patience alignment:
+/// common prefixes and suffixes are matched directly and the remaining
+/// gap is split at the longest increasing sequence of chunks that
+/// occur exactly once on each side.
+fn align(
+    a: &[String],
+    b: &[String],
+    mut ar: Range<usize>,
+    mut br: Range<usize>,
+    matches: &mut Vec<(usize, usize)>,
+) {
+    while !ar.is_empty() && !br.is_empty() && a[ar.start] == b[br.start] {
+        matches.push((ar.start, br.start));
+        ar.start += 1;
+        br.start += 1;
+    }
+    let mut suffix = Vec::<(usize, usize)>::new();
+    while !ar.is_empty() && !br.is_empty() && a[ar.end - 1] == b[br.end - 1] {
+        ar.end -= 1;
+        br.end -= 1;
+        suffix.push((ar.end, br.end));
+    }
+    if !ar.is_empty() && !br.is_empty() {
+        let anchors = unique_anchors(a, b, ar.clone(), br.clone());
+        if !anchors.is_empty() {
+            let (mut a_start, mut b_start) = (ar.start, br.start);
+            for (ap, bp) in anchors {
+                align(a, b, a_start..ap, b_start..bp, matches);
+                matches.push((ap, bp));
+                (a_start, b_start) = (ap + 1, bp + 1);
+            }
+            align(a, b, a_start..ar.end, b_start..br.end, matches);
+        }
+    }
+    matches.extend(suffix.into_iter().rev());
+}
+
+fn unique_anchors(
+    a: &[String],
+    b: &[String],
+    ar: Range<usize>,
+    br: Range<usize>,
+) -> Vec<(usize, usize)> {
+    let mut a_counts = HashMap::<&str, (usize, usize)>::new();
+    for idx in ar {
+        let entry = a_counts.entry(a[idx].as_str()).or_insert((0, 0));
+        entry.0 += 1;
+        entry.1 = idx;
+    }
+    let mut candidates = Vec::<(usize, usize)>::new();
+    let mut b_counts = HashMap::<&str, (usize, usize)>::new();
+    for idx in br {
+        let entry = b_counts.entry(b[idx].as_str()).or_insert((0, 0));
+        entry.0 += 1;
+        entry.1 = idx;
+    }
+    for (hash, (b_count, bp)) in b_counts {
+        if let Some((1, ap)) = a_counts.get(hash) {
+            if b_count == 1 {
+                candidates.push((*ap, bp));
+            }
+        }
+    }
+    candidates.sort();
+    longest_increasing(&candidates)
+}
+
+/// longest subsequence of `pairs` (sorted by their first element) whose
+/// second elements are strictly increasing
+fn longest_increasing(pairs: &[(usize, usize)]) -> Vec<(usize, usize)> {
+    let mut tails = Vec::<usize>::new();
+    let mut previous = vec![usize::MAX; pairs.len()];
+    for (idx, (_, bp)) in pairs.iter().enumerate() {
+        let pos = tails.partition_point(|&tail| pairs[tail].1 < *bp);
+        if pos > 0 {
+            previous[idx] = tails[pos - 1];
+        }
+        if pos == tails.len() {
+            tails.push(idx);
+        } else {
+            tails[pos] = idx;
+        }
+    }
+    let mut sequence = Vec::<(usize, usize)>::new();
+    let mut current = tails.last().copied().unwrap_or(usize::MAX);
+    while current != usize::MAX {
+        sequence.push(pairs[current]);
+        current = previous[current];
+    }
+    sequence.reverse();
+    sequence
+}
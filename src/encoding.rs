@@ -0,0 +1,206 @@
+use std::collections::BTreeMap;
+use std::fmt::{Display, Formatter};
+use std::str::FromStr;
+
+use ciborium::value::{Integer, Value};
+
+use crate::{Error, Result};
+
+/// tag 55799 of self-described CBOR, which every CBOR index starts with
+const CBOR_MAGIC: [u8; 3] = [0xD9, 0xD9, 0xF7];
+const CBOR_TAG: u64 = 55799;
+
+/// How the index of an unfolded file and the sub-indexes of a
+/// hierarchical manifest are encoded, detected whenever they are read.
+///
+/// The CBOR encoding is a self-described CBOR map of the same fields as
+/// the yaml one, where chunk keys are unsigned integers, values of 64
+/// hex digits or more, such as sha256s, are byte strings, decimal
+/// values are unsigned integers and anything else is a text string.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum IndexEncoding {
+    #[default]
+    Yaml,
+    Json,
+    Cbor,
+}
+
+impl IndexEncoding {
+    pub const ALL: [IndexEncoding; 3] = [
+        IndexEncoding::Yaml,
+        IndexEncoding::Json,
+        IndexEncoding::Cbor,
+    ];
+
+    /// encoding of the serialized index `bytes`, defaulting to yaml
+    pub fn detect(bytes: &[u8]) -> IndexEncoding {
+        if bytes.starts_with(&CBOR_MAGIC) {
+            return IndexEncoding::Cbor;
+        }
+        match bytes
+            .iter()
+            .find(|byte| !byte.is_ascii_whitespace())
+        {
+            Some(b'{') => IndexEncoding::Json,
+            _ => IndexEncoding::Yaml,
+        }
+    }
+
+    /// serializes the `fields` of an index
+    pub(crate) fn encode(
+        &self,
+        fields: &BTreeMap<String, String>,
+    ) -> Result<Vec<u8>> {
+        let failed = |error: String| {
+            Error::FlattenFileOutputError(format!(
+                "failed to serialize index as {self}: {error}"
+            ))
+        };
+        match self {
+            Self::Yaml => serde_yaml::to_string(fields)
+                .map(String::into_bytes)
+                .map_err(|error| failed(error.to_string())),
+            Self::Json => {
+                let mut bytes = serde_json::to_vec_pretty(fields)
+                    .map_err(|error| failed(error.to_string()))?;
+                bytes.push(b'\n');
+                Ok(bytes)
+            },
+            Self::Cbor => {
+                let map = fields
+                    .iter()
+                    .map(|(key, value)| (cbor_key(key), cbor_value(value)))
+                    .collect();
+                let mut bytes = Vec::new();
+                ciborium::into_writer(
+                    &Value::Tag(CBOR_TAG, Box::new(Value::Map(map))),
+                    &mut bytes,
+                )
+                .map_err(|error| failed(error.to_string()))?;
+                Ok(bytes)
+            },
+        }
+    }
+
+    /// deserializes the fields of an index from `bytes`. `origin` is
+    /// only used in error messages.
+    pub(crate) fn decode(
+        &self,
+        bytes: &[u8],
+        origin: impl Display,
+    ) -> Result<BTreeMap<String, String>> {
+        let invalid = |error: String| {
+            Error::UnreadableIndexError(format!(
+                "invalid {self} in '{origin}': {error}"
+            ))
+        };
+        match self {
+            Self::Yaml => {
+                let yaml = std::str::from_utf8(bytes).map_err(|error| {
+                    Error::UnreadableIndexError(format!(
+                        "invalid utf-8 in '{origin}': {error}"
+                    ))
+                })?;
+                serde_yaml::from_str(yaml)
+                    .map_err(|error| invalid(error.to_string()))
+            },
+            Self::Json => serde_json::from_slice(bytes)
+                .map_err(|error| invalid(error.to_string())),
+            Self::Cbor => {
+                let value = ciborium::from_reader::<Value, _>(bytes)
+                    .map_err(|error| invalid(error.to_string()))?;
+                let Value::Tag(CBOR_TAG, value) = value else {
+                    return Err(invalid("not self-described".to_string()));
+                };
+                let Value::Map(map) = *value else {
+                    return Err(invalid("expected a map".to_string()));
+                };
+                let mut fields = BTreeMap::<String, String>::new();
+                for (key, value) in map {
+                    let key = field_key(key).ok_or_else(|| {
+                        invalid("keys must be text or unsigned".to_string())
+                    })?;
+                    let value = field_value(value).ok_or_else(|| {
+                        invalid(format!(
+                            "'{key}' must be text, bytes or unsigned"
+                        ))
+                    })?;
+                    if fields.insert(key.clone(), value).is_some() {
+                        return Err(invalid(format!("duplicate key '{key}'")));
+                    }
+                }
+                Ok(fields)
+            },
+        }
+    }
+}
+
+fn cbor_key(key: &str) -> Value {
+    match u64::from_str_radix(key, 16) {
+        Ok(idx) if key == format!("{idx:032x}") =>
+            Value::Integer(Integer::from(idx)),
+        _ => Value::Text(key.to_string()),
+    }
+}
+
+fn field_key(key: Value) -> Option<String> {
+    match key {
+        Value::Integer(idx) =>
+            Some(format!("{:032x}", u64::try_from(idx).ok()?)),
+        Value::Text(key) => Some(key),
+        _ => None,
+    }
+}
+
+fn field_value(value: Value) -> Option<String> {
+    match value {
+        Value::Bytes(bytes) => Some(hex::encode(bytes)),
+        Value::Integer(value) => Some(u64::try_from(value).ok()?.to_string()),
+        Value::Text(value) => Some(value),
+        _ => None,
+    }
+}
+
+fn cbor_value(value: &str) -> Value {
+    if value.len() >= 64 {
+        if let Ok(bytes) = hex::decode(value) {
+            if hex::encode(&bytes) == value {
+                return Value::Bytes(bytes);
+            }
+        }
+    }
+    match value.parse::<u64>() {
+        Ok(number) if number.to_string() == value =>
+            Value::Integer(Integer::from(number)),
+        _ => Value::Text(value.to_string()),
+    }
+}
+
+impl Display for IndexEncoding {
+    fn fmt(&self, f: &mut Formatter) -> std::fmt::Result {
+        write!(
+            f,
+            "{}",
+            match self {
+                Self::Yaml => "yaml",
+                Self::Json => "json",
+                Self::Cbor => "cbor",
+            }
+        )
+    }
+}
+
+impl FromStr for IndexEncoding {
+    type Err = Error;
+
+    fn from_str(value: &str) -> Result<IndexEncoding> {
+        match value.to_lowercase().as_str() {
+            "yaml" => Ok(IndexEncoding::Yaml),
+            "json" => Ok(IndexEncoding::Json),
+            "cbor" => Ok(IndexEncoding::Cbor),
+            _ => Err(Error::InvalidIndexError(format!(
+                "unsupported index encoding '{value}', expected yaml, json or cbor"
+            ))),
+        }
+    }
+}
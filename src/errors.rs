@@ -5,15 +5,34 @@ use serde::{Deserialize, Serialize};
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum Error {
     IOError(String),
+    BundleInputError(String),
+    BundleOutputError(String),
     FlattenFileInputError(String),
     FlattenFileOutputError(String),
     UnflattenFileInputError(String),
     UnflattenFileOutputError(String),
     InvalidIndexError(String),
+    InvalidPackError(String),
     MissingIndexError(String),
+    PackInputError(String),
+    PackOutputError(String),
     UnreadableIndexError(String),
     CorruptedDataError(String),
     ChecksumMismatch(String),
+    Cancelled(String),
+    ProofError(String),
+    SignatureError(String),
+    ShareError(String),
+    ReplicaError(String),
+    VolumeError(String),
+    StubError(String),
+    GitFilterError(String),
+    ArchiveError(String),
+    MissingChunkError(String),
+    StoreError(String),
+    PullError(String),
+    ServeError(String),
+    HttpError(String),
 }
 impl Display for Error {
     fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
@@ -23,15 +42,34 @@ impl Display for Error {
             self.variant(),
             match self {
                 Self::IOError(e) => e.to_string(),
+                Self::BundleInputError(e) => e.to_string(),
+                Self::BundleOutputError(e) => e.to_string(),
                 Self::FlattenFileInputError(e) => e.to_string(),
                 Self::FlattenFileOutputError(e) => e.to_string(),
                 Self::UnflattenFileInputError(e) => e.to_string(),
                 Self::UnflattenFileOutputError(e) => e.to_string(),
                 Self::InvalidIndexError(e) => e.to_string(),
+                Self::InvalidPackError(e) => e.to_string(),
                 Self::MissingIndexError(e) => e.to_string(),
+                Self::PackInputError(e) => e.to_string(),
+                Self::PackOutputError(e) => e.to_string(),
                 Self::UnreadableIndexError(e) => e.to_string(),
                 Self::CorruptedDataError(e) => e.to_string(),
                 Self::ChecksumMismatch(e) => e.to_string(),
+                Self::Cancelled(e) => e.to_string(),
+                Self::ProofError(e) => e.to_string(),
+                Self::SignatureError(e) => e.to_string(),
+                Self::ShareError(e) => e.to_string(),
+                Self::ReplicaError(e) => e.to_string(),
+                Self::VolumeError(e) => e.to_string(),
+                Self::StubError(e) => e.to_string(),
+                Self::GitFilterError(e) => e.to_string(),
+                Self::ArchiveError(e) => e.to_string(),
+                Self::MissingChunkError(e) => e.to_string(),
+                Self::StoreError(e) => e.to_string(),
+                Self::PullError(e) => e.to_string(),
+                Self::ServeError(e) => e.to_string(),
+                Self::HttpError(e) => e.to_string(),
             }
         )
     }
@@ -41,16 +79,35 @@ impl Error {
     pub fn variant(&self) -> String {
         match self {
             Error::IOError(_) => "IOError",
+            Self::BundleInputError(_) => "BundleInputError",
+            Self::BundleOutputError(_) => "BundleOutputError",
             Self::FlattenFileInputError(_) => "FlattenFileInputError",
             Self::FlattenFileOutputError(_) => "FlattenFileOutputError",
             Self::UnflattenFileInputError(_) => "UnflattenFileInputError",
             Self::UnflattenFileOutputError(_) => "UnflattenFileOutputError",
             Self::InvalidIndexError(_) => "InvalidIndexError",
+            Self::InvalidPackError(_) => "InvalidPackError",
             Self::MissingIndexError(_) => "MissingIndexError",
+            Self::PackInputError(_) => "PackInputError",
+            Self::PackOutputError(_) => "PackOutputError",
             Self::UnreadableIndexError(_) => "UnreadableIndexError",
             Self::CorruptedDataError(_) => "CorruptedDataError",
             Self::ChecksumMismatch(_) => "ChecksumMismatch",
-       }
+            Self::Cancelled(_) => "Cancelled",
+            Self::ProofError(_) => "ProofError",
+            Self::SignatureError(_) => "SignatureError",
+            Self::ShareError(_) => "ShareError",
+            Self::ReplicaError(_) => "ReplicaError",
+            Self::VolumeError(_) => "VolumeError",
+            Self::StubError(_) => "StubError",
+            Self::GitFilterError(_) => "GitFilterError",
+            Self::ArchiveError(_) => "ArchiveError",
+            Self::MissingChunkError(_) => "MissingChunkError",
+            Self::StoreError(_) => "StoreError",
+            Self::PullError(_) => "PullError",
+            Self::ServeError(_) => "ServeError",
+            Self::HttpError(_) => "HttpError",
+        }
         .to_string()
     }
 }
@@ -79,7 +136,12 @@ impl std::process::Termination for Exit {
             Exit::Success => std::process::ExitCode::from(0),
             Exit::Error(error) => {
                 eprintln!("{}", error);
-                std::process::ExitCode::from(1)
+                match error {
+                    // the conventional status of a process interrupted
+                    // by SIGINT
+                    Error::Cancelled(_) => std::process::ExitCode::from(130),
+                    _ => std::process::ExitCode::from(1),
+                }
             },
         }
     }
@@ -1,18 +1,46 @@
-use std::collections::BTreeMap;
 use std::fmt::{Display, Formatter};
-use std::iter::IntoIterator;
+use std::fs::File;
+use std::io::{Read, Seek, SeekFrom};
+use std::ops::Range;
+use std::sync::{Mutex, PoisonError};
 
-use iocore::{Path, Size};
-use sha2::{Sha256, Digest};
+use iocore::Path;
+use sha2::{Digest, Sha256};
 
+use crate::cancel::CancelToken;
+use crate::index::{ChunkLayout, ChunkNaming, UnfoldIndex};
+use crate::jobs::{for_each_ordered, join};
+use crate::manifest::Subindex;
+use crate::sparse::{
+    holes, is_zero_chunk, push_zero, zero_extents, SparseFile,
+};
+use crate::store::{ChunkStore, DirStore};
 use crate::{Error, Result};
 
-const MAX_FILE_SIZE: u64 = u32::MAX as u64;
+/// largest file read whole into memory, as volumes, shares, stubs, git
+/// filters and async unfolds do, while unfolding and folding files
+/// streams them a chunk at a time
+pub(crate) const MAX_FILE_SIZE: u64 = u32::MAX as u64;
+pub(crate) const CHUNK_SIZE: usize = u16::MAX as usize;
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
 pub enum Action {
+    Adopt,
+    Bundle,
+    Dehydrate,
+    Export,
     Fold,
+    Gc,
+    Hydrate,
+    Import,
+    Migrate,
+    Pack,
+    Pull,
+    Relayout,
+    Unbundle,
     Unfold,
+    Unpack,
+    Verify,
 }
 impl Display for Action {
     fn fmt(&self, f: &mut Formatter) -> std::fmt::Result {
@@ -20,8 +48,22 @@ impl Display for Action {
             f,
             "{}",
             match self {
+                Self::Adopt => "Adopt",
+                Self::Bundle => "Bundle",
+                Self::Dehydrate => "Dehydrate",
+                Self::Export => "Export",
                 Self::Fold => "Fold",
+                Self::Gc => "Gc",
+                Self::Hydrate => "Hydrate",
+                Self::Import => "Import",
+                Self::Migrate => "Migrate",
+                Self::Pack => "Pack",
+                Self::Pull => "Pull",
+                Self::Relayout => "Relayout",
+                Self::Unbundle => "Unbundle",
                 Self::Unfold => "Unfold",
+                Self::Unpack => "Unpack",
+                Self::Verify => "Verify",
             }
         )
     }
@@ -60,13 +102,6 @@ pub(crate) fn checksum(bytes: &[u8]) -> Vec<u8> {
     hasher.finalize().to_vec()
 }
 
-pub(crate) fn read_bytes_and_checksum(
-    input_path: &Path,
-) -> Result<(Vec<u8>, Vec<u8>)> {
-    let bytes = input_path.read_bytes()?;
-    let slice = bytes.as_slice();
-    Ok((bytes.clone(), checksum(slice)))
-}
 pub(crate) fn validate_checksum(bytes: &[u8], expected: &[u8]) -> Result<()> {
     let actual = checksum(bytes);
     if actual.as_slice() != expected {
@@ -80,10 +115,59 @@ pub(crate) fn validate_checksum(bytes: &[u8], expected: &[u8]) -> Result<()> {
     }
 }
 
+/// validates `bytes` against the chunk `name`, which is the hex
+/// encoded sha256 of its contents
+pub(crate) fn validate_chunk(name: &str, bytes: &[u8]) -> Result<()> {
+    let sha256 = hex::decode(name).map_err(|error| {
+        Error::CorruptedDataError(format!(
+            "invalid hex in chunk name {name}: {error}"
+        ))
+    })?;
+    validate_checksum(bytes, &sha256).map_err(|error| {
+        Error::CorruptedDataError(format!("in chunk {name}: {error}"))
+    })
+}
+
 pub fn unfold_file<C: FnMut(Progress)>(
     input_path: &Path,
     output_path: &Path,
-    mut progress: C,
+    progress: C,
+) -> Result<Path> {
+    let layout = ChunkLayout::default();
+    unfold_file_with_layout(input_path, output_path, layout, progress)
+}
+
+/// unfolds the file at `input_path` into the new folder at
+/// `output_path`, naming and laying out chunks according to `layout`
+pub fn unfold_file_with_layout<C: FnMut(Progress)>(
+    input_path: &Path,
+    output_path: &Path,
+    layout: ChunkLayout,
+    progress: C,
+) -> Result<Path> {
+    let cancel = CancelToken::new();
+    unfold_file_with_cancel(
+        input_path,
+        output_path,
+        layout,
+        1,
+        &cancel,
+        progress,
+    )
+}
+
+/// unfolds the file at `input_path` into the new folder at
+/// `output_path` like [`unfold_file_with_layout`], hashing and writing
+/// chunks across `jobs` threads, 0 for one per cpu, until `cancel` is
+/// cancelled, removing the folder then unless `cancel` keeps partial
+/// output
+pub fn unfold_file_with_cancel<C: FnMut(Progress)>(
+    input_path: &Path,
+    output_path: &Path,
+    layout: ChunkLayout,
+    jobs: usize,
+    cancel: &CancelToken,
+    progress: C,
 ) -> Result<Path> {
     let input_path = input_path.canonicalize()?.relative_to_cwd();
     if !input_path.is_file() {
@@ -96,62 +180,485 @@ pub fn unfold_file<C: FnMut(Progress)>(
             "{output_path} already exists"
         )));
     }
-    let size = input_path.file_size();
-    if size.as_u64() > MAX_FILE_SIZE {
-        let max_file_size = Size::from(MAX_FILE_SIZE);
+    let store = DirStore::new(output_path);
+    let result = unfold_to_store_with_cancel(
+        &input_path,
+        &store,
+        layout,
+        jobs,
+        cancel,
+        progress,
+    );
+    cancel.roll_back(result, output_path)?;
+    Ok(output_path.clone())
+}
+
+/// unfolds the file at `input_path` into the chunks and index of
+/// `store`, returning the index
+pub fn unfold_to_store<S: ChunkStore + ?Sized, C: FnMut(Progress)>(
+    input_path: &Path,
+    store: &S,
+    progress: C,
+) -> Result<UnfoldIndex> {
+    let layout = ChunkLayout::default();
+    unfold_to_store_with_layout(input_path, store, layout, progress)
+}
+
+/// unfolds the file at `input_path` into the chunks and index of
+/// `store`, naming and laying out chunks according to `layout`, and
+/// returns the index
+pub fn unfold_to_store_with_layout<S, C>(
+    input_path: &Path,
+    store: &S,
+    layout: ChunkLayout,
+    progress: C,
+) -> Result<UnfoldIndex>
+where
+    S: ChunkStore + ?Sized,
+    C: FnMut(Progress),
+{
+    let cancel = CancelToken::new();
+    unfold_to_store_with_cancel(input_path, store, layout, 1, &cancel, progress)
+}
+
+/// unfolds the file at `input_path` into `store` like
+/// [`unfold_to_store_with_layout`], hashing and writing chunks across
+/// `jobs` threads, 0 for one per cpu, until `cancel` is cancelled,
+/// deleting then the chunks it wrote, but those the index already in
+/// `store` points at, unless `cancel` keeps partial output. The index
+/// is written last, so that a cancelled unfold leaves no index pointing
+/// at missing chunks.
+pub fn unfold_to_store_with_cancel<S, C>(
+    input_path: &Path,
+    store: &S,
+    layout: ChunkLayout,
+    jobs: usize,
+    cancel: &CancelToken,
+    mut progress: C,
+) -> Result<UnfoldIndex>
+where
+    S: ChunkStore + ?Sized,
+    C: FnMut(Progress),
+{
+    if !input_path.is_file() {
         return Err(Error::FlattenFileInputError(format!(
-            "{input_path} is too large {size} (max = {max_file_size})"
+            "{input_path} is not a file"
         )));
     }
     progress(Progress::Start(Action::Unfold));
-    let (bytes, sha256) = read_bytes_and_checksum(&input_path)?;
-    let mut index = BTreeMap::<String, String>::new();
-    index.insert("sha256".to_string(), hex::encode(&sha256));
-    let max_chunk_size = u16::MAX as usize;
-    let mut paths = Vec::<Path>::new();
-    let index_path = output_path.join("index");
-    paths.push(index_path.clone());
-    let chunk_count = if bytes.len() > max_chunk_size {
-        (f64::from(bytes.len() as u32) / f64::from(u16::MAX)).ceil() as usize
-    } else {
-        1usize
+    let input = Input::open(std::slice::from_ref(input_path))?
+        .with_holes(holes(input_path)?);
+    let index = unfold_input(
+        &input,
+        store,
+        layout,
+        Action::Unfold,
+        jobs,
+        cancel,
+        &mut progress,
+    )?;
+    store.write_index(&index)?;
+    progress(Progress::End(Action::Unfold));
+    Ok(index)
+}
+
+/// builds the new unfolded folder at `output_path` from `parts`, the
+/// pieces of a file split by other tools, such as `xaa`, `xab`, … from
+/// `split -b` or `.001`, `.002`, …, joined in the given order and
+/// chunked according to `layout`
+pub fn adopt<C: FnMut(Progress)>(
+    parts: &[Path],
+    output_path: &Path,
+    layout: ChunkLayout,
+    progress: C,
+) -> Result<UnfoldIndex> {
+    let cancel = CancelToken::new();
+    adopt_with_cancel(parts, output_path, layout, &cancel, progress)
+}
+
+/// builds the new unfolded folder at `output_path` from `parts` like
+/// [`adopt`] until `cancel` is cancelled, removing the folder then
+/// unless `cancel` keeps partial output
+pub fn adopt_with_cancel<C: FnMut(Progress)>(
+    parts: &[Path],
+    output_path: &Path,
+    layout: ChunkLayout,
+    cancel: &CancelToken,
+    mut progress: C,
+) -> Result<UnfoldIndex> {
+    if parts.is_empty() {
+        return Err(Error::FlattenFileInputError(
+            "no parts to adopt".to_string(),
+        ));
+    }
+    if let Some(part) = parts.iter().find(|part| !part.is_file()) {
+        return Err(Error::FlattenFileInputError(format!(
+            "{part} is not a file"
+        )));
+    }
+    if output_path.exists() {
+        return Err(Error::FlattenFileOutputError(format!(
+            "{output_path} already exists"
+        )));
+    }
+    progress(Progress::Start(Action::Adopt));
+    let input = Input::open(parts)?;
+    let store = DirStore::new(output_path);
+    let mut write = || {
+        let index = unfold_input(
+            &input,
+            &store,
+            layout,
+            Action::Adopt,
+            1,
+            cancel,
+            &mut progress,
+        )?;
+        store.write_index(&index)?;
+        Ok(index)
+    };
+    let index = cancel.roll_back(write(), output_path)?;
+    progress(Progress::End(Action::Adopt));
+    Ok(index)
+}
+
+/// A file being unfolded, read a chunk at a time from the files it is
+/// made of, one after the other, so that only the chunks being worked
+/// on are held in memory however large it is.
+struct Input {
+    parts: Vec<InputPart>,
+    size: u64,
+    holes: Vec<Range<u64>>,
+}
+
+/// one of the files an [`Input`] is made of, along with the range of
+/// the input it covers
+struct InputPart {
+    path: Path,
+    file: Mutex<File>,
+    range: Range<u64>,
+}
+
+impl Input {
+    /// the input made of the files at `paths` joined in order
+    fn open(paths: &[Path]) -> Result<Input> {
+        let mut parts = Vec::<InputPart>::with_capacity(paths.len());
+        let mut size = 0;
+        for path in paths {
+            let file = File::open(path)?;
+            let end = size + file.metadata()?.len();
+            parts.push(InputPart {
+                path: path.clone(),
+                file: Mutex::new(file),
+                range: size..end,
+            });
+            size = end;
+        }
+        Ok(Input {
+            parts,
+            size,
+            holes: Vec::new(),
+        })
+    }
+
+    /// the same input with the byte ranges of its holes, in order and
+    /// apart, whose chunks are taken to be zeros without looking at them
+    fn with_holes(self, holes: Vec<Range<u64>>) -> Input {
+        Input { holes, ..self }
+    }
+
+    fn chunk_count(&self) -> usize {
+        self.size.div_ceil(CHUNK_SIZE as u64) as usize
+    }
+
+    /// byte range of the input covered by the chunk at position `idx`
+    fn chunk_range(&self, idx: usize) -> Range<u64> {
+        let start = idx as u64 * CHUNK_SIZE as u64;
+        start..(start + CHUNK_SIZE as u64).min(self.size)
+    }
+
+    /// reads the chunk at position `idx`
+    fn read_chunk(&self, idx: usize) -> Result<Vec<u8>> {
+        let range = self.chunk_range(idx);
+        let mut chunk = vec![0; (range.end - range.start) as usize];
+        for part in &self.parts {
+            let start = range.start.max(part.range.start);
+            let end = range.end.min(part.range.end);
+            if start >= end {
+                continue;
+            }
+            let buffer = &mut chunk
+                [(start - range.start) as usize..(end - range.start) as usize];
+            let mut file = part
+                .file
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner);
+            file.seek(SeekFrom::Start(start - part.range.start))
+                .and_then(|_| file.read_exact(buffer))
+                .map_err(|error| {
+                    Error::FlattenFileInputError(format!(
+                        "failed to read chunk {}/{} of {}: {error}",
+                        idx + 1,
+                        self.chunk_count(),
+                        part.path
+                    ))
+                })?;
+        }
+        Ok(chunk)
+    }
+}
+
+/// fails unless `layout` can be unfolded into
+fn check_layout(layout: ChunkLayout) -> Result<()> {
+    if layout.fanout > ChunkLayout::MAX_FANOUT {
+        return Err(Error::FlattenFileOutputError(format!(
+            "fan-out {} is larger than {}",
+            layout.fanout,
+            ChunkLayout::MAX_FANOUT
+        )));
+    }
+    if let Some(span) = layout.span.filter(|span| !span.is_power_of_two()) {
+        return Err(Error::FlattenFileOutputError(format!(
+            "span {span} is not a power of two"
+        )));
+    }
+    Ok(())
+}
+
+/// writes every chunk of `input` into `store` under the name given by
+/// `layout` and returns the index of `input` without writing it,
+/// reporting progress as `action` and stopping once `cancel` is
+/// cancelled, deleting then the chunks it wrote unless `cancel` keeps
+/// partial output. Chunks are read, hashed and written across `jobs`
+/// threads while the whole input is hashed in order, and chunks which
+/// are all zeros are recorded as such rather than written, except with
+/// sequential naming which promises every part.
+fn unfold_input<S: ChunkStore + ?Sized, C: FnMut(Progress)>(
+    input: &Input,
+    store: &S,
+    layout: ChunkLayout,
+    action: Action,
+    jobs: usize,
+    cancel: &CancelToken,
+    mut progress: C,
+) -> Result<UnfoldIndex> {
+    check_layout(layout)?;
+    let chunk_count = input.chunk_count();
+    let location = store.location();
+    let written = Mutex::new(Vec::<String>::new());
+    let put = |idx: usize| {
+        let chunk = input.read_chunk(idx)?;
+        let sha256 = hex::encode(checksum(&chunk));
+        let start = input.chunk_range(idx).start;
+        let zero = layout.naming != ChunkNaming::Sequential
+            && is_zero_chunk(&chunk, start, &input.holes);
+        if !zero {
+            let name = layout.chunk_name(idx, chunk_count, &sha256);
+            store.put(&name, &chunk).map_err(|error| {
+                Error::FlattenFileOutputError(format!(
+                    "failed to write chunk {}/{chunk_count} to {location}: {error}",
+                    idx + 1
+                ))
+            })?;
+            written
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .push(name);
+        }
+        Ok((chunk, sha256, zero))
+    };
+    let mut sha256 = Sha256::new();
+    let mut chunks = Vec::<String>::with_capacity(chunk_count);
+    let mut zeros = Vec::<Range<usize>>::new();
+    let result =
+        for_each_ordered(jobs, chunk_count, put, |idx, (chunk, name, zero)| {
+            cancel.check()?;
+            sha256.update(&chunk);
+            chunks.push(name);
+            if zero {
+                push_zero(&mut zeros, idx);
+            }
+            let chunk_index = idx + 1;
+            progress(Progress::Chunk {
+                index: chunk_index,
+                count: chunk_count,
+                action,
+            });
+            // each sub-index goes out right after the last chunk it lists
+            let Some(span) = layout.span else {
+                return Ok(());
+            };
+            if chunk_index % span != 0 && chunk_index != chunk_count {
+                return Ok(());
+            }
+            let first = idx - idx % span;
+            let subindex = Subindex::new(first, &chunks[first..], layout)?;
+            store
+                .put(&subindex.name, &subindex.bytes)
+                .map_err(|error| {
+                    Error::FlattenFileOutputError(format!(
+                        "failed to write sub-index {} to {location}: {error}",
+                        subindex.name
+                    ))
+                })?;
+            written
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .push(subindex.name);
+            Ok(())
+        });
+    if matches!(result, Err(Error::Cancelled(_)))
+        && !cancel.keeps_partial_output()
+    {
+        let written = written
+            .into_inner()
+            .unwrap_or_else(PoisonError::into_inner);
+        delete_written(store, &written)?;
+    }
+    result?;
+    Ok(UnfoldIndex::new(sha256.finalize().to_vec(), input.size, chunks)
+        .with_layout(layout)
+        .with_zeros(zeros))
+}
+
+/// deletes the chunks named `written` from `store`, but those the index
+/// already in `store`, if any, points at
+fn delete_written<S: ChunkStore + ?Sized>(
+    store: &S,
+    written: &[String],
+) -> Result<()> {
+    let kept = store
+        .read_index()
+        .and_then(|index| index.chunk_files())
+        .unwrap_or_default();
+    for name in written
+        .iter()
+        .filter(|name| !kept.contains_key(*name))
+    {
+        if store.has(name)? {
+            store.delete(name)?;
+        }
+    }
+    Ok(())
+}
+
+/// hashes the chunks of `bytes` across `jobs` threads and returns the
+/// index of `bytes` laid out as `layout`, unless `cancel` is cancelled
+/// meanwhile. Given the `holes` of the file, if any, chunks
+/// which are all zeros are recorded as such, except with sequential
+/// naming which promises every part.
+pub(crate) fn index_chunks(
+    bytes: &[u8],
+    holes: Option<&[Range<u64>]>,
+    layout: ChunkLayout,
+    jobs: usize,
+    cancel: &CancelToken,
+) -> Result<UnfoldIndex> {
+    check_layout(layout)?;
+    let parts = bytes.chunks(CHUNK_SIZE).collect::<Vec<&[u8]>>();
+    let (sha256, chunks) = join(
+        jobs,
+        || checksum(bytes),
+        || {
+            let mut chunks = Vec::<String>::with_capacity(parts.len());
+            for_each_ordered(
+                jobs,
+                parts.len(),
+                |idx| Ok(hex::encode(checksum(parts[idx]))),
+                |_, sha256| {
+                    chunks.push(sha256);
+                    cancel.check()
+                },
+            )?;
+            Ok::<_, Error>(chunks)
+        },
+    );
+    let zeros = match holes {
+        Some(holes) if layout.naming != ChunkNaming::Sequential =>
+            zero_extents(bytes, holes),
+        _ => Vec::new(),
+    };
+    Ok(UnfoldIndex::new(sha256, bytes.len() as u64, chunks?)
+        .with_layout(layout)
+        .with_zeros(zeros))
+}
+
+/// writes every chunk of `bytes` into `store` under the name given by
+/// `index`, as [`index_chunks`] makes it, without writing the index
+/// itself, reporting progress as `action` and stopping once `cancel` is
+/// cancelled. Chunks are written across `jobs` threads.
+pub(crate) fn write_chunks<S: ChunkStore + ?Sized, C: FnMut(Progress)>(
+    bytes: &[u8],
+    index: &UnfoldIndex,
+    store: &S,
+    action: Action,
+    jobs: usize,
+    cancel: &CancelToken,
+    mut progress: C,
+) -> Result<()> {
+    let layout = index.layout();
+    let parts = bytes.chunks(CHUNK_SIZE).collect::<Vec<&[u8]>>();
+    let chunk_count = index.chunk_count();
+    let location = store.location();
+    let put = |idx: usize| {
+        if index.is_zero(idx) {
+            return Ok(());
+        }
+        store
+            .put(&index.chunk_name(idx), parts[idx])
+            .map_err(|error| {
+                Error::FlattenFileOutputError(format!(
+                    "failed to write chunk {}/{chunk_count} to {location}: {error}",
+                    idx + 1
+                ))
+            })
     };
-    for (idx, chunk) in bytes.chunks(max_chunk_size).enumerate() {
+    for_each_ordered(jobs, chunk_count, put, |idx, ()| {
+        cancel.check()?;
         let chunk_index = idx + 1;
-        let index_hex = format!("{idx:032x}");
-        let name = hex::encode(&checksum(&chunk));
-        let chunk_path = output_path.join(&name);
         progress(Progress::Chunk {
             index: chunk_index,
             count: chunk_count,
-            action: Action::Unfold,
+            action,
         });
-        chunk_path
-            .write(&chunk)
-            .map_err(|error| {
-                Error::FlattenFileOutputError(format!(
-                    "failed to write chunk {chunk_index}/{chunk_count} to {chunk_path}: {error}"
-                ))
-            })?;
-        index.insert(index_hex, name);
-    }
-    index_path.write(
-        serde_yaml::to_string(&index)
+        // each sub-index goes out right after the last chunk it lists
+        let Some(span) = layout.span else {
+            return Ok(());
+        };
+        if chunk_index % span != 0 && chunk_index != chunk_count {
+            return Ok(());
+        }
+        let subindex = index.subindex(idx / span)?;
+        store
+            .put(&subindex.name, &subindex.bytes)
             .map_err(|error| {
                 Error::FlattenFileOutputError(format!(
-                    "failed to serialize index as yaml: {error}"
+                    "failed to write sub-index {} to {location}: {error}",
+                    subindex.name
                 ))
-            })?
-            .as_bytes(),
-    )?;
-    progress(Progress::End(Action::Unfold));
-    Ok(output_path.clone())
+            })
+    })
 }
 
 pub fn fold_file<C: FnMut(Progress)>(
     input_path: &Path,
     output_path: &Path,
-    mut progress: C,
+    progress: C,
+) -> Result<Path> {
+    let cancel = CancelToken::new();
+    fold_file_with_cancel(input_path, output_path, 1, &cancel, progress)
+}
+
+/// folds the unfolded folder at `input_path` back into the file at
+/// `output_path` like [`fold_file`], reading and verifying chunks across
+/// `jobs` threads, 0 for one per cpu, until `cancel` is cancelled,
+/// removing the file then unless `cancel` keeps partial output
+pub fn fold_file_with_cancel<C: FnMut(Progress)>(
+    input_path: &Path,
+    output_path: &Path,
+    jobs: usize,
+    cancel: &CancelToken,
+    progress: C,
 ) -> Result<Path> {
     let input_path = input_path.canonicalize()?.relative_to_cwd();
     if !input_path.is_dir() {
@@ -159,117 +666,224 @@ pub fn fold_file<C: FnMut(Progress)>(
             "{input_path} is not a directory"
         )));
     }
+    let store = DirStore::new(&input_path);
+    fold_from_store_with_cancel(&store, output_path, jobs, cancel, progress)
+}
+
+/// folds the chunks of `store` back into the file at `output_path`,
+/// verifying every chunk as well as the whole file
+pub fn fold_from_store<S: ChunkStore + ?Sized, C: FnMut(Progress)>(
+    store: &S,
+    output_path: &Path,
+    progress: C,
+) -> Result<Path> {
+    let cancel = CancelToken::new();
+    fold_from_store_with_cancel(store, output_path, 1, &cancel, progress)
+}
+
+/// folds the chunks of `store` back into the file at `output_path` like
+/// [`fold_from_store`], reading and verifying chunks across `jobs`
+/// threads, 0 for one per cpu, until `cancel` is cancelled, removing the
+/// file then unless `cancel` keeps partial output
+pub fn fold_from_store_with_cancel<S, C>(
+    store: &S,
+    output_path: &Path,
+    jobs: usize,
+    cancel: &CancelToken,
+    mut progress: C,
+) -> Result<Path>
+where
+    S: ChunkStore + ?Sized,
+    C: FnMut(Progress),
+{
     if output_path.exists() {
         return Err(Error::UnflattenFileOutputError(format!(
             "{output_path} already exists"
         )));
     }
     progress(Progress::Start(Action::Fold));
-    let (sha256, input_paths) = read_unfold_index(&input_path)?;
-    let mut bytes = Vec::<u8>::new();
-    let chunk_count = input_paths.len();
-    for (idx, chunk_path) in input_paths.into_iter().enumerate() {
-        let chunk_index = idx + 1;
+    let index = read_unfold_index(store)?;
+    fold_into(store, &index, output_path, jobs, cancel, &mut progress)?;
+    progress(Progress::End(Action::Fold));
+    Ok(output_path.clone())
+}
 
-        let filename = chunk_path.name();
-        let sha256 = hex::decode(&filename).map_err(|error| {
-            Error::CorruptedDataError(format!(
-                "invalid hex ({filename}) in chunk path {chunk_path}: {error}"
-            ))
-        })?;
-        let chunk_bytes = chunk_path.read_bytes()?;
-        validate_checksum(&chunk_bytes, &sha256).map_err(|error| {
-            Error::CorruptedDataError(format!("in path {chunk_path}: {error}"))
-        })?;
+/// puts the chunks of `bytes` which `store` does not have yet and
+/// returns the index of `bytes`, reporting progress as `action`
+pub(crate) fn store_chunks<S: ChunkStore + ?Sized, C: FnMut(Progress)>(
+    store: &S,
+    bytes: &[u8],
+    action: Action,
+    mut progress: C,
+) -> Result<UnfoldIndex> {
+    let chunk_count = bytes.len().div_ceil(CHUNK_SIZE);
+    let mut chunks = Vec::<String>::with_capacity(chunk_count);
+    for (idx, chunk) in bytes.chunks(CHUNK_SIZE).enumerate() {
+        let name = hex::encode(checksum(chunk));
+        if !store.has(&name)? {
+            store.put(&name, chunk)?;
+        }
+        chunks.push(name);
         progress(Progress::Chunk {
-            index: chunk_index,
+            index: idx + 1,
             count: chunk_count,
-            action: Action::Fold,
+            action,
         });
-        bytes.extend(&chunk_bytes);
     }
-    validate_checksum(&bytes, &sha256).map_err(|error| {
-        Error::CorruptedDataError(format!(
-            "invalid checksum at {input_path}: {error}"
-        ))
-    })?;
-    output_path.mkdir_parents()?.write(&bytes)?;
-    progress(Progress::End(Action::Fold));
-    Ok(output_path.clone())
+    Ok(UnfoldIndex::new(checksum(bytes), bytes.len() as u64, chunks))
 }
 
-pub(crate) fn read_unfold_index(input_path: &Path) -> Result<(Vec<u8>, Vec<Path>)> {
-    let index_path = input_path
-        .join("index")
-        .canonicalize()?
-        .relative_to_cwd();
-    if !index_path.exists() {
-        return Err(Error::MissingIndexError(format!(
-            "'{index_path}' does not exist"
-        )));
-    }
-    if !index_path.is_file() {
-        return Err(Error::UnreadableIndexError(format!(
-            "'{index_path}' is not a readable file"
-        )));
+/// reads the chunk at position `idx` of `index` from `store`, or makes
+/// up the zeros of a zero chunk, which is not stored
+pub(crate) fn get_chunk<S: ChunkStore + ?Sized>(
+    store: &S,
+    index: &UnfoldIndex,
+    idx: usize,
+) -> Result<Vec<u8>> {
+    if index.is_zero(idx) {
+        let range = index.chunk_range(idx);
+        return Ok(vec![0; (range.end - range.start) as usize]);
     }
-    let yaml = index_path.read()?;
-    let mut index = serde_yaml::from_str::<BTreeMap<String, String>>(&yaml)
-        .map_err(|error| {
-            Error::UnreadableIndexError(format!(
-                "invalid yaml in '{index_path}': {error}"
-            ))
-        })?;
+    store.get(&index.chunk_name(idx))
+}
 
-    let sha256 = match index.remove("sha256") {
-        Some(sha256) => hex::decode(sha256.as_str()).map_err(|error| {
-            Error::InvalidIndexError(format!(
-                "invalid hex in 'sha256' field of '{index_path}': {error}"
-            ))
-        })?,
-        None =>
-            return Err(Error::InvalidIndexError(format!(
-                "missing 'sha256' field in '{index_path}'"
-            ))),
-    };
-    if index.is_empty() {
-        return Err(Error::InvalidIndexError(format!(
-            "empty index in '{index_path}'"
-        )));
-    };
+/// reads the chunk at position `idx` of `index` from `store`, which is
+/// at `location`, and verifies it
+fn read_chunk<S: ChunkStore + ?Sized>(
+    store: &S,
+    index: &UnfoldIndex,
+    idx: usize,
+    location: &str,
+) -> Result<Vec<u8>> {
+    let chunk_bytes = get_chunk(store, index, idx)?;
+    validate_chunk(&index.chunks()[idx], &chunk_bytes).map_err(|error| {
+        Error::CorruptedDataError(format!("in {location}: {error}"))
+    })?;
+    Ok(chunk_bytes)
+}
 
-    let mut ordered_index = Vec::<(usize, Path)>::new();
-    for (key, value) in index.iter() {
-        let ord = usize::from_str_radix(key, 16).map_err(|error| {
-            Error::InvalidIndexError(format!(
-                "invalid hex in field '{key}' of '{index_path}': {error}"
-            ))
+/// folds the chunks `index` points at from `store` into the new file at
+/// `output_path` a chunk at a time, verifying every chunk as well as
+/// the whole file, which is removed unless it matches, and stopping
+/// once `cancel` is cancelled, removing the file then unless `cancel`
+/// keeps partial output. Chunks are read and verified ahead of the
+/// writer across `jobs` threads.
+pub(crate) fn fold_into<S: ChunkStore + ?Sized, C: FnMut(Progress)>(
+    store: &S,
+    index: &UnfoldIndex,
+    output_path: &Path,
+    jobs: usize,
+    cancel: &CancelToken,
+    mut progress: C,
+) -> Result<()> {
+    let chunk_count = index.chunk_count();
+    let location = store.location();
+    let read = |idx: usize| read_chunk(store, index, idx, &location);
+    let mut write = || {
+        let mut file = SparseFile::create(output_path)?;
+        let mut sha256 = Sha256::new();
+        for_each_ordered(jobs, chunk_count, read, |idx, chunk_bytes| {
+            cancel.check()?;
+            progress(Progress::Chunk {
+                index: idx + 1,
+                count: chunk_count,
+                action: Action::Fold,
+            });
+            sha256.update(&chunk_bytes);
+            file.write_chunk(&chunk_bytes, index.is_zero(idx))
         })?;
-        let path = input_path.join(value);
-        if !path.exists() {
-            return Err(Error::InvalidIndexError(format!(
-                "'{key}' points to missing file '{path}'"
+        let actual = sha256.finalize();
+        if actual.as_slice() != index.sha256() {
+            return Err(Error::CorruptedDataError(format!(
+                "invalid checksum at {location}: expected: {} actual: {}",
+                hex::encode(index.sha256()),
+                hex::encode(actual)
             )));
         }
-        if !path.is_file() {
-            return Err(Error::InvalidIndexError(format!(
-                "'{key}' points to unreadable file '{path}'"
-            )));
-        }
-        ordered_index.push((ord, path));
+        file.finish()
+    };
+    let result = write();
+    if result
+        .as_ref()
+        .is_err_and(|error| !matches!(error, Error::Cancelled(_)))
+        && output_path.exists()
+    {
+        output_path.delete()?;
     }
-    ordered_index.sort_by(|a, b| a.0.cmp(&b.0));
-    let mut input_paths = Vec::<Path>::new();
-
-    for (exp, (idx, path)) in ordered_index.into_iter().enumerate() {
-        if exp == idx {
-            input_paths.push(path);
-        } else {
-            let key = format!("{idx:x}");
-            return Err(Error::InvalidIndexError(format!(
-                "mismatch index {exp} != {idx} in key '{key}' pointing at path '{path}'"
-            )));
+    cancel.roll_back(result, output_path)
+}
+
+/// reads the chunks `index` points at from `store` back into the
+/// original bytes, verifying every chunk as well as the whole file and
+/// reporting progress as `action` and stopping once `cancel` is
+/// cancelled. Chunks are read and verified ahead of the copy across
+/// `jobs` threads.
+pub(crate) fn fold_chunks<S: ChunkStore + ?Sized, C: FnMut(Progress)>(
+    store: &S,
+    index: &UnfoldIndex,
+    action: Action,
+    jobs: usize,
+    cancel: &CancelToken,
+    mut progress: C,
+) -> Result<Vec<u8>> {
+    let mut bytes = Vec::<u8>::with_capacity(index.size() as usize);
+    let chunk_count = index.chunk_count();
+    let location = store.location();
+    let read = |idx: usize| read_chunk(store, index, idx, &location);
+    for_each_ordered(jobs, chunk_count, read, |idx, chunk_bytes| {
+        cancel.check()?;
+        progress(Progress::Chunk {
+            index: idx + 1,
+            count: chunk_count,
+            action,
+        });
+        bytes.extend(&chunk_bytes);
+        Ok(())
+    })?;
+    validate_checksum(&bytes, index.sha256()).map_err(|error| {
+        Error::CorruptedDataError(format!(
+            "invalid checksum at {location}: {error}"
+        ))
+    })?;
+    Ok(bytes)
+}
+
+/// reads the index of `store` and checks that every chunk it points at
+/// is present under the name given by the naming the index records,
+/// telling apart chunks stored under another naming
+pub(crate) fn read_unfold_index<S: ChunkStore + ?Sized>(
+    store: &S,
+) -> Result<UnfoldIndex> {
+    let index = store.read_index()?;
+    let location = store.location();
+    let layout = index.layout();
+    let other_layouts = ChunkNaming::ALL
+        .into_iter()
+        .flat_map(|naming| {
+            (0..=ChunkLayout::MAX_FANOUT).map(move |fanout| ChunkLayout {
+                naming,
+                fanout,
+                ..layout
+            })
+        })
+        .filter(|other| *other != layout)
+        .collect::<Vec<ChunkLayout>>();
+    for (idx, name) in index.chunk_names().iter().enumerate() {
+        if index.is_zero(idx) || store.has(name)? {
+            continue;
+        }
+        let key = format!("{idx:x}");
+        for other in &other_layouts {
+            let other_name = index.clone().with_layout(*other).chunk_name(idx);
+            if store.has(&other_name)? {
+                return Err(Error::InvalidIndexError(format!(
+                    "'{key}' points to chunk '{name}' stored by {layout} in '{location}' which holds '{other_name}' stored by {other} instead"
+                )));
+            }
         }
+        return Err(Error::InvalidIndexError(format!(
+            "'{key}' points to missing chunk '{name}' in '{location}'"
+        )));
     }
-    Ok((sha256, input_paths))
+    Ok(index)
 }
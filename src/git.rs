@@ -0,0 +1,242 @@
+use std::io::{ErrorKind, Read, Write};
+
+use iocore::Size;
+
+use crate::file::{
+    store_chunks, validate_checksum, validate_chunk, MAX_FILE_SIZE,
+};
+use crate::index::UnfoldIndex;
+use crate::store::ChunkStore;
+use crate::{Action, Error, Result};
+
+/// first line of the pointers committed in place of file contents,
+/// followed by the index of the file
+pub const GIT_POINTER_HEADER: &str = "# unfolder pointer v1\n";
+
+/// largest payload of a pkt-line of the long-running filter protocol
+const MAX_PACKET_DATA: usize = 65516;
+
+/// whether `bytes` are a pointer written by [`git_clean`]
+pub fn is_git_pointer(bytes: &[u8]) -> bool {
+    bytes.starts_with(GIT_POINTER_HEADER.as_bytes())
+}
+
+/// stores the chunks of `bytes` in `store`, skipping those it already
+/// has, and returns the pointer to commit in their place. Pointers and
+/// empty contents are returned unchanged.
+pub fn git_clean<S: ChunkStore + ?Sized>(
+    store: &S,
+    bytes: &[u8],
+) -> Result<Vec<u8>> {
+    if bytes.is_empty() || is_git_pointer(bytes) {
+        return Ok(bytes.to_vec());
+    }
+    if bytes.len() as u64 > MAX_FILE_SIZE {
+        let size = Size::from(bytes.len() as u64);
+        let max_file_size = Size::from(MAX_FILE_SIZE);
+        return Err(Error::GitFilterError(format!(
+            "contents are too large {size} (max = {max_file_size})"
+        )));
+    }
+    let index = store_chunks(store, bytes, Action::Unfold, |_| {})?;
+    Ok(format!("{GIT_POINTER_HEADER}{}", index.to_yaml()?).into_bytes())
+}
+
+/// restores the contents a pointer written by [`git_clean`] points at
+/// from the chunks in `store`, verifying each chunk and the whole
+/// contents. Anything other than a pointer is returned unchanged.
+pub fn git_smudge<S: ChunkStore + ?Sized>(
+    store: &S,
+    bytes: &[u8],
+) -> Result<Vec<u8>> {
+    if !is_git_pointer(bytes) {
+        return Ok(bytes.to_vec());
+    }
+    let yaml = std::str::from_utf8(bytes).map_err(|error| {
+        Error::GitFilterError(format!("invalid utf-8 in pointer: {error}"))
+    })?;
+    let (index, _) = UnfoldIndex::parse(yaml, "pointer")?;
+    let location = store.location();
+    let mut contents = Vec::<u8>::with_capacity(index.size() as usize);
+    for (sha256, name) in index.chunks().iter().zip(index.chunk_names()) {
+        let chunk = store.get(&name).map_err(|error| {
+            Error::GitFilterError(format!(
+                "chunk {name} is not in {location}: {error}"
+            ))
+        })?;
+        validate_chunk(sha256, &chunk).map_err(|error| {
+            Error::CorruptedDataError(format!("in {location}: {error}"))
+        })?;
+        contents.extend(chunk);
+    }
+    validate_checksum(&contents, index.sha256()).map_err(|error| {
+        Error::CorruptedDataError(format!(
+            "invalid checksum of contents restored from {location}: {error}"
+        ))
+    })?;
+    Ok(contents)
+}
+
+/// runs the long-running filter process protocol of git (see
+/// `gitattributes(5)`) on `reader` and `writer`, answering `clean` and
+/// `smudge` requests with [`git_clean`] and [`git_smudge`] until git
+/// closes the connection
+pub fn git_filter_process<S, R, W>(
+    store: &S,
+    mut reader: R,
+    mut writer: W,
+) -> Result<()>
+where
+    S: ChunkStore + ?Sized,
+    R: Read,
+    W: Write,
+{
+    let welcome = read_text_packets(&mut reader)?.unwrap_or_default();
+    if welcome.first().map(String::as_str) != Some("git-filter-client")
+        || !welcome.iter().any(|line| line == "version=2")
+    {
+        return Err(Error::GitFilterError(format!(
+            "unsupported filter protocol handshake {welcome:?}"
+        )));
+    }
+    write_packet(&mut writer, b"git-filter-server\n")?;
+    write_packet(&mut writer, b"version=2\n")?;
+    write_flush(&mut writer)?;
+    let capabilities = read_text_packets(&mut reader)?.unwrap_or_default();
+    for capability in ["capability=clean", "capability=smudge"] {
+        if capabilities.iter().any(|line| line == capability) {
+            write_packet(&mut writer, format!("{capability}\n").as_bytes())?;
+        }
+    }
+    write_flush(&mut writer)?;
+
+    while let Some(headers) = read_text_packets(&mut reader)? {
+        let command = headers
+            .iter()
+            .find_map(|line| line.strip_prefix("command="))
+            .unwrap_or_default()
+            .to_string();
+        let pathname = headers
+            .iter()
+            .find_map(|line| line.strip_prefix("pathname="))
+            .unwrap_or_default()
+            .to_string();
+        let contents = read_contents(&mut reader)?;
+        let result = match command.as_str() {
+            "clean" => git_clean(store, &contents),
+            "smudge" => git_smudge(store, &contents),
+            _ => Err(Error::GitFilterError(format!(
+                "unsupported command '{command}'"
+            ))),
+        };
+        match result {
+            Ok(output) => {
+                write_packet(&mut writer, b"status=success\n")?;
+                write_flush(&mut writer)?;
+                for packet in output.chunks(MAX_PACKET_DATA) {
+                    write_packet(&mut writer, packet)?;
+                }
+                write_flush(&mut writer)?;
+                // an empty list keeps the status above
+                write_flush(&mut writer)?;
+            },
+            Err(error) => {
+                // git only reports that the filter failed
+                eprintln!("unfolder: {command} {pathname}: {error}");
+                write_packet(&mut writer, b"status=error\n")?;
+                write_flush(&mut writer)?;
+            },
+        }
+        writer.flush()?;
+    }
+    Ok(())
+}
+
+enum Packet {
+    Data(Vec<u8>),
+    Flush,
+    /// the connection was closed between two packets
+    End,
+}
+
+fn read_packet<R: Read>(reader: &mut R) -> Result<Packet> {
+    let mut length = [0u8; 4];
+    let mut read = 0;
+    while read < length.len() {
+        match reader.read(&mut length[read..]) {
+            Ok(0) if read == 0 => return Ok(Packet::End),
+            Ok(0) =>
+                return Err(Error::GitFilterError(
+                    "truncated pkt-line length".to_string(),
+                )),
+            Ok(count) => read += count,
+            Err(error) if error.kind() == ErrorKind::Interrupted => {},
+            Err(error) => return Err(error.into()),
+        }
+    }
+    let length = std::str::from_utf8(&length)
+        .ok()
+        .and_then(|length| usize::from_str_radix(length, 16).ok())
+        .ok_or_else(|| {
+            Error::GitFilterError(format!("invalid pkt-line length {length:?}"))
+        })?;
+    match length {
+        0 => Ok(Packet::Flush),
+        1..4 => Err(Error::GitFilterError(format!(
+            "invalid pkt-line length {length}"
+        ))),
+        _ => {
+            let mut data = vec![0u8; length - 4];
+            reader.read_exact(&mut data)?;
+            Ok(Packet::Data(data))
+        },
+    }
+}
+
+/// reads text pkt-lines up to the next flush packet, returning `None`
+/// if the connection was closed before the first one
+fn read_text_packets<R: Read>(reader: &mut R) -> Result<Option<Vec<String>>> {
+    let mut lines = Vec::<String>::new();
+    loop {
+        match read_packet(reader)? {
+            Packet::Data(data) => lines.push(
+                String::from_utf8_lossy(&data)
+                    .trim_end_matches('\n')
+                    .to_string(),
+            ),
+            Packet::Flush => return Ok(Some(lines)),
+            Packet::End if lines.is_empty() => return Ok(None),
+            Packet::End =>
+                return Err(Error::GitFilterError(
+                    "connection closed before a flush packet".to_string(),
+                )),
+        }
+    }
+}
+
+/// reads binary pkt-lines up to the next flush packet
+fn read_contents<R: Read>(reader: &mut R) -> Result<Vec<u8>> {
+    let mut contents = Vec::<u8>::new();
+    loop {
+        match read_packet(reader)? {
+            Packet::Data(data) => contents.extend(data),
+            Packet::Flush => return Ok(contents),
+            Packet::End =>
+                return Err(Error::GitFilterError(
+                    "connection closed while reading contents".to_string(),
+                )),
+        }
+    }
+}
+
+fn write_packet<W: Write>(writer: &mut W, data: &[u8]) -> Result<()> {
+    write!(writer, "{:04x}", data.len() + 4)?;
+    writer.write_all(data)?;
+    Ok(())
+}
+
+fn write_flush<W: Write>(writer: &mut W) -> Result<()> {
+    writer.write_all(b"0000")?;
+    writer.flush()?;
+    Ok(())
+}
@@ -0,0 +1,383 @@
+use std::collections::BTreeMap;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::{TcpListener, TcpStream, ToSocketAddrs};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::{Error, Result};
+
+/// longest request, status or header line accepted, in bytes
+const MAX_LINE: usize = 8 * 1024;
+/// most header lines accepted in a request or a response
+const MAX_HEADERS: usize = 100;
+/// most connections [`serve`] answers at once, answering any other one
+/// with a `503` right away
+const MAX_CONNECTIONS: usize = 64;
+/// longest time [`serve`] waits for a client to send or receive anything
+/// before closing its connection, and [`get`] waits for a server to
+/// accept, send or receive anything before failing
+const TIMEOUT: Duration = Duration::from_secs(30);
+/// largest response body [`get`] reads, well above a chunk or the index
+/// of a file of about a terabyte
+const MAX_BODY: u64 = 1024 * 1024 * 1024;
+
+/// A minimal HTTP/1.1 request: one request per connection, no body.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct Request {
+    pub method: String,
+    pub path: String,
+    pub headers: BTreeMap<String, String>,
+}
+impl Request {
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .get(&name.to_lowercase())
+            .map(|value| value.as_str())
+    }
+
+    /// path split into its non-empty segments
+    pub fn segments(&self) -> Vec<&str> {
+        self.path
+            .split('?')
+            .next()
+            .unwrap_or_default()
+            .split('/')
+            .filter(|segment| !segment.is_empty())
+            .collect()
+    }
+
+    fn read<R: BufRead>(reader: &mut R) -> Result<Request> {
+        let line = read_line(reader)?;
+        let mut parts = line.split_whitespace();
+        let (method, path) = match (parts.next(), parts.next()) {
+            (Some(method), Some(path)) =>
+                (method.to_string(), path.to_string()),
+            _ =>
+                return Err(Error::HttpError(format!(
+                    "invalid request line '{line}'"
+                ))),
+        };
+        let headers = read_headers(reader)?;
+        Ok(Request {
+            method,
+            path,
+            headers,
+        })
+    }
+}
+
+pub(crate) enum Body {
+    Bytes(Vec<u8>),
+    /// a reader yielding exactly the given number of bytes, written to
+    /// the connection without being buffered in memory
+    Reader(Box<dyn Read + Send>, u64),
+}
+
+pub(crate) struct Response {
+    pub status: u16,
+    pub headers: Vec<(String, String)>,
+    pub body: Body,
+}
+impl Response {
+    pub fn new(status: u16, body: Vec<u8>) -> Response {
+        Response {
+            status,
+            headers: Vec::new(),
+            body: Body::Bytes(body),
+        }
+    }
+
+    pub fn stream(
+        status: u16,
+        reader: impl Read + Send + 'static,
+        length: u64,
+    ) -> Response {
+        Response {
+            status,
+            headers: Vec::new(),
+            body: Body::Reader(Box::new(reader), length),
+        }
+    }
+
+    pub fn into_bytes(self) -> Result<Vec<u8>> {
+        match self.body {
+            Body::Bytes(bytes) => Ok(bytes),
+            Body::Reader(reader, length) => {
+                let mut bytes = Vec::<u8>::new();
+                reader.take(length).read_to_end(&mut bytes)?;
+                Ok(bytes)
+            },
+        }
+    }
+
+    pub fn text(status: u16, body: impl std::fmt::Display) -> Response {
+        Response::new(status, format!("{body}\n").into_bytes())
+            .with_header("Content-Type", "text/plain; charset=utf-8")
+    }
+
+    pub fn with_header(
+        mut self,
+        name: impl std::fmt::Display,
+        value: impl std::fmt::Display,
+    ) -> Response {
+        self.headers
+            .push((name.to_string(), value.to_string()));
+        self
+    }
+
+    fn reason(&self) -> &'static str {
+        match self.status {
+            200 => "OK",
+            206 => "Partial Content",
+            304 => "Not Modified",
+            400 => "Bad Request",
+            404 => "Not Found",
+            405 => "Method Not Allowed",
+            416 => "Range Not Satisfiable",
+            503 => "Service Unavailable",
+            _ => "Internal Server Error",
+        }
+    }
+
+    /// writes the response, omitting the body for `HEAD` requests while
+    /// keeping its `Content-Length`
+    fn write<W: Write>(self, writer: &mut W, head: bool) -> Result<()> {
+        write!(writer, "HTTP/1.1 {} {}\r\n", self.status, self.reason())?;
+        for (name, value) in &self.headers {
+            write!(writer, "{name}: {value}\r\n")?;
+        }
+        let length = match &self.body {
+            Body::Bytes(bytes) => bytes.len() as u64,
+            Body::Reader(_, length) => *length,
+        };
+        write!(writer, "Content-Length: {length}\r\n")?;
+        write!(writer, "Connection: close\r\n\r\n")?;
+        if !head {
+            match self.body {
+                Body::Bytes(bytes) => writer.write_all(&bytes)?,
+                Body::Reader(reader, length) => {
+                    let copied =
+                        std::io::copy(&mut reader.take(length), writer)?;
+                    if copied != length {
+                        return Err(Error::HttpError(format!(
+                            "response body ended after {copied} of {length} bytes"
+                        )));
+                    }
+                },
+            }
+        }
+        writer.flush()?;
+        Ok(())
+    }
+}
+
+/// accepts connections on `listener` forever, answering each one in
+/// its own thread with the response returned by `handler`, up to
+/// [`MAX_CONNECTIONS`] at once
+pub(crate) fn serve<H>(listener: TcpListener, handler: H) -> Result<()>
+where
+    H: Fn(&Request) -> Response + Send + Sync + 'static,
+{
+    let handler = Arc::new(handler);
+    let active = Arc::new(AtomicUsize::new(0));
+    for stream in listener.incoming() {
+        let mut stream = match stream {
+            Ok(stream) => stream,
+            Err(_) => continue,
+        };
+        if stream.set_read_timeout(Some(TIMEOUT)).is_err()
+            || stream.set_write_timeout(Some(TIMEOUT)).is_err()
+        {
+            continue;
+        }
+        if active.load(Ordering::SeqCst) >= MAX_CONNECTIONS {
+            let _ = Response::text(503, "too many connections")
+                .write(&mut stream, false);
+            continue;
+        }
+        let slot = Slot::take(&active);
+        let handler = handler.clone();
+        std::thread::spawn(move || {
+            let _ = handle(stream, handler.as_ref());
+            drop(slot);
+        });
+    }
+    Ok(())
+}
+
+/// one of the connections [`serve`] answers at once, given back when
+/// dropped, even if the handler panics
+struct Slot(Arc<AtomicUsize>);
+impl Slot {
+    fn take(active: &Arc<AtomicUsize>) -> Slot {
+        active.fetch_add(1, Ordering::SeqCst);
+        Slot(active.clone())
+    }
+}
+impl Drop for Slot {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+fn handle<H>(stream: TcpStream, handler: &H) -> Result<()>
+where
+    H: Fn(&Request) -> Response,
+{
+    let mut reader = BufReader::new(stream.try_clone()?);
+    let mut writer = stream;
+    let (response, head) = match Request::read(&mut reader) {
+        Ok(request) if request.method == "GET" => (handler(&request), false),
+        Ok(request) if request.method == "HEAD" => (handler(&request), true),
+        Ok(request) => (
+            Response::text(405, format!("{} not allowed", request.method)),
+            false,
+        ),
+        Err(error) => (Response::text(400, error), false),
+    };
+    response.write(&mut writer, head)
+}
+
+/// sends a `GET` request to `url` (`http://host:port/path`) with the
+/// given extra headers and returns the response
+pub(crate) fn get(url: &str, headers: &[(&str, &str)]) -> Result<Response> {
+    let rest = match url.strip_prefix("http://") {
+        Some(rest) => rest,
+        None =>
+            return Err(Error::HttpError(format!(
+                "unsupported url '{url}': only http:// is supported"
+            ))),
+    };
+    let (host, path) = match rest.find('/') {
+        Some(pos) => (&rest[..pos], &rest[pos..]),
+        None => (rest, "/"),
+    };
+    let address = if host.contains(':') {
+        host.to_string()
+    } else {
+        format!("{host}:80")
+    };
+    let mut stream = connect(&address)?;
+    write!(stream, "GET {path} HTTP/1.1\r\nHost: {host}\r\n")?;
+    for (name, value) in headers {
+        write!(stream, "{name}: {value}\r\n")?;
+    }
+    write!(stream, "Connection: close\r\n\r\n")?;
+    stream.flush()?;
+
+    let mut reader = BufReader::new(stream);
+    let line = read_line(&mut reader)?;
+    let status = line
+        .split_whitespace()
+        .nth(1)
+        .and_then(|status| status.parse::<u16>().ok())
+        .ok_or_else(|| {
+            Error::HttpError(format!("invalid status line '{line}' from {url}"))
+        })?;
+    let response_headers = read_headers(&mut reader)?;
+    let mut body = Vec::<u8>::new();
+    match response_headers.get("content-length") {
+        Some(length) => {
+            let length = length.parse::<u64>().map_err(|error| {
+                Error::HttpError(format!(
+                    "invalid content-length '{length}' from {url}: {error}"
+                ))
+            })?;
+            if length > MAX_BODY {
+                return Err(Error::HttpError(format!(
+                    "response from {url} is larger than {MAX_BODY} bytes"
+                )));
+            }
+            reader.take(length).read_to_end(&mut body)?;
+            if body.len() as u64 != length {
+                return Err(Error::HttpError(format!(
+                    "truncated response from {url}: expected {length} bytes, got {}",
+                    body.len()
+                )));
+            }
+        },
+        None => {
+            reader.take(MAX_BODY + 1).read_to_end(&mut body)?;
+            if body.len() as u64 > MAX_BODY {
+                return Err(Error::HttpError(format!(
+                    "response from {url} is larger than {MAX_BODY} bytes"
+                )));
+            }
+        },
+    }
+    Ok(Response {
+        status,
+        headers: response_headers.into_iter().collect(),
+        body: Body::Bytes(body),
+    })
+}
+
+/// connects to the first address `address` resolves to that accepts
+/// within [`TIMEOUT`], with the same timeout set for reads and writes
+fn connect(address: &str) -> Result<TcpStream> {
+    let failed = |error: std::io::Error| {
+        Error::HttpError(format!("failed to connect to {address}: {error}"))
+    };
+    let mut last_error = None;
+    for socket_address in address.to_socket_addrs().map_err(failed)? {
+        match TcpStream::connect_timeout(&socket_address, TIMEOUT) {
+            Ok(stream) => {
+                stream.set_read_timeout(Some(TIMEOUT))?;
+                stream.set_write_timeout(Some(TIMEOUT))?;
+                return Ok(stream);
+            },
+            Err(error) => last_error = Some(error),
+        }
+    }
+    Err(failed(last_error.unwrap_or_else(|| {
+        std::io::Error::new(
+            std::io::ErrorKind::NotFound,
+            "no address to connect to",
+        )
+    })))
+}
+
+/// reads a line of at most [`MAX_LINE`] bytes
+fn read_line<R: BufRead>(reader: &mut R) -> Result<String> {
+    let mut line = String::new();
+    reader
+        .take(MAX_LINE as u64 + 1)
+        .read_line(&mut line)?;
+    if line.len() > MAX_LINE {
+        return Err(Error::HttpError(format!(
+            "line longer than {MAX_LINE} bytes"
+        )));
+    }
+    Ok(line.trim_end_matches(['\r', '\n']).to_string())
+}
+
+fn read_headers<R: BufRead>(
+    reader: &mut R,
+) -> Result<BTreeMap<String, String>> {
+    let mut headers = BTreeMap::<String, String>::new();
+    for count in 0.. {
+        let line = read_line(reader)?;
+        if line.is_empty() {
+            break;
+        }
+        if count == MAX_HEADERS {
+            return Err(Error::HttpError(format!(
+                "more than {MAX_HEADERS} header lines"
+            )));
+        }
+        match line.split_once(':') {
+            Some((name, value)) => {
+                headers.insert(
+                    name.trim().to_lowercase(),
+                    value.trim().to_string(),
+                );
+            },
+            None =>
+                return Err(Error::HttpError(format!(
+                    "invalid header line '{line}'"
+                ))),
+        }
+    }
+    Ok(headers)
+}
@@ -0,0 +1,310 @@
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::Read;
+
+use iocore::{Path, PathDateTime, Size};
+
+use crate::cancel::CancelToken;
+use crate::file::{fold_chunks, store_chunks, MAX_FILE_SIZE};
+use crate::index::UnfoldIndex;
+use crate::maintenance::verify_index;
+use crate::store::{open_store, ChunkStore};
+use crate::{Action, Error, Progress, Result};
+
+/// first line of the stubs [`dehydrate`] replaces files with
+pub const STUB_HEADER: &str = "# unfolder stub v1\n";
+
+/// Contents of the stub a file is replaced with by [`dehydrate`]: the
+/// location of the store holding its chunks, its permissions and
+/// modification time, and its index.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Stub {
+    store: String,
+    mode: u32,
+    modified: (i64, u32),
+    index: UnfoldIndex,
+}
+
+impl Stub {
+    /// reads the stub at `path`
+    pub fn read(path: &Path) -> Result<Stub> {
+        Stub::parse(&path.read_bytes()?, path)
+    }
+
+    /// parses the bytes of a stub. `origin` is only used in error
+    /// messages.
+    pub fn parse(bytes: &[u8], origin: impl std::fmt::Display) -> Result<Stub> {
+        if !bytes.starts_with(STUB_HEADER.as_bytes()) {
+            return Err(Error::StubError(format!(
+                "'{origin}' is not a dehydrated stub"
+            )));
+        }
+        let yaml = std::str::from_utf8(bytes).map_err(|error| {
+            Error::StubError(format!("invalid utf-8 in '{origin}': {error}"))
+        })?;
+        let mut fields = serde_yaml::from_str::<BTreeMap<String, String>>(yaml)
+            .map_err(|error| {
+                Error::StubError(format!("invalid yaml in '{origin}': {error}"))
+            })?;
+        let mut field = |name: &str| {
+            fields.remove(name).ok_or_else(|| {
+                Error::StubError(format!(
+                    "missing '{name}' field in '{origin}'"
+                ))
+            })
+        };
+        let store = field("store")?;
+        let mode = field("mode")?;
+        let mode = u32::from_str_radix(&mode, 8).map_err(|error| {
+            Error::StubError(format!(
+                "invalid octal in 'mode' field of '{origin}': {error}"
+            ))
+        })?;
+        let modified = field("modified")?;
+        let modified = modified
+            .split_once('.')
+            .and_then(|(secs, nsecs)| {
+                Some((secs.parse::<i64>().ok()?, nsecs.parse::<u32>().ok()?))
+            })
+            .ok_or_else(|| {
+                Error::StubError(format!(
+                    "invalid timestamp '{modified}' in 'modified' field of '{origin}'"
+                ))
+            })?;
+        let (index, _) = UnfoldIndex::from_fields(fields, origin)?;
+        Ok(Stub {
+            store,
+            mode,
+            modified,
+            index,
+        })
+    }
+
+    pub fn to_bytes(&self) -> Result<Vec<u8>> {
+        let mut fields = self.index.to_flat_fields();
+        fields.insert("store".to_string(), self.store.clone());
+        fields.insert("mode".to_string(), format!("{:o}", self.mode));
+        let (secs, nsecs) = self.modified;
+        fields.insert("modified".to_string(), format!("{secs}.{nsecs:09}"));
+        let yaml = serde_yaml::to_string(&fields).map_err(|error| {
+            Error::StubError(format!(
+                "failed to serialize stub as yaml: {error}"
+            ))
+        })?;
+        Ok(format!("{STUB_HEADER}{yaml}").into_bytes())
+    }
+
+    /// location of the store holding the chunks, as accepted by
+    /// [`open_store`]
+    pub fn store(&self) -> &str {
+        &self.store
+    }
+
+    /// permission bits of the dehydrated file
+    pub fn mode(&self) -> u32 {
+        self.mode
+    }
+
+    /// modification time of the dehydrated file as seconds and
+    /// nanoseconds since the unix epoch
+    pub fn modified(&self) -> (i64, u32) {
+        self.modified
+    }
+
+    pub fn index(&self) -> &UnfoldIndex {
+        &self.index
+    }
+}
+
+/// whether the file at `path` is a stub written by [`dehydrate`],
+/// reading only its first line
+pub fn is_stub(path: &Path) -> Result<bool> {
+    if !path.is_file() {
+        return Ok(false);
+    }
+    let mut header = Vec::<u8>::with_capacity(STUB_HEADER.len());
+    File::open(path.path())?
+        .take(STUB_HEADER.len() as u64)
+        .read_to_end(&mut header)?;
+    Ok(header == STUB_HEADER.as_bytes())
+}
+
+/// moves the contents of the file at `path`, or of every file under
+/// it if it is a directory, into `store` and replaces them in place
+/// with stubs recording `store.location()`, so that [`hydrate`] can
+/// restore them. Each file is verified against `store` before being
+/// replaced. Empty files, symlinks, existing stubs and the store itself
+/// are skipped. Returns the paths of the dehydrated files.
+pub fn dehydrate<S: ChunkStore + ?Sized, C: FnMut(Progress)>(
+    path: &Path,
+    store: &S,
+    mut progress: C,
+) -> Result<Vec<Path>> {
+    let location = store.location();
+    let store_path = Path::new(
+        location
+            .strip_prefix("sqlite://")
+            .unwrap_or(&location),
+    )
+    .try_canonicalize();
+    if path.is_file() && is_stub(path)? {
+        return Err(Error::StubError(format!("{path} is already dehydrated")));
+    }
+    let mut dehydrated = Vec::<Path>::new();
+    for file in files_under(path, &store_path)? {
+        if is_stub(&file)? || file.file_size().as_u64() == 0 {
+            continue;
+        }
+        dehydrate_file(&file, store, &location, &mut progress)?;
+        dehydrated.push(file);
+    }
+    Ok(dehydrated)
+}
+
+/// restores the file at `path`, or every stub under it if it is a
+/// directory, from the store its stub records, along with its
+/// permissions and modification time. Every chunk and the whole
+/// contents are verified before the stub is replaced. Returns the
+/// paths of the hydrated files.
+pub fn hydrate<C: FnMut(Progress)>(
+    path: &Path,
+    mut progress: C,
+) -> Result<Vec<Path>> {
+    if path.is_file() && !is_stub(path)? {
+        return Err(Error::StubError(format!(
+            "'{path}' is not a dehydrated stub"
+        )));
+    }
+    let mut stores = BTreeMap::<String, Box<dyn ChunkStore>>::new();
+    let mut hydrated = Vec::<Path>::new();
+    for file in files_under(path, &Path::new(""))? {
+        if !is_stub(&file)? {
+            continue;
+        }
+        let stub = Stub::read(&file)?;
+        if !stores.contains_key(stub.store()) {
+            stores.insert(stub.store().to_string(), open_store(stub.store())?);
+        }
+        let store = stores[stub.store()].as_ref();
+        progress(Progress::Start(Action::Hydrate));
+        let bytes = fold_chunks(
+            store,
+            stub.index(),
+            Action::Hydrate,
+            1,
+            &CancelToken::new(),
+            &mut progress,
+        )?;
+        replace(&file, &bytes, stub.mode(), stub.modified())?;
+        progress(Progress::End(Action::Hydrate));
+        hydrated.push(file);
+    }
+    Ok(hydrated)
+}
+
+fn dehydrate_file<S: ChunkStore + ?Sized, C: FnMut(Progress)>(
+    path: &Path,
+    store: &S,
+    location: &str,
+    mut progress: C,
+) -> Result<()> {
+    let size = path.file_size();
+    if size.as_u64() > MAX_FILE_SIZE {
+        let max_file_size = Size::from(MAX_FILE_SIZE);
+        return Err(Error::StubError(format!(
+            "{path} is too large {size} (max = {max_file_size})"
+        )));
+    }
+    progress(Progress::Start(Action::Dehydrate));
+    let mode = path.check_permissions()?.into_u32();
+    let modified = path
+        .modified()
+        .ok_or_else(|| {
+            Error::StubError(format!(
+                "failed to read the modification time of {path}"
+            ))
+        })?
+        .timestamp();
+    let bytes = path.read_bytes()?;
+    let index = store_chunks(store, &bytes, Action::Dehydrate, &mut progress)?;
+    let stub = Stub {
+        store: location.to_string(),
+        mode,
+        modified,
+        index,
+    };
+    let stub_bytes = stub.to_bytes()?;
+    Stub::parse(&stub_bytes, path)
+        .and_then(|parsed| {
+            if parsed == stub {
+                Ok(())
+            } else {
+                Err(Error::StubError(format!(
+                    "stub does not round-trip: {parsed:?}"
+                )))
+            }
+        })
+        .and_then(|_| verify_index(store, stub.index(), Action::Verify, |_| {}))
+        .map_err(|error| {
+            Error::StubError(format!(
+                "refusing to dehydrate {path} into {location}: {error}"
+            ))
+        })?;
+    replace(path, &stub_bytes, mode, modified)?;
+    progress(Progress::End(Action::Dehydrate));
+    Ok(())
+}
+
+/// replaces the file at `path` with `bytes` through a sibling staging
+/// file, so that it holds either its previous or its new contents
+fn replace(
+    path: &Path,
+    bytes: &[u8],
+    mode: u32,
+    modified: (i64, u32),
+) -> Result<()> {
+    let mut staging_path = path.with_filename(format!(
+        ".{}.hydrating-{}",
+        path.name(),
+        std::process::id()
+    ));
+    let result = staging_path
+        .write(bytes)
+        .and_then(|mut staging_path| staging_path.set_mode(mode))
+        .and_then(|_| {
+            let (secs, nsecs) = modified;
+            staging_path
+                .set_modified_time(&PathDateTime::from_timestamp(secs, nsecs))
+        })
+        .and_then(|staging_path| staging_path.rename(path, false));
+    if let Err(error) = result {
+        staging_path.delete()?;
+        return Err(error.into());
+    }
+    Ok(())
+}
+
+/// `path` itself if it is a file, otherwise the regular files under it
+/// in order, leaving out symlinks and `excluded`
+fn files_under(path: &Path, excluded: &Path) -> Result<Vec<Path>> {
+    if path.is_file() {
+        return Ok(vec![path.clone()]);
+    }
+    if !path.is_dir() {
+        return Err(Error::StubError(format!(
+            "{path} is neither a file nor a directory"
+        )));
+    }
+    let mut files = Vec::<Path>::new();
+    for entry in path.list()? {
+        if entry.is_symlink() || entry.try_canonicalize() == *excluded {
+            continue;
+        }
+        if entry.is_dir() {
+            files.extend(files_under(&entry, excluded)?);
+        } else if entry.is_file() {
+            files.push(entry);
+        }
+    }
+    Ok(files)
+}
@@ -0,0 +1,837 @@
+use std::collections::BTreeMap;
+use std::fmt::{Display, Formatter};
+use std::ops::Range;
+use std::str::FromStr;
+
+use iocore::Path;
+
+use crate::encoding::IndexEncoding;
+use crate::file::CHUNK_SIZE;
+use crate::manifest::{ManifestIndex, Subindex};
+use crate::merkle::merkle_root;
+use crate::sign::IndexSignature;
+use crate::store::{ChunkStore, DirStore};
+use crate::{Error, Result};
+
+pub const INDEX_NAME: &str = "index";
+
+/// How the chunks of an unfolded file are named in its store, recorded
+/// in the `naming` field of the index. The index always points at the
+/// sha256 of each chunk, which readers verify regardless of naming.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ChunkNaming {
+    /// the hex encoded sha256 of the chunk, so that identical chunks
+    /// are stored once
+    #[default]
+    Hash,
+    /// `000001-<sha256>`, `000002-<sha256>`, … so that sorting by name
+    /// gives the order of the chunks
+    Ordered,
+    /// `part-0001.bin`, `part-0002.bin`, … in order, so that plain
+    /// `cat` rejoins them
+    Sequential,
+}
+
+impl ChunkNaming {
+    pub const ALL: [ChunkNaming; 3] = [
+        ChunkNaming::Hash,
+        ChunkNaming::Ordered,
+        ChunkNaming::Sequential,
+    ];
+
+    /// name of the chunk with contents `sha256` at position `idx` of
+    /// an index of `count` chunks
+    fn chunk_name(&self, idx: usize, count: usize, sha256: &str) -> String {
+        match self {
+            Self::Hash => sha256.to_string(),
+            Self::Ordered => {
+                let width = count.to_string().len().max(6);
+                format!("{:0width$}-{sha256}", idx + 1)
+            },
+            Self::Sequential => {
+                let width = count.to_string().len().max(4);
+                format!("part-{:0width$}.bin", idx + 1)
+            },
+        }
+    }
+
+    /// whether `name` is a valid chunk name under this naming
+    pub fn accepts(&self, name: &str) -> bool {
+        match self {
+            Self::Hash =>
+                name.len() == 64
+                    && name.bytes().all(|byte| byte.is_ascii_hexdigit()),
+            Self::Ordered =>
+                name.split_once('-')
+                    .is_some_and(|(number, sha256)| {
+                        number.len() >= 6
+                            && number.bytes().all(|byte| byte.is_ascii_digit())
+                            && Self::Hash.accepts(sha256)
+                    }),
+            Self::Sequential => name
+                .strip_prefix("part-")
+                .and_then(|name| name.strip_suffix(".bin"))
+                .is_some_and(|number| {
+                    number.len() >= 4
+                        && number.bytes().all(|byte| byte.is_ascii_digit())
+                }),
+        }
+    }
+}
+
+impl Display for ChunkNaming {
+    fn fmt(&self, f: &mut Formatter) -> std::fmt::Result {
+        write!(
+            f,
+            "{}",
+            match self {
+                Self::Hash => "hash",
+                Self::Ordered => "ordered",
+                Self::Sequential => "sequential",
+            }
+        )
+    }
+}
+
+impl FromStr for ChunkNaming {
+    type Err = Error;
+
+    fn from_str(value: &str) -> Result<ChunkNaming> {
+        match value.to_lowercase().as_str() {
+            "hash" => Ok(ChunkNaming::Hash),
+            "ordered" => Ok(ChunkNaming::Ordered),
+            "sequential" => Ok(ChunkNaming::Sequential),
+            _ => Err(Error::InvalidIndexError(format!(
+                "unsupported chunk naming '{value}', expected hash, ordered or sequential"
+            ))),
+        }
+    }
+}
+
+/// How the chunks of an unfolded file are named and laid out in its
+/// store, recorded in the `naming`, `fanout` and `span` fields of the
+/// index, and how the index itself is encoded.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct ChunkLayout {
+    pub naming: ChunkNaming,
+    /// levels of folders named after the first hex digit pairs of the
+    /// sha256 of each chunk it is nested in, e.g. `ab/cd/<name>` for 2,
+    /// which keeps folders small. 0 stores chunks flat.
+    pub fanout: usize,
+    /// number of chunks each sub-index of a hierarchical manifest
+    /// lists, see [`ManifestIndex`]. `None` lists every chunk in the
+    /// index itself.
+    pub span: Option<usize>,
+    /// encoding of the index and of its sub-indexes, detected when read
+    pub encoding: IndexEncoding,
+}
+
+impl ChunkLayout {
+    pub const MAX_FANOUT: usize = 4;
+
+    pub fn new(naming: ChunkNaming, fanout: usize) -> ChunkLayout {
+        ChunkLayout {
+            naming,
+            fanout,
+            span: None,
+            encoding: IndexEncoding::Yaml,
+        }
+    }
+
+    /// the same layout listing chunks in sub-indexes of `span` chunks
+    /// each, or in the index itself with `None`
+    pub fn with_span(self, span: Option<usize>) -> ChunkLayout {
+        ChunkLayout { span, ..self }
+    }
+
+    /// the same layout with the index encoded as `encoding`
+    pub fn with_encoding(self, encoding: IndexEncoding) -> ChunkLayout {
+        ChunkLayout { encoding, ..self }
+    }
+
+    /// name, including fan-out folders, of the chunk with contents
+    /// `sha256` at position `idx` of an index of `count` chunks
+    pub(crate) fn chunk_name(
+        &self,
+        idx: usize,
+        count: usize,
+        sha256: &str,
+    ) -> String {
+        let mut name = String::new();
+        for level in 0..self.fanout {
+            name.push_str(&sha256[level * 2..level * 2 + 2]);
+            name.push('/');
+        }
+        name.push_str(&self.naming.chunk_name(idx, count, sha256));
+        name
+    }
+
+    /// whether `name`, including fan-out folders, is the name of a chunk
+    /// stored under this layout or of a sub-index, which is named after
+    /// its sha256
+    pub(crate) fn accepts(&self, name: &str) -> bool {
+        let mut folders = name.split('/').collect::<Vec<&str>>();
+        let Some(name) = folders.pop() else {
+            return false;
+        };
+        folders.len() == self.fanout
+            && folders.iter().all(|folder| {
+                folder.len() == 2
+                    && folder
+                        .bytes()
+                        .all(|byte| byte.is_ascii_hexdigit())
+            })
+            && (self.naming.accepts(name) || ChunkNaming::Hash.accepts(name))
+    }
+}
+
+impl Display for ChunkLayout {
+    fn fmt(&self, f: &mut Formatter) -> std::fmt::Result {
+        write!(f, "{} naming", self.naming)?;
+        if self.fanout > 0 {
+            write!(f, " with fan-out {}", self.fanout)?;
+        }
+        if let Some(span) = self.span {
+            write!(f, " in sub-indexes of {span} chunks")?;
+        }
+        if self.encoding != IndexEncoding::Yaml {
+            write!(f, " and a {} index", self.encoding)?;
+        }
+        Ok(())
+    }
+}
+
+/// whether `name` is a folder [`ChunkLayout::fanout`] nests chunks in
+pub(crate) fn is_fanout_folder(name: &str) -> bool {
+    name.len() == 2 && name.bytes().all(|byte| byte.is_ascii_hexdigit())
+}
+
+/// In-memory representation of the `index` file written by
+/// [`unfold_file`](crate::unfold_file): the whole-file checksum, the
+/// whole-file size, the sha256 of each chunk in order, the root of the
+/// merkle tree over those, how chunks are named in the store, which
+/// chunks are all zeros and, optionally, an Ed25519 signature over all
+/// of the above.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UnfoldIndex {
+    sha256: Vec<u8>,
+    size: u64,
+    chunks: Vec<String>,
+    merkle_root: Vec<u8>,
+    layout: ChunkLayout,
+    /// positions of the chunks which are all zeros and not stored, in
+    /// order and apart
+    zeros: Vec<Range<usize>>,
+    signature: Option<Box<IndexSignature>>,
+}
+
+impl UnfoldIndex {
+    pub fn new(sha256: Vec<u8>, size: u64, chunks: Vec<String>) -> UnfoldIndex {
+        UnfoldIndex {
+            sha256,
+            size,
+            merkle_root: merkle_root(&chunks),
+            chunks,
+            layout: ChunkLayout::default(),
+            zeros: Vec::new(),
+            signature: None,
+        }
+    }
+
+    /// the same index with chunks laid out according to `layout`,
+    /// dropping any signature unless only the encoding of a flat index
+    /// changes, which the signature does not cover
+    pub fn with_layout(self, layout: ChunkLayout) -> UnfoldIndex {
+        let reencoded = ChunkLayout {
+            encoding: self.layout.encoding,
+            ..layout
+        };
+        let signature = match reencoded == self.layout && layout.span.is_none()
+        {
+            true => self.signature,
+            false => None,
+        };
+        UnfoldIndex {
+            layout,
+            signature,
+            ..self
+        }
+    }
+
+    pub fn with_naming(self, naming: ChunkNaming) -> UnfoldIndex {
+        let layout = ChunkLayout {
+            naming,
+            ..self.layout
+        };
+        self.with_layout(layout)
+    }
+
+    /// reads the index of the unfolded folder at `input_path` without
+    /// touching any of the chunks it points at, except for indexes
+    /// written before the `size` field existed, in which case the size
+    /// is derived from the metadata of the last chunk.
+    pub fn read(input_path: &Path) -> Result<UnfoldIndex> {
+        let store = DirStore::new(input_path);
+        let index_path = input_path.join(INDEX_NAME);
+        let bytes = store.get_index()?;
+        let (index, size) = match UnfoldIndex::decode(&bytes, &index_path)? {
+            ParsedIndex::Index(index, size) => (index, size),
+            ParsedIndex::Manifest(manifest) => return manifest.resolve(&store),
+        };
+        if size.is_some() {
+            return Ok(index);
+        }
+        let last = store.chunk_path(&index.chunk_name(index.chunks.len() - 1));
+        if !last.is_file() {
+            return Err(Error::InvalidIndexError(format!(
+                "missing 'size' field in '{index_path}' and last chunk '{last}' is not a readable file"
+            )));
+        }
+        let size = ((index.chunks.len() - 1) * CHUNK_SIZE) as u64
+            + last.file_size().as_u64();
+        Ok(index.with_size(size))
+    }
+
+    /// parses the yaml representation of an index, returning the
+    /// index along with the value of its `size` field if present.
+    /// Fails on the top-level index of a hierarchical manifest, whose
+    /// sub-indexes only its store has. `origin` is only used in error
+    /// messages.
+    pub(crate) fn parse(
+        yaml: &str,
+        origin: impl std::fmt::Display,
+    ) -> Result<(UnfoldIndex, Option<u64>)> {
+        UnfoldIndex::decode(yaml.as_bytes(), &origin)?.into_index(origin)
+    }
+
+    /// decodes an index or the top-level index of a hierarchical
+    /// manifest, in whichever [`IndexEncoding`] `bytes` are. `origin`
+    /// is only used in error messages.
+    pub(crate) fn decode(
+        bytes: &[u8],
+        origin: impl std::fmt::Display,
+    ) -> Result<ParsedIndex> {
+        let encoding = IndexEncoding::detect(bytes);
+        let index = encoding.decode(bytes, &origin)?;
+        UnfoldIndex::from_any_fields(index, encoding, origin)
+    }
+
+    /// builds an index from the fields of its yaml representation,
+    /// returning it along with the value of its `size` field if present
+    pub(crate) fn from_fields(
+        index: BTreeMap<String, String>,
+        origin: impl std::fmt::Display,
+    ) -> Result<(UnfoldIndex, Option<u64>)> {
+        UnfoldIndex::from_any_fields(index, IndexEncoding::Yaml, &origin)?
+            .into_index(origin)
+    }
+
+    /// builds an index, or the top-level index of a hierarchical
+    /// manifest, from the fields it was encoded with
+    fn from_any_fields(
+        mut index: BTreeMap<String, String>,
+        encoding: IndexEncoding,
+        origin: impl std::fmt::Display,
+    ) -> Result<ParsedIndex> {
+        let sha256 = match index.remove("sha256") {
+            Some(sha256) => hex::decode(sha256.as_str()).map_err(|error| {
+                Error::InvalidIndexError(format!(
+                    "invalid hex in 'sha256' field of '{origin}': {error}"
+                ))
+            })?,
+            None =>
+                return Err(Error::InvalidIndexError(format!(
+                    "missing 'sha256' field in '{origin}'"
+                ))),
+        };
+        let naming = match index.remove("naming") {
+            Some(naming) => naming.parse::<ChunkNaming>().map_err(|error| {
+                Error::InvalidIndexError(format!(
+                    "invalid 'naming' field in '{origin}': {error}"
+                ))
+            })?,
+            None => ChunkNaming::Hash,
+        };
+        let fanout = match index.remove("fanout") {
+            Some(fanout) => match fanout.parse::<usize>() {
+                Ok(fanout) if fanout <= ChunkLayout::MAX_FANOUT => fanout,
+                _ =>
+                    return Err(Error::InvalidIndexError(format!(
+                        "invalid 'fanout' field '{fanout}' in '{origin}', expected 0 to {}",
+                        ChunkLayout::MAX_FANOUT
+                    ))),
+            },
+            None => 0,
+        };
+        let root = match index.remove("merkle-root") {
+            Some(root) =>
+                Some(hex::decode(root.as_str()).map_err(|error| {
+                    Error::InvalidIndexError(format!(
+                    "invalid hex in 'merkle-root' field of '{origin}': {error}"
+                ))
+                })?),
+            None => None,
+        };
+        let signature = IndexSignature::from_fields(
+            index.remove("signer"),
+            index.remove("signature"),
+        )
+        .map_err(|error| {
+            Error::InvalidIndexError(format!("{error} in '{origin}'"))
+        })?
+        .map(Box::new);
+        let size = match index.remove("size") {
+            Some(size) => Some(size.parse::<u64>().map_err(|error| {
+                Error::InvalidIndexError(format!(
+                    "invalid integer in 'size' field of '{origin}': {error}"
+                ))
+            })?),
+            None => None,
+        };
+        let span = match index.remove("span") {
+            Some(span) => match span.parse::<usize>() {
+                Ok(span) if span.is_power_of_two() => Some(span),
+                _ =>
+                    return Err(Error::InvalidIndexError(format!(
+                        "invalid 'span' field '{span}' in '{origin}', expected a power of two"
+                    ))),
+            },
+            None => None,
+        };
+        let zeros = match index.remove("zero-extents") {
+            Some(zeros) => parse_zero_extents(&zeros).ok_or_else(|| {
+                Error::InvalidIndexError(format!(
+                    "invalid 'zero-extents' field '{zeros}' in '{origin}', expected first-last chunk positions in order"
+                ))
+            })?,
+            None => Vec::new(),
+        };
+        let layout = ChunkLayout::new(naming, fanout)
+            .with_span(span)
+            .with_encoding(encoding);
+        if let Some(count) = index.remove("chunks") {
+            for (field, missing) in [
+                ("size", size.is_none()),
+                ("merkle-root", root.is_none()),
+            ] {
+                if missing {
+                    return Err(Error::InvalidIndexError(format!(
+                        "missing '{field}' field in top-level index '{origin}'"
+                    )));
+                }
+            }
+            let header = UnfoldIndex {
+                sha256,
+                size: size.unwrap_or_default(),
+                chunks: Vec::new(),
+                merkle_root: root.unwrap_or_default(),
+                layout,
+                zeros,
+                signature,
+            };
+            return ManifestIndex::from_fields(header, &count, index, origin)
+                .map(ParsedIndex::Manifest);
+        }
+        if index.is_empty() {
+            return Err(Error::InvalidIndexError(format!(
+                "empty index in '{origin}'"
+            )));
+        };
+
+        let mut ordered_index = Vec::<(usize, String)>::new();
+        for (key, value) in index.into_iter() {
+            let ord = usize::from_str_radix(&key, 16).map_err(|error| {
+                Error::InvalidIndexError(format!(
+                    "invalid hex in field '{key}' of '{origin}': {error}"
+                ))
+            })?;
+            ordered_index.push((ord, value));
+        }
+        ordered_index.sort_by(|a, b| a.0.cmp(&b.0));
+        let mut chunks = Vec::<String>::new();
+        for (exp, (idx, name)) in ordered_index.into_iter().enumerate() {
+            let key = format!("{idx:x}");
+            if !ChunkNaming::Hash.accepts(&name) {
+                return Err(Error::InvalidIndexError(format!(
+                    "invalid sha256 '{name}' in key '{key}' of '{origin}'"
+                )));
+            }
+            if exp == idx {
+                chunks.push(name);
+            } else {
+                return Err(Error::InvalidIndexError(format!(
+                    "mismatch index {exp} != {idx} in key '{key}' pointing at '{name}' in '{origin}'"
+                )));
+            }
+        }
+        let merkle_root = merkle_root(&chunks);
+        if root.is_some_and(|root| root != merkle_root) {
+            return Err(Error::InvalidIndexError(format!(
+                "'merkle-root' field of '{origin}' does not match the chunks, expected {}",
+                hex::encode(&merkle_root)
+            )));
+        }
+        if zeros
+            .last()
+            .is_some_and(|zeros| zeros.end > chunks.len())
+        {
+            return Err(Error::InvalidIndexError(format!(
+                "'zero-extents' field of '{origin}' points past its {} chunks",
+                chunks.len()
+            )));
+        }
+        if size.is_some_and(|size| !size_matches(size, chunks.len())) {
+            return Err(Error::InvalidIndexError(format!(
+                "'size' field of '{origin}' does not match its {} chunks",
+                chunks.len()
+            )));
+        }
+        let index = UnfoldIndex {
+            sha256,
+            size: size.unwrap_or_default(),
+            chunks,
+            merkle_root,
+            layout,
+            zeros,
+            signature,
+        };
+        Ok(ParsedIndex::Index(index, size))
+    }
+
+    pub fn to_yaml(&self) -> Result<String> {
+        fields_to_yaml(&self.to_fields()?)
+    }
+
+    /// the index encoded according to its [`ChunkLayout::encoding`]
+    pub fn to_bytes(&self) -> Result<Vec<u8>> {
+        self.layout.encoding.encode(&self.to_fields()?)
+    }
+
+    /// the index listing every chunk, see
+    /// [`to_flat_fields`](UnfoldIndex::to_flat_fields), encoded
+    /// according to its [`ChunkLayout::encoding`]
+    pub(crate) fn to_flat_bytes(&self) -> Result<Vec<u8>> {
+        self.layout
+            .encoding
+            .encode(&self.to_flat_fields())
+    }
+
+    /// fields of the yaml representation of the index, which only
+    /// points at the sub-indexes of a hierarchical manifest
+    pub(crate) fn to_fields(&self) -> Result<BTreeMap<String, String>> {
+        if self.layout.span.is_none() {
+            return Ok(self.to_flat_fields());
+        }
+        let mut index = self.header_fields();
+        index.insert("chunks".to_string(), self.chunks.len().to_string());
+        for (idx, subindex) in self.subindexes().enumerate() {
+            let subindex = subindex?;
+            index.insert(format!("subindex-{idx:08x}"), subindex.sha256);
+            index.insert(
+                format!("subroot-{idx:08x}"),
+                hex::encode(subindex.root),
+            );
+        }
+        if let Some(signature) = &self.signature {
+            index.extend(signature.to_fields());
+        }
+        Ok(index)
+    }
+
+    /// fields of the yaml representation of the index listing every
+    /// chunk, even when laid out in a hierarchical manifest, so that it
+    /// can be read without its store
+    pub(crate) fn to_flat_fields(&self) -> BTreeMap<String, String> {
+        let mut index = self.header_fields();
+        for (idx, name) in self.chunks.iter().enumerate() {
+            index.insert(format!("{idx:032x}"), name.clone());
+        }
+        if let Some(signature) = &self.signature {
+            index.extend(signature.to_fields());
+        }
+        index
+    }
+
+    /// fields of the yaml representation of the index other than its
+    /// chunks and signature
+    fn header_fields(&self) -> BTreeMap<String, String> {
+        let mut index = BTreeMap::<String, String>::new();
+        index.insert("sha256".to_string(), hex::encode(&self.sha256));
+        index.insert("size".to_string(), self.size.to_string());
+        index.insert("merkle-root".to_string(), hex::encode(&self.merkle_root));
+        if self.layout.naming != ChunkNaming::Hash {
+            let naming = self.layout.naming.to_string();
+            index.insert("naming".to_string(), naming);
+        }
+        if self.layout.fanout > 0 {
+            let fanout = self.layout.fanout.to_string();
+            index.insert("fanout".to_string(), fanout);
+        }
+        if let Some(span) = self.layout.span {
+            index.insert("span".to_string(), span.to_string());
+        }
+        if !self.zeros.is_empty() {
+            let zeros = self
+                .zeros
+                .iter()
+                .map(|zeros| format!("{}-{}", zeros.start, zeros.end - 1))
+                .collect::<Vec<String>>();
+            index.insert("zero-extents".to_string(), zeros.join(","));
+        }
+        index
+    }
+
+    /// the header of a hierarchical manifest completed with the
+    /// `chunks` its sub-indexes list, failing unless they lead to its
+    /// merkle root. `origin` is only used in error messages.
+    pub(crate) fn with_chunks(
+        self,
+        chunks: Vec<String>,
+        origin: impl std::fmt::Display,
+    ) -> Result<UnfoldIndex> {
+        let merkle_root = merkle_root(&chunks);
+        if merkle_root != self.merkle_root {
+            return Err(Error::InvalidIndexError(format!(
+                "the sub-indexes of '{origin}' do not match its 'merkle-root' field, expected {}",
+                hex::encode(&merkle_root)
+            )));
+        }
+        Ok(UnfoldIndex {
+            chunks,
+            merkle_root,
+            ..self
+        })
+    }
+
+    /// sub-indexes listing the chunks [`ChunkLayout::span`] at a time
+    /// when laid out in a hierarchical manifest, in order, each built
+    /// only when reached
+    pub(crate) fn subindexes(
+        &self,
+    ) -> impl Iterator<Item = Result<Subindex>> + '_ {
+        let count = match self.layout.span {
+            Some(span) => self.chunks.len().div_ceil(span),
+            None => 0,
+        };
+        (0..count).map(|idx| self.subindex(idx))
+    }
+
+    /// the sub-index at position `idx` of a hierarchical manifest
+    pub(crate) fn subindex(&self, idx: usize) -> Result<Subindex> {
+        let span = self.layout.span.unwrap_or(usize::MAX);
+        let first = (idx.saturating_mul(span)).min(self.chunks.len());
+        let last = first.saturating_add(span).min(self.chunks.len());
+        Subindex::new(first, &self.chunks[first..last], self.layout)
+    }
+
+    /// the same index with the chunks at positions within `zeros`,
+    /// which must be in order and apart, left out of the store as they
+    /// are all zeros
+    pub(crate) fn with_zeros(self, zeros: Vec<Range<usize>>) -> UnfoldIndex {
+        UnfoldIndex { zeros, ..self }
+    }
+
+    pub(crate) fn with_size(self, size: u64) -> UnfoldIndex {
+        UnfoldIndex { size, ..self }
+    }
+
+    pub(crate) fn with_signature(
+        self,
+        signature: Option<IndexSignature>,
+    ) -> UnfoldIndex {
+        UnfoldIndex {
+            signature: signature.map(Box::new),
+            ..self
+        }
+    }
+
+    /// the signature over the index, if it was signed with
+    /// [`sign_store`](crate::sign_store)
+    pub fn signature(&self) -> Option<&IndexSignature> {
+        self.signature.as_deref()
+    }
+
+    pub fn sha256(&self) -> &[u8] {
+        &self.sha256
+    }
+
+    pub fn size(&self) -> u64 {
+        self.size
+    }
+
+    /// root of the merkle tree over the sha256 of every chunk, which
+    /// [`MerkleProof`](crate::MerkleProof)s of single chunks lead to.
+    /// Indexes written before the `merkle-root` field existed get it
+    /// computed from their chunks.
+    pub fn merkle_root(&self) -> &[u8] {
+        &self.merkle_root
+    }
+
+    pub fn chunks(&self) -> &[String] {
+        &self.chunks
+    }
+
+    pub fn chunk_count(&self) -> usize {
+        self.chunks.len()
+    }
+
+    pub fn layout(&self) -> ChunkLayout {
+        self.layout
+    }
+
+    pub fn naming(&self) -> ChunkNaming {
+        self.layout.naming
+    }
+
+    pub fn fanout(&self) -> usize {
+        self.layout.fanout
+    }
+
+    /// number of chunks each sub-index lists when laid out in a
+    /// hierarchical manifest
+    pub fn span(&self) -> Option<usize> {
+        self.layout.span
+    }
+
+    /// ranges of positions of the chunks which are all zeros, recorded
+    /// in the `zero-extents` field as `first-last` positions, which are
+    /// not stored and folded back as holes
+    pub fn zero_extents(&self) -> &[Range<usize>] {
+        &self.zeros
+    }
+
+    /// whether the chunk at position `idx` is all zeros and not stored
+    pub fn is_zero(&self, idx: usize) -> bool {
+        let next = self
+            .zeros
+            .partition_point(|zeros| zeros.end <= idx);
+        self.zeros
+            .get(next)
+            .is_some_and(|zeros| zeros.contains(&idx))
+    }
+
+    /// name, including fan-out folders, under which the chunk at
+    /// position `idx` is stored
+    pub fn chunk_name(&self, idx: usize) -> String {
+        self.layout
+            .chunk_name(idx, self.chunks.len(), &self.chunks[idx])
+    }
+
+    /// names under which the chunks are stored, in order
+    pub fn chunk_names(&self) -> Vec<String> {
+        (0..self.chunks.len())
+            .map(|idx| self.chunk_name(idx))
+            .collect()
+    }
+
+    /// names under which each distinct chunk is stored, by sha256
+    pub(crate) fn chunk_names_by_sha256(
+        &self,
+    ) -> Result<BTreeMap<String, Vec<String>>> {
+        let mut names = BTreeMap::<String, Vec<String>>::new();
+        for (name, sha256) in self.chunk_files()? {
+            names.entry(sha256).or_default().push(name);
+        }
+        Ok(names)
+    }
+
+    /// sha256 of the chunk stored under each distinct name, sorted by
+    /// name, including the sub-indexes of a hierarchical manifest but
+    /// not the zero chunks, which are not stored
+    pub fn chunk_files(&self) -> Result<BTreeMap<String, String>> {
+        let mut files = (0..self.chunks.len())
+            .filter(|idx| !self.is_zero(*idx))
+            .map(|idx| (self.chunk_name(idx), self.chunks[idx].clone()))
+            .collect::<BTreeMap<String, String>>();
+        for subindex in self.subindexes() {
+            let subindex = subindex?;
+            files.insert(subindex.name, subindex.sha256);
+        }
+        Ok(files)
+    }
+
+    /// byte range covered by the chunk at position `idx` in the
+    /// original file
+    pub fn chunk_range(&self, idx: usize) -> Range<u64> {
+        let start = (idx * CHUNK_SIZE) as u64;
+        let end = (start + CHUNK_SIZE as u64).min(self.size);
+        start..end
+    }
+
+    /// position in the index of the chunk containing the byte at
+    /// `offset` of the original file
+    pub fn chunk_index_at(&self, offset: u64) -> usize {
+        (offset / CHUNK_SIZE as u64) as usize
+    }
+
+    /// byte range covered by the chunks in `chunks`
+    pub fn byte_range(&self, chunks: Range<usize>) -> Range<u64> {
+        if chunks.is_empty() {
+            let offset = self
+                .chunk_range(chunks.start)
+                .start
+                .min(self.size);
+            return offset..offset;
+        }
+        let start = self.chunk_range(chunks.start).start;
+        let end = self.chunk_range(chunks.end - 1).end;
+        start..end
+    }
+}
+
+/// whether a file of `size` bytes splits into exactly `count` chunks
+pub(crate) fn size_matches(size: u64, count: usize) -> bool {
+    let Some(full) = (count as u64).checked_sub(1) else {
+        return size == 0;
+    };
+    let full = full.saturating_mul(CHUNK_SIZE as u64);
+    full < size && size - full <= CHUNK_SIZE as u64
+}
+
+/// parses the `zero-extents` field: comma separated `first-last`
+/// positions of chunks, in order and apart
+fn parse_zero_extents(value: &str) -> Option<Vec<Range<usize>>> {
+    let mut extents = Vec::<Range<usize>>::new();
+    for extent in value.split(',') {
+        let (first, last) = extent.split_once('-')?;
+        let first = first.parse::<usize>().ok()?;
+        let last = last.parse::<usize>().ok()?;
+        if last < first
+            || extents
+                .last()
+                .is_some_and(|previous| previous.end >= first)
+        {
+            return None;
+        }
+        extents.push(first..last + 1);
+    }
+    Some(extents)
+}
+
+fn fields_to_yaml(fields: &BTreeMap<String, String>) -> Result<String> {
+    let bytes = IndexEncoding::Yaml.encode(fields)?;
+    Ok(String::from_utf8(bytes).unwrap_or_default())
+}
+
+/// An index as parsed: either a whole index along with the value of its
+/// `size` field if present, or the top-level index of a hierarchical
+/// manifest whose sub-indexes are yet to be read from its store.
+#[derive(Debug, Clone)]
+pub(crate) enum ParsedIndex {
+    Index(UnfoldIndex, Option<u64>),
+    Manifest(ManifestIndex),
+}
+
+impl ParsedIndex {
+    pub(crate) fn into_index(
+        self,
+        origin: impl std::fmt::Display,
+    ) -> Result<(UnfoldIndex, Option<u64>)> {
+        match self {
+            ParsedIndex::Index(index, size) => Ok((index, size)),
+            ParsedIndex::Manifest(_) => Err(Error::InvalidIndexError(format!(
+                "'{origin}' is the top-level index of a hierarchical manifest, whose sub-indexes are read from its store"
+            ))),
+        }
+    }
+}
@@ -0,0 +1,107 @@
+use std::collections::BTreeMap;
+use std::panic::{catch_unwind, resume_unwind, AssertUnwindSafe};
+use std::sync::{mpsc, Mutex};
+use std::thread;
+
+use crate::Result;
+
+/// number of threads `jobs` stands for, where 0 means one per
+/// available cpu
+pub(crate) fn threads(jobs: usize) -> usize {
+    match jobs {
+        0 => thread::available_parallelism().map_or(1, usize::from),
+        jobs => jobs,
+    }
+}
+
+/// runs `work` on every position below `count` across `jobs` threads,
+/// see [`threads`], and hands each result to `sink` on the calling
+/// thread in order of position, stopping at the first error. At most
+/// twice as many positions as there are threads are worked on ahead of
+/// `sink`, so that memory use stays bounded however large `count` is.
+pub(crate) fn for_each_ordered<R, W, S>(
+    jobs: usize,
+    count: usize,
+    work: W,
+    mut sink: S,
+) -> Result<()>
+where
+    R: Send,
+    W: Fn(usize) -> Result<R> + Sync,
+    S: FnMut(usize, R) -> Result<()>,
+{
+    let jobs = threads(jobs).min(count);
+    if jobs <= 1 {
+        for idx in 0..count {
+            sink(idx, work(idx)?)?;
+        }
+        return Ok(());
+    }
+    let (tasks, queue) = mpsc::channel::<usize>();
+    let queue = Mutex::new(queue);
+    thread::scope(|scope| {
+        // dropping `tasks` on the way out, errors included, lets the
+        // threads finish before the scope joins them
+        let tasks = tasks;
+        let (results, finished) = mpsc::channel();
+        for _ in 0..jobs {
+            let (queue, work, results) = (&queue, &work, results.clone());
+            scope.spawn(move || loop {
+                let next = queue.lock().map(|queue| queue.recv());
+                let Ok(Ok(idx)) = next else {
+                    break;
+                };
+                let result = catch_unwind(AssertUnwindSafe(|| work(idx)));
+                if results.send((idx, result)).is_err() {
+                    break;
+                }
+            });
+        }
+        drop(results);
+        let mut pending = 0..count;
+        for idx in pending.by_ref().take(jobs * 2) {
+            let _ = tasks.send(idx);
+        }
+        let mut done = BTreeMap::new();
+        for idx in 0..count {
+            let result = loop {
+                if let Some(result) = done.remove(&idx) {
+                    break result;
+                }
+                let (position, result) = finished
+                    .recv()
+                    .expect("threads only stop once every position is sent");
+                done.insert(position, result);
+            };
+            match result {
+                Ok(result) => sink(idx, result?)?,
+                Err(panic) => resume_unwind(panic),
+            }
+            if let Some(next) = pending.next() {
+                let _ = tasks.send(next);
+            }
+        }
+        Ok(())
+    })
+}
+
+/// runs `first` and `second` at once when more than one job is
+/// allowed, see [`threads`], or one after the other otherwise
+pub(crate) fn join<A, B, FA, FB>(jobs: usize, first: FA, second: FB) -> (A, B)
+where
+    A: Send,
+    FA: FnOnce() -> A + Send,
+    FB: FnOnce() -> B,
+{
+    if threads(jobs) <= 1 {
+        return (first(), second());
+    }
+    thread::scope(|scope| {
+        let first = scope.spawn(first);
+        let second = second();
+        match first.join() {
+            Ok(first) => (first, second),
+            Err(panic) => resume_unwind(panic),
+        }
+    })
+}
@@ -11,4 +11,147 @@ pub use cli::{ArgsDispatcher, ParserDispatcher, SubcommandDispatcher};
 pub(crate) mod file;
 
 #[doc(inline)]
-pub use file::{fold_file, unfold_file, Progress, Action};
+pub use file::{
+    adopt, adopt_with_cancel, fold_file, fold_file_with_cancel,
+    fold_from_store, fold_from_store_with_cancel, unfold_file,
+    unfold_file_with_cancel, unfold_file_with_layout, unfold_to_store,
+    unfold_to_store_with_cancel, unfold_to_store_with_layout, Action, Progress,
+};
+
+pub(crate) mod cancel;
+
+#[doc(inline)]
+pub use cancel::CancelToken;
+
+pub(crate) mod jobs;
+
+pub(crate) mod encoding;
+pub(crate) mod index;
+pub(crate) mod sparse;
+pub(crate) mod store;
+
+#[doc(inline)]
+pub use store::{open_store, ChunkStore, DirStore, MemoryStore};
+
+#[cfg(feature = "s3")]
+pub(crate) mod s3;
+
+#[cfg(feature = "s3")]
+#[doc(inline)]
+pub use s3::{S3Config, S3Store};
+
+#[cfg(feature = "sqlite")]
+pub(crate) mod sqlite;
+
+#[cfg(feature = "sqlite")]
+#[doc(inline)]
+pub use sqlite::SqliteStore;
+
+#[cfg(feature = "async")]
+pub(crate) mod asynchronous;
+
+#[cfg(feature = "async")]
+#[doc(inline)]
+pub use asynchronous::{
+    fold_async, unfold_async, verify_async, AsyncChunkStore, BlockingStore,
+};
+
+pub(crate) mod maintenance;
+
+#[doc(inline)]
+pub use maintenance::{
+    convert_index, gc_store, migrate_store, relayout_store, verify_store,
+};
+
+pub(crate) mod git;
+
+#[doc(inline)]
+pub use git::{
+    git_clean, git_filter_process, git_smudge, is_git_pointer,
+    GIT_POINTER_HEADER,
+};
+
+pub(crate) mod hydrate;
+
+#[doc(inline)]
+pub use hydrate::{dehydrate, hydrate, is_stub, Stub, STUB_HEADER};
+
+pub(crate) mod archive;
+
+#[doc(inline)]
+pub use archive::{
+    export, export_store, export_to_file, import, ArchiveFormat, ArchiveStore,
+};
+#[doc(inline)]
+pub use encoding::IndexEncoding;
+#[doc(inline)]
+pub use index::{ChunkLayout, ChunkNaming, UnfoldIndex};
+
+pub(crate) mod diff;
+
+#[doc(inline)]
+pub use diff::{diff_indexes, diff_unfolded, ChangeKind, Diff, DiffRange};
+
+pub(crate) mod bundle;
+
+#[doc(inline)]
+pub use bundle::{bundle, read_inventory, unbundle, unbundle_to_store};
+
+pub(crate) mod pack;
+
+#[doc(inline)]
+pub use pack::{
+    pack, pack_store, unpack, unpack_to_store, Frame, PackReader, PackWriter,
+};
+
+pub(crate) mod http;
+pub(crate) mod remote;
+
+#[doc(inline)]
+pub use remote::{pull, serve_file, serve_store};
+
+pub(crate) mod volume;
+
+#[doc(inline)]
+pub use volume::{
+    fold_from_volumes, unfold_to_volumes, unfold_to_volumes_with_cancel,
+    Volume, VolumeLabel, VolumeSpec, VolumeStore, VOLUME_NAME,
+};
+
+pub(crate) mod replica;
+
+#[doc(inline)]
+pub use replica::{verify_replicas, ReplicaStore};
+
+pub(crate) mod share;
+
+#[doc(inline)]
+pub use share::{
+    fold_from_shares, fold_from_shares_with_cancel, share_numbers,
+    unfold_to_shares, unfold_to_shares_with_cancel, ShareLabel, SHARE_NAME,
+};
+
+pub(crate) mod reader;
+
+#[doc(inline)]
+pub use reader::FoldedReader;
+
+pub(crate) mod sign;
+
+pub use ed25519_dalek::{SigningKey, VerifyingKey};
+#[doc(inline)]
+pub use sign::{
+    check_signature, fold_signed, fold_signed_with_cancel, generate_key,
+    read_signing_key, read_verifying_key, sign_store, verify_signed_store,
+    IndexSignature,
+};
+
+pub(crate) mod merkle;
+
+#[doc(inline)]
+pub use merkle::{merkle_root, MerkleProof};
+
+pub(crate) mod manifest;
+
+#[doc(inline)]
+pub use manifest::ManifestIndex;
@@ -1,7 +1,24 @@
+use std::io::{IsTerminal, Read, Write};
+
 use clap::{Parser, Subcommand};
-use unfolder::cli::{ArgsDispatcher, ParserDispatcher, SubcommandDispatcher};
-use unfolder::{unfold_file, fold_file, Error, Exit, Result};
 use iocore::Path;
+use unfolder::cli::{ArgsDispatcher, ParserDispatcher, SubcommandDispatcher};
+use unfolder::{
+    adopt_with_cancel, bundle, check_signature, convert_index, dehydrate,
+    diff_unfolded, export_to_file, fold_file_with_cancel,
+    fold_from_shares_with_cancel, fold_from_store_with_cancel,
+    fold_signed_with_cancel, gc_store, generate_key, git_clean,
+    git_filter_process, git_smudge, hydrate, import, migrate_store, open_store,
+    pack, pull, read_inventory, read_signing_key, read_verifying_key,
+    relayout_store, serve_file, serve_store, share_numbers, sign_store,
+    unbundle, unbundle_to_store, unfold_file_with_cancel,
+    unfold_to_shares_with_cancel, unfold_to_store_with_cancel,
+    unfold_to_volumes_with_cancel, unpack, verify_replicas,
+    verify_signed_store, verify_store, ArchiveFormat, CancelToken, ChunkLayout,
+    ChunkNaming, ChunkStore, DirStore, Error, Exit, IndexEncoding, MerkleProof,
+    Progress, ReplicaStore, Result, VerifyingKey, VolumeSpec, VolumeStore,
+    SHARE_NAME, VOLUME_NAME,
+};
 
 #[derive(Parser, Debug, Clone)]
 #[command(
@@ -30,59 +47,380 @@ impl ParserDispatcher<Error> for Cli {
 
 #[derive(Subcommand, Debug, Clone)]
 pub enum Command {
+    Adopt(AdoptOpt),
+    Bundle(BundleOpt),
+    Dehydrate(DehydrateOpt),
+    Diff(DiffOpt),
+    Export(ExportOpt),
     Fold(FoldOpt),
+    Gc(GcOpt),
+    GitFilter(GitFilterOpt),
+    Hydrate(HydrateOpt),
+    Import(ImportOpt),
+    Index(IndexOpt),
+    Keygen(KeygenOpt),
+    Migrate(MigrateOpt),
+    Pack(PackOpt),
+    Proof(ProofOpt),
+    Pull(PullOpt),
+    Relayout(RelayoutOpt),
+    Serve(ServeOpt),
+    ServeFile(ServeFileOpt),
+    Unbundle(UnbundleOpt),
     Unfold(UnfoldOpt),
+    Unpack(UnpackOpt),
+    Verify(VerifyOpt),
 }
 impl SubcommandDispatcher<Error> for Command {
     fn dispatch(&self) -> Result<()> {
         match self {
+            Command::Adopt(op) => op.dispatch()?,
+            Command::Bundle(op) => op.dispatch()?,
+            Command::Dehydrate(op) => op.dispatch()?,
+            Command::Diff(op) => op.dispatch()?,
+            Command::Export(op) => op.dispatch()?,
             Command::Fold(op) => op.dispatch()?,
+            Command::Gc(op) => op.dispatch()?,
+            Command::GitFilter(op) => op.dispatch()?,
+            Command::Hydrate(op) => op.dispatch()?,
+            Command::Import(op) => op.dispatch()?,
+            Command::Index(op) => op.dispatch()?,
+            Command::Keygen(op) => op.dispatch()?,
+            Command::Migrate(op) => op.dispatch()?,
+            Command::Pack(op) => op.dispatch()?,
+            Command::Proof(op) => op.dispatch()?,
+            Command::Pull(op) => op.dispatch()?,
+            Command::Relayout(op) => op.dispatch()?,
+            Command::Serve(op) => op.dispatch()?,
+            Command::ServeFile(op) => op.dispatch()?,
+            Command::Unbundle(op) => op.dispatch()?,
             Command::Unfold(op) => op.dispatch()?,
+            Command::Unpack(op) => op.dispatch()?,
+            Command::Verify(op) => op.dispatch()?,
         }
         Ok(())
     }
 }
 
 #[derive(Parser, Debug, Clone)]
-#[command(about = "unfolds the input file into multiple files in the output folder")]
+#[command(
+    about = "builds an unfolded folder from the ordered parts of a file split by other tools, e.g.: xaa xab … from split -b"
+)]
+pub struct AdoptOpt {
+    #[arg(required = true, num_args = 2.., help = "parts in order followed by the output folder")]
+    paths: Vec<Path>,
+
+    #[arg(
+        long,
+        default_value = "hash",
+        help = "chunk names: hash, ordered or sequential"
+    )]
+    naming: ChunkNaming,
+
+    #[arg(long, default_value_t = 0, value_parser = clap::value_parser!(u8).range(0..=4), help = "levels of two hex digit folders chunks are nested in, e.g.: ab/cd/abcdef… with 2")]
+    fanout: u8,
+
+    #[arg(
+        long,
+        help = "keeps the partial output folder when interrupted by Ctrl-C rather than removing it"
+    )]
+    keep_partial: bool,
+}
+
+impl AdoptOpt {
+    pub fn layout(&self) -> ChunkLayout {
+        ChunkLayout::new(self.naming, self.fanout as usize)
+    }
+}
+
+impl ArgsDispatcher<Error> for AdoptOpt {
+    fn dispatch(&self) -> Result<()> {
+        let (output_path, parts) = self.paths.split_last().unwrap();
+        let output = output_path.to_string();
+        let cancel = cancel_on_ctrl_c(self.keep_partial)?;
+        let index = adopt_with_cancel(
+            parts,
+            output_path,
+            self.layout(),
+            &cancel,
+            |progress| {
+                println!("{progress} => {output}");
+            },
+        )?;
+        println!(
+            "adopted {} parts as {} chunks into {output}",
+            parts.len(),
+            index.chunk_count()
+        );
+        Ok(())
+    }
+}
+
+#[derive(Parser, Debug, Clone)]
+#[command(
+    about = "unfolds the input file into multiple files in the output folder"
+)]
 pub struct UnfoldOpt {
-    #[arg(required = true, help = "input file")]
+    #[arg(
+        required = true,
+        help = "input file"
+    )]
     input_path: Path,
 
-    #[arg(required = true, help = "output folder")]
-    output_path: Path,
+    #[arg(required_unless_present_any = ["volumes", "targets"], conflicts_with_all = ["volumes", "targets"], help = "output folder, sqlite://path or s3://bucket/prefix")]
+    output_path: Option<String>,
+
+    #[arg(
+        long,
+        default_value = "hash",
+        help = "chunk names: hash, ordered 000001-<hash>, 000002-<hash>, … or sequential part-0001.bin, part-0002.bin, … which plain cat can rejoin"
+    )]
+    naming: ChunkNaming,
+
+    #[arg(long, default_value_t = 0, value_parser = clap::value_parser!(u8).range(0..=4), help = "levels of two hex digit folders chunks are nested in, e.g.: ab/cd/abcdef… with 2")]
+    fanout: u8,
+
+    #[arg(
+        long = "volume",
+        value_name = "FOLDER:CAPACITY",
+        help = "spreads chunks across folders in order, each up to its capacity, e.g.: --volume /mnt/a:500GB --volume /mnt/b:500GB"
+    )]
+    volumes: Vec<VolumeSpec>,
+
+    #[arg(
+        long = "target",
+        conflicts_with = "volumes",
+        help = "writes every chunk and the index to each of several folders, sqlite://path or s3://bucket/prefix replicas"
+    )]
+    targets: Vec<String>,
+
+    #[arg(
+        long,
+        requires = "targets",
+        help = "number of replicas, which must match the number of --target"
+    )]
+    replicas: Option<usize>,
+
+    #[arg(long, requires = "threshold", conflicts_with_all = ["volumes", "targets"], value_parser = clap::value_parser!(u8).range(2..), help = "splits the input into this many share folders share-1, share-2, … in the output folder, any --threshold of which fold it back while fewer reveal nothing")]
+    shares: Option<u8>,
+
+    #[arg(long, requires = "shares", value_parser = clap::value_parser!(u8).range(2..), help = "number of shares needed to fold the input back")]
+    threshold: Option<u8>,
+
+    #[arg(
+        long,
+        value_name = "KEY",
+        conflicts_with = "shares",
+        help = "secret key written by the keygen command to sign the index with"
+    )]
+    sign_key: Option<Path>,
+
+    #[arg(long, value_name = "SPAN", default_value_t = 0, conflicts_with_all = ["volumes", "shares"], help = "lists chunks in sub-indexes of this many chunks, a power of two, the index points at, so that reading part of a file of millions of chunks only reads the sub-indexes covering it, 0 for a flat index")]
+    manifest: usize,
+
+    #[arg(
+        long,
+        default_value = "yaml",
+        conflicts_with = "shares",
+        help = "index encoding: yaml, json or compact cbor"
+    )]
+    index_encoding: IndexEncoding,
+
+    #[arg(
+        long,
+        default_value_t = 1,
+        env = "UNFOLDER_JOBS",
+        help = "threads hashing and writing chunks at once, 0 for one per cpu"
+    )]
+    jobs: usize,
+
+    #[arg(
+        long,
+        help = "keeps the partial output folder when interrupted by Ctrl-C rather than removing it"
+    )]
+    keep_partial: bool,
 }
 impl UnfoldOpt {
     pub fn input_path(&self) -> Path {
         self.input_path.clone()
     }
 
-    pub fn output_path(&self) -> Path {
+    pub fn output_path(&self) -> Option<String> {
         self.output_path.clone()
     }
+
+    pub fn layout(&self) -> ChunkLayout {
+        let span = (self.manifest > 0).then_some(self.manifest);
+        ChunkLayout::new(self.naming, self.fanout as usize)
+            .with_span(span)
+            .with_encoding(self.index_encoding)
+    }
 }
 
 impl ArgsDispatcher<Error> for UnfoldOpt {
     fn dispatch(&self) -> Result<()> {
         let input_path = self.input_path.to_string();
-        unfold_file(&self.input_path, &self.output_path, |progress| {
+        let progress = |progress: Progress| {
             println!("{input_path} => {progress}");
-        })?;
+        };
+        let signing_key = self
+            .sign_key
+            .as_ref()
+            .map(read_signing_key)
+            .transpose()?;
+        let cancel = cancel_on_ctrl_c(self.keep_partial)?;
+        let sign = |store: &dyn ChunkStore| -> Result<()> {
+            if let Some(key) = &signing_key {
+                sign_store(store, key)?;
+                println!("signed the index of {}", store.location());
+            }
+            Ok(())
+        };
+        if !self.targets.is_empty() {
+            let targets = self.targets.len();
+            if self
+                .replicas
+                .is_some_and(|replicas| replicas != targets)
+            {
+                return Err(Error::ReplicaError(format!(
+                    "--replicas {} does not match the {targets} --target given",
+                    self.replicas.unwrap_or_default()
+                )));
+            }
+            let store = ReplicaStore::open(&self.targets)?;
+            if let Some(replica) = store
+                .replicas()
+                .iter()
+                .find(|replica| replica.get_index().is_ok())
+            {
+                return Err(Error::ReplicaError(format!(
+                    "{} already holds an unfolded file",
+                    replica.location()
+                )));
+            }
+            unfold_to_store_with_cancel(
+                &self.input_path,
+                &store,
+                self.layout(),
+                self.jobs,
+                &cancel,
+                progress,
+            )?;
+            sign(&store)?;
+            return Ok(());
+        }
+        let Some(output_path) = &self.output_path else {
+            unfold_to_volumes_with_cancel(
+                &self.input_path,
+                &self.volumes,
+                self.layout(),
+                self.jobs,
+                &cancel,
+                progress,
+            )?;
+            for volume in &self.volumes {
+                sign(&DirStore::new(volume.path()))?;
+            }
+            return Ok(());
+        };
+        if let (Some(shares), Some(threshold)) = (self.shares, self.threshold) {
+            let paths = unfold_to_shares_with_cancel(
+                &self.input_path,
+                &Path::new(output_path),
+                shares,
+                threshold,
+                &cancel,
+                progress,
+            )?;
+            for path in paths {
+                println!("wrote {path}");
+            }
+            return Ok(());
+        }
+        if is_store_url(output_path) {
+            let store = open_store(output_path)?;
+            unfold_to_store_with_cancel(
+                &self.input_path,
+                store.as_ref(),
+                self.layout(),
+                self.jobs,
+                &cancel,
+                progress,
+            )?;
+            sign(store.as_ref())?;
+        } else {
+            let output_path = Path::new(output_path);
+            unfold_file_with_cancel(
+                &self.input_path,
+                &output_path,
+                self.layout(),
+                self.jobs,
+                &cancel,
+                progress,
+            )?;
+            sign(&DirStore::new(&output_path))?;
+        }
         Ok(())
     }
 }
 
 #[derive(Parser, Debug, Clone)]
-#[command(about = "folds multiple files from the input folder into a single output file")]
+#[command(
+    about = "folds multiple files from the input folder into a single output file"
+)]
 pub struct FoldOpt {
-    #[arg(required = true, help = "input folder")]
-    input_path: Path,
+    #[arg(
+        required = true,
+        help = "input folder, tar or zip archive, sqlite://path or s3://bucket/prefix"
+    )]
+    input_path: String,
 
-    #[arg(required = true, help = "output file")]
+    #[arg(
+        required = true,
+        help = "output file"
+    )]
     output_path: Path,
+
+    #[arg(
+        long = "volume",
+        help = "root of another volume when the input is one of several volumes, prompted for when missing"
+    )]
+    volumes: Vec<Path>,
+
+    #[arg(
+        long = "replica",
+        conflicts_with = "volumes",
+        help = "another replica of the input to read chunks from when the input misses them or holds corrupted ones"
+    )]
+    replicas: Vec<String>,
+
+    #[arg(long = "share", conflicts_with_all = ["volumes", "replicas"], help = "another share folder when the input is a share folder")]
+    shares: Vec<Path>,
+
+    #[arg(
+        long = "trusted-key",
+        value_name = "KEY",
+        conflicts_with = "shares",
+        help = "public key written by the keygen command, refusing to fold unless the index is signed by one of them"
+    )]
+    trusted_keys: Vec<Path>,
+
+    #[arg(
+        long,
+        default_value_t = 1,
+        env = "UNFOLDER_JOBS",
+        help = "threads reading and verifying chunks at once, 0 for one per cpu"
+    )]
+    jobs: usize,
+
+    #[arg(
+        long,
+        help = "keeps the partial output file when interrupted by Ctrl-C rather than removing it"
+    )]
+    keep_partial: bool,
 }
 impl FoldOpt {
-    pub fn input_path(&self) -> Path {
+    pub fn input_path(&self) -> String {
         self.input_path.clone()
     }
 
@@ -94,14 +432,926 @@ impl FoldOpt {
 impl ArgsDispatcher<Error> for FoldOpt {
     fn dispatch(&self) -> Result<()> {
         let output_path = self.output_path.to_string();
-        fold_file(&self.input_path, &self.output_path, |progress| {
+        let progress = |progress: Progress| {
+            println!("{progress} => {output_path}");
+        };
+        let input_path = Path::new(&self.input_path);
+        let trusted_keys = read_trusted_keys(&self.trusted_keys)?;
+        let cancel = cancel_on_ctrl_c(self.keep_partial)?;
+        let fold_store = |store: &dyn ChunkStore, progress| {
+            fold_store(
+                store,
+                &self.output_path,
+                &trusted_keys,
+                self.jobs,
+                &cancel,
+                progress,
+            )
+        };
+        if !self.shares.is_empty() || input_path.join(SHARE_NAME).is_file() {
+            if !trusted_keys.is_empty() {
+                return Err(Error::SignatureError(format!(
+                    "{input_path} is a share folder, which cannot be signed"
+                )));
+            }
+            let mut share_paths = vec![input_path];
+            share_paths.extend(self.shares.iter().cloned());
+            let labels = fold_from_shares_with_cancel(
+                &share_paths,
+                &self.output_path,
+                &cancel,
+                progress,
+            )?;
+            println!(
+                "folded {output_path} from shares {} of {}",
+                share_numbers(&labels),
+                labels[0].shares()
+            );
+        } else if !self.replicas.is_empty() {
+            let mut locations = vec![self.input_path.clone()];
+            locations.extend(self.replicas.iter().cloned());
+            let store = ReplicaStore::open(&locations)?;
+            let result = fold_store(&store, progress);
+            for problem in store.problems() {
+                eprintln!("warning: {problem}");
+            }
+            result?;
+        } else if !self.volumes.is_empty()
+            || input_path.join(VOLUME_NAME).is_file()
+        {
+            let mut roots = vec![input_path];
+            roots.extend(self.volumes.iter().cloned());
+            let store = open_volumes(&roots)?;
+            fold_store(&store, progress)?;
+        } else if is_store_url(&self.input_path) || input_path.is_file() {
+            let store = open_store(&self.input_path)?;
+            fold_store(store.as_ref(), progress)?;
+        } else if !trusted_keys.is_empty() {
+            let store = DirStore::new(&input_path);
+            fold_store(&store, progress)?;
+        } else {
+            fold_file_with_cancel(
+                &input_path,
+                &self.output_path,
+                self.jobs,
+                &cancel,
+                progress,
+            )?;
+        }
+
+        Ok(())
+    }
+}
+
+/// cancels the returned token on the first Ctrl-C, so that unfolds and
+/// folds stop between chunks and roll back, and exits on the second
+fn cancel_on_ctrl_c(keep_partial: bool) -> Result<CancelToken> {
+    let cancel = CancelToken::new();
+    let cancel = if keep_partial {
+        cancel.keeping_partial_output()
+    } else {
+        cancel
+    };
+    let handler = cancel.clone();
+    ctrlc::set_handler(move || {
+        if handler.is_cancelled() {
+            std::process::exit(130);
+        }
+        eprintln!("cancelling, press Ctrl-C again to exit right away");
+        handler.cancel();
+    })
+    .map_err(|error| {
+        Error::IOError(format!("failed to handle Ctrl-C: {error}"))
+    })?;
+    Ok(cancel)
+}
+
+/// reads the public keys at `paths`
+fn read_trusted_keys(paths: &[Path]) -> Result<Vec<VerifyingKey>> {
+    paths.iter().map(read_verifying_key).collect()
+}
+
+/// folds `store` into `output_path`, checking the signature of its
+/// index first when any `trusted_keys` are given
+fn fold_store<S: ChunkStore + ?Sized, C: FnMut(Progress)>(
+    store: &S,
+    output_path: &Path,
+    trusted_keys: &[VerifyingKey],
+    jobs: usize,
+    cancel: &CancelToken,
+    progress: C,
+) -> Result<Path> {
+    if trusted_keys.is_empty() {
+        fold_from_store_with_cancel(store, output_path, jobs, cancel, progress)
+    } else {
+        fold_signed_with_cancel(
+            store,
+            output_path,
+            trusted_keys,
+            jobs,
+            cancel,
+            progress,
+        )
+    }
+}
+
+/// opens the volumes at `roots` and, on a terminal, prompts for the
+/// folders of the missing ones until every volume is open or an empty
+/// line is entered
+fn open_volumes(roots: &[Path]) -> Result<VolumeStore> {
+    let mut store = VolumeStore::open(roots)?;
+    let stdin = std::io::stdin();
+    while stdin.is_terminal() {
+        let Some(volume) = store
+            .missing_volumes()
+            .into_iter()
+            .next()
+            .cloned()
+        else {
+            break;
+        };
+        eprint!("insert {volume} and enter the folder it is mounted at: ");
+        let mut line = String::new();
+        stdin.read_line(&mut line)?;
+        let line = line.trim();
+        if line.is_empty() {
+            break;
+        }
+        match store.add(&Path::new(line)) {
+            Ok(volume) => eprintln!("found {volume}"),
+            Err(error) => eprintln!("{error}"),
+        }
+    }
+    store.check()?;
+    Ok(store)
+}
+
+#[derive(Parser, Debug, Clone)]
+#[command(
+    about = "compares two unfolded folders chunk by chunk from their indexes"
+)]
+pub struct DiffOpt {
+    #[arg(
+        required = true,
+        help = "unfolded folder a"
+    )]
+    a: Path,
+
+    #[arg(
+        required = true,
+        help = "unfolded folder b"
+    )]
+    b: Path,
+
+    #[arg(
+        long,
+        help = "print the diff as json"
+    )]
+    json: bool,
+}
+
+impl ArgsDispatcher<Error> for DiffOpt {
+    fn dispatch(&self) -> Result<()> {
+        let diff = diff_unfolded(&self.a, &self.b)?;
+        if self.json {
+            let json =
+                serde_json::to_string_pretty(&diff).map_err(|error| {
+                    Error::IOError(format!(
+                        "failed to serialize diff as json: {error}"
+                    ))
+                })?;
+            println!("{json}");
+        } else {
+            println!("{diff}");
+        }
+        Ok(())
+    }
+}
+
+#[derive(Parser, Debug, Clone)]
+#[command(
+    about = "writes the index and the chunks missing on the receiving side of an unfolded folder into a single file"
+)]
+pub struct BundleOpt {
+    #[arg(
+        required = true,
+        help = "unfolded folder"
+    )]
+    input_path: Path,
+
+    #[arg(
+        required = true,
+        help = "output bundle file"
+    )]
+    output_path: Path,
+
+    #[arg(
+        long,
+        help = "chunks the receiver already has: an unfolded folder, an index file or a file with one chunk name per line"
+    )]
+    have: Option<Path>,
+}
+
+impl ArgsDispatcher<Error> for BundleOpt {
+    fn dispatch(&self) -> Result<()> {
+        let have = match &self.have {
+            Some(path) => read_inventory(path)?,
+            None => Default::default(),
+        };
+        let output_path = self.output_path.to_string();
+        bundle(&self.input_path, &have, &self.output_path, |progress| {
+            println!("{progress} => {output_path}");
+        })?;
+        Ok(())
+    }
+}
+
+#[derive(Parser, Debug, Clone)]
+#[command(
+    about = "verifies and merges the chunks of a bundle into an unfolded folder"
+)]
+pub struct UnbundleOpt {
+    #[arg(
+        required = true,
+        help = "input bundle file"
+    )]
+    input_path: Path,
+
+    #[arg(
+        required = true,
+        help = "unfolded folder, sqlite://path or s3://bucket/prefix"
+    )]
+    output_path: String,
+}
+
+impl ArgsDispatcher<Error> for UnbundleOpt {
+    fn dispatch(&self) -> Result<()> {
+        let input_path = self.input_path.to_string();
+        let progress = |progress: Progress| {
+            println!("{input_path} => {progress}");
+        };
+        if is_store_url(&self.output_path) {
+            let store = open_store(&self.output_path)?;
+            unbundle_to_store(&self.input_path, store.as_ref(), progress)?;
+        } else {
+            unbundle(
+                &self.input_path,
+                &Path::new(&self.output_path),
+                progress,
+            )?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Parser, Debug, Clone)]
+#[command(
+    about = "writes the index and chunks of an unfolded folder to stdout as a single stream"
+)]
+pub struct PackOpt {
+    #[arg(
+        required = true,
+        help = "unfolded folder"
+    )]
+    input_path: Path,
+}
+
+impl ArgsDispatcher<Error> for PackOpt {
+    fn dispatch(&self) -> Result<()> {
+        let input_path = self.input_path.to_string();
+        let stdout = std::io::BufWriter::new(std::io::stdout().lock());
+        pack(&self.input_path, stdout, |progress| {
+            eprintln!("{input_path} => {progress}");
+        })?;
+        Ok(())
+    }
+}
+
+#[derive(Parser, Debug, Clone)]
+#[command(
+    about = "reads a stream written by `pack` from stdin into a new unfolded folder"
+)]
+pub struct UnpackOpt {
+    #[arg(
+        required = true,
+        help = "output folder"
+    )]
+    output_path: Path,
+}
+
+impl ArgsDispatcher<Error> for UnpackOpt {
+    fn dispatch(&self) -> Result<()> {
+        let output_path = self.output_path.to_string();
+        let stdin = std::io::BufReader::new(std::io::stdin().lock());
+        unpack(stdin, &self.output_path, |progress| {
+            eprintln!("{progress} => {output_path}");
+        })?;
+        Ok(())
+    }
+}
+
+#[derive(Parser, Debug, Clone)]
+#[command(
+    about = "serves the manifests and chunks of the unfolded folders in a store over http"
+)]
+pub struct ServeOpt {
+    #[arg(
+        required = true,
+        help = "folder containing unfolded folders"
+    )]
+    store: Path,
+
+    #[arg(
+        long,
+        default_value = "127.0.0.1:8080",
+        help = "address to listen on"
+    )]
+    bind: String,
+}
+
+impl ArgsDispatcher<Error> for ServeOpt {
+    fn dispatch(&self) -> Result<()> {
+        let listener =
+            std::net::TcpListener::bind(&self.bind).map_err(|error| {
+                Error::ServeError(format!(
+                    "failed to bind {}: {error}",
+                    self.bind
+                ))
+            })?;
+        println!("serving {} on http://{}", self.store, listener.local_addr()?);
+        serve_store(&self.store, listener)?;
+        Ok(())
+    }
+}
+
+#[derive(Parser, Debug, Clone)]
+#[command(
+    about = "serves the folded content of an unfolded folder over http without writing it to disk"
+)]
+pub struct ServeFileOpt {
+    #[arg(
+        required = true,
+        help = "unfolded folder"
+    )]
+    input_path: Path,
+
+    #[arg(
+        long,
+        default_value = "127.0.0.1:8080",
+        help = "address to listen on"
+    )]
+    bind: String,
+}
+
+impl ArgsDispatcher<Error> for ServeFileOpt {
+    fn dispatch(&self) -> Result<()> {
+        let listener =
+            std::net::TcpListener::bind(&self.bind).map_err(|error| {
+                Error::ServeError(format!(
+                    "failed to bind {}: {error}",
+                    self.bind
+                ))
+            })?;
+        println!(
+            "serving {} on http://{}",
+            self.input_path,
+            listener.local_addr()?
+        );
+        serve_file(&self.input_path, listener)?;
+        Ok(())
+    }
+}
+
+#[derive(Parser, Debug, Clone)]
+#[command(
+    about = "downloads the chunks of a served manifest that are missing locally"
+)]
+pub struct PullOpt {
+    #[arg(
+        required = true,
+        help = "manifest url, e.g.: http://host:port/<manifest>"
+    )]
+    url: String,
+
+    #[arg(
+        required = true,
+        help = "unfolded folder"
+    )]
+    output_path: Path,
+}
+
+impl ArgsDispatcher<Error> for PullOpt {
+    fn dispatch(&self) -> Result<()> {
+        let url = self.url.clone();
+        pull(&self.url, &self.output_path, |progress| {
+            println!("{url} => {progress}");
+        })?;
+        Ok(())
+    }
+}
+
+/// whether `location` names a remote store rather than a folder
+fn is_store_url(location: &str) -> bool {
+    location.starts_with("s3://") || location.starts_with("sqlite://")
+}
+
+/// `location` with local paths made absolute, so that it can be opened
+/// again from any working directory
+fn absolute_location(location: &str) -> String {
+    match location.strip_prefix("sqlite://") {
+        Some(path) =>
+            format!("sqlite://{}", Path::new(path).try_canonicalize()),
+        None if location.starts_with("s3://") => location.to_string(),
+        None => Path::new(location).try_canonicalize().to_string(),
+    }
+}
+
+#[derive(Parser, Debug, Clone)]
+#[command(
+    about = "checks every chunk and the whole-file checksum of an unfolded folder or store without folding it"
+)]
+pub struct VerifyOpt {
+    #[arg(
+        required = true,
+        help = "unfolded folder, sqlite://path or s3://bucket/prefix"
+    )]
+    location: String,
+
+    #[arg(
+        long = "replica",
+        help = "another replica to verify and compare with the location"
+    )]
+    replicas: Vec<String>,
+
+    #[arg(
+        long = "trusted-key",
+        value_name = "KEY",
+        help = "public key written by the keygen command, failing unless the index is signed by one of them"
+    )]
+    trusted_keys: Vec<Path>,
+}
+
+impl ArgsDispatcher<Error> for VerifyOpt {
+    fn dispatch(&self) -> Result<()> {
+        let location = self.location.clone();
+        let trusted_keys = read_trusted_keys(&self.trusted_keys)?;
+        if !self.replicas.is_empty() {
+            let mut locations = vec![self.location.clone()];
+            locations.extend(self.replicas.iter().cloned());
+            let store = ReplicaStore::open(&locations)?;
+            let index = verify_replicas(&store, |progress| {
+                println!("{location} => {progress}");
+            })?;
+            if !trusted_keys.is_empty() {
+                check_signature(&index, &trusted_keys)?;
+            }
+            println!("{} replicas agree", locations.len());
+            return Ok(());
+        }
+        let store = open_store(&self.location)?;
+        let progress = |progress: Progress| {
+            println!("{location} => {progress}");
+        };
+        if trusted_keys.is_empty() {
+            verify_store(store.as_ref(), progress)?;
+        } else {
+            verify_signed_store(store.as_ref(), &trusted_keys, progress)?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Parser, Debug, Clone)]
+#[command(
+    about = "prints the proof that a chunk belongs to the file with the merkle root of the index of an unfolded folder or store"
+)]
+pub struct ProofOpt {
+    #[arg(
+        required = true,
+        help = "unfolded folder, sqlite://path or s3://bucket/prefix"
+    )]
+    location: String,
+
+    #[arg(
+        required = true,
+        help = "position of the chunk, starting at 0"
+    )]
+    chunk_index: usize,
+}
+
+impl ArgsDispatcher<Error> for ProofOpt {
+    fn dispatch(&self) -> Result<()> {
+        let store = open_store(&self.location)?;
+        let proof = MerkleProof::read(store.as_ref(), self.chunk_index)?;
+        print!("{}", proof.to_yaml()?);
+        Ok(())
+    }
+}
+
+#[derive(Parser, Debug, Clone)]
+#[command(about = "works on the index of an unfolded folder or store")]
+pub struct IndexOpt {
+    #[command(subcommand)]
+    command: IndexCommand,
+}
+
+impl ArgsDispatcher<Error> for IndexOpt {
+    fn dispatch(&self) -> Result<()> {
+        self.command.dispatch()
+    }
+}
+
+#[derive(Subcommand, Debug, Clone)]
+pub enum IndexCommand {
+    Convert(IndexConvertOpt),
+}
+impl SubcommandDispatcher<Error> for IndexCommand {
+    fn dispatch(&self) -> Result<()> {
+        match self {
+            IndexCommand::Convert(op) => op.dispatch()?,
+        }
+        Ok(())
+    }
+}
+
+#[derive(Parser, Debug, Clone)]
+#[command(
+    about = "converts the index of an unfolded folder or store, along with its sub-indexes, between yaml, json and compact cbor, which are told apart when read"
+)]
+pub struct IndexConvertOpt {
+    #[arg(
+        required = true,
+        help = "unfolded folder, sqlite://path or s3://bucket/prefix"
+    )]
+    location: String,
+
+    #[arg(
+        long,
+        required = true,
+        help = "index encoding: yaml, json or cbor"
+    )]
+    to: IndexEncoding,
+}
+
+impl ArgsDispatcher<Error> for IndexConvertOpt {
+    fn dispatch(&self) -> Result<()> {
+        let location = self.location.clone();
+        let store = open_store(&self.location)?;
+        convert_index(store.as_ref(), self.to, |progress| {
+            println!("{location} => {progress}");
+        })?;
+        println!("converted the index of {location} to {}", self.to);
+        Ok(())
+    }
+}
+
+#[derive(Parser, Debug, Clone)]
+#[command(
+    about = "generates an Ed25519 key pair to sign indexes with, writing the secret key to the given path and the public key next to it with a .pub extension"
+)]
+pub struct KeygenOpt {
+    #[arg(
+        required = true,
+        help = "path of the secret key"
+    )]
+    path: Path,
+}
+
+impl ArgsDispatcher<Error> for KeygenOpt {
+    fn dispatch(&self) -> Result<()> {
+        let public_key_path = generate_key(&self.path)?;
+        println!(
+            "wrote secret key {} and public key {public_key_path}",
+            self.path
+        );
+        Ok(())
+    }
+}
+
+#[derive(Parser, Debug, Clone)]
+#[command(
+    about = "deletes the chunks of an unfolded folder or store which its index does not point at"
+)]
+pub struct GcOpt {
+    #[arg(
+        required = true,
+        help = "unfolded folder, sqlite://path or s3://bucket/prefix"
+    )]
+    location: String,
+}
+
+impl ArgsDispatcher<Error> for GcOpt {
+    fn dispatch(&self) -> Result<()> {
+        let location = self.location.clone();
+        let store = open_store(&self.location)?;
+        let removed = gc_store(store.as_ref(), |progress| {
+            println!("{location} => {progress}");
+        })?;
+        println!("removed {} chunks from {location}", removed.len());
+        Ok(())
+    }
+}
+
+#[derive(Parser, Debug, Clone)]
+#[command(
+    about = "moves the chunks of an unfolded folder or store between flat and fanned-out folders, or to another naming"
+)]
+pub struct RelayoutOpt {
+    #[arg(
+        required = true,
+        help = "unfolded folder, sqlite://path or s3://bucket/prefix"
+    )]
+    location: String,
+
+    #[arg(long, required = true, value_parser = clap::value_parser!(u8).range(0..=4), help = "levels of two hex digit folders chunks are nested in, 0 for flat")]
+    fanout: u8,
+
+    #[arg(
+        long,
+        help = "chunk names: hash, ordered or sequential, defaults to the current naming"
+    )]
+    naming: Option<ChunkNaming>,
+
+    #[arg(
+        long,
+        value_name = "SPAN",
+        help = "number of chunks each sub-index lists, a power of two, 0 for a flat index, defaults to the current one"
+    )]
+    manifest: Option<usize>,
+}
+
+impl ArgsDispatcher<Error> for RelayoutOpt {
+    fn dispatch(&self) -> Result<()> {
+        let location = self.location.clone();
+        let store = open_store(&self.location)?;
+        let current = store.read_index()?.layout();
+        let naming = self.naming.unwrap_or(current.naming);
+        let span = match self.manifest {
+            Some(span) => (span > 0).then_some(span),
+            None => current.span,
+        };
+        let layout = ChunkLayout::new(naming, self.fanout as usize)
+            .with_span(span)
+            .with_encoding(current.encoding);
+        relayout_store(store.as_ref(), layout, |progress| {
+            println!("{location} => {progress}");
+        })?;
+        println!("relaid out {location} with {layout}");
+        Ok(())
+    }
+}
+
+#[derive(Parser, Debug, Clone)]
+#[command(
+    about = "copies the index and chunks of an unfolded folder or store into another one, e.g.: from a folder into sqlite://path and back"
+)]
+pub struct MigrateOpt {
+    #[arg(
+        required = true,
+        help = "source unfolded folder, sqlite://path or s3://bucket/prefix"
+    )]
+    input_path: String,
+
+    #[arg(
+        required = true,
+        help = "target unfolded folder, sqlite://path or s3://bucket/prefix"
+    )]
+    output_path: String,
+}
+
+impl ArgsDispatcher<Error> for MigrateOpt {
+    fn dispatch(&self) -> Result<()> {
+        let output_path = self.output_path.clone();
+        let source = open_store(&self.input_path)?;
+        let target = open_store(&self.output_path)?;
+        migrate_store(source.as_ref(), target.as_ref(), |progress| {
             println!("{progress} => {output_path}");
         })?;
+        Ok(())
+    }
+}
+
+#[derive(Parser, Debug, Clone)]
+#[command(
+    about = "writes the index and chunks of an unfolded folder or store into a deterministic tar or zip archive"
+)]
+pub struct ExportOpt {
+    #[arg(
+        required = true,
+        help = "unfolded folder, sqlite://path or s3://bucket/prefix"
+    )]
+    input_path: String,
+
+    #[arg(
+        required = true,
+        help = "output archive"
+    )]
+    output_path: Path,
+
+    #[arg(
+        long,
+        help = "tar or zip, defaults to zip for .zip outputs and tar otherwise"
+    )]
+    format: Option<ArchiveFormat>,
+}
+
+impl ArgsDispatcher<Error> for ExportOpt {
+    fn dispatch(&self) -> Result<()> {
+        let output_path = self.output_path.to_string();
+        let format = self.format.unwrap_or_else(|| {
+            ArchiveFormat::from_extension(&self.output_path)
+        });
+        let store = open_store(&self.input_path)?;
+        export_to_file(
+            store.as_ref(),
+            format,
+            &self.output_path,
+            |progress| {
+                println!("{progress} => {output_path}");
+            },
+        )?;
+        Ok(())
+    }
+}
+
+#[derive(Parser, Debug, Clone)]
+#[command(
+    about = "extracts a tar or zip archive of an unfolded folder into a new unfolded folder"
+)]
+pub struct ImportOpt {
+    #[arg(
+        required = true,
+        help = "input archive"
+    )]
+    input_path: Path,
+
+    #[arg(
+        required = true,
+        help = "output folder"
+    )]
+    output_path: Path,
+}
+
+impl ArgsDispatcher<Error> for ImportOpt {
+    fn dispatch(&self) -> Result<()> {
+        let input_path = self.input_path.to_string();
+        import(&self.input_path, &self.output_path, |progress| {
+            println!("{input_path} => {progress}");
+        })?;
+        Ok(())
+    }
+}
+
+#[derive(Parser, Debug, Clone)]
+#[command(
+    about = "git filter driver replacing file contents with pointers on commit and restoring them from a chunk store on checkout"
+)]
+pub struct GitFilterOpt {
+    #[command(subcommand)]
+    mode: GitFilterMode,
+
+    #[arg(
+        long,
+        global = true,
+        env = "UNFOLDER_GIT_STORE",
+        help = "chunk store, defaults to the unfolder folder inside the git directory"
+    )]
+    store: Option<String>,
+}
+
+#[derive(Subcommand, Debug, Clone)]
+pub enum GitFilterMode {
+    #[command(
+        about = "replaces the contents on stdin with a pointer on stdout"
+    )]
+    Clean(GitFilterPathOpt),
+    #[command(
+        about = "restores the contents of the pointer on stdin to stdout"
+    )]
+    Smudge(GitFilterPathOpt),
+    #[command(
+        about = "speaks the long-running filter protocol of git on stdin and stdout"
+    )]
+    Process,
+}
+
+#[derive(Parser, Debug, Clone)]
+pub struct GitFilterPathOpt {
+    #[arg(
+        help = "path of the file being filtered, as passed by git with %f, named in error messages"
+    )]
+    path: Option<String>,
+}
+
+impl GitFilterOpt {
+    fn store(&self) -> Result<String> {
+        if let Some(store) = &self.store {
+            return Ok(store.clone());
+        }
+        let output = std::process::Command::new("git")
+            .args(["rev-parse", "--git-common-dir"])
+            .output()
+            .map_err(|error| {
+                Error::GitFilterError(format!("failed to run git: {error}"))
+            })?;
+        if !output.status.success() {
+            return Err(Error::GitFilterError(format!(
+                "failed to find the git directory: {}",
+                String::from_utf8_lossy(&output.stderr).trim()
+            )));
+        }
+        let git_dir = String::from_utf8_lossy(&output.stdout)
+            .trim()
+            .to_string();
+        Ok(Path::new(git_dir).join("unfolder").to_string())
+    }
+}
+
+/// [`git_clean`] or [`git_smudge`]
+type GitFilter = fn(&(dyn ChunkStore + 'static), &[u8]) -> Result<Vec<u8>>;
+
+impl ArgsDispatcher<Error> for GitFilterOpt {
+    fn dispatch(&self) -> Result<()> {
+        let store = open_store(&self.store()?)?;
+        let stdin = std::io::stdin().lock();
+        let mut stdout = std::io::BufWriter::new(std::io::stdout().lock());
+        let (filter, command, opt): (GitFilter, _, _) = match &self.mode {
+            GitFilterMode::Clean(opt) => (git_clean, "clean", opt),
+            GitFilterMode::Smudge(opt) => (git_smudge, "smudge", opt),
+            GitFilterMode::Process =>
+                return git_filter_process(store.as_ref(), stdin, stdout),
+        };
+        let mut bytes = Vec::<u8>::new();
+        std::io::BufReader::new(stdin).read_to_end(&mut bytes)?;
+        // git only reports that the filter failed, not on which file
+        let output =
+            filter(store.as_ref(), &bytes).map_err(|error| {
+                match &opt.path {
+                    Some(path) => Error::GitFilterError(format!(
+                        "{command} {path}: {error}"
+                    )),
+                    None => error,
+                }
+            })?;
+        stdout.write_all(&output)?;
+        stdout.flush()?;
+        Ok(())
+    }
+}
 
+#[derive(Parser, Debug, Clone)]
+#[command(
+    about = "moves the contents of a file, or of every file in a directory, into a chunk store and replaces them with small stubs"
+)]
+pub struct DehydrateOpt {
+    #[arg(
+        required = true,
+        help = "file or directory to dehydrate"
+    )]
+    path: Path,
+
+    #[arg(
+        long,
+        required = true,
+        help = "chunk store folder, sqlite://path or s3://bucket/prefix"
+    )]
+    store: String,
+}
+
+impl ArgsDispatcher<Error> for DehydrateOpt {
+    fn dispatch(&self) -> Result<()> {
+        let store = open_store(&absolute_location(&self.store))?;
+        let path = self.path.clone();
+        let dehydrated = dehydrate(&self.path, store.as_ref(), |progress| {
+            println!("{path} => {progress}");
+        })?;
+        println!("dehydrated {} files into {}", dehydrated.len(), self.store);
         Ok(())
     }
 }
 
+#[derive(Parser, Debug, Clone)]
+#[command(
+    about = "restores a file, or every stub in a directory, replaced by dehydrate from the chunk store its stub points at"
+)]
+pub struct HydrateOpt {
+    #[arg(
+        required = true,
+        help = "stub or directory to hydrate"
+    )]
+    path: Path,
+}
+
+impl ArgsDispatcher<Error> for HydrateOpt {
+    fn dispatch(&self) -> Result<()> {
+        let path = self.path.clone();
+        let hydrated = hydrate(&self.path, |progress| {
+            println!("{path} => {progress}");
+        })?;
+        println!("hydrated {} files", hydrated.len());
+        Ok(())
+    }
+}
 
 fn main() -> Exit {
     Cli::main()
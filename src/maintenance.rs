@@ -0,0 +1,261 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::ops::Range;
+
+use sha2::{Digest, Sha256};
+
+use crate::encoding::IndexEncoding;
+use crate::file::{get_chunk, read_unfold_index, validate_chunk};
+use crate::index::{ChunkLayout, ChunkNaming, UnfoldIndex};
+use crate::store::ChunkStore;
+use crate::{Action, Error, Progress, Result};
+
+/// checks, without writing anything, that every chunk the index of
+/// `store` points at is present, matches its name and has the expected
+/// length, and that together they match the whole-file checksum
+pub fn verify_store<S: ChunkStore + ?Sized, C: FnMut(Progress)>(
+    store: &S,
+    mut progress: C,
+) -> Result<UnfoldIndex> {
+    progress(Progress::Start(Action::Verify));
+    let index = read_unfold_index(store)?;
+    verify_index(store, &index, Action::Verify, &mut progress)?;
+    progress(Progress::End(Action::Verify));
+    Ok(index)
+}
+
+/// checks that every chunk `index` points at is present in `store`,
+/// matches its name and has the expected length, and that together
+/// they match the whole-file checksum, reporting progress as `action`
+pub(crate) fn verify_index<S: ChunkStore + ?Sized, C: FnMut(Progress)>(
+    store: &S,
+    index: &UnfoldIndex,
+    action: Action,
+    mut progress: C,
+) -> Result<()> {
+    let location = store.location();
+    let chunk_count = index.chunk_count();
+    let mut hasher = Sha256::new();
+    for (idx, sha256) in index.chunks().iter().enumerate() {
+        let name = index.chunk_name(idx);
+        let bytes = get_chunk(store, index, idx)?;
+        validate_chunk(sha256, &bytes).map_err(|error| {
+            Error::CorruptedDataError(format!("in {location}: {error}"))
+        })?;
+        let expected = index.chunk_range(idx);
+        if bytes.len() as u64 != expected.end - expected.start {
+            return Err(Error::CorruptedDataError(format!(
+                "chunk {name} in {location} has {} bytes, expected {}",
+                bytes.len(),
+                expected.end - expected.start
+            )));
+        }
+        hasher.update(&bytes);
+        progress(Progress::Chunk {
+            index: idx + 1,
+            count: chunk_count,
+            action,
+        });
+    }
+    let actual = hasher.finalize();
+    if actual.as_slice() != index.sha256() {
+        return Err(Error::CorruptedDataError(format!(
+            "invalid checksum at {location}: expected: {} actual: {}",
+            hex::encode(index.sha256()),
+            hex::encode(actual)
+        )));
+    }
+    Ok(())
+}
+
+/// deletes the chunks of `store` which its index does not point at and
+/// returns their names, leaving alone any other file, whose name is not
+/// that of a chunk under the layout of the index. Fails without
+/// deleting anything if `store` has no index.
+pub fn gc_store<S: ChunkStore + ?Sized, C: FnMut(Progress)>(
+    store: &S,
+    mut progress: C,
+) -> Result<Vec<String>> {
+    progress(Progress::Start(Action::Gc));
+    let index = store.read_index()?;
+    let referenced = index.chunk_files()?;
+    let layout = index.layout();
+    let garbage = store
+        .list()?
+        .into_iter()
+        .filter(|name| !referenced.contains_key(name) && layout.accepts(name))
+        .collect::<Vec<String>>();
+    let chunk_count = garbage.len();
+    for (idx, name) in garbage.iter().enumerate() {
+        store.delete(name)?;
+        progress(Progress::Chunk {
+            index: idx + 1,
+            count: chunk_count,
+            action: Action::Gc,
+        });
+    }
+    progress(Progress::End(Action::Gc));
+    Ok(garbage)
+}
+
+/// copies the chunks the index of `source` points at into `target`,
+/// verifying each one and skipping those `target` already has, then
+/// writes the index. `source` is left untouched.
+pub fn migrate_store<S, T, C>(
+    source: &S,
+    target: &T,
+    progress: C,
+) -> Result<UnfoldIndex>
+where
+    S: ChunkStore + ?Sized,
+    T: ChunkStore + ?Sized,
+    C: FnMut(Progress),
+{
+    copy_store(source, target, Action::Migrate, progress)
+}
+
+/// copies the chunks the index of `source` points at into `target`,
+/// verifying each one and skipping those `target` already has, then
+/// writes the index, reporting progress as `action`
+pub(crate) fn copy_store<S, T, C>(
+    source: &S,
+    target: &T,
+    action: Action,
+    mut progress: C,
+) -> Result<UnfoldIndex>
+where
+    S: ChunkStore + ?Sized,
+    T: ChunkStore + ?Sized,
+    C: FnMut(Progress),
+{
+    progress(Progress::Start(action));
+    let index = read_unfold_index(source)?;
+    let location = source.location();
+    let files = index.chunk_files()?;
+    let chunk_count = files.len();
+    for (idx, (name, sha256)) in files.iter().enumerate() {
+        if !target.has(name)? {
+            let bytes = source.get(name)?;
+            validate_chunk(sha256, &bytes).map_err(|error| {
+                Error::CorruptedDataError(format!("in {location}: {error}"))
+            })?;
+            target.put(name, &bytes)?;
+        }
+        progress(Progress::Chunk {
+            index: idx + 1,
+            count: chunk_count,
+            action,
+        });
+    }
+    target.write_index(&index)?;
+    progress(Progress::End(action));
+    Ok(index)
+}
+
+/// re-encodes the index of `store`, along with the sub-indexes of a
+/// hierarchical manifest, as `encoding` and returns it
+pub fn convert_index<S: ChunkStore + ?Sized, C: FnMut(Progress)>(
+    store: &S,
+    encoding: IndexEncoding,
+    progress: C,
+) -> Result<UnfoldIndex> {
+    let layout = store
+        .read_index()?
+        .layout()
+        .with_encoding(encoding);
+    relayout_store(store, layout, progress)
+}
+
+/// moves the chunks of `store` to the names given by `layout`, e.g.:
+/// between flat and fanned-out folders, and returns the new index.
+/// Chunks are copied and verified before the new index is written, and
+/// the old names are only deleted afterwards, so that the store always
+/// has a complete index.
+pub fn relayout_store<S: ChunkStore + ?Sized, C: FnMut(Progress)>(
+    store: &S,
+    layout: ChunkLayout,
+    mut progress: C,
+) -> Result<UnfoldIndex> {
+    if layout.fanout > ChunkLayout::MAX_FANOUT {
+        return Err(Error::StoreError(format!(
+            "fan-out {} is larger than {}",
+            layout.fanout,
+            ChunkLayout::MAX_FANOUT
+        )));
+    }
+    if let Some(span) = layout.span.filter(|span| !span.is_power_of_two()) {
+        return Err(Error::StoreError(format!(
+            "span {span} is not a power of two"
+        )));
+    }
+    progress(Progress::Start(Action::Relayout));
+    let index = read_unfold_index(store)?;
+    let mut relaid = index.clone().with_layout(layout);
+    // sequential naming promises every part, zero chunks included
+    if layout.naming == ChunkNaming::Sequential {
+        relaid = relaid.with_zeros(Vec::new());
+    }
+    let location = store.location();
+    let old_names = index.chunk_names_by_sha256()?;
+    let zero_chunks = index
+        .zero_extents()
+        .iter()
+        .flat_map(|zeros| zeros.clone())
+        .map(|idx| (index.chunks()[idx].clone(), index.chunk_range(idx)))
+        .collect::<BTreeMap<String, Range<u64>>>();
+    let mut subindexes = BTreeSet::<String>::new();
+    for subindex in relaid.subindexes() {
+        let subindex = subindex?;
+        if !store.has(&subindex.name)? {
+            store.put(&subindex.name, &subindex.bytes)?;
+        }
+        subindexes.insert(subindex.name);
+    }
+    let files = relaid
+        .chunk_files()?
+        .into_iter()
+        .filter(|(name, _)| !subindexes.contains(name))
+        .collect::<BTreeMap<String, String>>();
+    let chunk_count = files.len();
+    for (idx, (name, sha256)) in files.iter().enumerate() {
+        // other than sha256 names, only the names the old index gives
+        // the same contents can be kept: anything else might be stale
+        let current = old_names
+            .get(sha256)
+            .is_some_and(|names| names.contains(name));
+        let kept = current
+            || (layout.naming == ChunkNaming::Hash && store.has(name)?);
+        if !kept {
+            let bytes = match zero_chunks.get(sha256) {
+                Some(range) => vec![0; (range.end - range.start) as usize],
+                None => {
+                    let Some(old_name) = old_names
+                        .get(sha256)
+                        .and_then(|names| names.first())
+                    else {
+                        return Err(Error::CorruptedDataError(format!(
+                            "chunk {sha256} of {location} is not in its index"
+                        )));
+                    };
+                    store.get(old_name)?
+                },
+            };
+            validate_chunk(sha256, &bytes).map_err(|error| {
+                Error::CorruptedDataError(format!("in {location}: {error}"))
+            })?;
+            store.put(name, &bytes)?;
+        }
+        progress(Progress::Chunk {
+            index: idx + 1,
+            count: chunk_count,
+            action: Action::Relayout,
+        });
+    }
+    store.write_index(&relaid)?;
+    for name in index.chunk_files()?.into_keys() {
+        if !files.contains_key(&name) && !subindexes.contains(&name) {
+            store.delete(&name)?;
+        }
+    }
+    progress(Progress::End(Action::Relayout));
+    Ok(relaid)
+}
@@ -0,0 +1,333 @@
+use std::collections::BTreeMap;
+use std::fmt::Display;
+use std::ops::Range;
+
+use sha2::{Digest, Sha256};
+
+use crate::encoding::IndexEncoding;
+use crate::file::validate_chunk;
+use crate::index::{size_matches, ChunkLayout, ChunkNaming, UnfoldIndex};
+use crate::merkle::{merkle_root, MerkleProof, MerkleTree};
+use crate::store::ChunkStore;
+use crate::{Error, Result};
+
+/// sub-index listing `chunks`, the first of which is at position
+/// `first` of the file, keyed like the chunks of a flat index and
+/// encoded as `encoding`
+fn subindex_bytes(
+    first: usize,
+    chunks: &[String],
+    encoding: IndexEncoding,
+) -> Result<Vec<u8>> {
+    let fields = chunks
+        .iter()
+        .enumerate()
+        .map(|(idx, sha256)| (format!("{:032x}", first + idx), sha256.clone()))
+        .collect::<BTreeMap<String, String>>();
+    encoding.encode(&fields)
+}
+
+/// A sub-index of a hierarchical manifest as stored: a blob named after
+/// its sha256, nested in the fan-out folders of the store, along with
+/// the root of the merkle subtree over the chunks it lists.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct Subindex {
+    pub(crate) name: String,
+    pub(crate) sha256: String,
+    pub(crate) root: Vec<u8>,
+    pub(crate) bytes: Vec<u8>,
+}
+
+impl Subindex {
+    /// the sub-index listing `chunks`, the first of which is at
+    /// position `first` of the file, laid out as `layout`
+    pub(crate) fn new(
+        first: usize,
+        chunks: &[String],
+        layout: ChunkLayout,
+    ) -> Result<Subindex> {
+        let bytes = subindex_bytes(first, chunks, layout.encoding)?;
+        let sha256 = hex::encode(Sha256::digest(&bytes));
+        Ok(Subindex {
+            name: subindex_name(&sha256, layout.fanout),
+            sha256,
+            root: merkle_root(chunks),
+            bytes,
+        })
+    }
+}
+
+fn subindex_name(sha256: &str, fanout: usize) -> String {
+    ChunkLayout::new(ChunkNaming::Hash, fanout).chunk_name(0, 0, sha256)
+}
+
+/// Top-level index of a hierarchical manifest, written instead of a
+/// flat index for files laid out with a [`ChunkLayout::span`]: the
+/// fields of the index along with the number of chunks, the sha256 of
+/// each sub-index, which lists the sha256 of `span` chunks in order,
+/// and the root of the merkle subtree over those chunks. Sub-indexes
+/// are stored alongside the chunks and only read when the chunks they
+/// list are, so that the top-level index stays small for files of
+/// millions of chunks. As `span` is a power of two, the subtree roots
+/// lead to the merkle root of the file, so that proving a chunk only
+/// needs the sub-index listing it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ManifestIndex {
+    /// the index without its chunks
+    header: UnfoldIndex,
+    chunk_count: usize,
+    subindexes: Vec<String>,
+    /// root of the merkle subtree over the chunks of each sub-index
+    roots: Vec<Vec<u8>>,
+}
+
+impl ManifestIndex {
+    /// builds the top-level index of a hierarchical manifest out of
+    /// `header`, the `chunks` field and the `subindex-<n>` and
+    /// `subroot-<n>` `fields` of its yaml representation, failing
+    /// unless the subtree roots lead to the merkle root of `header`.
+    /// `origin` is only used in error messages.
+    pub(crate) fn from_fields(
+        header: UnfoldIndex,
+        chunks: &str,
+        fields: BTreeMap<String, String>,
+        origin: impl Display,
+    ) -> Result<ManifestIndex> {
+        let Some(span) = header.span() else {
+            return Err(Error::InvalidIndexError(format!(
+                "missing 'span' field in top-level index '{origin}'"
+            )));
+        };
+        let chunk_count = match chunks.parse::<usize>() {
+            Ok(count) if count > 0 => count,
+            _ =>
+                return Err(Error::InvalidIndexError(format!(
+                    "invalid 'chunks' field '{chunks}' in '{origin}', expected a positive integer"
+                ))),
+        };
+        let zeros = header.zero_extents().last();
+        if zeros.is_some_and(|zeros| zeros.end > chunk_count) {
+            return Err(Error::InvalidIndexError(format!(
+                "'zero-extents' field of '{origin}' points past its {chunk_count} chunks"
+            )));
+        }
+        if !size_matches(header.size(), chunk_count) {
+            return Err(Error::InvalidIndexError(format!(
+                "'size' field of '{origin}' does not match its {chunk_count} chunks"
+            )));
+        }
+        let expected = chunk_count.div_ceil(span);
+        let (mut subindexes, mut roots) = (Vec::new(), Vec::new());
+        for (key, sha256) in fields {
+            // `subroot-<n>` fields sort after every `subindex-<n>` one
+            let full = roots.len() == expected;
+            let (prefix, hashes) = match subindexes.len() < expected {
+                true => ("subindex", &mut subindexes),
+                false => ("subroot", &mut roots),
+            };
+            let name = format!("{prefix}-{:08x}", hashes.len());
+            if key != name || full {
+                return Err(Error::InvalidIndexError(format!(
+                    "unexpected field '{key}' in top-level index '{origin}', expected '{name}'"
+                )));
+            }
+            if !ChunkNaming::Hash.accepts(&sha256) {
+                return Err(Error::InvalidIndexError(format!(
+                    "invalid sha256 '{sha256}' in key '{key}' of '{origin}'"
+                )));
+            }
+            hashes.push(sha256);
+        }
+        if roots.len() != expected {
+            return Err(Error::InvalidIndexError(format!(
+                "top-level index '{origin}' has {} sub-indexes and {} subtree roots, expected {expected} for {chunk_count} chunks of {span}",
+                subindexes.len(),
+                roots.len()
+            )));
+        }
+        let roots = roots
+            .iter()
+            .map(|root| hex::decode(root).unwrap_or_default())
+            .collect::<Vec<Vec<u8>>>();
+        let merkle_root = MerkleTree::over_subtrees(roots.clone()).root();
+        if merkle_root != header.merkle_root() {
+            return Err(Error::InvalidIndexError(format!(
+                "the subtree roots of '{origin}' do not match its 'merkle-root' field, expected {}",
+                hex::encode(&merkle_root)
+            )));
+        }
+        Ok(ManifestIndex {
+            header,
+            chunk_count,
+            subindexes,
+            roots,
+        })
+    }
+
+    /// the index without its chunks, whose [`UnfoldIndex::chunks`] is
+    /// empty
+    pub fn header(&self) -> &UnfoldIndex {
+        &self.header
+    }
+
+    pub fn sha256(&self) -> &[u8] {
+        self.header.sha256()
+    }
+
+    pub fn size(&self) -> u64 {
+        self.header.size()
+    }
+
+    pub fn merkle_root(&self) -> &[u8] {
+        self.header.merkle_root()
+    }
+
+    pub fn layout(&self) -> ChunkLayout {
+        self.header.layout()
+    }
+
+    /// number of chunks each sub-index lists
+    pub fn span(&self) -> usize {
+        self.header.span().unwrap_or(1)
+    }
+
+    pub fn chunk_count(&self) -> usize {
+        self.chunk_count
+    }
+
+    /// sha256 of each sub-index in order
+    pub fn subindexes(&self) -> &[String] {
+        &self.subindexes
+    }
+
+    /// tree over the subtree roots of the sub-indexes, whose root is
+    /// the merkle root of the file
+    pub(crate) fn subtree(&self) -> MerkleTree {
+        MerkleTree::over_subtrees(self.roots.clone())
+    }
+
+    /// position of the sub-index listing the chunk at position `idx`
+    pub fn subindex_at(&self, idx: usize) -> usize {
+        idx / self.span()
+    }
+
+    /// positions of the chunks the sub-index at position `idx` lists
+    pub fn subindex_chunks(&self, idx: usize) -> Range<usize> {
+        let start = (idx * self.span()).min(self.chunk_count);
+        start..(start + self.span()).min(self.chunk_count)
+    }
+
+    /// name under which the sub-index at position `idx` is stored
+    pub fn subindex_name(&self, idx: usize) -> String {
+        subindex_name(&self.subindexes[idx], self.header.fanout())
+    }
+
+    /// reads and verifies the sub-index at position `idx` from
+    /// `store`, returning the sha256 of the chunks it lists in order
+    pub fn read_subindex<S: ChunkStore + ?Sized>(
+        &self,
+        store: &S,
+        idx: usize,
+    ) -> Result<Vec<String>> {
+        if idx >= self.subindexes.len() {
+            return Err(Error::InvalidIndexError(format!(
+                "sub-index {idx} out of bounds in {}",
+                store.location()
+            )));
+        }
+        let bytes = store.get(&self.subindex_name(idx))?;
+        self.parse_subindex(idx, &bytes, &store.location())
+    }
+
+    /// verifies the `bytes` of the sub-index at position `idx`, read
+    /// from the store at `location`, returning the sha256 of the chunks
+    /// it lists in order
+    pub(crate) fn parse_subindex(
+        &self,
+        idx: usize,
+        bytes: &[u8],
+        location: &str,
+    ) -> Result<Vec<String>> {
+        let origin = format!("{location}/{}", self.subindex_name(idx));
+        validate_chunk(&self.subindexes[idx], bytes).map_err(|error| {
+            Error::CorruptedDataError(format!("in {origin}: {error}"))
+        })?;
+        let fields = IndexEncoding::detect(bytes).decode(bytes, &origin)?;
+        let range = self.subindex_chunks(idx);
+        if fields.len() != range.len() {
+            return Err(Error::InvalidIndexError(format!(
+                "sub-index '{origin}' lists {} chunks, expected {}",
+                fields.len(),
+                range.len()
+            )));
+        }
+        let mut chunks = Vec::<String>::new();
+        for (expected, (key, sha256)) in range.zip(fields) {
+            if key != format!("{expected:032x}")
+                || !ChunkNaming::Hash.accepts(&sha256)
+            {
+                return Err(Error::InvalidIndexError(format!(
+                    "unexpected key '{key}' pointing at '{sha256}' in sub-index '{origin}', expected chunk {expected}"
+                )));
+            }
+            chunks.push(sha256);
+        }
+        if merkle_root(&chunks) != self.roots[idx] {
+            return Err(Error::InvalidIndexError(format!(
+                "sub-index '{origin}' does not match its 'subroot-{idx:08x}' field"
+            )));
+        }
+        Ok(chunks)
+    }
+
+    /// proof that the chunk at position `idx` belongs to the file with
+    /// the merkle root of the manifest, reading only the sub-index
+    /// listing it from `store`
+    pub fn proof<S: ChunkStore + ?Sized>(
+        &self,
+        store: &S,
+        idx: usize,
+    ) -> Result<MerkleProof> {
+        if idx >= self.chunk_count {
+            return Err(Error::ProofError(format!(
+                "chunk {idx} out of bounds, the index has {} chunks",
+                self.chunk_count
+            )));
+        }
+        let position = self.subindex_at(idx);
+        let chunks = self.read_subindex(store, position)?;
+        self.subindex_proof(&self.subtree(), position, &chunks, idx)
+    }
+
+    /// proof of the chunk at position `idx`, listed in `chunks` by the
+    /// sub-index at position `subindex`, out of the `subtree` over the
+    /// subtree roots
+    pub(crate) fn subindex_proof(
+        &self,
+        subtree: &MerkleTree,
+        subindex: usize,
+        chunks: &[String],
+        idx: usize,
+    ) -> Result<MerkleProof> {
+        let first = self.subindex_chunks(subindex).start;
+        let proof = MerkleTree::new(chunks).proof(idx - first)?;
+        Ok(proof.within(idx, self.chunk_count, subtree.node_siblings(subindex)))
+    }
+
+    /// the whole index, reading every sub-index from `store` and
+    /// checking that they lead to the merkle root of the manifest
+    pub fn resolve<S: ChunkStore + ?Sized>(
+        &self,
+        store: &S,
+    ) -> Result<UnfoldIndex> {
+        // grown as each sub-index is verified rather than sized by the
+        // untrusted chunk count of the top-level index
+        let mut chunks = Vec::<String>::new();
+        for idx in 0..self.subindexes.len() {
+            chunks.extend(self.read_subindex(store, idx)?);
+        }
+        self.header
+            .clone()
+            .with_chunks(chunks, format!("{}/index", store.location()))
+    }
+}
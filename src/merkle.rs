@@ -0,0 +1,334 @@
+use std::collections::BTreeMap;
+use std::fmt::Display;
+
+use sha2::{Digest, Sha256};
+
+use crate::index::{ParsedIndex, UnfoldIndex};
+use crate::store::{parse_store_index, ChunkStore};
+use crate::{Error, Result};
+
+/// prefix of the hash of a leaf, keeping leaves and nodes apart so that
+/// a node can never pass for a chunk
+const LEAF_PREFIX: u8 = 0x00;
+/// prefix of the hash of a node over two children
+const NODE_PREFIX: u8 = 0x01;
+
+fn leaf_hash(sha256: &[u8]) -> Vec<u8> {
+    let mut hasher = Sha256::new();
+    hasher.update([LEAF_PREFIX]);
+    hasher.update(sha256);
+    hasher.finalize().to_vec()
+}
+
+fn node_hash(left: &[u8], right: &[u8]) -> Vec<u8> {
+    let mut hasher = Sha256::new();
+    hasher.update([NODE_PREFIX]);
+    hasher.update(left);
+    hasher.update(right);
+    hasher.finalize().to_vec()
+}
+
+fn decode_sha256(sha256: &str) -> Option<Vec<u8>> {
+    hex::decode(sha256)
+        .ok()
+        .filter(|bytes| bytes.len() == 32)
+}
+
+/// Merkle tree over the sha256 of every chunk in order, kept whole so
+/// that proofs of many chunks are cheap. A node without a sibling moves
+/// up a level unchanged rather than being paired with itself.
+#[derive(Debug, Clone)]
+pub(crate) struct MerkleTree {
+    chunks: Vec<Vec<u8>>,
+    /// levels of the tree from the leaves up to the root
+    levels: Vec<Vec<Vec<u8>>>,
+}
+
+impl MerkleTree {
+    /// builds the tree over the hex encoded sha256 of `chunks`, which
+    /// are validated when the index is parsed
+    pub(crate) fn new(chunks: &[String]) -> MerkleTree {
+        let chunks = chunks
+            .iter()
+            .map(|sha256| hex::decode(sha256).unwrap_or_default())
+            .collect::<Vec<_>>();
+        let leaves = chunks
+            .iter()
+            .map(|sha256| leaf_hash(sha256))
+            .collect::<Vec<_>>();
+        MerkleTree::from_nodes(chunks, leaves)
+    }
+
+    /// builds the tree over `nodes`, the roots of the subtrees over
+    /// consecutive runs of chunks of the same power of two size, the
+    /// last one possibly shorter, such as the sub-indexes of a
+    /// hierarchical manifest. Such a tree has no chunks to prove, only
+    /// nodes, see [`node_siblings`](MerkleTree::node_siblings).
+    pub(crate) fn over_subtrees(nodes: Vec<Vec<u8>>) -> MerkleTree {
+        MerkleTree::from_nodes(Vec::new(), nodes)
+    }
+
+    fn from_nodes(chunks: Vec<Vec<u8>>, nodes: Vec<Vec<u8>>) -> MerkleTree {
+        let mut levels = vec![nodes];
+        while levels[levels.len() - 1].len() > 1 {
+            let level = levels[levels.len() - 1]
+                .chunks(2)
+                .map(|pair| match pair {
+                    [left, right] => node_hash(left, right),
+                    [node] => node.clone(),
+                    _ => unreachable!(),
+                })
+                .collect();
+            levels.push(level);
+        }
+        MerkleTree { chunks, levels }
+    }
+
+    /// the root of the tree, or the sha256 of nothing without chunks
+    pub(crate) fn root(&self) -> Vec<u8> {
+        match self.levels[self.levels.len() - 1].first() {
+            Some(root) => root.clone(),
+            None => Sha256::digest([]).to_vec(),
+        }
+    }
+
+    /// proof of inclusion of the chunk at position `idx`
+    pub(crate) fn proof(&self, idx: usize) -> Result<MerkleProof> {
+        let Some(sha256) = self.chunks.get(idx) else {
+            return Err(Error::ProofError(format!(
+                "chunk {idx} out of bounds, the index has {} chunks",
+                self.chunks.len()
+            )));
+        };
+        Ok(MerkleProof {
+            chunk: idx,
+            chunks: self.chunks.len(),
+            sha256: sha256.clone(),
+            siblings: self.node_siblings(idx),
+        })
+    }
+
+    /// siblings met on the way from the node at position `idx` of the
+    /// lowest level up to the root
+    pub(crate) fn node_siblings(&self, idx: usize) -> Vec<Vec<u8>> {
+        let mut siblings = Vec::new();
+        let mut position = idx;
+        for level in &self.levels[..self.levels.len() - 1] {
+            if let Some(sibling) = level.get(position ^ 1) {
+                siblings.push(sibling.clone());
+            }
+            position /= 2;
+        }
+        siblings
+    }
+}
+
+/// root of the merkle tree over the hex encoded sha256 of every chunk
+/// in order, as recorded in the `merkle-root` field of the index, or
+/// the sha256 of nothing without chunks
+pub fn merkle_root(chunks: &[String]) -> Vec<u8> {
+    MerkleTree::new(chunks).root()
+}
+
+/// Proof that the chunk with a given sha256 is the chunk at position
+/// `chunk` of a file of `chunks` chunks whose index has a given merkle
+/// root: the siblings met on the way from its leaf up to the root.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MerkleProof {
+    chunk: usize,
+    chunks: usize,
+    sha256: Vec<u8>,
+    siblings: Vec<Vec<u8>>,
+}
+
+impl MerkleProof {
+    /// proof of inclusion of the chunk at position `idx` of `index`
+    pub fn new(index: &UnfoldIndex, idx: usize) -> Result<MerkleProof> {
+        MerkleTree::new(index.chunks()).proof(idx)
+    }
+
+    /// proof of inclusion of the chunk at position `idx` of the index
+    /// of `store`, reading only the sub-index listing it for
+    /// hierarchical manifests
+    pub fn read<S: ChunkStore + ?Sized>(
+        store: &S,
+        idx: usize,
+    ) -> Result<MerkleProof> {
+        match parse_store_index(store)? {
+            ParsedIndex::Index(..) =>
+                MerkleProof::new(&store.read_index()?, idx),
+            ParsedIndex::Manifest(manifest) => manifest.proof(store, idx),
+        }
+    }
+
+    /// the proof of the same chunk at position `chunk` of a file of
+    /// `chunks` chunks, given the proof within the aligned subtree of
+    /// a power of two size it belongs to and the `siblings` of that
+    /// subtree up to the root
+    pub(crate) fn within(
+        self,
+        chunk: usize,
+        chunks: usize,
+        siblings: Vec<Vec<u8>>,
+    ) -> MerkleProof {
+        let mut proof = MerkleProof {
+            chunk,
+            chunks,
+            ..self
+        };
+        proof.siblings.extend(siblings);
+        proof
+    }
+
+    /// position of the chunk in the file
+    pub fn chunk(&self) -> usize {
+        self.chunk
+    }
+
+    /// number of chunks of the file
+    pub fn chunks(&self) -> usize {
+        self.chunks
+    }
+
+    /// sha256 of the chunk
+    pub fn sha256(&self) -> &[u8] {
+        &self.sha256
+    }
+
+    pub fn siblings(&self) -> &[Vec<u8>] {
+        &self.siblings
+    }
+
+    /// the merkle root this proof leads to, failing when it has too
+    /// few or too many siblings for the position of the chunk
+    pub fn root(&self) -> Result<Vec<u8>> {
+        let mut siblings = self.siblings.iter();
+        let mut hash = leaf_hash(&self.sha256);
+        let (mut position, mut width) = (self.chunk, self.chunks);
+        while width > 1 {
+            if position ^ 1 < width {
+                let Some(sibling) = siblings.next() else {
+                    return Err(Error::ProofError(format!(
+                        "too few siblings to prove chunk {}",
+                        self.chunk
+                    )));
+                };
+                hash = if position % 2 == 0 {
+                    node_hash(&hash, sibling)
+                } else {
+                    node_hash(sibling, &hash)
+                };
+            }
+            position /= 2;
+            width = width.div_ceil(2);
+        }
+        if siblings.next().is_some() {
+            return Err(Error::ProofError(format!(
+                "too many siblings to prove chunk {}",
+                self.chunk
+            )));
+        }
+        Ok(hash)
+    }
+
+    /// checks that `bytes` are the chunk this proof is about and that
+    /// the proof leads to the trusted merkle `root`
+    pub fn verify(&self, bytes: &[u8], root: &[u8]) -> Result<()> {
+        let sha256 = Sha256::digest(bytes).to_vec();
+        if sha256 != self.sha256 {
+            return Err(Error::ProofError(format!(
+                "chunk {} has sha256 {}, the proof is about {}",
+                self.chunk,
+                hex::encode(sha256),
+                hex::encode(&self.sha256)
+            )));
+        }
+        let actual = self.root()?;
+        if actual != root {
+            return Err(Error::ProofError(format!(
+                "proof of chunk {} leads to merkle root {}, expected {}",
+                self.chunk,
+                hex::encode(actual),
+                hex::encode(root)
+            )));
+        }
+        Ok(())
+    }
+
+    /// parses the yaml representation written by
+    /// [`to_yaml`](MerkleProof::to_yaml). `origin` is only used in
+    /// error messages.
+    pub fn parse(yaml: &str, origin: impl Display) -> Result<MerkleProof> {
+        let mut fields = serde_yaml::from_str::<BTreeMap<String, String>>(yaml)
+            .map_err(|error| {
+                Error::ProofError(format!(
+                    "invalid yaml in '{origin}': {error}"
+                ))
+            })?;
+        let mut field = |name: &str| {
+            fields.remove(name).ok_or_else(|| {
+                Error::ProofError(format!(
+                    "missing '{name}' field in '{origin}'"
+                ))
+            })
+        };
+        let invalid = |name: &str, value: &str| {
+            Error::ProofError(format!(
+                "invalid '{name}' field '{value}' in '{origin}'"
+            ))
+        };
+        let chunk = field("chunk")?;
+        let chunk = chunk
+            .parse::<usize>()
+            .map_err(|_| invalid("chunk", &chunk))?;
+        let chunks = field("chunks")?;
+        let chunks = match chunks.parse::<usize>() {
+            Ok(count) if chunk < count => count,
+            _ => return Err(invalid("chunks", &chunks)),
+        };
+        let sha256 = field("sha256")?;
+        let sha256 =
+            decode_sha256(&sha256).ok_or_else(|| invalid("sha256", &sha256))?;
+        let mut siblings = Vec::new();
+        loop {
+            let name = format!("sibling-{:02}", siblings.len());
+            let Ok(sibling) = field(&name) else {
+                break;
+            };
+            siblings.push(
+                decode_sha256(&sibling)
+                    .ok_or_else(|| invalid(&name, &sibling))?,
+            );
+        }
+        // the root is informative only: the proof is checked against a
+        // root obtained elsewhere
+        fields.remove("root");
+        if let Some(name) = fields.keys().next() {
+            return Err(Error::ProofError(format!(
+                "unexpected '{name}' field in '{origin}'"
+            )));
+        }
+        Ok(MerkleProof {
+            chunk,
+            chunks,
+            sha256,
+            siblings,
+        })
+    }
+
+    pub fn to_yaml(&self) -> Result<String> {
+        let mut fields = BTreeMap::<String, String>::new();
+        fields.insert("chunk".to_string(), self.chunk.to_string());
+        fields.insert("chunks".to_string(), self.chunks.to_string());
+        fields.insert("sha256".to_string(), hex::encode(&self.sha256));
+        fields.insert("root".to_string(), hex::encode(self.root()?));
+        for (idx, sibling) in self.siblings.iter().enumerate() {
+            fields.insert(format!("sibling-{idx:02}"), hex::encode(sibling));
+        }
+        serde_yaml::to_string(&fields).map_err(|error| {
+            Error::ProofError(format!(
+                "failed to serialize proof as yaml: {error}"
+            ))
+        })
+    }
+}
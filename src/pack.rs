@@ -0,0 +1,415 @@
+use std::io::{Read, Write};
+
+use iocore::Path;
+
+use crate::file::validate_chunk;
+use crate::index::UnfoldIndex;
+use crate::store::{ChunkStore, DirStore};
+use crate::{Action, Error, Progress, Result};
+
+const MAGIC: &[u8; 16] = b"unfolder-pack-01";
+const TAG_INDEX: u8 = b'I';
+const TAG_CHUNK: u8 = b'C';
+const TAG_END: u8 = b'E';
+
+/// A frame of a pack stream.
+///
+/// A stream starts with a 16-byte magic followed by frames made of a
+/// one-byte tag, a big-endian `u64` payload length and the payload:
+///
+/// - `I`: number of chunk frames that follow as a big-endian `u64`
+///   followed by the index listing every chunk, in its own encoding
+/// - `C`: length of the chunk name as one byte, the chunk name and the
+///   chunk bytes
+/// - `E`: empty, marks the end of the stream
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Frame {
+    Index {
+        chunk_count: usize,
+        index: UnfoldIndex,
+    },
+    Chunk {
+        name: String,
+        bytes: Vec<u8>,
+    },
+    End,
+}
+
+/// writes a pack stream into any [`Write`]
+pub struct PackWriter<W: Write> {
+    writer: W,
+}
+impl<W: Write> PackWriter<W> {
+    pub fn new(mut writer: W) -> Result<PackWriter<W>> {
+        writer.write_all(MAGIC)?;
+        Ok(PackWriter { writer })
+    }
+
+    /// writes the index frame, which must come first and announce the
+    /// number of chunk frames that follow
+    pub fn write_index(
+        &mut self,
+        index: &UnfoldIndex,
+        chunk_count: usize,
+    ) -> Result<()> {
+        let count = (chunk_count as u64).to_be_bytes();
+        let bytes = index.to_flat_bytes()?;
+        self.write_frame(TAG_INDEX, &[&count, &bytes])
+    }
+
+    pub fn write_chunk(&mut self, name: &str, bytes: &[u8]) -> Result<()> {
+        if name.is_empty() || name.len() > u8::MAX as usize {
+            return Err(Error::InvalidPackError(format!(
+                "chunk name '{name}' must be between 1 and 255 bytes long"
+            )));
+        }
+        self.write_frame(
+            TAG_CHUNK,
+            &[
+                &[name.len() as u8],
+                name.as_bytes(),
+                bytes,
+            ],
+        )
+    }
+
+    /// writes the end frame, flushes and returns the inner writer
+    pub fn finish(mut self) -> Result<W> {
+        self.write_frame(TAG_END, &[])?;
+        self.writer.flush()?;
+        Ok(self.writer)
+    }
+
+    fn write_frame(&mut self, tag: u8, parts: &[&[u8]]) -> Result<()> {
+        let length = parts
+            .iter()
+            .map(|part| part.len() as u64)
+            .sum::<u64>();
+        self.writer.write_all(&[tag])?;
+        self.writer.write_all(&length.to_be_bytes())?;
+        for part in parts {
+            self.writer.write_all(part)?;
+        }
+        Ok(())
+    }
+}
+
+/// reads a pack stream from any [`Read`], verifying every chunk
+/// against its name as it goes
+pub struct PackReader<R: Read> {
+    reader: R,
+    finished: bool,
+}
+impl<R: Read> PackReader<R> {
+    pub fn new(mut reader: R) -> Result<PackReader<R>> {
+        let mut magic = [0u8; 16];
+        reader.read_exact(&mut magic).map_err(|error| {
+            Error::InvalidPackError(format!("failed to read header: {error}"))
+        })?;
+        if &magic != MAGIC {
+            return Err(Error::InvalidPackError(
+                "not an unfolder pack stream".to_string(),
+            ));
+        }
+        Ok(PackReader {
+            reader,
+            finished: false,
+        })
+    }
+
+    /// reads the index frame at the start of the stream
+    pub fn read_index(&mut self) -> Result<(usize, UnfoldIndex)> {
+        match self.read_frame()? {
+            Frame::Index { chunk_count, index } => Ok((chunk_count, index)),
+            frame => Err(Error::InvalidPackError(format!(
+                "expected index frame at the start of the stream, found {}",
+                frame.kind()
+            ))),
+        }
+    }
+
+    /// reads the next chunk frame, returning `None` at the end frame
+    pub fn read_chunk(&mut self) -> Result<Option<(String, Vec<u8>)>> {
+        match self.read_frame()? {
+            Frame::Chunk { name, bytes } => Ok(Some((name, bytes))),
+            Frame::End => Ok(None),
+            frame => Err(Error::InvalidPackError(format!(
+                "unexpected {} frame",
+                frame.kind()
+            ))),
+        }
+    }
+
+    pub fn read_frame(&mut self) -> Result<Frame> {
+        if self.finished {
+            return Ok(Frame::End);
+        }
+        let mut header = [0u8; 9];
+        self.reader
+            .read_exact(&mut header)
+            .map_err(|error| {
+                Error::InvalidPackError(format!(
+                    "truncated frame header: {error}"
+                ))
+            })?;
+        let mut length = [0u8; 8];
+        length.copy_from_slice(&header[1..]);
+        let length = u64::from_be_bytes(length);
+        let mut payload = Vec::<u8>::new();
+        (&mut self.reader)
+            .take(length)
+            .read_to_end(&mut payload)?;
+        if payload.len() as u64 != length {
+            return Err(Error::InvalidPackError(format!(
+                "truncated frame: expected {length} bytes, got {}",
+                payload.len()
+            )));
+        }
+        match header[0] {
+            TAG_INDEX => Frame::index(payload),
+            TAG_CHUNK => Frame::chunk(payload),
+            TAG_END => {
+                self.finished = true;
+                Ok(Frame::End)
+            },
+            tag => Err(Error::InvalidPackError(format!(
+                "unknown frame tag {tag:#04x}"
+            ))),
+        }
+    }
+
+    pub fn into_inner(self) -> R {
+        self.reader
+    }
+}
+
+impl Frame {
+    fn kind(&self) -> &'static str {
+        match self {
+            Frame::Index { .. } => "index",
+            Frame::Chunk { .. } => "chunk",
+            Frame::End => "end",
+        }
+    }
+
+    fn index(payload: Vec<u8>) -> Result<Frame> {
+        if payload.len() < 8 {
+            return Err(Error::InvalidPackError(
+                "truncated index frame".to_string(),
+            ));
+        }
+        let mut count = [0u8; 8];
+        count.copy_from_slice(&payload[..8]);
+        let origin = "pack stream";
+        let index = UnfoldIndex::decode(&payload[8..], origin)?
+            .into_index(origin)?
+            .0;
+        Ok(Frame::Index {
+            chunk_count: u64::from_be_bytes(count) as usize,
+            index,
+        })
+    }
+
+    fn chunk(mut payload: Vec<u8>) -> Result<Frame> {
+        let name_length = match payload.first() {
+            Some(length) => *length as usize,
+            None =>
+                return Err(Error::InvalidPackError(
+                    "empty chunk frame".to_string(),
+                )),
+        };
+        if payload.len() < 1 + name_length {
+            return Err(Error::InvalidPackError(
+                "truncated chunk name".to_string(),
+            ));
+        }
+        let bytes = payload.split_off(1 + name_length);
+        let name =
+            String::from_utf8(payload[1..].to_vec()).map_err(|error| {
+                Error::InvalidPackError(format!("invalid chunk name: {error}"))
+            })?;
+        validate_chunk(&name, &bytes)?;
+        Ok(Frame::Chunk { name, bytes })
+    }
+}
+
+/// writes the index and every chunk of the unfolded folder at
+/// `input_path` as a pack stream into `writer`
+pub fn pack<W: Write, C: FnMut(Progress)>(
+    input_path: &Path,
+    writer: W,
+    progress: C,
+) -> Result<W> {
+    if !input_path.is_dir() {
+        return Err(Error::PackInputError(format!(
+            "{input_path} is not a directory"
+        )));
+    }
+    pack_store(&DirStore::new(input_path), writer, progress)
+}
+
+/// writes the index and every chunk of `store` as a pack stream into
+/// `writer`
+pub fn pack_store<S: ChunkStore + ?Sized, W: Write, C: FnMut(Progress)>(
+    store: &S,
+    writer: W,
+    mut progress: C,
+) -> Result<W> {
+    progress(Progress::Start(Action::Pack));
+    let index = store.read_index()?;
+    let names = index.chunk_names_by_sha256()?;
+    let mut writer = PackWriter::new(writer)?;
+    writer.write_index(&index, names.len())?;
+    let chunk_count = names.len();
+    let location = store.location();
+    for (idx, (sha256, names)) in names.into_iter().enumerate() {
+        let name = &names[0];
+        let bytes = store.get(name).map_err(|error| {
+            Error::PackInputError(format!(
+                "failed to read chunk {name} from {location}: {error}"
+            ))
+        })?;
+        writer.write_chunk(&sha256, &bytes)?;
+        progress(Progress::Chunk {
+            index: idx + 1,
+            count: chunk_count,
+            action: Action::Pack,
+        });
+    }
+    let writer = writer.finish()?;
+    progress(Progress::End(Action::Pack));
+    Ok(writer)
+}
+
+/// reads a pack stream from `reader` into a new unfolded folder at
+/// `output_path`. Chunks are verified and staged in a sibling folder
+/// which is only renamed to `output_path` once the whole stream has
+/// been read and every chunk in the index is present, so a corrupted or
+/// truncated stream never leaves a partial `output_path` behind.
+pub fn unpack<R: Read, C: FnMut(Progress)>(
+    reader: R,
+    output_path: &Path,
+    mut progress: C,
+) -> Result<Path> {
+    if output_path.exists() {
+        return Err(Error::PackOutputError(format!(
+            "{output_path} already exists"
+        )));
+    }
+    let staging_path = output_path.with_filename(format!(
+        ".{}.unpacking-{}",
+        output_path.name(),
+        std::process::id()
+    ));
+    if staging_path.exists() {
+        staging_path.delete()?;
+    }
+    progress(Progress::Start(Action::Unpack));
+    let result = staging_path
+        .mkdir()
+        .map_err(Error::from)
+        .and_then(|_| {
+            unpack_to_store(
+                reader,
+                &DirStore::new(&staging_path),
+                &mut progress,
+            )
+        });
+    match result {
+        Ok(_) => {
+            staging_path.rename(output_path, true)?;
+            progress(Progress::End(Action::Unpack));
+            Ok(output_path.clone())
+        },
+        Err(error) => {
+            staging_path.delete()?;
+            Err(error)
+        },
+    }
+}
+
+/// reads a pack stream from `reader` into `store`, writing the index
+/// only once every chunk it points at is present in `store`
+pub fn unpack_to_store<R: Read, S: ChunkStore + ?Sized, C: FnMut(Progress)>(
+    reader: R,
+    store: &S,
+    mut progress: C,
+) -> Result<UnfoldIndex> {
+    let index = unpack_into(reader, store, Action::Unpack, &mut progress)?;
+    if let Some(name) = missing_chunk(&index, store)? {
+        return Err(Error::PackOutputError(format!(
+            "chunk {name} is neither in the pack stream nor in {}",
+            store.location()
+        )));
+    }
+    store.write_index(&index)?;
+    Ok(index)
+}
+
+/// reads a pack stream from `reader` and writes its chunks into
+/// `store`, skipping chunks that already exist with the right contents
+/// and rewriting those that are corrupted. Returns the index read from
+/// the stream without writing it.
+pub(crate) fn unpack_into<R, S, C>(
+    reader: R,
+    store: &S,
+    action: Action,
+    progress: &mut C,
+) -> Result<UnfoldIndex>
+where
+    R: Read,
+    S: ChunkStore + ?Sized,
+    C: FnMut(Progress),
+{
+    let mut reader = PackReader::new(reader)?;
+    let (chunk_count, index) = reader.read_index()?;
+    let names = index.chunk_names_by_sha256()?;
+    let mut chunk_index = 0usize;
+    while let Some((sha256, bytes)) = reader.read_chunk()? {
+        let Some(names) = names.get(&sha256) else {
+            return Err(Error::InvalidPackError(format!(
+                "chunk {sha256} is not in the index of the stream"
+            )));
+        };
+        for name in names {
+            // a chunk that fails to read is rewritten, and `put` reports
+            // the error if the store is really unusable
+            let intact = store.get(name).is_ok_and(|existing| {
+                validate_chunk(&sha256, &existing).is_ok()
+            });
+            if !intact {
+                store.put(name, &bytes)?;
+            }
+        }
+        chunk_index += 1;
+        progress(Progress::Chunk {
+            index: chunk_index,
+            count: chunk_count,
+            action,
+        });
+    }
+    Ok(index)
+}
+
+/// first chunk of `index` missing from `store`
+pub(crate) fn missing_chunk<S: ChunkStore + ?Sized>(
+    index: &UnfoldIndex,
+    store: &S,
+) -> Result<Option<String>> {
+    let subindexes = index
+        .subindexes()
+        .map(|subindex| subindex.map(|subindex| subindex.name))
+        .collect::<Result<Vec<String>>>()?;
+    let names = index
+        .chunk_names()
+        .into_iter()
+        .enumerate()
+        .filter(|(idx, _)| !index.is_zero(*idx))
+        .map(|(_, name)| name);
+    for name in names.chain(subindexes) {
+        if !store.has(&name)? {
+            return Ok(Some(name));
+        }
+    }
+    Ok(None)
+}
@@ -0,0 +1,245 @@
+use std::io::{Read, Seek, SeekFrom};
+use std::sync::Arc;
+
+use iocore::Path;
+
+use crate::file::validate_chunk;
+use crate::index::{ParsedIndex, UnfoldIndex};
+use crate::manifest::ManifestIndex;
+use crate::merkle::{MerkleProof, MerkleTree};
+use crate::store::{parse_store_index, ChunkStore, DirStore};
+use crate::{Error, Result};
+
+/// [`Read`] + [`Seek`] view of the original file of an unfolded file
+/// which only reads, and verifies, the chunks covering the bytes being
+/// read. The whole-file checksum is not verified since that would
+/// require reading every chunk. Given a trusted merkle root with
+/// [`with_merkle_root`](FoldedReader::with_merkle_root), each chunk is
+/// also proven to belong to the file with that root. The sub-indexes
+/// of a hierarchical manifest are only read when the chunks they list
+/// are. Clones share the store and the index and have their own
+/// position.
+#[derive(Debug)]
+pub struct FoldedReader<S: ChunkStore = DirStore> {
+    store: Arc<S>,
+    index: Arc<UnfoldIndex>,
+    manifest: Option<Arc<ManifestIndex>>,
+    /// trusted merkle root along with the tree over the index, or over
+    /// the subtree roots of the sub-indexes of a hierarchical manifest
+    merkle: Option<(Vec<u8>, Arc<MerkleTree>)>,
+    /// last sub-index read along with its position
+    subindex: Option<(usize, Arc<Vec<String>>)>,
+    position: u64,
+    chunk_index: Option<usize>,
+    chunk: Vec<u8>,
+}
+
+impl FoldedReader<DirStore> {
+    /// opens the unfolded folder at `path`
+    pub fn open(path: &Path) -> Result<FoldedReader<DirStore>> {
+        FoldedReader::from_store(DirStore::new(path))
+    }
+}
+
+impl<S: ChunkStore> FoldedReader<S> {
+    pub fn from_store(store: S) -> Result<FoldedReader<S>> {
+        let (index, manifest) = match parse_store_index(&store)? {
+            ParsedIndex::Index(..) => (store.read_index()?, None),
+            ParsedIndex::Manifest(manifest) =>
+                (manifest.header().clone(), Some(Arc::new(manifest))),
+        };
+        Ok(FoldedReader {
+            store: Arc::new(store),
+            index: Arc::new(index),
+            manifest,
+            merkle: None,
+            subindex: None,
+            position: 0,
+            chunk_index: None,
+            chunk: Vec::new(),
+        })
+    }
+
+    /// verifies every chunk read against the trusted merkle `root`,
+    /// e.g. one published along with the file, with a proof of its
+    /// inclusion rather than against the index alone
+    pub fn with_merkle_root(self, root: &[u8]) -> FoldedReader<S> {
+        let tree = match &self.manifest {
+            Some(manifest) => manifest.subtree(),
+            None => MerkleTree::new(self.index.chunks()),
+        };
+        let tree = Arc::new(tree);
+        FoldedReader {
+            merkle: Some((root.to_vec(), tree)),
+            chunk_index: None,
+            chunk: Vec::new(),
+            ..self
+        }
+    }
+
+    /// the index of the file, without any chunks for hierarchical
+    /// manifests, see [`manifest`](FoldedReader::manifest)
+    pub fn index(&self) -> &UnfoldIndex {
+        &self.index
+    }
+
+    /// the top-level index of a hierarchical manifest
+    pub fn manifest(&self) -> Option<&ManifestIndex> {
+        self.manifest.as_deref()
+    }
+
+    pub fn store(&self) -> &S {
+        &self.store
+    }
+
+    pub fn size(&self) -> u64 {
+        self.index.size()
+    }
+
+    /// proof that the chunk at position `idx` in the index belongs to
+    /// the file with the merkle root of the index, reading only the
+    /// sub-index listing it for hierarchical manifests
+    pub fn proof(&self, idx: usize) -> Result<MerkleProof> {
+        match (&self.merkle, &self.manifest) {
+            (_, Some(manifest)) => {
+                let position = manifest.subindex_at(idx);
+                match (&self.subindex, &self.merkle) {
+                    (Some((cached, chunks)), Some((_, tree)))
+                        if *cached == position =>
+                        manifest.subindex_proof(tree, position, chunks, idx),
+                    _ => manifest.proof(self.store.as_ref(), idx),
+                }
+            },
+            (Some((_, tree)), None) => tree.proof(idx),
+            (None, None) => MerkleProof::new(&self.index, idx),
+        }
+    }
+
+    pub fn chunk_count(&self) -> usize {
+        match &self.manifest {
+            Some(manifest) => manifest.chunk_count(),
+            None => self.index.chunk_count(),
+        }
+    }
+
+    /// sha256 of the chunk at position `idx` in the index, reading the
+    /// sub-index listing it for hierarchical manifests
+    fn chunk_sha256(&mut self, idx: usize) -> Result<String> {
+        if idx >= self.chunk_count() {
+            return Err(Error::InvalidIndexError(format!(
+                "chunk {idx} out of bounds in {}",
+                self.store.location()
+            )));
+        }
+        let Some(manifest) = &self.manifest else {
+            return Ok(self.index.chunks()[idx].clone());
+        };
+        let position = manifest.subindex_at(idx);
+        let chunks = match &self.subindex {
+            Some((cached, chunks)) if *cached == position => chunks.clone(),
+            _ => {
+                let chunks = Arc::new(
+                    manifest.read_subindex(self.store.as_ref(), position)?,
+                );
+                self.subindex = Some((position, chunks.clone()));
+                chunks
+            },
+        };
+        Ok(chunks[idx - manifest.subindex_chunks(position).start].clone())
+    }
+
+    /// reads and verifies the chunk at position `idx` in the index,
+    /// keeping the last one read in memory
+    pub fn read_chunk(&mut self, idx: usize) -> Result<&[u8]> {
+        if self.chunk_index != Some(idx) {
+            let location = self.store.location();
+            let sha256 = self.chunk_sha256(idx)?;
+            let name = self.index.layout().chunk_name(
+                idx,
+                self.chunk_count(),
+                &sha256,
+            );
+            let bytes = if self.index.is_zero(idx) {
+                let range = self.index.chunk_range(idx);
+                vec![0; (range.end - range.start) as usize]
+            } else {
+                self.store.get(&name)?
+            };
+            validate_chunk(&sha256, &bytes).map_err(|error| {
+                Error::CorruptedDataError(format!("in {location}: {error}"))
+            })?;
+            let expected = self.index.chunk_range(idx);
+            if bytes.len() as u64 != expected.end - expected.start {
+                return Err(Error::CorruptedDataError(format!(
+                    "chunk {name} in {location} has {} bytes, expected {}",
+                    bytes.len(),
+                    expected.end - expected.start
+                )));
+            }
+            if let Some((root, _)) = &self.merkle {
+                self.proof(idx)?
+                    .verify(&bytes, root)
+                    .map_err(|error| {
+                        Error::ProofError(format!("in {location}: {error}"))
+                    })?;
+            }
+            self.chunk_index = Some(idx);
+            self.chunk = bytes;
+        }
+        Ok(&self.chunk)
+    }
+}
+
+impl<S: ChunkStore> Clone for FoldedReader<S> {
+    fn clone(&self) -> FoldedReader<S> {
+        FoldedReader {
+            store: self.store.clone(),
+            index: self.index.clone(),
+            manifest: self.manifest.clone(),
+            merkle: self.merkle.clone(),
+            subindex: self.subindex.clone(),
+            position: self.position,
+            chunk_index: None,
+            chunk: Vec::new(),
+        }
+    }
+}
+
+impl<S: ChunkStore> Read for FoldedReader<S> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        if buf.is_empty() || self.position >= self.size() {
+            return Ok(0);
+        }
+        let idx = self.index.chunk_index_at(self.position);
+        let start = self.index.chunk_range(idx).start;
+        let offset = (self.position - start) as usize;
+        let chunk = self
+            .read_chunk(idx)
+            .map_err(std::io::Error::other)?;
+        let count = buf.len().min(chunk.len() - offset);
+        buf[..count].copy_from_slice(&chunk[offset..offset + count]);
+        self.position += count as u64;
+        Ok(count)
+    }
+}
+
+impl<S: ChunkStore> Seek for FoldedReader<S> {
+    fn seek(&mut self, pos: SeekFrom) -> std::io::Result<u64> {
+        let position = match pos {
+            SeekFrom::Start(offset) => Some(offset),
+            SeekFrom::End(offset) => self.size().checked_add_signed(offset),
+            SeekFrom::Current(offset) =>
+                self.position.checked_add_signed(offset),
+        };
+        match position {
+            Some(position) => {
+                self.position = position;
+                Ok(position)
+            },
+            None => Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                "invalid seek to a negative or overflowing position",
+            )),
+        }
+    }
+}
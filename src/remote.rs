@@ -0,0 +1,268 @@
+use std::collections::BTreeMap;
+use std::io::{Seek, SeekFrom};
+use std::net::TcpListener;
+use std::ops::Range;
+
+use iocore::Path;
+
+use crate::file::validate_chunk;
+use crate::http::{self, Request, Response};
+use crate::index::{
+    is_fanout_folder, ChunkLayout, ChunkNaming, ParsedIndex, UnfoldIndex,
+    INDEX_NAME,
+};
+use crate::reader::FoldedReader;
+use crate::store::{ChunkStore, DirStore};
+use crate::{Action, Error, Progress, Result};
+
+/// serves the unfolded folders inside `store` over HTTP until the
+/// process exits:
+///
+/// - `GET /` lists the names of the unfolded folders, one per line
+/// - `GET /<name>` and `GET /<name>/index` return the index of `<name>`
+/// - `GET /<name>/<chunk>` returns the bytes of a chunk of `<name>`
+pub fn serve_store(store: &Path, listener: TcpListener) -> Result<()> {
+    if !store.is_dir() {
+        return Err(Error::ServeError(format!("{store} is not a directory")));
+    }
+    let store = store.clone();
+    http::serve(listener, move |request| handle_store_request(&store, request))
+}
+
+fn handle_store_request(store: &Path, request: &Request) -> Response {
+    let segments = request.segments();
+    match segments.as_slice() {
+        [] => match list_manifests(store) {
+            Ok(names) => Response::text(200, names.join("\n")),
+            Err(error) => Response::text(500, error),
+        },
+        [name] | [name, INDEX_NAME] => match manifest_path(store, name) {
+            Some(path) => file_response(&path.join(INDEX_NAME), "text/yaml"),
+            None => Response::text(404, format!("no manifest named {name}")),
+        },
+        [name, chunk @ ..] => {
+            let chunk = chunk.join("/");
+            match manifest_path(store, name) {
+                Some(path) if is_chunk_name(&chunk) => file_response(
+                    &path.join(&chunk),
+                    "application/octet-stream",
+                ),
+                Some(_) =>
+                    Response::text(400, format!("invalid chunk name {chunk}")),
+                None =>
+                    Response::text(404, format!("no manifest named {name}")),
+            }
+        },
+    }
+}
+
+fn list_manifests(store: &Path) -> Result<Vec<String>> {
+    let mut names = store
+        .list()?
+        .into_iter()
+        .filter(|path| path.join(INDEX_NAME).is_file())
+        .map(|path| path.name())
+        .collect::<Vec<String>>();
+    names.sort();
+    Ok(names)
+}
+
+fn manifest_path(store: &Path, name: &str) -> Option<Path> {
+    if name.starts_with('.') || name.contains('\\') {
+        return None;
+    }
+    let path = store.join(name);
+    if path.join(INDEX_NAME).is_file() {
+        Some(path)
+    } else {
+        None
+    }
+}
+
+/// whether `name` is accepted by some [`ChunkNaming`], optionally
+/// nested in fan-out folders, e.g.: `ab/cd/abcd…`
+fn is_chunk_name(name: &str) -> bool {
+    let components = name.split('/').collect::<Vec<&str>>();
+    let (name, folders) = components.split_last().unwrap_or((&"", &[]));
+    folders.len() <= ChunkLayout::MAX_FANOUT
+        && folders
+            .iter()
+            .all(|folder| is_fanout_folder(folder))
+        && ChunkNaming::ALL
+            .iter()
+            .any(|naming| naming.accepts(name))
+}
+
+fn file_response(path: &Path, content_type: &str) -> Response {
+    if !path.is_file() {
+        return Response::text(404, format!("{} not found", path.name()));
+    }
+    match path.read_bytes() {
+        Ok(bytes) =>
+            Response::new(200, bytes).with_header("Content-Type", content_type),
+        Err(error) => Response::text(500, error),
+    }
+}
+
+/// downloads the manifest at `url` (`http://host:port/<name>`) into the
+/// unfolded folder at `output_path`, fetching only the chunks that are
+/// not already present there and verifying each one against its name.
+/// The index is written last.
+pub fn pull<C: FnMut(Progress)>(
+    url: &str,
+    output_path: &Path,
+    mut progress: C,
+) -> Result<Path> {
+    if output_path.exists() && !output_path.is_dir() {
+        return Err(Error::PullError(format!(
+            "{output_path} is not a directory"
+        )));
+    }
+    let url = url.trim_end_matches('/');
+    progress(Progress::Start(Action::Pull));
+    let response = fetch(&format!("{url}/{INDEX_NAME}"))?;
+    let index_bytes = response.into_bytes()?;
+    let store = DirStore::new(&output_path.mkdir()?);
+    let index = match UnfoldIndex::decode(&index_bytes, url)? {
+        ParsedIndex::Index(index, _) => index,
+        // the sub-indexes come first as they list the chunks to fetch
+        ParsedIndex::Manifest(manifest) => {
+            for (idx, sha256) in manifest.subindexes().iter().enumerate() {
+                let name = manifest.subindex_name(idx);
+                if store.has(&name)? {
+                    continue;
+                }
+                let subindex_url = format!("{url}/{name}");
+                let bytes = fetch(&subindex_url)?.into_bytes()?;
+                validate_chunk(sha256, &bytes).map_err(|error| {
+                    Error::CorruptedDataError(format!(
+                        "from {subindex_url}: {error}"
+                    ))
+                })?;
+                store.put(&name, &bytes)?;
+            }
+            manifest.resolve(&store)?
+        },
+    };
+    let mut missing = BTreeMap::<String, String>::new();
+    for (name, sha256) in index.chunk_files()? {
+        if !store.has(&name)? {
+            missing.insert(name, sha256);
+        }
+    }
+    let chunk_count = missing.len();
+    for (idx, (name, sha256)) in missing.iter().enumerate() {
+        let chunk_url = format!("{url}/{name}");
+        let bytes = fetch(&chunk_url)?.into_bytes()?;
+        validate_chunk(sha256, &bytes).map_err(|error| {
+            Error::CorruptedDataError(format!("from {chunk_url}: {error}"))
+        })?;
+        store.put(name, &bytes)?;
+        progress(Progress::Chunk {
+            index: idx + 1,
+            count: chunk_count,
+            action: Action::Pull,
+        });
+    }
+    store.put_index(&index_bytes)?;
+    progress(Progress::End(Action::Pull));
+    Ok(output_path.clone())
+}
+
+fn fetch(url: &str) -> Result<Response> {
+    let response = http::get(url, &[])?;
+    if response.status != 200 {
+        let status = response.status;
+        let message = String::from_utf8_lossy(&response.into_bytes()?)
+            .trim()
+            .to_string();
+        return Err(Error::PullError(format!(
+            "GET {url} returned {status}: {message}"
+        )));
+    }
+    Ok(response)
+}
+
+/// serves the original file of the unfolded folder at `input_path`
+/// over HTTP until the process exits, on any path, with its whole-file
+/// sha256 as `ETag` and support for single `Range` requests. Only the
+/// chunks covering the requested range are read, and the file is never
+/// written to disk.
+pub fn serve_file(input_path: &Path, listener: TcpListener) -> Result<()> {
+    let reader = FoldedReader::open(input_path)?;
+    http::serve(listener, move |request| {
+        handle_file_request(reader.clone(), request)
+    })
+}
+
+fn handle_file_request(
+    mut reader: FoldedReader,
+    request: &Request,
+) -> Response {
+    let size = reader.size();
+    let etag = format!("\"{}\"", hex::encode(reader.index().sha256()));
+    if request.header("If-None-Match") == Some(etag.as_str()) {
+        return Response::new(304, Vec::new()).with_header("ETag", etag);
+    }
+    let range = match request.header("Range") {
+        Some(range)
+            if request
+                .header("If-Range")
+                .is_none_or(|tag| tag == etag) =>
+            match parse_range(range, size) {
+                Some(Ok(range)) => Some(range),
+                Some(Err(())) =>
+                    return Response::text(
+                        416,
+                        format!("invalid range {range}"),
+                    )
+                    .with_header("Content-Range", format!("bytes */{size}")),
+                None => None,
+            },
+        _ => None,
+    };
+    let (status, range) = match range {
+        Some(range) => (206, range),
+        None => (200, 0..size),
+    };
+    if let Err(error) = reader.seek(SeekFrom::Start(range.start)) {
+        return Response::text(500, error);
+    }
+    let response = Response::stream(status, reader, range.end - range.start)
+        .with_header("Content-Type", "application/octet-stream")
+        .with_header("Accept-Ranges", "bytes")
+        .with_header("ETag", etag);
+    if status == 206 {
+        response.with_header(
+            "Content-Range",
+            format!("bytes {}-{}/{size}", range.start, range.end - 1),
+        )
+    } else {
+        response
+    }
+}
+
+/// parses a `Range` header value for a resource of `size` bytes into a
+/// byte range. Returns `None` for values that should be ignored, such as
+/// other units or multiple ranges, and `Some(Err(()))` for ranges that
+/// cannot be satisfied.
+fn parse_range(
+    value: &str,
+    size: u64,
+) -> Option<std::result::Result<Range<u64>, ()>> {
+    let spec = value.trim().strip_prefix("bytes=")?;
+    if spec.contains(',') {
+        return None;
+    }
+    let (start, end) = spec.split_once('-')?;
+    let (start, end) = (start.trim(), end.trim());
+    let range = match (start.parse::<u64>(), end.parse::<u64>()) {
+        (Ok(start), Ok(end)) if start <= end && start < size =>
+            start..end.saturating_add(1).min(size),
+        (Ok(start), Err(_)) if end.is_empty() && start < size => start..size,
+        (Err(_), Ok(suffix)) if start.is_empty() && suffix > 0 && size > 0 =>
+            size - suffix.min(size)..size,
+        _ => return Some(Err(())),
+    };
+    Some(Ok(range))
+}
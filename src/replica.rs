@@ -0,0 +1,263 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::sync::RwLock;
+
+use crate::file::validate_chunk;
+use crate::index::UnfoldIndex;
+use crate::maintenance::verify_store;
+use crate::store::{open_store, parse_store_index, ChunkStore};
+use crate::{Error, Progress, Result};
+
+/// [`ChunkStore`] mirroring every chunk and the index across several
+/// replicas. Writes go to every replica, while reads come from the
+/// first replica holding a healthy copy: chunks are checked against the
+/// sha256 the index records for them, and every unreadable or corrupted
+/// copy skipped along the way is kept, with the location of its
+/// replica, for [`ReplicaStore::problems`].
+pub struct ReplicaStore {
+    replicas: Vec<Box<dyn ChunkStore>>,
+    expected: RwLock<Option<BTreeMap<String, String>>>,
+    problems: RwLock<Vec<String>>,
+}
+
+impl ReplicaStore {
+    pub fn new(replicas: Vec<Box<dyn ChunkStore>>) -> Result<ReplicaStore> {
+        if replicas.is_empty() {
+            return Err(Error::ReplicaError("no replicas given".to_string()));
+        }
+        let mut locations = BTreeSet::<String>::new();
+        for replica in &replicas {
+            let location = replica.location();
+            if !locations.insert(location.clone()) {
+                return Err(Error::ReplicaError(format!(
+                    "{location} is given more than once"
+                )));
+            }
+        }
+        Ok(ReplicaStore {
+            replicas,
+            expected: RwLock::new(None),
+            problems: RwLock::new(Vec::new()),
+        })
+    }
+
+    /// opens every location with [`open_store`]
+    pub fn open(locations: &[String]) -> Result<ReplicaStore> {
+        ReplicaStore::new(
+            locations
+                .iter()
+                .map(|location| open_store(location))
+                .collect::<Result<Vec<Box<dyn ChunkStore>>>>()?,
+        )
+    }
+
+    pub fn replicas(&self) -> &[Box<dyn ChunkStore>] {
+        &self.replicas
+    }
+
+    /// unreadable or corrupted copies skipped since the store was
+    /// opened, each along with the location of its replica
+    pub fn problems(&self) -> Vec<String> {
+        self.problems
+            .read()
+            .map(|problems| problems.clone())
+            .unwrap_or_default()
+    }
+
+    fn report(&self, problem: String) {
+        if let Ok(mut problems) = self.problems.write() {
+            problems.push(problem);
+        }
+    }
+
+    /// sha256 of the chunk stored under `name` according to the index
+    fn expected(&self, name: &str) -> Option<String> {
+        if let Some(expected) = self.expected.read().ok()?.as_ref() {
+            return expected.get(name).cloned();
+        }
+        let expected = self
+            .read_index()
+            .and_then(|index| index.chunk_files())
+            .unwrap_or_default();
+        let sha256 = expected.get(name).cloned();
+        *self.expected.write().ok()? = Some(expected);
+        sha256
+    }
+
+    fn invalidate_expected(&self) {
+        if let Ok(mut expected) = self.expected.write() {
+            *expected = None;
+        }
+    }
+}
+
+impl ChunkStore for ReplicaStore {
+    fn put(&self, name: &str, bytes: &[u8]) -> Result<()> {
+        for replica in &self.replicas {
+            replica.put(name, bytes)?;
+        }
+        Ok(())
+    }
+
+    fn get(&self, name: &str) -> Result<Vec<u8>> {
+        let expected = self.expected(name);
+        let mut problems = Vec::<String>::new();
+        for replica in &self.replicas {
+            let location = replica.location();
+            let result = replica.get(name).and_then(|bytes| {
+                if let Some(sha256) = &expected {
+                    validate_chunk(sha256, &bytes)?;
+                }
+                Ok(bytes)
+            });
+            match result {
+                Ok(bytes) => return Ok(bytes),
+                Err(error) => {
+                    let problem = format!("replica {location}: {error}");
+                    self.report(problem.clone());
+                    problems.push(problem);
+                },
+            }
+        }
+        Err(Error::CorruptedDataError(format!(
+            "no healthy replica holds chunk '{name}': {}",
+            problems.join("; ")
+        )))
+    }
+
+    fn has(&self, name: &str) -> Result<bool> {
+        for replica in &self.replicas {
+            if replica.has(name)? {
+                return Ok(true);
+            }
+        }
+        Ok(false)
+    }
+
+    fn list(&self) -> Result<Vec<String>> {
+        let mut names = BTreeSet::<String>::new();
+        for replica in &self.replicas {
+            names.extend(replica.list()?);
+        }
+        Ok(names.into_iter().collect())
+    }
+
+    fn delete(&self, name: &str) -> Result<()> {
+        for replica in &self.replicas {
+            if replica.has(name)? {
+                replica.delete(name)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn put_index(&self, bytes: &[u8]) -> Result<()> {
+        self.invalidate_expected();
+        for replica in &self.replicas {
+            replica.put_index(bytes)?;
+        }
+        Ok(())
+    }
+
+    /// returns the index of the first replica holding a parseable one
+    fn get_index(&self) -> Result<Vec<u8>> {
+        let mut last_error = None;
+        for replica in &self.replicas {
+            match parse_store_index(replica.as_ref()) {
+                Ok(_) => return replica.get_index(),
+                Err(error) => {
+                    self.report(format!(
+                        "replica {}: {error}",
+                        replica.location()
+                    ));
+                    last_error = Some(error);
+                },
+            }
+        }
+        Err(last_error.unwrap_or_else(|| {
+            Error::MissingIndexError("no replicas given".to_string())
+        }))
+    }
+
+    /// returns the index of the first replica holding a readable one,
+    /// resolving the sub-indexes of a hierarchical manifest from that
+    /// replica alone
+    fn read_index(&self) -> Result<UnfoldIndex> {
+        let mut last_error = None;
+        for replica in &self.replicas {
+            match replica.read_index() {
+                Ok(index) => return Ok(index),
+                Err(error) => {
+                    self.report(format!(
+                        "replica {}: {error}",
+                        replica.location()
+                    ));
+                    last_error = Some(error);
+                },
+            }
+        }
+        Err(last_error.unwrap_or_else(|| {
+            Error::MissingIndexError("no replicas given".to_string())
+        }))
+    }
+
+    fn location(&self) -> String {
+        format!(
+            "replicas {}",
+            self.replicas
+                .iter()
+                .map(|replica| replica.location())
+                .collect::<Vec<String>>()
+                .join(", ")
+        )
+    }
+}
+
+/// verifies every replica of `store` on its own and checks that their
+/// indexes agree, failing with every replica which is broken or
+/// disagrees with the first healthy one. Returns the index the
+/// replicas agree on.
+pub fn verify_replicas<C: FnMut(Progress)>(
+    store: &ReplicaStore,
+    mut progress: C,
+) -> Result<UnfoldIndex> {
+    let mut problems = Vec::<String>::new();
+    let mut agreed: Option<(String, UnfoldIndex)> = None;
+    for replica in store.replicas() {
+        let location = replica.location();
+        let index = match verify_store(replica.as_ref(), &mut progress) {
+            Ok(index) => index,
+            Err(error) => {
+                problems.push(format!("replica {location}: {error}"));
+                continue;
+            },
+        };
+        match &agreed {
+            None => agreed = Some((location, index)),
+            Some((first, first_index)) =>
+                if *first_index != index {
+                    problems.push(format!(
+                        "replica {location} disagrees with replica {first}: {}",
+                        describe_difference(first_index, &index)
+                    ));
+                },
+        }
+    }
+    match agreed {
+        Some((_, index)) if problems.is_empty() => Ok(index),
+        _ => Err(Error::ReplicaError(problems.join("; "))),
+    }
+}
+
+fn describe_difference(a: &UnfoldIndex, b: &UnfoldIndex) -> String {
+    if a.sha256() != b.sha256() {
+        return format!(
+            "whole-file sha256 {} instead of {}",
+            hex::encode(b.sha256()),
+            hex::encode(a.sha256())
+        );
+    }
+    if a.layout() != b.layout() {
+        return format!("{} instead of {}", b.layout(), a.layout());
+    }
+    "indexes differ".to_string()
+}
@@ -0,0 +1,666 @@
+use std::fmt::{Display, Formatter};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use hmac::{Hmac, Mac};
+use sha2::{Digest, Sha256};
+
+use crate::index::{is_fanout_folder, ChunkLayout, ChunkNaming, INDEX_NAME};
+use crate::store::ChunkStore;
+use crate::{Error, Result};
+
+const DEFAULT_REGION: &str = "us-east-1";
+const DEFAULT_MULTIPART_THRESHOLD: usize = 16 * 1024 * 1024;
+const DEFAULT_PART_SIZE: usize = 8 * 1024 * 1024;
+
+/// Endpoint, region and credentials of an S3-compatible service.
+#[derive(Clone, PartialEq, Eq)]
+pub struct S3Config {
+    /// base url of the service, e.g.: `https://s3.us-east-1.amazonaws.com`
+    /// or `http://127.0.0.1:9000`
+    pub endpoint: String,
+    pub region: String,
+    pub access_key: String,
+    pub secret_key: String,
+    pub session_token: Option<String>,
+}
+
+impl S3Config {
+    /// reads the configuration from the environment:
+    ///
+    /// - `UNFOLDER_S3_ENDPOINT`, `AWS_ENDPOINT_URL_S3` or
+    ///   `AWS_ENDPOINT_URL`, defaulting to the AWS endpoint of the region
+    /// - `AWS_REGION` or `AWS_DEFAULT_REGION`, defaulting to `us-east-1`
+    /// - `AWS_ACCESS_KEY_ID`, `AWS_SECRET_ACCESS_KEY` and optionally
+    ///   `AWS_SESSION_TOKEN`
+    pub fn from_env() -> Result<S3Config> {
+        let region = env(&["AWS_REGION", "AWS_DEFAULT_REGION"])
+            .unwrap_or_else(|| DEFAULT_REGION.to_string());
+        let endpoint = env(&[
+            "UNFOLDER_S3_ENDPOINT",
+            "AWS_ENDPOINT_URL_S3",
+            "AWS_ENDPOINT_URL",
+        ])
+        .unwrap_or_else(|| format!("https://s3.{region}.amazonaws.com"));
+        let access_key = env(&["AWS_ACCESS_KEY_ID"]).ok_or_else(|| {
+            Error::StoreError("AWS_ACCESS_KEY_ID is not set".to_string())
+        })?;
+        let secret_key = env(&["AWS_SECRET_ACCESS_KEY"]).ok_or_else(|| {
+            Error::StoreError("AWS_SECRET_ACCESS_KEY is not set".to_string())
+        })?;
+        Ok(S3Config {
+            endpoint,
+            region,
+            access_key,
+            secret_key,
+            session_token: env(&["AWS_SESSION_TOKEN"]),
+        })
+    }
+}
+
+impl std::fmt::Debug for S3Config {
+    fn fmt(&self, f: &mut Formatter) -> std::fmt::Result {
+        f.debug_struct("S3Config")
+            .field("endpoint", &self.endpoint)
+            .field("region", &self.region)
+            .field("access_key", &self.access_key)
+            .finish_non_exhaustive()
+    }
+}
+
+fn env(names: &[&str]) -> Option<String> {
+    names
+        .iter()
+        .filter_map(|name| std::env::var(name).ok())
+        .find(|value| !value.is_empty())
+}
+
+/// [`ChunkStore`] keeping the index and chunks as objects of an
+/// S3-compatible bucket: `<prefix>/index` and `<prefix>/<chunk>`,
+/// addressed path-style (`<endpoint>/<bucket>/<key>`).
+///
+/// Since chunk names are the sha256 of their contents, chunks already
+/// present in the bucket are not uploaded again. Objects larger than
+/// the multipart threshold are uploaded in parts and only become
+/// visible once every part was uploaded; failed uploads are aborted.
+#[derive(Debug, Clone)]
+pub struct S3Store {
+    config: S3Config,
+    bucket: String,
+    prefix: String,
+    host: String,
+    multipart_threshold: usize,
+    part_size: usize,
+    agent: ureq::Agent,
+}
+
+impl S3Store {
+    /// opens `url` (`s3://bucket/prefix`) with the given configuration
+    pub fn new(url: &str, config: S3Config) -> Result<S3Store> {
+        let path = url.strip_prefix("s3://").ok_or_else(|| {
+            Error::StoreError(format!("'{url}' is not an s3:// url"))
+        })?;
+        let (bucket, prefix) = path.split_once('/').unwrap_or((path, ""));
+        if bucket.is_empty() {
+            return Err(Error::StoreError(format!("no bucket in '{url}'")));
+        }
+        let endpoint = config.endpoint.trim_end_matches('/');
+        let host = endpoint
+            .strip_prefix("https://")
+            .or_else(|| endpoint.strip_prefix("http://"))
+            .and_then(|rest| rest.split('/').next())
+            .filter(|host| !host.is_empty())
+            .ok_or_else(|| {
+                Error::StoreError(format!(
+                    "invalid s3 endpoint '{}'",
+                    config.endpoint
+                ))
+            })?
+            .to_string();
+        let agent = ureq::Agent::config_builder()
+            .http_status_as_error(false)
+            .build()
+            .new_agent();
+        Ok(S3Store {
+            config: S3Config {
+                endpoint: endpoint.to_string(),
+                ..config
+            },
+            bucket: bucket.to_string(),
+            prefix: prefix.trim_matches('/').to_string(),
+            host,
+            multipart_threshold: DEFAULT_MULTIPART_THRESHOLD,
+            part_size: DEFAULT_PART_SIZE,
+            agent,
+        })
+    }
+
+    /// opens `url` (`s3://bucket/prefix`) configured from the
+    /// environment, see [`S3Config::from_env`]
+    pub fn open(url: &str) -> Result<S3Store> {
+        S3Store::new(url, S3Config::from_env()?)
+    }
+
+    /// uploads objects larger than `threshold` bytes in parts of
+    /// `part_size` bytes. S3 requires every part but the last to be at
+    /// least 5 MiB.
+    pub fn with_multipart(
+        mut self,
+        threshold: usize,
+        part_size: usize,
+    ) -> S3Store {
+        self.multipart_threshold = threshold;
+        self.part_size = part_size.max(1);
+        self
+    }
+
+    pub fn bucket(&self) -> &str {
+        &self.bucket
+    }
+
+    pub fn prefix(&self) -> &str {
+        &self.prefix
+    }
+
+    fn key(&self, name: &str) -> String {
+        if self.prefix.is_empty() {
+            name.to_string()
+        } else {
+            format!("{}/{name}", self.prefix)
+        }
+    }
+
+    fn put_object(&self, key: &str, bytes: &[u8]) -> Result<()> {
+        if bytes.len() > self.multipart_threshold {
+            return self.put_multipart(key, bytes);
+        }
+        self.request("PUT", key, &[], bytes)?
+            .expect(200, "PUT", key)?;
+        Ok(())
+    }
+
+    fn put_multipart(&self, key: &str, bytes: &[u8]) -> Result<()> {
+        let response = self
+            .request("POST", key, &[("uploads", "")], &[])?
+            .expect(200, "POST", key)?;
+        let upload_id = xml_values(&response.text(), "UploadId")
+            .into_iter()
+            .next()
+            .ok_or_else(|| {
+                Error::StoreError(format!(
+                    "no UploadId in response to multipart upload of {}",
+                    self.describe(key)
+                ))
+            })?;
+        match self.upload_parts(key, &upload_id, bytes) {
+            Ok(()) => Ok(()),
+            Err(error) => {
+                let _ = self.request(
+                    "DELETE",
+                    key,
+                    &[("uploadId", &upload_id)],
+                    &[],
+                );
+                Err(error)
+            },
+        }
+    }
+
+    fn upload_parts(
+        &self,
+        key: &str,
+        upload_id: &str,
+        bytes: &[u8],
+    ) -> Result<()> {
+        let mut completion = String::from("<CompleteMultipartUpload>");
+        for (idx, part) in bytes.chunks(self.part_size).enumerate() {
+            let number = (idx + 1).to_string();
+            let response = self
+                .request(
+                    "PUT",
+                    key,
+                    &[
+                        ("partNumber", &number),
+                        ("uploadId", upload_id),
+                    ],
+                    part,
+                )?
+                .expect(200, "PUT", key)?;
+            let etag = response.header("etag").ok_or_else(|| {
+                Error::StoreError(format!(
+                    "no ETag for part {number} of {}",
+                    self.describe(key)
+                ))
+            })?;
+            completion.push_str(&format!(
+                "<Part><PartNumber>{number}</PartNumber><ETag>{}</ETag></Part>",
+                xml_escape(etag)
+            ));
+        }
+        completion.push_str("</CompleteMultipartUpload>");
+        let response = self
+            .request(
+                "POST",
+                key,
+                &[("uploadId", upload_id)],
+                completion.as_bytes(),
+            )?
+            .expect(200, "POST", key)?;
+        // errors completing an upload may come with a 200 status
+        if let Some(error) = xml_values(&response.text(), "Error").first() {
+            return Err(Error::StoreError(format!(
+                "failed to complete multipart upload of {}: {error}",
+                self.describe(key)
+            )));
+        }
+        Ok(())
+    }
+
+    fn get_object(&self, key: &str) -> Result<Option<Vec<u8>>> {
+        let response = self.request("GET", key, &[], &[])?;
+        if response.status == 404 {
+            return Ok(None);
+        }
+        Ok(Some(response.expect(200, "GET", key)?.body))
+    }
+
+    fn describe(&self, key: &str) -> String {
+        format!("s3://{}/{key}", self.bucket)
+    }
+
+    /// sends a request signed with AWS Signature Version 4 for `key`,
+    /// or for the bucket itself if `key` is empty
+    fn request(
+        &self,
+        method: &str,
+        key: &str,
+        query: &[(&str, &str)],
+        body: &[u8],
+    ) -> Result<S3Response> {
+        let endpoint_path = self
+            .config
+            .endpoint
+            .split_once("://")
+            .and_then(|(_, rest)| rest.find('/').map(|pos| &rest[pos..]))
+            .unwrap_or_default();
+        let mut path =
+            format!("{endpoint_path}/{}", uri_encode(&self.bucket, false));
+        if !key.is_empty() {
+            path.push('/');
+            path.push_str(&uri_encode(key, false));
+        }
+        let mut query = query
+            .iter()
+            .map(|(name, value)| {
+                (uri_encode(name, true), uri_encode(value, true))
+            })
+            .collect::<Vec<(String, String)>>();
+        query.sort();
+        let query = query
+            .iter()
+            .map(|(name, value)| format!("{name}={value}"))
+            .collect::<Vec<String>>()
+            .join("&");
+
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|duration| duration.as_secs())
+            .unwrap_or_default();
+        let timestamp = amz_timestamp(now);
+        let date = &timestamp[..8];
+        let payload_hash = hex::encode(Sha256::digest(body));
+        let mut headers = vec![
+            ("host", self.host.clone()),
+            ("x-amz-content-sha256", payload_hash.clone()),
+            ("x-amz-date", timestamp.clone()),
+        ];
+        if let Some(token) = &self.config.session_token {
+            headers.push(("x-amz-security-token", token.clone()));
+        }
+        let signed_headers = headers
+            .iter()
+            .map(|(name, _)| *name)
+            .collect::<Vec<&str>>()
+            .join(";");
+        let canonical_headers = headers
+            .iter()
+            .map(|(name, value)| format!("{name}:{}\n", value.trim()))
+            .collect::<String>();
+        let canonical_request = format!(
+            "{method}\n{path}\n{query}\n{canonical_headers}\n{signed_headers}\n{payload_hash}"
+        );
+        let scope = format!("{date}/{}/s3/aws4_request", self.config.region);
+        let string_to_sign = format!(
+            "AWS4-HMAC-SHA256\n{timestamp}\n{scope}\n{}",
+            hex::encode(Sha256::digest(canonical_request.as_bytes()))
+        );
+        let mut key_bytes = hmac_sha256(
+            format!("AWS4{}", self.config.secret_key).as_bytes(),
+            date,
+        );
+        for part in [
+            self.config.region.as_str(),
+            "s3",
+            "aws4_request",
+        ] {
+            key_bytes = hmac_sha256(&key_bytes, part);
+        }
+        let signature = hex::encode(hmac_sha256(&key_bytes, &string_to_sign));
+        let authorization = format!(
+            "AWS4-HMAC-SHA256 Credential={}/{scope}, SignedHeaders={signed_headers}, Signature={signature}",
+            self.config.access_key
+        );
+
+        let mut url =
+            format!("{}{}", self.config.endpoint, &path[endpoint_path.len()..]);
+        if !query.is_empty() {
+            url.push('?');
+            url.push_str(&query);
+        }
+        let mut request = ureq::http::Request::builder()
+            .method(method)
+            .uri(&url)
+            .header("Authorization", authorization);
+        for (name, value) in &headers {
+            request = request.header(*name, value);
+        }
+        if method == "PUT" || method == "POST" {
+            request = request.header("Content-Length", body.len());
+        }
+        let request = request.body(body).map_err(|error| {
+            Error::StoreError(format!(
+                "invalid request {method} {url}: {error}"
+            ))
+        })?;
+        let response = self.agent.run(request).map_err(|error| {
+            Error::StoreError(format!("{method} {url} failed: {error}"))
+        })?;
+        let status = response.status().as_u16();
+        let response_headers = response
+            .headers()
+            .iter()
+            .map(|(name, value)| {
+                (
+                    name.as_str().to_lowercase(),
+                    String::from_utf8_lossy(value.as_bytes()).to_string(),
+                )
+            })
+            .collect::<Vec<(String, String)>>();
+        let body = if method == "HEAD" {
+            Vec::new()
+        } else {
+            response
+                .into_body()
+                .into_with_config()
+                .limit(u64::MAX)
+                .read_to_vec()
+                .map_err(|error| {
+                    Error::StoreError(format!(
+                        "failed to read response to {method} {url}: {error}"
+                    ))
+                })?
+        };
+        Ok(S3Response {
+            status,
+            headers: response_headers,
+            body,
+        })
+    }
+}
+
+impl Display for S3Store {
+    fn fmt(&self, f: &mut Formatter) -> std::fmt::Result {
+        if self.prefix.is_empty() {
+            write!(f, "s3://{}", self.bucket)
+        } else {
+            write!(f, "s3://{}/{}", self.bucket, self.prefix)
+        }
+    }
+}
+
+impl ChunkStore for S3Store {
+    /// uploads `bytes`, skipping the upload when `name` is a sha256 which
+    /// already exists, since that object can only hold the same bytes
+    fn put(&self, name: &str, bytes: &[u8]) -> Result<()> {
+        let content_addressed = name
+            .rsplit('/')
+            .next()
+            .is_some_and(|name| ChunkNaming::Hash.accepts(name));
+        if content_addressed && self.has(name)? {
+            return Ok(());
+        }
+        self.put_object(&self.key(name), bytes)
+    }
+
+    fn get(&self, name: &str) -> Result<Vec<u8>> {
+        let key = self.key(name);
+        self.get_object(&key)?.ok_or_else(|| {
+            Error::MissingChunkError(format!(
+                "{} does not exist",
+                self.describe(&key)
+            ))
+        })
+    }
+
+    fn has(&self, name: &str) -> Result<bool> {
+        let key = self.key(name);
+        let response = self.request("HEAD", &key, &[], &[])?;
+        if response.status == 404 {
+            return Ok(false);
+        }
+        response.expect(200, "HEAD", &key)?;
+        Ok(true)
+    }
+
+    fn list(&self) -> Result<Vec<String>> {
+        let prefix = self.key("");
+        let mut names = Vec::<String>::new();
+        let mut token: Option<String> = None;
+        loop {
+            let mut query = vec![
+                ("list-type", "2"),
+                ("prefix", prefix.as_str()),
+            ];
+            if let Some(token) = &token {
+                query.push(("continuation-token", token.as_str()));
+            }
+            let response = self
+                .request("GET", "", &query, &[])?
+                .expect(200, "GET", &prefix)?;
+            let xml = response.text();
+            names.extend(
+                xml_values(&xml, "Key")
+                    .into_iter()
+                    .filter_map(|key| {
+                        key.strip_prefix(&prefix).map(String::from)
+                    })
+                    .filter(|name| {
+                        !name.is_empty()
+                            && name != INDEX_NAME
+                            && !name.starts_with('.')
+                            && is_chunk_key(name)
+                    }),
+            );
+            token = match xml_values(&xml, "IsTruncated").first() {
+                Some(truncated) if truncated == "true" =>
+                    xml_values(&xml, "NextContinuationToken")
+                        .into_iter()
+                        .next(),
+                _ => None,
+            };
+            if token.is_none() {
+                break;
+            }
+        }
+        names.sort();
+        Ok(names)
+    }
+
+    fn delete(&self, name: &str) -> Result<()> {
+        let key = self.key(name);
+        let response = self.request("DELETE", &key, &[], &[])?;
+        if response.status != 404 {
+            response.expect(204, "DELETE", &key)?;
+        }
+        Ok(())
+    }
+
+    fn put_index(&self, bytes: &[u8]) -> Result<()> {
+        self.put_object(&self.key(INDEX_NAME), bytes)
+    }
+
+    fn get_index(&self) -> Result<Vec<u8>> {
+        let key = self.key(INDEX_NAME);
+        self.get_object(&key)?.ok_or_else(|| {
+            Error::MissingIndexError(format!(
+                "{} does not exist",
+                self.describe(&key)
+            ))
+        })
+    }
+
+    fn location(&self) -> String {
+        self.to_string()
+    }
+}
+
+struct S3Response {
+    status: u16,
+    headers: Vec<(String, String)>,
+    body: Vec<u8>,
+}
+
+impl S3Response {
+    fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(header, _)| header == name)
+            .map(|(_, value)| value.as_str())
+    }
+
+    fn text(&self) -> String {
+        String::from_utf8_lossy(&self.body).to_string()
+    }
+
+    /// fails with the status and the error message from the body
+    /// unless the status is `expected` or, for `DELETE`, `200`
+    fn expect(
+        self,
+        expected: u16,
+        method: &str,
+        key: &str,
+    ) -> Result<S3Response> {
+        if self.status == expected || (self.status == 200 && method == "DELETE")
+        {
+            return Ok(self);
+        }
+        let text = self.text();
+        let message = xml_values(&text, "Message")
+            .into_iter()
+            .next()
+            .unwrap_or_else(|| text.trim().to_string());
+        Err(Error::StoreError(format!(
+            "{method} {key} returned {}: {message}",
+            self.status
+        )))
+    }
+}
+
+fn hmac_sha256(key: &[u8], data: &str) -> Vec<u8> {
+    let mut mac = Hmac::<Sha256>::new_from_slice(key)
+        .expect("hmac accepts keys of any length");
+    mac.update(data.as_bytes());
+    mac.finalize().into_bytes().to_vec()
+}
+
+/// percent-encodes everything but unreserved characters, and `/`
+/// unless `encode_slash`, as required by Signature Version 4
+fn uri_encode(value: &str, encode_slash: bool) -> String {
+    let mut encoded = String::with_capacity(value.len());
+    for byte in value.bytes() {
+        match byte {
+            b'A'..=b'Z'
+            | b'a'..=b'z'
+            | b'0'..=b'9'
+            | b'-'
+            | b'.'
+            | b'_'
+            | b'~' => encoded.push(byte as char),
+            b'/' if !encode_slash => encoded.push('/'),
+            _ => encoded.push_str(&format!("%{byte:02X}")),
+        }
+    }
+    encoded
+}
+
+/// formats seconds since the unix epoch as `YYYYMMDDTHHMMSSZ`
+fn amz_timestamp(seconds: u64) -> String {
+    let days = (seconds / 86400) as i64;
+    let time = seconds % 86400;
+    // days to civil date, from Howard Hinnant's `civil_from_days`
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z - era * 146097;
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 {
+        mp + 3
+    } else {
+        mp - 9
+    };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+    format!(
+        "{year:04}{month:02}{day:02}T{:02}{:02}{:02}Z",
+        time / 3600,
+        time % 3600 / 60,
+        time % 60
+    )
+}
+
+/// contents of every `<tag>` element of `xml`, unescaped
+fn xml_values(xml: &str, tag: &str) -> Vec<String> {
+    let open = format!("<{tag}>");
+    let close = format!("</{tag}>");
+    let mut values = Vec::<String>::new();
+    let mut rest = xml;
+    while let Some(start) = rest.find(&open) {
+        rest = &rest[start + open.len()..];
+        match rest.find(&close) {
+            Some(end) => {
+                values.push(xml_unescape(&rest[..end]));
+                rest = &rest[end + close.len()..];
+            },
+            None => break,
+        }
+    }
+    values
+}
+
+fn xml_escape(value: &str) -> String {
+    value
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+fn xml_unescape(value: &str) -> String {
+    value
+        .replace("&quot;", "\"")
+        .replace("&apos;", "'")
+        .replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&amp;", "&")
+}
+
+/// whether `name` is either a plain name or a name nested in at most
+/// [`ChunkLayout::MAX_FANOUT`] fan-out folders, e.g.: `ab/cd/abcd…`
+fn is_chunk_key(name: &str) -> bool {
+    let folders = name.split('/').collect::<Vec<&str>>();
+    let (_, folders) = folders.split_last().unwrap_or((&"", &[]));
+    folders.len() <= ChunkLayout::MAX_FANOUT
+        && folders
+            .iter()
+            .all(|folder| is_fanout_folder(folder))
+}
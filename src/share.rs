@@ -0,0 +1,447 @@
+use std::collections::BTreeMap;
+
+use iocore::{Path, Size};
+
+use crate::cancel::CancelToken;
+use crate::file::{
+    checksum, fold_chunks, index_chunks, read_unfold_index, validate_checksum,
+    write_chunks, CHUNK_SIZE, MAX_FILE_SIZE,
+};
+use crate::index::ChunkLayout;
+use crate::store::{ChunkStore, DirStore};
+use crate::{Action, Error, Progress, Result};
+
+/// name of the label [`unfold_to_shares`] writes next to the index of
+/// every share folder
+pub const SHARE_NAME: &str = "share";
+
+/// length of the random identifier telling apart the shares of
+/// different splits
+const SET_SIZE: usize = 16;
+
+/// Contents of the `share` file [`unfold_to_shares`] writes next to the
+/// index of every share folder: which share it is, how many shares were
+/// written, how many are needed to fold the file back and the
+/// identifier of the split the share belongs to.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ShareLabel {
+    number: u8,
+    shares: u8,
+    threshold: u8,
+    set: Vec<u8>,
+}
+
+impl ShareLabel {
+    /// reads the label of the share folder at `path`
+    pub fn read(path: &Path) -> Result<ShareLabel> {
+        let label_path = path.join(SHARE_NAME);
+        if !label_path.is_file() {
+            return Err(Error::ShareError(format!(
+                "{path} is not a share: '{label_path}' is not a readable file"
+            )));
+        }
+        ShareLabel::parse(&label_path.read()?, &label_path)
+    }
+
+    /// parses the yaml of a label. `origin` is only used in error
+    /// messages.
+    pub fn parse(
+        yaml: &str,
+        origin: impl std::fmt::Display,
+    ) -> Result<ShareLabel> {
+        let mut fields = serde_yaml::from_str::<BTreeMap<String, String>>(yaml)
+            .map_err(|error| {
+                Error::ShareError(format!(
+                    "invalid yaml in '{origin}': {error}"
+                ))
+            })?;
+        let mut field = |name: &str| {
+            let value = fields.remove(name).ok_or_else(|| {
+                Error::ShareError(format!(
+                    "missing '{name}' field in '{origin}'"
+                ))
+            })?;
+            Ok::<(String, String), Error>((name.to_string(), value))
+        };
+        let invalid = |(name, value): (String, String)| {
+            Error::ShareError(format!(
+                "invalid '{name}' field '{value}' in '{origin}'"
+            ))
+        };
+        let parse_u8 = |field: (String, String)| {
+            field
+                .1
+                .parse::<u8>()
+                .map_err(|_| invalid(field.clone()))
+        };
+        let set = field("set")?;
+        let set = hex::decode(&set.1)
+            .ok()
+            .filter(|set| set.len() == SET_SIZE)
+            .ok_or_else(|| invalid(set))?;
+        let shares = field("shares")?;
+        let threshold = field("threshold")?;
+        let number = field("share")?;
+        let label = ShareLabel {
+            number: parse_u8(number.clone())?,
+            shares: parse_u8(shares.clone())?,
+            threshold: parse_u8(threshold.clone())?,
+            set,
+        };
+        if label.threshold < 2 || label.threshold > label.shares {
+            return Err(invalid(threshold));
+        }
+        if label.number < 1 || label.number > label.shares {
+            return Err(invalid(number));
+        }
+        if let Some(name) = fields.keys().next() {
+            return Err(Error::ShareError(format!(
+                "unexpected '{name}' field in '{origin}'"
+            )));
+        }
+        Ok(label)
+    }
+
+    pub fn to_yaml(&self) -> Result<String> {
+        let mut fields = BTreeMap::<String, String>::new();
+        fields.insert("share".to_string(), self.number.to_string());
+        fields.insert("shares".to_string(), self.shares.to_string());
+        fields.insert("threshold".to_string(), self.threshold.to_string());
+        fields.insert("set".to_string(), hex::encode(&self.set));
+        serde_yaml::to_string(&fields).map_err(|error| {
+            Error::ShareError(format!(
+                "failed to serialize share label as yaml: {error}"
+            ))
+        })
+    }
+
+    /// position of the share, starting at 1, which is also the point
+    /// the share evaluates the secret polynomials at
+    pub fn number(&self) -> u8 {
+        self.number
+    }
+
+    /// how many shares were written
+    pub fn shares(&self) -> u8 {
+        self.shares
+    }
+
+    /// how many shares are needed to fold the file back
+    pub fn threshold(&self) -> u8 {
+        self.threshold
+    }
+
+    /// random identifier of the split the share belongs to
+    pub fn set(&self) -> &[u8] {
+        &self.set
+    }
+}
+
+/// splits the file at `input_path` into `shares` unfolded folders named
+/// `share-1`, `share-2`, … under `output_path` with Shamir's secret
+/// sharing over GF(256), such that any `threshold` of them fold the
+/// file back while fewer reveal nothing about it but its size. The
+/// whole-file sha256 is split along with the file so that it can be
+/// verified once folded without being readable from any share. Returns
+/// the paths of the share folders.
+pub fn unfold_to_shares<C: FnMut(Progress)>(
+    input_path: &Path,
+    output_path: &Path,
+    shares: u8,
+    threshold: u8,
+    progress: C,
+) -> Result<Vec<Path>> {
+    let cancel = CancelToken::new();
+    unfold_to_shares_with_cancel(
+        input_path,
+        output_path,
+        shares,
+        threshold,
+        &cancel,
+        progress,
+    )
+}
+
+/// splits the file at `input_path` into shares under `output_path` like
+/// [`unfold_to_shares`] until `cancel` is cancelled, removing then
+/// `output_path` unless `cancel` keeps partial output
+pub fn unfold_to_shares_with_cancel<C: FnMut(Progress)>(
+    input_path: &Path,
+    output_path: &Path,
+    shares: u8,
+    threshold: u8,
+    cancel: &CancelToken,
+    mut progress: C,
+) -> Result<Vec<Path>> {
+    if !input_path.is_file() {
+        return Err(Error::FlattenFileInputError(format!(
+            "{input_path} is not a file"
+        )));
+    }
+    let size = input_path.file_size();
+    if size.as_u64() > MAX_FILE_SIZE - 32 {
+        let max_file_size = Size::from(MAX_FILE_SIZE - 32);
+        return Err(Error::FlattenFileInputError(format!(
+            "{input_path} is too large {size} (max = {max_file_size})"
+        )));
+    }
+    if threshold < 2 || threshold > shares {
+        return Err(Error::ShareError(format!(
+            "threshold {threshold} must be between 2 and the {shares} shares"
+        )));
+    }
+    if output_path.exists() {
+        return Err(Error::FlattenFileOutputError(format!(
+            "{output_path} already exists"
+        )));
+    }
+    progress(Progress::Start(Action::Unfold));
+    let mut secret = input_path.read_bytes()?;
+    secret.extend(checksum(&secret));
+    let mut set = vec![0u8; SET_SIZE];
+    random_bytes(&mut set)?;
+    let mut share_bytes =
+        vec![Vec::<u8>::with_capacity(secret.len()); shares as usize];
+    let mut coefficients = vec![0u8; CHUNK_SIZE * (threshold as usize - 1)];
+    for chunk in secret.chunks(CHUNK_SIZE) {
+        cancel.check()?;
+        random_bytes(&mut coefficients)?;
+        for (idx, share) in share_bytes.iter_mut().enumerate() {
+            let x = idx as u8 + 1;
+            share.extend(chunk.iter().enumerate().map(|(offset, byte)| {
+                let mut y = 0u8;
+                for degree in (0..threshold as usize - 1).rev() {
+                    let coefficient =
+                        coefficients[degree * CHUNK_SIZE + offset];
+                    y = gf_mul(y, x) ^ coefficient;
+                }
+                gf_mul(y, x) ^ byte
+            }));
+        }
+    }
+    let mut paths = Vec::<Path>::with_capacity(shares as usize);
+    let mut write = || {
+        for (idx, bytes) in share_bytes.iter().enumerate() {
+            let path = output_path.join(format!("share-{}", idx + 1));
+            let store = DirStore::new(&path).reserving(SHARE_NAME);
+            let index =
+                index_chunks(bytes, None, ChunkLayout::default(), 1, cancel)?;
+            write_chunks(
+                bytes,
+                &index,
+                &store,
+                Action::Unfold,
+                1,
+                cancel,
+                &mut progress,
+            )?;
+            let label = ShareLabel {
+                number: idx as u8 + 1,
+                shares,
+                threshold,
+                set: set.clone(),
+            };
+            path.join(SHARE_NAME)
+                .write(label.to_yaml()?.as_bytes())?;
+            store.write_index(&index)?;
+            paths.push(path);
+        }
+        Ok(())
+    };
+    let result = write();
+    cancel.roll_back(result, output_path)?;
+    progress(Progress::End(Action::Unfold));
+    Ok(paths)
+}
+
+/// folds the file split by [`unfold_to_shares`] back into `output_path`
+/// from the share folders at `share_paths`, given in any order. Shares
+/// are used in the given order until enough of them are read, skipping
+/// shares which are duplicated or fail to verify, and the folded file is
+/// verified against its whole-file sha256. Returns the labels of the
+/// shares used.
+pub fn fold_from_shares<C: FnMut(Progress)>(
+    share_paths: &[Path],
+    output_path: &Path,
+    progress: C,
+) -> Result<Vec<ShareLabel>> {
+    let cancel = CancelToken::new();
+    fold_from_shares_with_cancel(share_paths, output_path, &cancel, progress)
+}
+
+/// folds the shares at `share_paths` into `output_path` like
+/// [`fold_from_shares`] until `cancel` is cancelled. The file is only
+/// written once every share is read, so that a cancelled fold leaves
+/// nothing behind.
+pub fn fold_from_shares_with_cancel<C: FnMut(Progress)>(
+    share_paths: &[Path],
+    output_path: &Path,
+    cancel: &CancelToken,
+    mut progress: C,
+) -> Result<Vec<ShareLabel>> {
+    if output_path.exists() {
+        return Err(Error::UnflattenFileOutputError(format!(
+            "{output_path} already exists"
+        )));
+    }
+    let Some(first) = share_paths.first() else {
+        return Err(Error::ShareError("no shares to fold".to_string()));
+    };
+    let expected = ShareLabel::read(first)?;
+    let threshold = expected.threshold as usize;
+    progress(Progress::Start(Action::Fold));
+    let mut used = Vec::<(ShareLabel, Vec<u8>)>::with_capacity(threshold);
+    let mut problems = Vec::<String>::new();
+    for path in share_paths {
+        if used.len() == threshold {
+            break;
+        }
+        let label = ShareLabel::read(path)?;
+        if label.set != expected.set
+            || label.shares != expected.shares
+            || label.threshold != expected.threshold
+        {
+            return Err(Error::ShareError(format!(
+                "{path} is share {} of another split than {first}",
+                label.number
+            )));
+        }
+        if used
+            .iter()
+            .any(|(other, _)| other.number == label.number)
+        {
+            continue;
+        }
+        let store = DirStore::new(path).reserving(SHARE_NAME);
+        let bytes = read_unfold_index(&store).and_then(|index| {
+            fold_chunks(&store, &index, Action::Fold, 1, cancel, &mut progress)
+        });
+        match bytes {
+            Ok(bytes) => used.push((label, bytes)),
+            Err(Error::Cancelled(message)) =>
+                return Err(Error::Cancelled(message)),
+            Err(error) => problems
+                .push(format!("share {} at {path}: {error}", label.number)),
+        }
+    }
+    if used.len() < threshold {
+        let mut message = format!(
+            "{} of {} shares are needed, only {} could be read",
+            threshold,
+            expected.shares,
+            used.len()
+        );
+        if !problems.is_empty() {
+            message.push_str(&format!(": {}", problems.join("; ")));
+        }
+        return Err(Error::ShareError(message));
+    }
+    let size = used[0].1.len();
+    if let Some((label, bytes)) =
+        used.iter().find(|(_, bytes)| bytes.len() != size)
+    {
+        return Err(Error::ShareError(format!(
+            "share {} holds {} bytes, expected {size}",
+            label.number,
+            bytes.len()
+        )));
+    }
+    if size < 32 {
+        return Err(Error::ShareError(format!(
+            "shares hold {size} bytes, expected at least 32"
+        )));
+    }
+    let xs = used
+        .iter()
+        .map(|(label, _)| label.number)
+        .collect::<Vec<u8>>();
+    let weights = xs
+        .iter()
+        .map(|x| {
+            let (numerator, denominator) = xs
+                .iter()
+                .filter(|other| *other != x)
+                .fold((1u8, 1u8), |(numerator, denominator), other| {
+                    (gf_mul(numerator, *other), gf_mul(denominator, other ^ x))
+                });
+            gf_mul(numerator, gf_inv(denominator))
+        })
+        .collect::<Vec<u8>>();
+    let mut secret = vec![0u8; size];
+    for ((_, bytes), weight) in used.iter().zip(&weights) {
+        for (byte, y) in secret.iter_mut().zip(bytes) {
+            *byte ^= gf_mul(*y, *weight);
+        }
+    }
+    let sha256 = secret.split_off(size - 32);
+    let labels = used
+        .into_iter()
+        .map(|(label, _)| label)
+        .collect::<Vec<ShareLabel>>();
+    validate_checksum(&secret, &sha256).map_err(|error| {
+        Error::CorruptedDataError(format!(
+            "file folded from shares {} does not match its sha256: {error}",
+            share_numbers(&labels)
+        ))
+    })?;
+    cancel.check()?;
+    output_path.mkdir_parents()?.write(&secret)?;
+    progress(Progress::End(Action::Fold));
+    Ok(labels)
+}
+
+/// numbers of `labels` as `1, 3 and 4`
+pub fn share_numbers(labels: &[ShareLabel]) -> String {
+    let numbers = labels
+        .iter()
+        .map(|label| label.number.to_string())
+        .collect::<Vec<String>>();
+    match numbers.split_last() {
+        Some((last, rest)) if !rest.is_empty() =>
+            format!("{} and {last}", rest.join(", ")),
+        _ => numbers.join(""),
+    }
+}
+
+fn random_bytes(bytes: &mut [u8]) -> Result<()> {
+    getrandom::getrandom(bytes).map_err(|error| {
+        Error::ShareError(format!("failed to read random bytes: {error}"))
+    })
+}
+
+/// exponents and logarithms of GF(256) with the AES polynomial and
+/// generator 3, exponents being doubled to skip reducing sums of
+/// logarithms
+const GF_TABLES: ([u8; 512], [u8; 256]) = {
+    let mut exp = [0u8; 512];
+    let mut log = [0u8; 256];
+    let mut value = 1u8;
+    let mut idx = 0;
+    while idx < 255 {
+        exp[idx] = value;
+        exp[idx + 255] = value;
+        log[value as usize] = idx as u8;
+        let doubled = value << 1
+            ^ if value & 0x80 != 0 {
+                0x1B
+            } else {
+                0
+            };
+        value ^= doubled;
+        idx += 1;
+    }
+    (exp, log)
+};
+
+fn gf_mul(a: u8, b: u8) -> u8 {
+    if a == 0 || b == 0 {
+        return 0;
+    }
+    let (exp, log) = &GF_TABLES;
+    exp[log[a as usize] as usize + log[b as usize] as usize]
+}
+
+fn gf_inv(a: u8) -> u8 {
+    let (exp, log) = &GF_TABLES;
+    exp[255 - log[a as usize] as usize]
+}
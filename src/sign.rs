@@ -0,0 +1,264 @@
+use std::collections::BTreeMap;
+use std::io::Write;
+use std::os::unix::fs::OpenOptionsExt;
+
+use ed25519_dalek::{Signature, Signer, SigningKey, VerifyingKey};
+use iocore::Path;
+
+use crate::cancel::CancelToken;
+use crate::file::{fold_into, read_unfold_index};
+use crate::index::UnfoldIndex;
+use crate::maintenance::verify_index;
+use crate::store::ChunkStore;
+use crate::{Action, Error, Progress, Result};
+
+/// prefix of the message signed by [`sign_store`], so that index
+/// signatures cannot be mistaken for signatures over anything else
+const SIGNATURE_CONTEXT: &[u8] = b"unfolder index signature v1\n";
+
+/// Ed25519 signature over every other field of an index, recorded in
+/// its `signer` and `signature` fields along with the public key it was
+/// made with.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IndexSignature {
+    signer: VerifyingKey,
+    signature: Signature,
+}
+
+impl IndexSignature {
+    /// public key of the signer
+    pub fn signer(&self) -> &VerifyingKey {
+        &self.signer
+    }
+
+    pub fn signature(&self) -> &Signature {
+        &self.signature
+    }
+
+    /// parses the `signer` and `signature` fields of an index, which
+    /// are either both present or both absent
+    pub(crate) fn from_fields(
+        signer: Option<String>,
+        signature: Option<String>,
+    ) -> std::result::Result<Option<IndexSignature>, String> {
+        let (signer, signature) = match (signer, signature) {
+            (None, None) => return Ok(None),
+            (Some(signer), Some(signature)) => (signer, signature),
+            (Some(_), None) => return Err("missing 'signature' field".into()),
+            (None, Some(_)) => return Err("missing 'signer' field".into()),
+        };
+        let signer = hex::decode(&signer)
+            .ok()
+            .and_then(|bytes| bytes.try_into().ok())
+            .and_then(|bytes| VerifyingKey::from_bytes(&bytes).ok())
+            .ok_or_else(|| format!("invalid 'signer' field '{signer}'"))?;
+        let signature = hex::decode(&signature)
+            .ok()
+            .and_then(|bytes| Signature::from_slice(&bytes).ok())
+            .ok_or_else(|| {
+                format!("invalid 'signature' field '{signature}'")
+            })?;
+        Ok(Some(IndexSignature { signer, signature }))
+    }
+
+    pub(crate) fn to_fields(&self) -> BTreeMap<String, String> {
+        BTreeMap::from([
+            ("signer".to_string(), hex::encode(self.signer.as_bytes())),
+            ("signature".to_string(), hex::encode(self.signature.to_bytes())),
+        ])
+    }
+}
+
+/// message signed for `index`: every field of its yaml representation
+/// but the signature itself
+fn signed_message(index: &UnfoldIndex) -> Result<Vec<u8>> {
+    let yaml = index.clone().with_signature(None).to_yaml()?;
+    Ok([SIGNATURE_CONTEXT, yaml.as_bytes()].concat())
+}
+
+/// generates a new Ed25519 key pair, writing the hex encoded secret key
+/// to `secret_key_path`, readable by its owner only, and the public key
+/// next to it with a `.pub` extension. Returns the path of the public
+/// key.
+pub fn generate_key(secret_key_path: &Path) -> Result<Path> {
+    let public_key_path = Path::new(format!("{secret_key_path}.pub").as_str());
+    for path in [secret_key_path, &public_key_path] {
+        if path.exists() {
+            return Err(Error::SignatureError(format!(
+                "{path} already exists"
+            )));
+        }
+    }
+    let mut seed = [0u8; 32];
+    getrandom::getrandom(&mut seed).map_err(|error| {
+        Error::SignatureError(format!("failed to read random bytes: {error}"))
+    })?;
+    let key = SigningKey::from_bytes(&seed);
+    // created readable by its owner only, rather than chmodded after
+    // the key is already on disk
+    let mut secret_key_file = std::fs::OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .mode(0o600)
+        .open(secret_key_path.mkdir_parents()?.to_string())?;
+    writeln!(secret_key_file, "{}", hex::encode(key.to_bytes()))?;
+    secret_key_file.sync_all()?;
+    public_key_path.write(
+        format!("{}\n", hex::encode(key.verifying_key().as_bytes())).as_bytes(),
+    )?;
+    Ok(public_key_path)
+}
+
+/// reads the secret key written by [`generate_key`] at `path`
+pub fn read_signing_key(path: &Path) -> Result<SigningKey> {
+    Ok(SigningKey::from_bytes(&read_key_bytes(path, "secret")?))
+}
+
+/// reads the public key written by [`generate_key`] at `path`
+pub fn read_verifying_key(path: &Path) -> Result<VerifyingKey> {
+    VerifyingKey::from_bytes(&read_key_bytes(path, "public")?).map_err(
+        |error| {
+            Error::SignatureError(format!(
+                "invalid public key in {path}: {error}"
+            ))
+        },
+    )
+}
+
+fn read_key_bytes(path: &Path, kind: &str) -> Result<[u8; 32]> {
+    if !path.is_file() {
+        return Err(Error::SignatureError(format!(
+            "{kind} key {path} is not a readable file"
+        )));
+    }
+    let contents = path.read_bytes()?;
+    hex::decode(contents.trim_ascii())
+        .ok()
+        .and_then(|bytes| bytes.try_into().ok())
+        .ok_or_else(|| {
+            Error::SignatureError(format!(
+                "{path} does not hold a hex encoded Ed25519 {kind} key"
+            ))
+        })
+}
+
+/// signs the index of `store` with `key`, replacing any previous
+/// signature, and returns the signed index
+pub fn sign_store<S: ChunkStore + ?Sized>(
+    store: &S,
+    key: &SigningKey,
+) -> Result<UnfoldIndex> {
+    let index = read_unfold_index(store)?;
+    let signature = key.sign(&signed_message(&index)?);
+    let index = index.with_signature(Some(IndexSignature {
+        signer: key.verifying_key(),
+        signature,
+    }));
+    store.write_index(&index)?;
+    Ok(index)
+}
+
+/// checks that `index` is signed by one of `trusted_keys` and that the
+/// signature matches its contents, returning the signer. Since the
+/// index records the sha256 of every chunk and of the whole file, a
+/// valid signature covers the chunks as well.
+pub fn check_signature<'a>(
+    index: &'a UnfoldIndex,
+    trusted_keys: &[VerifyingKey],
+) -> Result<&'a VerifyingKey> {
+    let Some(signature) = index.signature() else {
+        return Err(Error::SignatureError(
+            "the index is not signed".to_string(),
+        ));
+    };
+    let signer = signature.signer();
+    if !trusted_keys.contains(signer) {
+        return Err(Error::SignatureError(format!(
+            "the index is signed by {}, which is not a trusted key",
+            hex::encode(signer.as_bytes())
+        )));
+    }
+    signer
+        .verify_strict(&signed_message(index)?, signature.signature())
+        .map_err(|error| {
+            Error::SignatureError(format!(
+                "invalid signature by {}, the index was altered after signing: {error}",
+                hex::encode(signer.as_bytes())
+            ))
+        })?;
+    Ok(signer)
+}
+
+/// like [`verify_store`](crate::verify_store), failing unless the index
+/// of `store` is validly signed by one of `trusted_keys`
+pub fn verify_signed_store<S: ChunkStore + ?Sized, C: FnMut(Progress)>(
+    store: &S,
+    trusted_keys: &[VerifyingKey],
+    mut progress: C,
+) -> Result<UnfoldIndex> {
+    progress(Progress::Start(Action::Verify));
+    let index = read_unfold_index(store)?;
+    check_signature(&index, trusted_keys)
+        .map_err(|error| signature_error_at(error, &store.location()))?;
+    verify_index(store, &index, Action::Verify, &mut progress)?;
+    progress(Progress::End(Action::Verify));
+    Ok(index)
+}
+
+/// like [`fold_from_store`](crate::fold_from_store), refusing to write
+/// anything unless the index of `store` is validly signed by one of
+/// `trusted_keys`
+pub fn fold_signed<S: ChunkStore + ?Sized, C: FnMut(Progress)>(
+    store: &S,
+    output_path: &Path,
+    trusted_keys: &[VerifyingKey],
+    progress: C,
+) -> Result<Path> {
+    let cancel = CancelToken::new();
+    fold_signed_with_cancel(
+        store,
+        output_path,
+        trusted_keys,
+        1,
+        &cancel,
+        progress,
+    )
+}
+
+/// folds `store` into `output_path` like [`fold_signed`], reading and
+/// verifying chunks across `jobs` threads, 0 for one per cpu, until
+/// `cancel` is cancelled, removing the file then unless `cancel` keeps
+/// partial output
+pub fn fold_signed_with_cancel<S, C>(
+    store: &S,
+    output_path: &Path,
+    trusted_keys: &[VerifyingKey],
+    jobs: usize,
+    cancel: &CancelToken,
+    mut progress: C,
+) -> Result<Path>
+where
+    S: ChunkStore + ?Sized,
+    C: FnMut(Progress),
+{
+    if output_path.exists() {
+        return Err(Error::UnflattenFileOutputError(format!(
+            "{output_path} already exists"
+        )));
+    }
+    progress(Progress::Start(Action::Fold));
+    let index = read_unfold_index(store)?;
+    check_signature(&index, trusted_keys)
+        .map_err(|error| signature_error_at(error, &store.location()))?;
+    fold_into(store, &index, output_path, jobs, cancel, &mut progress)?;
+    progress(Progress::End(Action::Fold));
+    Ok(output_path.clone())
+}
+
+fn signature_error_at(error: Error, location: &str) -> Error {
+    match error {
+        Error::SignatureError(message) =>
+            Error::SignatureError(format!("in {location}: {message}")),
+        error => error,
+    }
+}
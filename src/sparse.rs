@@ -0,0 +1,127 @@
+use std::fs::File;
+use std::io::{Seek, SeekFrom, Write};
+use std::ops::Range;
+
+use iocore::Path;
+
+use crate::file::CHUNK_SIZE;
+use crate::Result;
+
+/// byte ranges of the holes of the file at `path` as reported by
+/// `SEEK_HOLE` and `SEEK_DATA`, or none where the filesystem does not
+/// report them
+#[cfg(target_os = "linux")]
+pub(crate) fn holes(path: &Path) -> Result<Vec<Range<u64>>> {
+    use std::os::fd::AsRawFd;
+
+    let file = File::open(path)?;
+    let size = file.metadata()?.len();
+    let fd = file.as_raw_fd();
+    let seek = |offset: u64, whence: libc::c_int| {
+        // SAFETY: `fd` stays open as long as `file`, and lseek only
+        // moves its offset
+        let offset = unsafe { libc::lseek(fd, offset as libc::off_t, whence) };
+        u64::try_from(offset).ok()
+    };
+    let mut holes = Vec::<Range<u64>>::new();
+    let mut offset = 0;
+    while offset < size {
+        let Some(start) = seek(offset, libc::SEEK_HOLE) else {
+            break;
+        };
+        if start >= size {
+            break;
+        }
+        // no data past a hole which runs to the end of the file
+        let end = seek(start, libc::SEEK_DATA)
+            .unwrap_or(size)
+            .min(size);
+        holes.push(start..end);
+        offset = end;
+    }
+    Ok(holes)
+}
+
+#[cfg(not(target_os = "linux"))]
+pub(crate) fn holes(_path: &Path) -> Result<Vec<Range<u64>>> {
+    Ok(Vec::new())
+}
+
+/// whether `chunk`, which starts at `start` in its file, is all zeros,
+/// taking it to be so without looking at it when within one of `holes`,
+/// which are in order and apart
+pub(crate) fn is_zero_chunk(
+    chunk: &[u8],
+    start: u64,
+    holes: &[Range<u64>],
+) -> bool {
+    let end = start + chunk.len() as u64;
+    let next = holes.partition_point(|hole| hole.end <= start);
+    let in_hole = holes
+        .get(next)
+        .is_some_and(|hole| hole.start <= start && end <= hole.end);
+    in_hole || chunk.iter().all(|byte| *byte == 0)
+}
+
+/// ranges of positions of the chunks of `bytes` which are all zeros,
+/// see [`is_zero_chunk`]
+pub(crate) fn zero_extents(
+    bytes: &[u8],
+    holes: &[Range<u64>],
+) -> Vec<Range<usize>> {
+    let mut extents = Vec::<Range<usize>>::new();
+    for (idx, chunk) in bytes.chunks(CHUNK_SIZE).enumerate() {
+        if is_zero_chunk(chunk, (idx * CHUNK_SIZE) as u64, holes) {
+            push_zero(&mut extents, idx);
+        }
+    }
+    extents
+}
+
+/// adds the chunk at position `idx`, past every chunk in `extents`, to
+/// the ranges of positions of zero chunks
+pub(crate) fn push_zero(extents: &mut Vec<Range<usize>>, idx: usize) {
+    match extents.last_mut() {
+        Some(extent) if extent.end == idx => extent.end = idx + 1,
+        _ => extents.push(idx..idx + 1),
+    }
+}
+
+/// file written a chunk at a time, seeking over zero chunks rather than
+/// writing them so that they become holes on filesystems which support
+/// sparse files
+pub(crate) struct SparseFile {
+    file: File,
+    size: u64,
+}
+
+impl SparseFile {
+    /// creates the file at `path` along with its parent folders
+    pub(crate) fn create(path: &Path) -> Result<SparseFile> {
+        let file = File::create(path.mkdir_parents()?)?;
+        Ok(SparseFile { file, size: 0 })
+    }
+
+    /// appends `chunk`, leaving a hole for it when `zero`
+    pub(crate) fn write_chunk(
+        &mut self,
+        chunk: &[u8],
+        zero: bool,
+    ) -> Result<()> {
+        if zero {
+            self.file
+                .seek(SeekFrom::Current(chunk.len() as i64))?;
+        } else {
+            self.file.write_all(chunk)?;
+        }
+        self.size += chunk.len() as u64;
+        Ok(())
+    }
+
+    /// extends the file over a trailing hole, which seeking alone does
+    /// not
+    pub(crate) fn finish(self) -> Result<()> {
+        self.file.set_len(self.size)?;
+        Ok(())
+    }
+}
@@ -0,0 +1,166 @@
+use std::fmt::{Display, Formatter};
+use std::sync::{Mutex, MutexGuard};
+
+use iocore::Path;
+use rusqlite::{params, Connection, OptionalExtension};
+
+use crate::store::ChunkStore;
+use crate::{Error, Result};
+
+const SCHEMA: &str = "
+PRAGMA journal_mode = WAL;
+PRAGMA synchronous = NORMAL;
+CREATE TABLE IF NOT EXISTS chunks (
+    name TEXT PRIMARY KEY NOT NULL,
+    bytes BLOB NOT NULL
+) WITHOUT ROWID;
+CREATE TABLE IF NOT EXISTS manifest (
+    id INTEGER PRIMARY KEY CHECK (id = 0),
+    bytes BLOB NOT NULL
+);
+";
+
+/// [`ChunkStore`] keeping the index and chunks as rows of a single
+/// SQLite database file, which avoids one file per chunk.
+#[derive(Debug)]
+pub struct SqliteStore {
+    path: Path,
+    connection: Mutex<Connection>,
+}
+
+impl SqliteStore {
+    /// opens the database at `path`, creating it if it does not exist
+    pub fn open(path: &Path) -> Result<SqliteStore> {
+        if path.exists() && !path.is_file() {
+            return Err(Error::StoreError(format!("{path} is not a file")));
+        }
+        if !path.exists() {
+            path.mkdir_parents()?;
+        }
+        let connection = Connection::open(path.to_string())
+            .and_then(|connection| {
+                connection.execute_batch(SCHEMA)?;
+                Ok(connection)
+            })
+            .map_err(|error| {
+                Error::StoreError(format!("failed to open {path}: {error}"))
+            })?;
+        Ok(SqliteStore {
+            path: path.clone(),
+            connection: Mutex::new(connection),
+        })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    fn connection(&self) -> Result<MutexGuard<'_, Connection>> {
+        self.connection.lock().map_err(|error| {
+            Error::StoreError(format!(
+                "poisoned connection to {}: {error}",
+                self.path
+            ))
+        })
+    }
+
+    fn error(&self, action: &str, error: rusqlite::Error) -> Error {
+        Error::StoreError(format!(
+            "failed to {action} in {}: {error}",
+            self.path
+        ))
+    }
+}
+
+impl Display for SqliteStore {
+    fn fmt(&self, f: &mut Formatter) -> std::fmt::Result {
+        write!(f, "sqlite://{}", self.path)
+    }
+}
+
+impl ChunkStore for SqliteStore {
+    fn put(&self, name: &str, bytes: &[u8]) -> Result<()> {
+        self.connection()?
+            .execute(
+                "INSERT OR REPLACE INTO chunks (name, bytes) VALUES (?1, ?2)",
+                params![name, bytes],
+            )
+            .map_err(|error| {
+                self.error(&format!("write chunk {name}"), error)
+            })?;
+        Ok(())
+    }
+
+    fn get(&self, name: &str) -> Result<Vec<u8>> {
+        self.connection()?
+            .query_row(
+                "SELECT bytes FROM chunks WHERE name = ?1",
+                params![name],
+                |row| row.get::<_, Vec<u8>>(0),
+            )
+            .optional()
+            .map_err(|error| self.error(&format!("read chunk {name}"), error))?
+            .ok_or_else(|| {
+                Error::MissingChunkError(format!("'{name}' is not in {self}"))
+            })
+    }
+
+    fn has(&self, name: &str) -> Result<bool> {
+        self.connection()?
+            .query_row(
+                "SELECT EXISTS (SELECT 1 FROM chunks WHERE name = ?1)",
+                params![name],
+                |row| row.get::<_, bool>(0),
+            )
+            .map_err(|error| {
+                self.error(&format!("look up chunk {name}"), error)
+            })
+    }
+
+    fn list(&self) -> Result<Vec<String>> {
+        let connection = self.connection()?;
+        let mut statement = connection
+            .prepare("SELECT name FROM chunks ORDER BY name")
+            .map_err(|error| self.error("list chunks", error))?;
+        let names = statement
+            .query_map([], |row| row.get::<_, String>(0))
+            .and_then(|rows| rows.collect::<rusqlite::Result<Vec<String>>>())
+            .map_err(|error| self.error("list chunks", error))?;
+        Ok(names)
+    }
+
+    fn delete(&self, name: &str) -> Result<()> {
+        self.connection()?
+            .execute("DELETE FROM chunks WHERE name = ?1", params![name])
+            .map_err(|error| {
+                self.error(&format!("delete chunk {name}"), error)
+            })?;
+        Ok(())
+    }
+
+    fn put_index(&self, bytes: &[u8]) -> Result<()> {
+        self.connection()?
+            .execute(
+                "INSERT OR REPLACE INTO manifest (id, bytes) VALUES (0, ?1)",
+                params![bytes],
+            )
+            .map_err(|error| self.error("write index", error))?;
+        Ok(())
+    }
+
+    fn get_index(&self) -> Result<Vec<u8>> {
+        self.connection()?
+            .query_row("SELECT bytes FROM manifest WHERE id = 0", [], |row| {
+                row.get::<_, Vec<u8>>(0)
+            })
+            .optional()
+            .map_err(|error| self.error("read index", error))?
+            .ok_or_else(|| {
+                Error::MissingIndexError(format!("{self} has no index"))
+            })
+    }
+
+    fn location(&self) -> String {
+        self.to_string()
+    }
+}
@@ -0,0 +1,345 @@
+use std::collections::BTreeMap;
+use std::fmt::{Debug, Display, Formatter};
+use std::sync::RwLock;
+
+use iocore::Path;
+
+use crate::file::CHUNK_SIZE;
+use crate::index::{
+    is_fanout_folder, ChunkLayout, ParsedIndex, UnfoldIndex, INDEX_NAME,
+};
+use crate::{Error, Result};
+
+/// Storage for the chunks and the index of one unfolded file.
+///
+/// Chunks are addressed by the names their [`ChunkLayout`] gives them:
+/// the hex encoded sha256 of their contents, possibly prefixed with
+/// their position, or their position alone, so that a name does not
+/// always determine its contents. Implementations are not expected to
+/// validate contents: [`fold_from_store`](crate::fold_from_store) and
+/// friends verify every chunk they read.
+pub trait ChunkStore: Send + Sync {
+    /// stores `bytes` under `name`, replacing any previous contents
+    fn put(&self, name: &str, bytes: &[u8]) -> Result<()>;
+
+    /// returns the contents stored under `name`
+    fn get(&self, name: &str) -> Result<Vec<u8>>;
+
+    fn has(&self, name: &str) -> Result<bool>;
+
+    /// names of every chunk in the store, excluding the index, sorted
+    fn list(&self) -> Result<Vec<String>>;
+
+    fn delete(&self, name: &str) -> Result<()>;
+
+    /// stores the serialized index
+    fn put_index(&self, bytes: &[u8]) -> Result<()>;
+
+    /// returns the serialized index, or
+    /// [`Error::MissingIndexError`] if there is none
+    fn get_index(&self) -> Result<Vec<u8>>;
+
+    /// human-readable location of the store used in messages
+    fn location(&self) -> String;
+
+    /// reads the whole index, reading every sub-index of a hierarchical
+    /// manifest in turn, see [`FoldedReader`](crate::FoldedReader) and
+    /// [`MerkleProof::read`](crate::MerkleProof::read) to only read the
+    /// sub-indexes listing given chunks
+    fn read_index(&self) -> Result<UnfoldIndex> {
+        let (index, size) = match parse_store_index(self)? {
+            ParsedIndex::Index(index, size) => (index, size),
+            ParsedIndex::Manifest(manifest) => return manifest.resolve(self),
+        };
+        if size.is_some() {
+            return Ok(index);
+        }
+        // indexes written before the `size` field existed
+        let count = index.chunk_count();
+        let last = self.get(&index.chunk_name(count - 1))?;
+        let size = ((count - 1) * CHUNK_SIZE + last.len()) as u64;
+        Ok(index.with_size(size))
+    }
+
+    fn write_index(&self, index: &UnfoldIndex) -> Result<()> {
+        self.put_index(&index.to_bytes()?)
+    }
+}
+
+/// reads and parses the index of `store`, leaving the sub-indexes of a
+/// hierarchical manifest unread
+pub(crate) fn parse_store_index<S: ChunkStore + ?Sized>(
+    store: &S,
+) -> Result<ParsedIndex> {
+    let bytes = store.get_index()?;
+    let origin = format!("{}/{INDEX_NAME}", store.location());
+    UnfoldIndex::decode(&bytes, &origin)
+}
+
+/// [`ChunkStore`] keeping the index and chunks as files in a folder:
+/// `<path>/index` and `<path>/<chunk>`, or `<path>/ab/cd/<chunk>` for
+/// chunks laid out with fan-out.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DirStore {
+    path: Path,
+    reserved: Vec<&'static str>,
+}
+
+impl DirStore {
+    pub fn new(path: &Path) -> DirStore {
+        DirStore {
+            path: path.clone(),
+            reserved: Vec::new(),
+        }
+    }
+
+    /// the same store keeping the file `name` at its root, such as the
+    /// label of a volume, out of its chunks
+    pub fn reserving(mut self, name: &'static str) -> DirStore {
+        self.reserved.push(name);
+        self
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn chunk_path(&self, name: &str) -> Path {
+        self.path.join(name)
+    }
+}
+
+impl Display for DirStore {
+    fn fmt(&self, f: &mut Formatter) -> std::fmt::Result {
+        write!(f, "{}", self.path)
+    }
+}
+
+impl ChunkStore for DirStore {
+    fn put(&self, name: &str, bytes: &[u8]) -> Result<()> {
+        let chunk_path = self.chunk_path(name);
+        chunk_path.write(bytes).map_err(|error| {
+            Error::StoreError(format!("failed to write {chunk_path}: {error}"))
+        })?;
+        Ok(())
+    }
+
+    fn get(&self, name: &str) -> Result<Vec<u8>> {
+        let chunk_path = self.chunk_path(name);
+        if !chunk_path.is_file() {
+            return Err(Error::MissingChunkError(format!(
+                "'{chunk_path}' is not a readable file"
+            )));
+        }
+        Ok(chunk_path.read_bytes()?)
+    }
+
+    fn has(&self, name: &str) -> Result<bool> {
+        Ok(self.chunk_path(name).is_file())
+    }
+
+    fn list(&self) -> Result<Vec<String>> {
+        if !self.path.is_dir() {
+            return Ok(Vec::new());
+        }
+        let mut names = Vec::<String>::new();
+        list_chunk_files(&self.path, "", 0, &mut names)?;
+        names.retain(|name| {
+            name != INDEX_NAME && !self.reserved.contains(&name.as_str())
+        });
+        names.sort();
+        Ok(names)
+    }
+
+    /// deletes the chunk along with the fan-out folders it leaves empty
+    fn delete(&self, name: &str) -> Result<()> {
+        let mut path = self.chunk_path(name).delete()?;
+        for _ in name.matches('/') {
+            path = path.parent().unwrap_or_else(|| self.path.clone());
+            if path == self.path || !path.list()?.is_empty() {
+                break;
+            }
+            path = path.delete()?;
+        }
+        Ok(())
+    }
+
+    fn put_index(&self, bytes: &[u8]) -> Result<()> {
+        let index_path = self.path.join(INDEX_NAME);
+        index_path.write(bytes).map_err(|error| {
+            Error::StoreError(format!("failed to write {index_path}: {error}"))
+        })?;
+        Ok(())
+    }
+
+    fn get_index(&self) -> Result<Vec<u8>> {
+        let index_path = self.path.join(INDEX_NAME);
+        if !index_path.exists() {
+            return Err(Error::MissingIndexError(format!(
+                "'{index_path}' does not exist"
+            )));
+        }
+        if !index_path.is_file() {
+            return Err(Error::UnreadableIndexError(format!(
+                "'{index_path}' is not a readable file"
+            )));
+        }
+        Ok(index_path.read_bytes()?)
+    }
+
+    fn location(&self) -> String {
+        self.path.to_string()
+    }
+
+    /// derives the size of indexes written before the `size` field
+    /// existed from the metadata of the last chunk rather than its
+    /// contents
+    fn read_index(&self) -> Result<UnfoldIndex> {
+        UnfoldIndex::read(&self.path)
+    }
+}
+
+/// collects the names of the files under `folder` relative to the store
+/// root, descending into at most [`ChunkLayout::MAX_FANOUT`] levels of
+/// fan-out folders
+fn list_chunk_files(
+    folder: &Path,
+    prefix: &str,
+    depth: usize,
+    names: &mut Vec<String>,
+) -> Result<()> {
+    for path in folder.list()? {
+        let name = path.name();
+        if name.starts_with('.') {
+            continue;
+        }
+        if path.is_file() {
+            names.push(format!("{prefix}{name}"));
+        } else if path.is_dir()
+            && depth < ChunkLayout::MAX_FANOUT
+            && is_fanout_folder(&name)
+        {
+            list_chunk_files(
+                &path,
+                &format!("{prefix}{name}/"),
+                depth + 1,
+                names,
+            )?;
+        }
+    }
+    Ok(())
+}
+
+/// opens the store at `location`: a bucket and prefix for
+/// `s3://bucket/prefix` urls, which requires the `s3` feature, a
+/// database file for `sqlite://path` urls, which requires the `sqlite`
+/// feature, an [`ArchiveStore`](crate::ArchiveStore) for tar and zip
+/// files, or a [`DirStore`] for anything else
+pub fn open_store(location: &str) -> Result<Box<dyn ChunkStore>> {
+    if location.starts_with("s3://") {
+        #[cfg(feature = "s3")]
+        return Ok(Box::new(crate::s3::S3Store::open(location)?));
+        #[cfg(not(feature = "s3"))]
+        return Err(Error::StoreError(format!(
+            "cannot open {location}: unfolder was built without the `s3` feature"
+        )));
+    }
+    if let Some(path) = location.strip_prefix("sqlite://") {
+        #[cfg(feature = "sqlite")]
+        return Ok(Box::new(crate::sqlite::SqliteStore::open(&Path::new(
+            path,
+        ))?));
+        #[cfg(not(feature = "sqlite"))]
+        return Err(Error::StoreError(format!(
+            "cannot open {path}: unfolder was built without the `sqlite` feature"
+        )));
+    }
+    let path = Path::new(location);
+    if path.is_file() {
+        return Ok(Box::new(crate::archive::ArchiveStore::open(&path)?));
+    }
+    Ok(Box::new(DirStore::new(&path)))
+}
+
+/// [`ChunkStore`] keeping the index and chunks in memory, useful for
+/// tests and for embedding.
+#[derive(Default)]
+pub struct MemoryStore {
+    chunks: RwLock<BTreeMap<String, Vec<u8>>>,
+    index: RwLock<Option<Vec<u8>>>,
+}
+
+impl MemoryStore {
+    pub fn new() -> MemoryStore {
+        MemoryStore::default()
+    }
+}
+
+impl Debug for MemoryStore {
+    fn fmt(&self, f: &mut Formatter) -> std::fmt::Result {
+        f.debug_struct("MemoryStore")
+            .field("chunks", &self.list().unwrap_or_default().len())
+            .finish()
+    }
+}
+
+impl ChunkStore for MemoryStore {
+    fn put(&self, name: &str, bytes: &[u8]) -> Result<()> {
+        write_lock(&self.chunks)?.insert(name.to_string(), bytes.to_vec());
+        Ok(())
+    }
+
+    fn get(&self, name: &str) -> Result<Vec<u8>> {
+        match read_lock(&self.chunks)?.get(name) {
+            Some(bytes) => Ok(bytes.clone()),
+            None => Err(Error::MissingChunkError(format!(
+                "'{name}' is not in memory store"
+            ))),
+        }
+    }
+
+    fn has(&self, name: &str) -> Result<bool> {
+        Ok(read_lock(&self.chunks)?.contains_key(name))
+    }
+
+    fn list(&self) -> Result<Vec<String>> {
+        Ok(read_lock(&self.chunks)?.keys().cloned().collect())
+    }
+
+    fn delete(&self, name: &str) -> Result<()> {
+        write_lock(&self.chunks)?.remove(name);
+        Ok(())
+    }
+
+    fn put_index(&self, bytes: &[u8]) -> Result<()> {
+        *write_lock(&self.index)? = Some(bytes.to_vec());
+        Ok(())
+    }
+
+    fn get_index(&self) -> Result<Vec<u8>> {
+        match read_lock(&self.index)?.as_ref() {
+            Some(bytes) => Ok(bytes.clone()),
+            None => Err(Error::MissingIndexError(
+                "memory store has no index".to_string(),
+            )),
+        }
+    }
+
+    fn location(&self) -> String {
+        "memory".to_string()
+    }
+}
+
+fn read_lock<T>(lock: &RwLock<T>) -> Result<std::sync::RwLockReadGuard<'_, T>> {
+    lock.read().map_err(|error| {
+        Error::StoreError(format!("poisoned memory store lock: {error}"))
+    })
+}
+
+fn write_lock<T>(
+    lock: &RwLock<T>,
+) -> Result<std::sync::RwLockWriteGuard<'_, T>> {
+    lock.write().map_err(|error| {
+        Error::StoreError(format!("poisoned memory store lock: {error}"))
+    })
+}
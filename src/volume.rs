@@ -0,0 +1,685 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt::{Display, Formatter};
+use std::ops::Range;
+use std::str::FromStr;
+
+use iocore::{Path, Size};
+
+use crate::cancel::CancelToken;
+use crate::file::{fold_from_store, index_chunks, CHUNK_SIZE, MAX_FILE_SIZE};
+use crate::index::{ChunkLayout, UnfoldIndex, INDEX_NAME};
+use crate::store::{ChunkStore, DirStore};
+use crate::{Action, Error, Progress, Result};
+
+/// name of the label [`unfold_to_volumes`] writes next to the index of
+/// every volume
+pub const VOLUME_NAME: &str = "volume";
+
+/// folder [`unfold_to_volumes`] may write at most `capacity` bytes of
+/// chunks, index and label into, parsed from `folder:capacity`, e.g.:
+/// `/mnt/a:500GB` or `/mnt/b:1.5TiB`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VolumeSpec {
+    path: Path,
+    capacity: u64,
+}
+
+impl VolumeSpec {
+    pub fn new(path: &Path, capacity: u64) -> VolumeSpec {
+        VolumeSpec {
+            path: path.clone(),
+            capacity,
+        }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// capacity in bytes
+    pub fn capacity(&self) -> u64 {
+        self.capacity
+    }
+}
+
+impl FromStr for VolumeSpec {
+    type Err = Error;
+
+    fn from_str(value: &str) -> Result<VolumeSpec> {
+        let invalid = || {
+            Error::VolumeError(format!(
+                "invalid volume '{value}', expected folder:capacity, e.g.: /mnt/a:500GB"
+            ))
+        };
+        let (path, capacity) = value.rsplit_once(':').ok_or_else(invalid)?;
+        if path.is_empty() {
+            return Err(invalid());
+        }
+        let capacity = parse_capacity(capacity).ok_or_else(invalid)?;
+        Ok(VolumeSpec::new(&Path::new(path), capacity))
+    }
+}
+
+/// parses sizes such as `4096`, `500GB` or `1.5TiB`, where `K`, `M`,
+/// `G` and `T` are powers of 1000 and `Ki`, `Mi`, `Gi` and `Ti` powers
+/// of 1024
+fn parse_capacity(value: &str) -> Option<u64> {
+    let value = value.trim();
+    let end = value
+        .find(|c: char| !c.is_ascii_digit() && c != '.')
+        .unwrap_or(value.len());
+    let (number, unit) = value.split_at(end);
+    let number = number.parse::<f64>().ok()?;
+    let multiplier = match unit.trim().to_lowercase().as_str() {
+        "" | "b" => 1u64,
+        "k" | "kb" => 1000,
+        "ki" | "kib" => 1 << 10,
+        "m" | "mb" => 1_000_000,
+        "mi" | "mib" => 1 << 20,
+        "g" | "gb" => 1_000_000_000,
+        "gi" | "gib" => 1 << 30,
+        "t" | "tb" => 1_000_000_000_000,
+        "ti" | "tib" => 1 << 40,
+        _ => return None,
+    };
+    Some((number * multiplier as f64) as u64)
+}
+
+/// one of the folders a file was unfolded across by
+/// [`unfold_to_volumes`], holding the chunks in `chunks` which no
+/// earlier volume holds
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Volume {
+    number: usize,
+    count: usize,
+    path: String,
+    chunks: Range<usize>,
+}
+
+impl Volume {
+    /// position of the volume, starting at 1
+    pub fn number(&self) -> usize {
+        self.number
+    }
+
+    /// folder the volume was written to
+    pub fn path(&self) -> &str {
+        &self.path
+    }
+
+    /// indexes of the chunks on the volume, starting at 0
+    pub fn chunks(&self) -> Range<usize> {
+        self.chunks.clone()
+    }
+}
+
+impl Display for Volume {
+    fn fmt(&self, f: &mut Formatter) -> std::fmt::Result {
+        write!(f, "volume {} of {} (", self.number, self.count)?;
+        if self.chunks.is_empty() {
+            write!(f, "no chunks")?;
+        } else {
+            write!(
+                f,
+                "chunks {} to {}",
+                self.chunks.start + 1,
+                self.chunks.end
+            )?;
+        }
+        write!(f, ", written to {})", self.path)
+    }
+}
+
+/// Contents of the `volume` file [`unfold_to_volumes`] writes next to
+/// the index of every volume: which volume it is, the whole-file
+/// checksum of the unfolded file and the chunks every volume holds.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VolumeLabel {
+    number: usize,
+    sha256: Vec<u8>,
+    volumes: Vec<Volume>,
+}
+
+impl VolumeLabel {
+    /// reads the label of the volume at `path`
+    pub fn read(path: &Path) -> Result<VolumeLabel> {
+        let label_path = path.join(VOLUME_NAME);
+        if !label_path.is_file() {
+            return Err(Error::VolumeError(format!(
+                "{path} is not a volume: '{label_path}' is not a readable file"
+            )));
+        }
+        VolumeLabel::parse(&label_path.read()?, &label_path)
+    }
+
+    /// parses the yaml of a label. `origin` is only used in error
+    /// messages.
+    pub fn parse(
+        yaml: &str,
+        origin: impl std::fmt::Display,
+    ) -> Result<VolumeLabel> {
+        let mut fields = serde_yaml::from_str::<BTreeMap<String, String>>(yaml)
+            .map_err(|error| {
+                Error::VolumeError(format!(
+                    "invalid yaml in '{origin}': {error}"
+                ))
+            })?;
+        let mut field = |name: &str| {
+            fields.remove(name).ok_or_else(|| {
+                Error::VolumeError(format!(
+                    "missing '{name}' field in '{origin}'"
+                ))
+            })
+        };
+        let invalid = |name: &str, value: &str| {
+            Error::VolumeError(format!(
+                "invalid '{name}' field '{value}' in '{origin}'"
+            ))
+        };
+        let sha256 = field("sha256")?;
+        let sha256 = hex::decode(&sha256)
+            .ok()
+            .filter(|sha256| sha256.len() == 32)
+            .ok_or_else(|| invalid("sha256", &sha256))?;
+        let count = field("volumes")?;
+        let count = count
+            .parse::<usize>()
+            .ok()
+            .filter(|count| *count > 0)
+            .ok_or_else(|| invalid("volumes", &count))?;
+        let number = field("volume")?;
+        let number = number
+            .parse::<usize>()
+            .ok()
+            .filter(|number| (1..=count).contains(number))
+            .ok_or_else(|| invalid("volume", &number))?;
+        let mut volumes = Vec::<Volume>::with_capacity(count);
+        let mut start = 0;
+        for volume_number in 1..=count {
+            let name = format!("volume-{volume_number}");
+            let value = field(&name)?;
+            let (chunks, path) = value
+                .split_once(' ')
+                .ok_or_else(|| invalid(&name, &value))?;
+            let chunks = match chunks {
+                "none" => start..start,
+                chunks => chunks
+                    .split_once('-')
+                    .and_then(|(first, last)| {
+                        Some(first.parse::<usize>().ok()?..last.parse().ok()?)
+                    })
+                    .filter(|chunks| chunks.start == start + 1)
+                    .map(|chunks| start..chunks.end)
+                    .filter(|chunks| !chunks.is_empty())
+                    .ok_or_else(|| invalid(&name, &value))?,
+            };
+            start = chunks.end;
+            volumes.push(Volume {
+                number: volume_number,
+                count,
+                path: path.to_string(),
+                chunks,
+            });
+        }
+        if let Some(name) = fields.keys().next() {
+            return Err(Error::VolumeError(format!(
+                "unexpected '{name}' field in '{origin}'"
+            )));
+        }
+        Ok(VolumeLabel {
+            number,
+            sha256,
+            volumes,
+        })
+    }
+
+    pub fn to_yaml(&self) -> Result<String> {
+        let mut fields = BTreeMap::<String, String>::new();
+        fields.insert("sha256".to_string(), hex::encode(&self.sha256));
+        fields.insert("volume".to_string(), self.number.to_string());
+        fields.insert("volumes".to_string(), self.volumes.len().to_string());
+        for volume in &self.volumes {
+            let chunks = if volume.chunks.is_empty() {
+                "none".to_string()
+            } else {
+                format!("{}-{}", volume.chunks.start + 1, volume.chunks.end)
+            };
+            fields.insert(
+                format!("volume-{}", volume.number),
+                format!("{chunks} {}", volume.path),
+            );
+        }
+        serde_yaml::to_string(&fields).map_err(|error| {
+            Error::VolumeError(format!(
+                "failed to serialize volume label as yaml: {error}"
+            ))
+        })
+    }
+
+    /// the volume this label was read from
+    pub fn volume(&self) -> &Volume {
+        &self.volumes[self.number - 1]
+    }
+
+    /// every volume of the unfolded file, in order
+    pub fn volumes(&self) -> &[Volume] {
+        &self.volumes
+    }
+
+    /// whole-file checksum of the unfolded file
+    pub fn sha256(&self) -> &[u8] {
+        &self.sha256
+    }
+
+    /// the volume holding the chunk at `idx`, unless an earlier volume
+    /// already holds a chunk by the same name
+    fn volume_of(&self, idx: usize) -> &Volume {
+        self.volumes
+            .iter()
+            .find(|volume| volume.chunks.contains(&idx))
+            .unwrap_or_else(|| self.volume())
+    }
+}
+
+/// unfolds the file at `input_path` across the folders of `volumes` in
+/// order, filling each up to its capacity before moving on to the next,
+/// and writes a copy of the index along with a [`VolumeLabel`] to every
+/// volume. Nothing is written unless the whole file fits.
+pub fn unfold_to_volumes<C: FnMut(Progress)>(
+    input_path: &Path,
+    volumes: &[VolumeSpec],
+    layout: ChunkLayout,
+    progress: C,
+) -> Result<UnfoldIndex> {
+    let cancel = CancelToken::new();
+    unfold_to_volumes_with_cancel(
+        input_path, volumes, layout, 1, &cancel, progress,
+    )
+}
+
+/// unfolds the file at `input_path` across `volumes` like
+/// [`unfold_to_volumes`], hashing chunks across `jobs` threads, 0 for
+/// one per cpu, until `cancel` is cancelled, removing then the
+/// volume folders it created and the chunks it wrote into the others,
+/// unless `cancel` keeps partial output
+pub fn unfold_to_volumes_with_cancel<C: FnMut(Progress)>(
+    input_path: &Path,
+    volumes: &[VolumeSpec],
+    layout: ChunkLayout,
+    jobs: usize,
+    cancel: &CancelToken,
+    mut progress: C,
+) -> Result<UnfoldIndex> {
+    if !input_path.is_file() {
+        return Err(Error::FlattenFileInputError(format!(
+            "{input_path} is not a file"
+        )));
+    }
+    let size = input_path.file_size();
+    if size.as_u64() > MAX_FILE_SIZE {
+        let max_file_size = Size::from(MAX_FILE_SIZE);
+        return Err(Error::FlattenFileInputError(format!(
+            "{input_path} is too large {size} (max = {max_file_size})"
+        )));
+    }
+    if layout.fanout > ChunkLayout::MAX_FANOUT {
+        return Err(Error::FlattenFileOutputError(format!(
+            "fan-out {} is larger than {}",
+            layout.fanout,
+            ChunkLayout::MAX_FANOUT
+        )));
+    }
+    if layout.span.is_some() {
+        return Err(Error::VolumeError(
+            "hierarchical manifests cannot be split across volumes".to_string(),
+        ));
+    }
+    if volumes.is_empty() {
+        return Err(Error::VolumeError("no volumes to unfold to".to_string()));
+    }
+    let mut paths = BTreeSet::<String>::new();
+    for spec in volumes {
+        let path = spec.path();
+        if path.exists() && !path.is_dir() {
+            return Err(Error::VolumeError(format!(
+                "{path} is not a directory"
+            )));
+        }
+        if path.join(INDEX_NAME).exists() || path.join(VOLUME_NAME).exists() {
+            return Err(Error::VolumeError(format!(
+                "{path} already holds an unfolded file"
+            )));
+        }
+        if !paths.insert(path.try_canonicalize().to_string()) {
+            return Err(Error::VolumeError(format!(
+                "{path} is given more than once"
+            )));
+        }
+    }
+    progress(Progress::Start(Action::Unfold));
+    let bytes = input_path.read_bytes()?;
+    let index = index_chunks(&bytes, None, layout, jobs, cancel)?;
+    let index_bytes = index.to_bytes()?;
+    let names = index.chunk_names();
+    let chunk_count = names.len();
+    let mut label = VolumeLabel {
+        number: volumes.len(),
+        sha256: index.sha256().to_vec(),
+        volumes: volumes
+            .iter()
+            .enumerate()
+            .map(|(idx, spec)| Volume {
+                number: idx + 1,
+                count: volumes.len(),
+                path: spec.path().to_string(),
+                chunks: 0..0,
+            })
+            .collect(),
+    };
+    // every range is written as `none` or at most as long as `n-n`
+    let range_size = 2 * chunk_count.to_string().len() + 1;
+    let reserved = (index_bytes.len()
+        + label.to_yaml()?.len()
+        + volumes.len() * range_size) as u64;
+
+    let mut owners = BTreeMap::<&str, usize>::new();
+    let mut start = 0;
+    for (volume, spec) in label.volumes.iter_mut().zip(volumes) {
+        if reserved > spec.capacity() {
+            return Err(Error::VolumeError(format!(
+                "{} cannot even hold the index and label of {input_path}, which take {}",
+                spec.path(),
+                Size::from(reserved)
+            )));
+        }
+        let mut used = reserved;
+        let mut end = start;
+        while end < chunk_count {
+            if !owners.contains_key(names[end].as_str()) {
+                let chunk_size = chunk_size(end, bytes.len()) as u64;
+                if used + chunk_size > spec.capacity() {
+                    break;
+                }
+                used += chunk_size;
+                owners.insert(&names[end], volume.number);
+            }
+            end += 1;
+        }
+        volume.chunks = start..end;
+        start = end;
+    }
+    if start < chunk_count {
+        let left = (start..chunk_count)
+            .filter(|idx| !owners.contains_key(names[*idx].as_str()))
+            .map(|idx| chunk_size(idx, bytes.len()) as u64)
+            .sum::<u64>();
+        return Err(Error::VolumeError(format!(
+            "{input_path} does not fit on the given volumes: chunks {} to {chunk_count} taking {} are left over",
+            start + 1,
+            Size::from(left)
+        )));
+    }
+
+    let created = volumes
+        .iter()
+        .map(|spec| !spec.path().exists())
+        .collect::<Vec<bool>>();
+    let mut stores = Vec::<DirStore>::with_capacity(volumes.len());
+    for spec in volumes {
+        stores
+            .push(DirStore::new(&spec.path().mkdir()?).reserving(VOLUME_NAME));
+    }
+    let mut write = || {
+        for (idx, chunk) in bytes.chunks(CHUNK_SIZE).enumerate() {
+            cancel.check()?;
+            let name = &names[idx];
+            let number = label.volume_of(idx).number;
+            if owners[name.as_str()] == number {
+                let store = &stores[number - 1];
+                store.put(name, chunk).map_err(|error| {
+                    Error::FlattenFileOutputError(format!(
+                        "failed to write chunk {}/{chunk_count} to {store}: {error}",
+                        idx + 1
+                    ))
+                })?;
+            }
+            progress(Progress::Chunk {
+                index: idx + 1,
+                count: chunk_count,
+                action: Action::Unfold,
+            });
+        }
+        Ok(())
+    };
+    let result = write();
+    if matches!(result, Err(Error::Cancelled(_)))
+        && !cancel.keeps_partial_output()
+    {
+        for ((store, created), number) in stores.iter().zip(created).zip(1..) {
+            if created {
+                store.path().delete()?;
+                continue;
+            }
+            for (name, _) in owners
+                .iter()
+                .filter(|(_, owner)| **owner == number)
+            {
+                if store.has(name)? {
+                    store.delete(name)?;
+                }
+            }
+        }
+    }
+    result?;
+    for (idx, store) in stores.iter().enumerate() {
+        label.number = idx + 1;
+        store
+            .path()
+            .join(VOLUME_NAME)
+            .write(label.to_yaml()?.as_bytes())?;
+        store.put_index(&index_bytes)?;
+    }
+    progress(Progress::End(Action::Unfold));
+    Ok(index)
+}
+
+fn chunk_size(idx: usize, size: usize) -> usize {
+    CHUNK_SIZE.min(size - idx * CHUNK_SIZE)
+}
+
+/// folds the file unfolded across the volumes at `roots`, given in any
+/// order, into `output_path`, failing with the volumes which are not
+/// among `roots` or miss chunks, see [`VolumeStore::check`]
+pub fn fold_from_volumes<C: FnMut(Progress)>(
+    roots: &[Path],
+    output_path: &Path,
+    progress: C,
+) -> Result<Path> {
+    let store = VolumeStore::open(roots)?;
+    store.check()?;
+    fold_from_store(&store, output_path, progress)
+}
+
+/// read-only [`ChunkStore`] over some or all of the volumes written by
+/// [`unfold_to_volumes`], reading every chunk from the volume its label
+/// says holds it.
+pub struct VolumeStore {
+    label: VolumeLabel,
+    stores: BTreeMap<usize, DirStore>,
+    owners: BTreeMap<String, usize>,
+}
+
+impl VolumeStore {
+    /// opens the volumes at `roots`, given in any order, which must all
+    /// belong to the same unfolded file. Volumes which are not among
+    /// `roots` can be added with [`VolumeStore::add`].
+    pub fn open(roots: &[Path]) -> Result<VolumeStore> {
+        let Some(first) = roots.first() else {
+            return Err(Error::VolumeError("no volumes to fold".to_string()));
+        };
+        let label = VolumeLabel::read(first)?;
+        let index = DirStore::new(first).read_index()?;
+        let covered = label
+            .volumes()
+            .last()
+            .map(|volume| volume.chunks.end);
+        if index.sha256() != label.sha256()
+            || covered != Some(index.chunk_count())
+        {
+            return Err(Error::VolumeError(format!(
+                "the index of {first} does not match its volume label"
+            )));
+        }
+        let mut owners = BTreeMap::<String, usize>::new();
+        for (idx, name) in index.chunk_names().into_iter().enumerate() {
+            let number = label.volume_of(idx).number;
+            owners.entry(name).or_insert(number);
+        }
+        let mut store = VolumeStore {
+            label,
+            stores: BTreeMap::new(),
+            owners,
+        };
+        for root in roots {
+            store.add(root)?;
+        }
+        Ok(store)
+    }
+
+    /// adds the volume at `root`, which must belong to the same
+    /// unfolded file as the volumes already open
+    pub fn add(&mut self, root: &Path) -> Result<&Volume> {
+        let label = VolumeLabel::read(root)?;
+        if label.sha256() != self.label.sha256()
+            || label.volumes() != self.label.volumes()
+        {
+            return Err(Error::VolumeError(format!(
+                "{root} is {} of another unfolded file",
+                label.volume()
+            )));
+        }
+        let number = label.volume().number();
+        if let Some(store) = self.stores.get(&number) {
+            if store.path().try_canonicalize() != root.try_canonicalize() {
+                return Err(Error::VolumeError(format!(
+                    "{root} and {store} are both {}",
+                    label.volume()
+                )));
+            }
+        }
+        self.stores
+            .insert(number, DirStore::new(root).reserving(VOLUME_NAME));
+        Ok(&self.label.volumes[number - 1])
+    }
+
+    /// the volumes which are not open yet, in order
+    pub fn missing_volumes(&self) -> Vec<&Volume> {
+        self.label
+            .volumes()
+            .iter()
+            .filter(|volume| !self.stores.contains_key(&volume.number))
+            .collect()
+    }
+
+    /// checks that every volume is open and holds its chunks, telling
+    /// exactly which volumes are missing and which miss chunks
+    pub fn check(&self) -> Result<()> {
+        let mut problems = self
+            .missing_volumes()
+            .into_iter()
+            .map(|volume| format!("{volume} was not given"))
+            .collect::<Vec<String>>();
+        for (number, store) in &self.stores {
+            let mut missing = Vec::<&str>::new();
+            for (name, owner) in &self.owners {
+                if owner == number && !store.has(name)? {
+                    missing.push(name);
+                }
+            }
+            if let Some(name) = missing.first() {
+                problems.push(format!(
+                    "{} at {store} misses {} chunks, e.g.: '{name}'",
+                    self.label.volumes[number - 1],
+                    missing.len()
+                ));
+            }
+        }
+        if problems.is_empty() {
+            return Ok(());
+        }
+        Err(Error::VolumeError(problems.join("; ")))
+    }
+
+    pub fn label(&self) -> &VolumeLabel {
+        &self.label
+    }
+
+    fn read_only(&self) -> Error {
+        Error::VolumeError(format!("volumes {} are read-only", self.location()))
+    }
+}
+
+impl ChunkStore for VolumeStore {
+    fn put(&self, _name: &str, _bytes: &[u8]) -> Result<()> {
+        Err(self.read_only())
+    }
+
+    fn get(&self, name: &str) -> Result<Vec<u8>> {
+        let Some(number) = self.owners.get(name) else {
+            return Err(Error::MissingChunkError(format!(
+                "'{name}' is not on volumes {}",
+                self.location()
+            )));
+        };
+        match self.stores.get(number) {
+            Some(store) => store.get(name),
+            None => Err(Error::MissingChunkError(format!(
+                "'{name}' is on {} which was not given",
+                self.label.volumes[number - 1]
+            ))),
+        }
+    }
+
+    fn has(&self, name: &str) -> Result<bool> {
+        match self
+            .owners
+            .get(name)
+            .and_then(|number| self.stores.get(number))
+        {
+            Some(store) => store.has(name),
+            None => Ok(false),
+        }
+    }
+
+    fn list(&self) -> Result<Vec<String>> {
+        let mut names = BTreeSet::<String>::new();
+        for store in self.stores.values() {
+            names.extend(store.list()?);
+        }
+        Ok(names.into_iter().collect())
+    }
+
+    fn delete(&self, _name: &str) -> Result<()> {
+        Err(self.read_only())
+    }
+
+    fn put_index(&self, _bytes: &[u8]) -> Result<()> {
+        Err(self.read_only())
+    }
+
+    fn get_index(&self) -> Result<Vec<u8>> {
+        match self.stores.values().next() {
+            Some(store) => store.get_index(),
+            None =>
+                Err(Error::MissingIndexError("no volume is open".to_string())),
+        }
+    }
+
+    fn location(&self) -> String {
+        self.stores
+            .values()
+            .map(|store| store.location())
+            .collect::<Vec<String>>()
+            .join(", ")
+    }
+}
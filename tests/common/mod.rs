@@ -0,0 +1,31 @@
+#![allow(dead_code)]
+use iocore::Path;
+
+pub fn fixtures_path() -> Path {
+    let path = Path::new(file!())
+        .parent()
+        .unwrap()
+        .parent()
+        .unwrap()
+        .join("fixtures");
+    assert!(path.is_dir(), "{path} should exist");
+    path
+}
+pub fn output_file_path(name: &str) -> Path {
+    Path::new(file!())
+        .parent()
+        .unwrap()
+        .parent()
+        .unwrap()
+        .join("output")
+        .join(name)
+        .mkdir_parents()
+        .unwrap()
+}
+pub fn output_folder_path(name: &str) -> Path {
+    output_file_path(name).mkdir().unwrap()
+}
+
+pub fn fixture_path(name: &str) -> Path {
+    fixtures_path().join(name)
+}
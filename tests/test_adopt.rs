@@ -0,0 +1,101 @@
+use unfolder::{
+    adopt, fold_file, gc_store, pack, unfold_file_with_layout, unpack,
+    verify_store, ChunkLayout, ChunkNaming, ChunkStore, DirStore, Error,
+    Result, UnfoldIndex,
+};
+
+mod common;
+use common::{fixture_path, output_file_path, output_folder_path};
+
+#[test]
+fn test_adopt_split_parts() -> Result<()> {
+    let input_path = fixture_path("labs-google.har");
+    let bytes = input_path.read_bytes()?;
+    let parts_path = output_folder_path("adopt-parts")
+        .delete()?
+        .mkdir()?;
+    let parts = bytes
+        .chunks(100_000)
+        .enumerate()
+        .map(|(idx, part)| {
+            let name = format!(
+                "x{}{}",
+                (b'a' + (idx / 26) as u8) as char,
+                (b'a' + (idx % 26) as u8) as char
+            );
+            parts_path.join(name).write(part)
+        })
+        .collect::<std::result::Result<Vec<_>, _>>()?;
+    assert_eq!(parts.len(), 41);
+
+    let output_path = output_folder_path("adopt-unfolded").delete()?;
+    let index = adopt(&parts, &output_path, ChunkLayout::default(), |_| {})?;
+    assert_eq!(index, UnfoldIndex::read(&output_path)?);
+    assert_eq!(index.size(), bytes.len() as u64);
+    assert_eq!(index.chunk_count(), 62);
+    let folded_path = output_file_path("adopt-folded.har").delete()?;
+    fold_file(&output_path, &folded_path, |_| {})?;
+    assert_eq!(folded_path.read_bytes()?, bytes);
+
+    match adopt(&parts, &output_path, ChunkLayout::default(), |_| {}) {
+        Err(Error::FlattenFileOutputError(_)) => {},
+        other => panic!("expected FlattenFileOutputError, got {other:?}"),
+    }
+    let reversed = parts.iter().rev().cloned().collect::<Vec<_>>();
+    let reversed_path = output_folder_path("adopt-reversed").delete()?;
+    let reversed_index =
+        adopt(&reversed, &reversed_path, ChunkLayout::default(), |_| {})?;
+    assert_ne!(reversed_index.sha256(), index.sha256());
+    Ok(())
+}
+
+#[test]
+fn test_unfold_with_sequential_naming() -> Result<()> {
+    let mut bytes = vec![0u8; u16::MAX as usize * 3 + 5];
+    bytes[u16::MAX as usize + 1] = 1;
+    let input_path = output_file_path("sequential.bin").write(&bytes)?;
+    let output_path = output_folder_path("sequential-unfolded").delete()?;
+    let layout = ChunkLayout::new(ChunkNaming::Sequential, 0);
+    unfold_file_with_layout(&input_path, &output_path, layout, |_| {})?;
+    let store = DirStore::new(&output_path);
+    let names = store.list()?;
+    assert_eq!(
+        names,
+        vec![
+            "part-0001.bin",
+            "part-0002.bin",
+            "part-0003.bin",
+            "part-0004.bin"
+        ]
+    );
+    assert!(names
+        .iter()
+        .all(|name| ChunkNaming::Sequential.accepts(name)));
+    let joined = names
+        .iter()
+        .map(|name| store.get(name))
+        .collect::<Result<Vec<_>>>()?
+        .concat();
+    assert_eq!(joined, bytes);
+
+    let index = verify_store(&store, |_| {})?;
+    assert_eq!(index.naming(), ChunkNaming::Sequential);
+    assert_eq!(index.chunks()[0], index.chunks()[2]);
+    assert!(gc_store(&store, |_| {})?.is_empty());
+
+    let unpacked_path = output_folder_path("sequential-unpacked").delete()?;
+    let stream = pack(&output_path, Vec::<u8>::new(), |_| {})?;
+    unpack(stream.as_slice(), &unpacked_path, |_| {})?;
+    assert_eq!(DirStore::new(&unpacked_path).list()?, names);
+    let folded_path = output_file_path("sequential-folded.bin").delete()?;
+    fold_file(&unpacked_path, &folded_path, |_| {})?;
+    assert_eq!(folded_path.read_bytes()?, bytes);
+
+    store.delete("part-0003.bin")?;
+    match verify_store(&store, |_| {}) {
+        Err(Error::InvalidIndexError(message)) =>
+            assert!(message.contains("part-0003.bin"), "{message}"),
+        other => panic!("expected InvalidIndexError, got {other:?}"),
+    }
+    Ok(())
+}
@@ -0,0 +1,142 @@
+use std::fs::File;
+
+use unfolder::{
+    export, fold_from_store, import, unfold_file, verify_store, ArchiveFormat,
+    ArchiveStore, ChunkStore, Error, Result,
+};
+
+mod common;
+use common::{fixture_path, output_file_path, output_folder_path};
+
+#[test]
+fn test_export_is_deterministic_and_foldable() -> Result<()> {
+    let input_path = fixture_path("labs-google_fx_tools_flow.png");
+    let unfolded = unfold_file(
+        &input_path,
+        &output_folder_path("labs-google_fx_tools_flow.png.export").delete()?,
+        |_| {},
+    )?;
+    for format in [ArchiveFormat::Tar, ArchiveFormat::Zip] {
+        let first =
+            output_file_path(&format!("flow.export-1.{format}")).delete()?;
+        let second =
+            output_file_path(&format!("flow.export-2.{format}")).delete()?;
+        export(&unfolded, format, &first, |_| {})?;
+        export(&unfolded, format, &second, |_| {})?;
+        assert_eq!(first.read_bytes()?, second.read_bytes()?);
+
+        let store = ArchiveStore::open(&first)?;
+        assert_eq!(store.format(), format);
+        assert_eq!(store.get_index()?, unfolded.join("index").read_bytes()?);
+        let output_path =
+            output_file_path(&format!("flow.export-folded.{format}"))
+                .delete()?;
+        fold_from_store(&store, &output_path, |_| {})?;
+        assert_eq!(output_path.read_bytes()?, input_path.read_bytes()?);
+        match store.put_index(b"") {
+            Err(Error::ArchiveError(_)) => {},
+            other => panic!("expected ArchiveError, got {other:?}"),
+        }
+    }
+
+    let tar = output_file_path("flow.export-1.tar");
+    let mut archive = tar::Archive::new(File::open(tar.to_string())?);
+    let names = archive
+        .entries()?
+        .map(|entry| Ok(entry?.path()?.to_string_lossy().to_string()))
+        .collect::<std::io::Result<Vec<String>>>()?;
+    let mut chunks = unfolded
+        .list()?
+        .into_iter()
+        .map(|path| path.name())
+        .filter(|name| name != "index")
+        .collect::<Vec<String>>();
+    chunks.sort();
+    assert_eq!(names[0], "index");
+    assert_eq!(names[1..], chunks);
+    Ok(())
+}
+
+#[test]
+fn test_import_and_read_archives_of_unfolded_folders() -> Result<()> {
+    let input_path = fixture_path("deepmind.flow");
+    let unfolded = unfold_file(
+        &input_path,
+        &output_folder_path("deepmind.flow.archived").delete()?,
+        |_| {},
+    )?;
+    // an archive of the folder itself, as made by `tar cf`
+    let archive_path =
+        output_file_path("deepmind.flow.archived.tar").delete()?;
+    let mut builder =
+        tar::Builder::new(File::create(archive_path.to_string())?);
+    builder.append_dir_all("deepmind.flow.archived", unfolded.to_string())?;
+    builder.into_inner()?;
+
+    let store = ArchiveStore::open(&archive_path)?;
+    let index = verify_store(&store, |_| {})?;
+    assert_eq!(store.list()?, index.chunks().to_vec());
+
+    let imported = output_folder_path("deepmind.flow.imported").delete()?;
+    let mut messages = Vec::<String>::new();
+    import(&archive_path, &imported, |progress| {
+        messages.push(progress.to_string());
+    })?;
+    assert_eq!(
+        messages,
+        vec![
+            "Import start",
+            "Import chunk 1/1",
+            "Import end"
+        ]
+    );
+    assert_eq!(
+        imported.join("index").read_bytes()?,
+        unfolded.join("index").read_bytes()?
+    );
+    assert_eq!(
+        imported.join(&index.chunks()[0]).read_bytes()?,
+        input_path.read_bytes()?
+    );
+    Ok(())
+}
+
+#[test]
+fn test_import_rejects_corrupted_archives() -> Result<()> {
+    let unfolded = unfold_file(
+        &fixture_path("deepmind.flow"),
+        &output_folder_path("deepmind.flow.corrupted-archive").delete()?,
+        |_| {},
+    )?;
+    let chunk = unfolded
+        .list()?
+        .into_iter()
+        .find(|path| path.name() != "index")
+        .unwrap();
+    let mut bytes = chunk.read_bytes()?;
+    bytes[0] ^= 0xFF;
+    chunk.write(&bytes)?;
+    let archive_path =
+        output_file_path("deepmind.flow.corrupted-archive.zip").delete()?;
+    export(&unfolded, ArchiveFormat::Zip, &archive_path, |_| {}).unwrap_err();
+    assert!(!archive_path.exists());
+
+    let mut writer =
+        zip::ZipWriter::new(File::create(archive_path.to_string())?);
+    for path in unfolded.list()? {
+        writer
+            .start_file(path.name(), zip::write::SimpleFileOptions::default())
+            .unwrap();
+        std::io::Write::write_all(&mut writer, &path.read_bytes()?)?;
+    }
+    writer.finish().unwrap();
+
+    let imported =
+        output_folder_path("deepmind.flow.corrupted-import").delete()?;
+    match import(&archive_path, &imported, |_| {}) {
+        Err(Error::CorruptedDataError(_)) => {},
+        other => panic!("expected CorruptedDataError, got {other:?}"),
+    }
+    assert!(!imported.exists());
+    Ok(())
+}
@@ -0,0 +1,132 @@
+#![cfg(feature = "async")]
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use tokio::sync::{mpsc, Notify};
+use unfolder::{
+    fold_async, fold_file, unfold_async, verify_async, Action, AsyncChunkStore,
+    BlockingStore, ChunkLayout, ChunkNaming, DirStore, Error, MemoryStore,
+    Progress, Result,
+};
+
+mod common;
+use common::{fixture_path, output_file_path, output_folder_path};
+
+/// [`AsyncChunkStore`] which never finishes writing the chunk after
+/// `limit` of them, notifying `stalled` instead
+struct StallingStore {
+    inner: BlockingStore<MemoryStore>,
+    puts: AtomicUsize,
+    limit: usize,
+    stalled: Notify,
+}
+
+impl AsyncChunkStore for StallingStore {
+    async fn put(&self, name: &str, bytes: &[u8]) -> Result<()> {
+        if self.puts.fetch_add(1, Ordering::SeqCst) == self.limit {
+            self.stalled.notify_one();
+            std::future::pending::<()>().await;
+        }
+        self.inner.put(name, bytes).await
+    }
+
+    async fn get(&self, name: &str) -> Result<Vec<u8>> {
+        self.inner.get(name).await
+    }
+
+    async fn has(&self, name: &str) -> Result<bool> {
+        self.inner.has(name).await
+    }
+
+    async fn list(&self) -> Result<Vec<String>> {
+        self.inner.list().await
+    }
+
+    async fn delete(&self, name: &str) -> Result<()> {
+        self.inner.delete(name).await
+    }
+
+    async fn put_index(&self, bytes: &[u8]) -> Result<()> {
+        self.inner.put_index(bytes).await
+    }
+
+    async fn get_index(&self) -> Result<Vec<u8>> {
+        self.inner.get_index().await
+    }
+
+    fn location(&self) -> String {
+        self.inner.location()
+    }
+}
+
+#[tokio::test]
+async fn test_unfold_fold_and_verify_asynchronously() -> Result<()> {
+    let input_path = fixture_path("labs-google.har");
+    let input = input_path.read_bytes()?;
+    let output_path = output_folder_path("async-unfolded").delete()?;
+    let store = BlockingStore::new(DirStore::new(&output_path));
+    let layout = ChunkLayout::new(ChunkNaming::Hash, 1).with_span(Some(8));
+    let (sender, mut receiver) = mpsc::unbounded_channel();
+    let index =
+        unfold_async(&input[..], &store, layout, Some(sender.clone())).await?;
+    assert_eq!(index.layout(), layout);
+    assert_eq!(store.list().await?.len(), 62 + 8);
+
+    let mut folded = Vec::<u8>::new();
+    assert_eq!(fold_async(&store, &mut folded, Some(sender)).await?, index);
+    assert_eq!(folded, input);
+    let mut events = Vec::<Progress>::new();
+    while let Some(event) = receiver.recv().await {
+        events.push(event);
+    }
+    assert_eq!(events.len(), 2 * (62 + 2));
+    assert_eq!(events[0], Progress::Start(Action::Unfold));
+    assert_eq!(events[64], Progress::Start(Action::Fold));
+    assert_eq!(
+        events[126],
+        Progress::Chunk {
+            index: 62,
+            count: 62,
+            action: Action::Fold
+        }
+    );
+
+    let folded_path = output_file_path("async-folded.har").delete()?;
+    fold_file(&output_path, &folded_path, |_| {})?;
+    assert_eq!(folded_path.read_bytes()?, input);
+    assert_eq!(verify_async(&store, None).await?, index);
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_dropped_unfold_writes_no_index() -> Result<()> {
+    let input = fixture_path("labs-google.har").read_bytes()?;
+    let store = StallingStore {
+        inner: BlockingStore::new(MemoryStore::new()),
+        puts: AtomicUsize::new(0),
+        limit: 10,
+        stalled: Notify::new(),
+    };
+    let layout = ChunkLayout::new(ChunkNaming::Hash, 0);
+    tokio::select! {
+        result = unfold_async(&input[..], &store, layout, None) =>
+            panic!("expected the unfold to stall, got {result:?}"),
+        _ = store.stalled.notified() => {},
+    }
+    assert_eq!(store.list().await?.len(), 10);
+    match store.get_index().await {
+        Err(Error::MissingIndexError(_)) => {},
+        other => panic!("expected MissingIndexError, got {other:?}"),
+    }
+
+    let store = BlockingStore::new(MemoryStore::new());
+    let index = unfold_async(&input[..], &store, layout, None).await?;
+    store
+        .put(&index.chunk_name(3), b"tampered")
+        .await?;
+    match verify_async(&store, None).await {
+        Err(Error::CorruptedDataError(message)) =>
+            assert!(message.contains(&index.chunks()[3]), "{message}"),
+        other => panic!("expected CorruptedDataError, got {other:?}"),
+    }
+    Ok(())
+}
@@ -0,0 +1,134 @@
+use std::collections::BTreeSet;
+
+use unfolder::{
+    bundle, fold_file, read_inventory, unbundle, unbundle_to_store,
+    unfold_file, verify_store, ChunkStore, DirStore, Error, Result,
+};
+
+mod common;
+use common::{fixture_path, output_file_path, output_folder_path};
+
+const CHUNK: usize = u16::MAX as usize;
+
+#[test]
+fn test_bundle_only_missing_chunks() -> Result<()> {
+    let input_path = fixture_path("labs-google.har");
+    let mut modified = input_path.read_bytes()?;
+    modified[CHUNK * 5 + 1] ^= 0xFF;
+    modified.extend(vec![0x2A; 1024]);
+    let modified_path =
+        output_file_path("labs-google.har.bundle-v2").delete()?;
+    modified_path.write(&modified)?;
+
+    let receiver = unfold_file(
+        &input_path,
+        &output_folder_path("labs-google.har.bundle-receiver").delete()?,
+        |_| {},
+    )?;
+    let sender = unfold_file(
+        &modified_path,
+        &output_folder_path("labs-google.har.bundle-sender").delete()?,
+        |_| {},
+    )?;
+
+    let have = read_inventory(&receiver)?;
+    let bundle_path = output_file_path("labs-google.har.bundle").delete()?;
+    let mut messages = Vec::<String>::new();
+    bundle(&sender, &have, &bundle_path, |progress| {
+        messages.push(progress.to_string());
+    })?;
+    assert_eq!(
+        messages,
+        vec![
+            "Bundle start",
+            "Bundle chunk 1/2",
+            "Bundle chunk 2/2",
+            "Bundle end"
+        ]
+    );
+    assert!(bundle_path.file_size().as_u64() < (CHUNK * 3) as u64);
+
+    unbundle(&bundle_path, &receiver, |_| {})?;
+    let folded = output_file_path("labs-google.har.bundle-folded").delete()?;
+    fold_file(&receiver, &folded, |_| {})?;
+    assert_eq!(folded.read_bytes()?, modified);
+    Ok(())
+}
+
+#[test]
+fn test_unbundle_rejects_missing_and_corrupted_chunks() -> Result<()> {
+    let sender = unfold_file(
+        &fixture_path("labs-google_fx_tools_flow.png"),
+        &output_folder_path("labs-google_fx_tools_flow.png.bundle-sender")
+            .delete()?,
+        |_| {},
+    )?;
+    let have = read_inventory(&sender)?;
+    let first = have.iter().next().unwrap().clone();
+    let have = have
+        .into_iter()
+        .filter(|name| *name != first)
+        .collect::<BTreeSet<_>>();
+
+    let bundle_path =
+        output_file_path("labs-google_fx_tools_flow.png.bundle").delete()?;
+    bundle(&sender, &have, &bundle_path, |_| {})?;
+    let receiver =
+        output_folder_path("labs-google_fx_tools_flow.png.bundle-receiver")
+            .delete()?;
+    match unbundle(&bundle_path, &receiver, |_| {}) {
+        Err(Error::BundleOutputError(_)) => {},
+        other => panic!("expected BundleOutputError, got {other:?}"),
+    }
+    assert!(!receiver.exists());
+
+    let mut bytes = bundle_path.read_bytes()?;
+    let last = bytes.len() - 10;
+    bytes[last] ^= 0xFF;
+    bundle_path.write(&bytes)?;
+    match unbundle(&bundle_path, &receiver, |_| {}) {
+        Err(Error::CorruptedDataError(_)) => {},
+        other => panic!("expected CorruptedDataError, got {other:?}"),
+    }
+    Ok(())
+}
+
+#[test]
+fn test_unbundle_repairs_corrupted_local_chunks() -> Result<()> {
+    let input_path = fixture_path("labs-google_fx_tools_flow.png");
+    let sender = unfold_file(
+        &input_path,
+        &output_folder_path("labs-google_fx_tools_flow.png.repair-sender")
+            .delete()?,
+        |_| {},
+    )?;
+    let receiver = unfold_file(
+        &input_path,
+        &output_folder_path("labs-google_fx_tools_flow.png.repair-receiver")
+            .delete()?,
+        |_| {},
+    )?;
+    let have = read_inventory(&receiver)?;
+    let first = have.iter().next().unwrap().clone();
+    let have = have
+        .into_iter()
+        .filter(|name| *name != first)
+        .collect::<BTreeSet<_>>();
+    let bundle_path =
+        output_file_path("labs-google_fx_tools_flow.png.repair-bundle")
+            .delete()?;
+    bundle(&sender, &have, &bundle_path, |_| {})?;
+
+    let store = DirStore::new(&receiver);
+    store.put(&first, b"corrupted")?;
+    assert!(verify_store(&store, |_| {}).is_err());
+
+    unbundle_to_store(&bundle_path, &store, |_| {})?;
+    verify_store(&store, |_| {})?;
+    let folded =
+        output_file_path("labs-google_fx_tools_flow.png.repair-folded")
+            .delete()?;
+    fold_file(&receiver, &folded, |_| {})?;
+    assert_eq!(folded.read_bytes()?, input_path.read_bytes()?);
+    Ok(())
+}
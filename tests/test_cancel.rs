@@ -0,0 +1,218 @@
+use unfolder::{
+    adopt_with_cancel, fold_file_with_cancel, unfold_file_with_cancel,
+    unfold_to_shares_with_cancel, unfold_to_store, unfold_to_store_with_cancel,
+    unfold_to_volumes_with_cancel, verify_store, CancelToken, ChunkLayout,
+    ChunkStore, DirStore, Error, MemoryStore, Progress, Result, VolumeSpec,
+};
+
+mod common;
+use common::{fixture_path, output_file_path, output_folder_path};
+
+/// progress callback cancelling `cancel` once chunk `at` is done
+fn cancel_at(cancel: &CancelToken, at: usize) -> impl FnMut(Progress) {
+    let cancel = cancel.clone();
+    move |progress| {
+        if let Progress::Chunk { index, .. } = progress {
+            if index == at {
+                cancel.cancel();
+            }
+        }
+    }
+}
+
+#[test]
+fn test_cancelled_unfold_removes_or_keeps_its_output() -> Result<()> {
+    let input_path = fixture_path("labs-google.har");
+    let output_path = output_folder_path("cancel-unfolded").delete()?;
+    let layout = ChunkLayout::default();
+    let cancel = CancelToken::new();
+    let progress = cancel_at(&cancel, 10);
+    match unfold_file_with_cancel(
+        &input_path,
+        &output_path,
+        layout,
+        1,
+        &cancel,
+        progress,
+    ) {
+        Err(Error::Cancelled(_)) => {},
+        other => panic!("expected Cancelled, got {other:?}"),
+    }
+    assert!(!output_path.exists());
+
+    let cancel = CancelToken::new().keeping_partial_output();
+    let progress = cancel_at(&cancel, 10);
+    let result = unfold_file_with_cancel(
+        &input_path,
+        &output_path,
+        layout,
+        1,
+        &cancel,
+        progress,
+    );
+    assert!(matches!(result, Err(Error::Cancelled(_))), "{result:?}");
+    let store = DirStore::new(&output_path);
+    assert!((10..62).contains(&store.list()?.len()));
+    assert!(matches!(store.get_index(), Err(Error::MissingIndexError(_))));
+
+    output_path.delete()?;
+    let cancel = CancelToken::new();
+    unfold_file_with_cancel(
+        &input_path,
+        &output_path,
+        layout,
+        1,
+        &cancel,
+        |_| {},
+    )?;
+    assert_eq!(store.list()?.len(), 62);
+    Ok(())
+}
+
+#[test]
+fn test_cancelled_fold_leaves_no_output() -> Result<()> {
+    let input_path = fixture_path("labs-google.har");
+    let unfolded_path = output_folder_path("cancel-fold").delete()?;
+    let cancel = CancelToken::new();
+    let layout = ChunkLayout::default();
+    unfold_file_with_cancel(
+        &input_path,
+        &unfolded_path,
+        layout,
+        1,
+        &cancel,
+        |_| {},
+    )?;
+
+    let folded_path = output_file_path("cancel-folded.har").delete()?;
+    let progress = cancel_at(&cancel, 30);
+    match fold_file_with_cancel(
+        &unfolded_path,
+        &folded_path,
+        1,
+        &cancel,
+        progress,
+    ) {
+        Err(Error::Cancelled(_)) => {},
+        other => panic!("expected Cancelled, got {other:?}"),
+    }
+    assert!(!folded_path.exists());
+
+    let cancel = CancelToken::new();
+    fold_file_with_cancel(&unfolded_path, &folded_path, 1, &cancel, |_| {})?;
+    assert_eq!(folded_path.read_bytes()?, input_path.read_bytes()?);
+    Ok(())
+}
+
+#[test]
+fn test_cancelled_share_unfold_leaves_no_output() -> Result<()> {
+    let input_path = fixture_path("labs-google.har");
+    let output_path = output_folder_path("cancel-shares").delete()?;
+    let cancel = CancelToken::new();
+    let progress = cancel_at(&cancel, 10);
+    let result = unfold_to_shares_with_cancel(
+        &input_path,
+        &output_path,
+        3,
+        2,
+        &cancel,
+        progress,
+    );
+    assert!(matches!(result, Err(Error::Cancelled(_))), "{result:?}");
+    assert!(!output_path.exists());
+    Ok(())
+}
+
+#[test]
+fn test_cancelled_volume_unfold_removes_its_chunks() -> Result<()> {
+    let input_path = fixture_path("labs-google.har");
+    let root = output_folder_path("cancel-volumes").delete()?;
+    let existing = root.join("a").mkdir()?;
+    let volumes = [
+        VolumeSpec::new(&existing, 3_000_000),
+        VolumeSpec::new(&root.join("b"), 3_000_000),
+    ];
+    let cancel = CancelToken::new();
+    let progress = cancel_at(&cancel, 50);
+    let result = unfold_to_volumes_with_cancel(
+        &input_path,
+        &volumes,
+        ChunkLayout::default(),
+        1,
+        &cancel,
+        progress,
+    );
+    assert!(matches!(result, Err(Error::Cancelled(_))), "{result:?}");
+    assert!(existing.exists());
+    assert!(DirStore::new(&existing).list()?.is_empty());
+    assert!(!root.join("b").exists());
+    Ok(())
+}
+
+#[test]
+fn test_cancelled_store_unfold_deletes_the_chunks_it_wrote() -> Result<()> {
+    let store = MemoryStore::new();
+    let first = unfold_to_store(
+        &fixture_path("labs-google_fx_tools_flow.png"),
+        &store,
+        |_| {},
+    )?;
+    let kept = store.list()?;
+    let input_path = fixture_path("labs-google.har");
+    let layout = ChunkLayout::default().with_span(Some(4));
+    let cancel = CancelToken::new();
+    let progress = cancel_at(&cancel, 10);
+    let result = unfold_to_store_with_cancel(
+        &input_path,
+        &store,
+        layout,
+        4,
+        &cancel,
+        progress,
+    );
+    assert!(matches!(result, Err(Error::Cancelled(_))), "{result:?}");
+    assert_eq!(store.list()?, kept);
+    assert_eq!(verify_store(&store, |_| {})?, first);
+
+    let cancel = CancelToken::new().keeping_partial_output();
+    let progress = cancel_at(&cancel, 10);
+    let result = unfold_to_store_with_cancel(
+        &input_path,
+        &store,
+        layout,
+        4,
+        &cancel,
+        progress,
+    );
+    assert!(matches!(result, Err(Error::Cancelled(_))), "{result:?}");
+    // ten chunks and the two sub-indexes listing them, if not more
+    assert!(store.list()?.len() >= kept.len() + 12);
+    assert_eq!(verify_store(&store, |_| {})?, first);
+    Ok(())
+}
+
+#[test]
+fn test_cancelled_adopt_leaves_no_output() -> Result<()> {
+    let bytes = fixture_path("labs-google.har").read_bytes()?;
+    let parts_path = output_folder_path("cancel-adopt-parts")
+        .delete()?
+        .mkdir()?;
+    let parts = bytes
+        .chunks(1_000_000)
+        .enumerate()
+        .map(|(idx, part)| parts_path.join(format!("x{idx}")).write(part))
+        .collect::<std::result::Result<Vec<_>, _>>()?;
+    let output_path = output_folder_path("cancel-adopt").delete()?;
+    let cancel = CancelToken::new();
+    let progress = cancel_at(&cancel, 10);
+    let result = adopt_with_cancel(
+        &parts,
+        &output_path,
+        ChunkLayout::default(),
+        &cancel,
+        progress,
+    );
+    assert!(matches!(result, Err(Error::Cancelled(_))), "{result:?}");
+    assert!(!output_path.exists());
+    Ok(())
+}
@@ -0,0 +1,131 @@
+use std::process::Command;
+
+use unfolder::{diff_unfolded, unfold_file, ChangeKind, Diff, Result};
+
+mod common;
+use common::{fixture_path, output_file_path, output_folder_path};
+
+const CHUNK: usize = u16::MAX as usize;
+
+#[test]
+fn test_diff_changed_and_inserted_chunks() -> Result<()> {
+    let input_path = fixture_path("labs-google.har");
+    let bytes = input_path.read_bytes()?;
+
+    let mut modified = bytes.clone();
+    modified[CHUNK * 3 + 7] ^= 0xFF;
+    let inserted = vec![0x2A; CHUNK];
+    modified.splice(CHUNK * 10..CHUNK * 10, inserted);
+    let modified_path =
+        output_file_path("labs-google.har.modified").delete()?;
+    modified_path.write(&modified)?;
+
+    let a = unfold_file(
+        &input_path,
+        &output_folder_path("labs-google.har.diff-a").delete()?,
+        |_| {},
+    )?;
+    let b = unfold_file(
+        &modified_path,
+        &output_folder_path("labs-google.har.diff-b").delete()?,
+        |_| {},
+    )?;
+
+    let diff = diff_unfolded(&a, &b)?;
+    assert!(!diff.identical);
+    assert_eq!(diff.a_size, bytes.len() as u64);
+    assert_eq!(diff.b_size, modified.len() as u64);
+    assert_eq!(diff.b_chunks, diff.a_chunks + 1);
+    assert_eq!(diff.shared_chunks, diff.b_chunks - 2);
+    assert_eq!(
+        diff.ranges
+            .iter()
+            .map(|range| (
+                range.kind,
+                range.a_chunks.clone(),
+                range.b_chunks.clone()
+            ))
+            .collect::<Vec<_>>(),
+        vec![
+            (ChangeKind::Identical, 0..3, 0..3),
+            (ChangeKind::Changed, 3..4, 3..4),
+            (ChangeKind::Identical, 4..10, 4..10),
+            (ChangeKind::Inserted, 10..10, 10..11),
+            (ChangeKind::Identical, 10..diff.a_chunks, 11..diff.b_chunks),
+        ]
+    );
+    let changed = &diff.ranges[1];
+    assert_eq!(changed.a_bytes, (CHUNK * 3) as u64..(CHUNK * 4) as u64);
+    let inserted = &diff.ranges[3];
+    assert_eq!(inserted.a_bytes, (CHUNK * 10) as u64..(CHUNK * 10) as u64);
+    assert_eq!(inserted.b_bytes, (CHUNK * 10) as u64..(CHUNK * 11) as u64);
+    let last = &diff.ranges[4];
+    assert_eq!(last.a_bytes.end, bytes.len() as u64);
+    assert_eq!(last.b_bytes.end, modified.len() as u64);
+    Ok(())
+}
+
+#[test]
+fn test_diff_identical_files() -> Result<()> {
+    let a = unfold_file(
+        &fixture_path("policies-google-com_privacy.png"),
+        &output_folder_path("policies-google-com_privacy.png.diff-a")
+            .delete()?,
+        |_| {},
+    )?;
+    let b = unfold_file(
+        &fixture_path("policies-google-com_privacy-1.png"),
+        &output_folder_path("policies-google-com_privacy.png.diff-b")
+            .delete()?,
+        |_| {},
+    )?;
+    let diff = diff_unfolded(&a, &b)?;
+    assert!(diff.identical);
+    assert_eq!(diff.ranges.len(), 1);
+    assert_eq!(diff.ranges[0].kind, ChangeKind::Identical);
+    assert_eq!(diff.shared_ratio(), 1.0);
+    Ok(())
+}
+
+#[test]
+fn test_diff_json_output() -> Result<()> {
+    let a = unfold_file(
+        &fixture_path("deepmind.flow"),
+        &output_folder_path("deepmind.flow.diff-json-a").delete()?,
+        |_| {},
+    )?;
+    let b = unfold_file(
+        &fixture_path("labs-google_fx_tools_flow.png"),
+        &output_folder_path("deepmind.flow.diff-json-b").delete()?,
+        |_| {},
+    )?;
+    let output = Command::new(env!("CARGO_BIN_EXE_unfolder"))
+        .args([
+            "diff",
+            "--json",
+            &a.to_string(),
+            &b.to_string(),
+        ])
+        .output()?;
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    let json = serde_json::from_slice::<serde_json::Value>(&output.stdout)
+        .expect("diff --json should print json");
+    let diff = diff_unfolded(&a, &b)?;
+    assert_eq!(json["identical"], false);
+    assert_eq!(json["a_size"], diff.a_size);
+    assert_eq!(json["b_chunks"], diff.b_chunks);
+    assert_eq!(json["shared_chunks"], 0);
+    let range = &json["ranges"][0];
+    assert_eq!(range["kind"], "changed");
+    assert_eq!(range["a_chunks"]["start"], 0);
+    assert_eq!(range["b_bytes"]["end"], diff.b_size);
+    assert_eq!(
+        serde_json::from_value::<Diff>(json).expect("json should be a diff"),
+        diff
+    );
+    Ok(())
+}
@@ -0,0 +1,84 @@
+use unfolder::{
+    convert_index, fold_from_store, generate_key, read_signing_key, sign_store,
+    unfold_file, unfold_file_with_layout, verify_signed_store, ChunkLayout,
+    ChunkNaming, ChunkStore, DirStore, Error, FoldedReader, IndexEncoding,
+    Result,
+};
+
+mod common;
+use common::{fixture_path, output_file_path, output_folder_path};
+
+#[test]
+fn test_index_converts_between_encodings() -> Result<()> {
+    let secret_key_path = output_file_path("encoding-key").delete()?;
+    output_file_path("encoding-key.pub").delete()?;
+    generate_key(&secret_key_path)?;
+    let signing_key = read_signing_key(&secret_key_path)?;
+
+    let input_path = fixture_path("labs-google.har");
+    let unfolded_path = output_folder_path("encoding-unfolded").delete()?;
+    unfold_file(&input_path, &unfolded_path, |_| {})?;
+    let store = DirStore::new(&unfolded_path);
+    let index = sign_store(&store, &signing_key)?;
+    let yaml_size = store.get_index()?.len();
+
+    for encoding in IndexEncoding::ALL.into_iter().rev() {
+        let converted = convert_index(&store, encoding, |_| {})?;
+        assert_eq!(converted.layout().encoding, encoding);
+        assert_eq!(IndexEncoding::detect(&store.get_index()?), encoding);
+        let read = store.read_index()?;
+        assert_eq!(read, converted);
+        assert_eq!(read.chunks(), index.chunks());
+        assert_eq!(read.merkle_root(), index.merkle_root());
+        verify_signed_store(&store, &[signing_key.verifying_key()], |_| {})?;
+        if encoding == IndexEncoding::Cbor {
+            assert!(store.get_index()?.len() < yaml_size / 2);
+            let folded_path =
+                output_file_path("encoding-folded.har").delete()?;
+            fold_from_store(&store, &folded_path, |_| {})?;
+            assert_eq!(folded_path.read_bytes()?, input_path.read_bytes()?);
+        }
+    }
+    assert_eq!(store.get_index()?.len(), yaml_size);
+
+    let mut garbage = store.get_index()?;
+    garbage.splice(0..0, [0xD9, 0xD9, 0xF7, 0xFF]);
+    store.put_index(&garbage)?;
+    match store.read_index() {
+        Err(Error::UnreadableIndexError(message)) =>
+            assert!(message.starts_with("invalid cbor in"), "{message}"),
+        other => panic!("expected UnreadableIndexError, got {other:?}"),
+    }
+    Ok(())
+}
+
+#[test]
+fn test_subindexes_follow_the_encoding_of_the_index() -> Result<()> {
+    let input_path = fixture_path("labs-google.har");
+    let input = input_path.read_bytes()?;
+    let unfolded_path = output_folder_path("encoding-manifest").delete()?;
+    let layout = ChunkLayout::new(ChunkNaming::Hash, 0)
+        .with_span(Some(16))
+        .with_encoding(IndexEncoding::Cbor);
+    unfold_file_with_layout(&input_path, &unfolded_path, layout, |_| {})?;
+    let store = DirStore::new(&unfolded_path);
+
+    let reader = FoldedReader::open(&unfolded_path)?;
+    let manifest = reader.manifest().unwrap().clone();
+    assert_eq!(manifest.layout(), layout);
+    for idx in 0..manifest.subindexes().len() {
+        let bytes = store.get(&manifest.subindex_name(idx))?;
+        assert_eq!(IndexEncoding::detect(&bytes), IndexEncoding::Cbor);
+    }
+    let mut reader = reader.clone();
+    assert_eq!(reader.read_chunk(61)?, &input[61 * 65535..]);
+
+    let index = convert_index(&store, IndexEncoding::Json, |_| {})?;
+    assert_eq!(index.layout(), layout.with_encoding(IndexEncoding::Json));
+    assert_eq!(store.list()?.len(), 62 + 4);
+    assert!(!store.has(&manifest.subindex_name(0))?);
+    let folded_path = output_file_path("encoding-manifest.har").delete()?;
+    fold_from_store(&store, &folded_path, |_| {})?;
+    assert_eq!(folded_path.read_bytes()?, input);
+    Ok(())
+}
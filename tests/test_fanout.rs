@@ -0,0 +1,130 @@
+use unfolder::{
+    export_to_file, fold_from_store, gc_store, open_store, relayout_store,
+    unfold_file_with_layout, verify_store, ArchiveFormat, ChunkLayout,
+    ChunkNaming, ChunkStore, DirStore, Error, Result,
+};
+
+mod common;
+use common::{fixture_path, output_file_path, output_folder_path};
+
+#[test]
+fn test_unfold_with_fanout() -> Result<()> {
+    let input_path = fixture_path("labs-google_fx_tools_flow.png");
+    let output_path = output_folder_path("fanout-unfolded").delete()?;
+    let layout = ChunkLayout::new(ChunkNaming::Hash, 2);
+    unfold_file_with_layout(&input_path, &output_path, layout, |_| {})?;
+    let store = DirStore::new(&output_path);
+    let index = verify_store(&store, |_| {})?;
+    assert_eq!(index.layout(), layout);
+    assert!(output_path
+        .join("index")
+        .read()?
+        .contains("fanout: '2'"));
+    for sha256 in index.chunks() {
+        let name = format!("{}/{}/{sha256}", &sha256[0..2], &sha256[2..4]);
+        assert!(output_path.join(&name).is_file(), "{name}");
+    }
+    assert_eq!(
+        store.list()?,
+        index
+            .chunk_files()?
+            .into_keys()
+            .collect::<Vec<String>>()
+    );
+
+    let stray = format!("ff/ee/ffee{}", "0".repeat(60));
+    store.put(&stray, b"stray")?;
+    assert_eq!(gc_store(&store, |_| {})?, vec![stray]);
+    assert!(!output_path.join("ff").exists());
+
+    let too_deep = ChunkLayout::new(ChunkNaming::Hash, 5);
+    let too_deep_path = output_folder_path("fanout-too-deep").delete()?;
+    match unfold_file_with_layout(&input_path, &too_deep_path, too_deep, |_| {})
+    {
+        Err(Error::FlattenFileOutputError(message)) =>
+            assert!(message.contains("fan-out 5"), "{message}"),
+        other => panic!("expected FlattenFileOutputError, got {other:?}"),
+    }
+    Ok(())
+}
+
+#[test]
+fn test_relayout_between_flat_and_fanout() -> Result<()> {
+    let input_path = fixture_path("labs-google_fx_tools_flow-1.png");
+    let output_path = output_folder_path("relayout-unfolded").delete()?;
+    let flat = ChunkLayout::new(ChunkNaming::Hash, 0);
+    unfold_file_with_layout(&input_path, &output_path, flat, |_| {})?;
+    let store = DirStore::new(&output_path);
+
+    let fanned_out = ChunkLayout::new(ChunkNaming::Ordered, 3);
+    let index = relayout_store(&store, fanned_out, |_| {})?;
+    assert_eq!(verify_store(&store, |_| {})?, index);
+    assert_eq!(
+        store.list()?,
+        index
+            .chunk_files()?
+            .into_keys()
+            .collect::<Vec<String>>()
+    );
+    assert!(store
+        .list()?
+        .iter()
+        .all(|name| name.matches('/').count() == 3));
+
+    let archive_path = output_file_path("relayout.tar").delete()?;
+    export_to_file(&store, ArchiveFormat::Tar, &archive_path, |_| {})?;
+    let archive = open_store(&archive_path.to_string())?;
+    let folded_path = output_file_path("relayout-archive.png").delete()?;
+    fold_from_store(archive.as_ref(), &folded_path, |_| {})?;
+    assert_eq!(folded_path.read_bytes()?, input_path.read_bytes()?);
+
+    let index = relayout_store(&store, flat, |_| {})?;
+    assert_eq!(index.layout(), flat);
+    assert_eq!(store.list()?, index.chunks().to_vec());
+    assert!(output_path
+        .list()?
+        .iter()
+        .all(|path| path.is_file()));
+    assert_eq!(relayout_store(&store, flat, |_| {})?, index);
+    assert_eq!(verify_store(&store, |_| {})?, index);
+
+    let too_deep = ChunkLayout::new(ChunkNaming::Hash, 5);
+    match relayout_store(&store, too_deep, |_| {}) {
+        Err(Error::StoreError(message)) =>
+            assert!(message.contains("fan-out 5"), "{message}"),
+        other => panic!("expected StoreError, got {other:?}"),
+    }
+    assert_eq!(store.read_index()?, index);
+    Ok(())
+}
+
+#[test]
+fn test_index_fanout_is_validated() -> Result<()> {
+    let input_path = fixture_path("labs-google_fx_tools_flow-1.png");
+    let output_path = output_folder_path("fanout-mismatch").delete()?;
+    let layout = ChunkLayout::new(ChunkNaming::Hash, 1);
+    unfold_file_with_layout(&input_path, &output_path, layout, |_| {})?;
+    let store = DirStore::new(&output_path);
+    let index = store.read_index()?;
+    store.write_index(&index.clone().with_layout(ChunkLayout::default()))?;
+    match verify_store(&store, |_| {}) {
+        Err(Error::InvalidIndexError(message)) => {
+            assert!(
+                message.contains("hash naming with fan-out 1"),
+                "{message}"
+            );
+        },
+        other => panic!("expected InvalidIndexError, got {other:?}"),
+    }
+
+    let yaml = index.to_yaml()?;
+    store.put_index(
+        yaml.replace("fanout: '1'", "fanout: '5'")
+            .as_bytes(),
+    )?;
+    match store.read_index() {
+        Err(Error::InvalidIndexError(_)) => {},
+        other => panic!("expected InvalidIndexError, got {other:?}"),
+    }
+    Ok(())
+}
@@ -1,30 +1,9 @@
-use iocore::Path;
 use unfolder::{fold_file, unfold_file, Result};
 
-fn fixtures_path() -> Path {
-    let path = Path::new(file!())
-        .parent()
-        .unwrap()
-        .join("fixtures");
-    assert!(path.is_dir(), "{path} should exist");
-    path
-}
-fn output_file_path(name: &str) -> Path {
-    Path::new(file!())
-        .parent()
-        .unwrap()
-        .join("output")
-        .join(name)
-        .mkdir_parents()
-        .unwrap()
-}
-fn output_folder_path(name: &str) -> Path {
-    output_file_path(name).mkdir().unwrap()
-}
-
-fn fixture_path(name: &str) -> Path {
-    fixtures_path().join(name)
-}
+mod common;
+use common::{
+    fixture_path, fixtures_path, output_file_path, output_folder_path,
+};
 
 #[test]
 fn test_unfold_file() -> Result<()> {
@@ -0,0 +1,194 @@
+use std::io::Write;
+use std::process::{Command, Output, Stdio};
+
+use iocore::Path;
+use unfolder::{
+    git_clean, is_git_pointer, ChunkStore, DirStore, MemoryStore, Result,
+    UnfoldIndex,
+};
+
+mod common;
+use common::{fixture_path, output_folder_path};
+
+const UNFOLDER: &str = env!("CARGO_BIN_EXE_unfolder");
+
+fn run_git(repo: &Path, args: &[&str]) -> Output {
+    Command::new("git")
+        .arg("-C")
+        .arg(repo.to_string())
+        .args(args)
+        .env("GIT_CONFIG_NOSYSTEM", "1")
+        .env("GIT_CONFIG_GLOBAL", "/dev/null")
+        .env_remove("UNFOLDER_GIT_STORE")
+        .output()
+        .expect("git should be installed")
+}
+
+fn git(repo: &Path, args: &[&str]) -> Vec<u8> {
+    let output = run_git(repo, args);
+    assert!(
+        output.status.success(),
+        "git {args:?} failed: {}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    output.stdout
+}
+
+/// creates a repository filtering `*.bin` files through `filter`, a
+/// list of `filter.unfolder.*` settings
+fn init_repo(name: &str, filter: &[(&str, String)]) -> Result<Path> {
+    let repo = output_folder_path(name).delete()?.mkdir()?;
+    git(&repo, &["init", "-q"]);
+    git(&repo, &["config", "user.name", "unfolder"]);
+    git(
+        &repo,
+        &[
+            "config",
+            "user.email",
+            "unfolder@localhost",
+        ],
+    );
+    for (key, value) in filter {
+        git(
+            &repo,
+            &[
+                "config",
+                &format!("filter.unfolder.{key}"),
+                value,
+            ],
+        );
+    }
+    git(
+        &repo,
+        &[
+            "config",
+            "filter.unfolder.required",
+            "true",
+        ],
+    );
+    repo.join(".gitattributes")
+        .write(b"*.bin filter=unfolder\n")?;
+    Ok(repo)
+}
+
+fn committed_index(repo: &Path, name: &str) -> UnfoldIndex {
+    let blob = git(repo, &["cat-file", "-p", &format!("HEAD:{name}")]);
+    assert!(is_git_pointer(&blob), "{name} should be committed as a pointer");
+    let store = MemoryStore::new();
+    store.put_index(&blob).unwrap();
+    store.read_index().unwrap()
+}
+
+#[test]
+fn test_git_clean_and_smudge_filters() -> Result<()> {
+    let repo = init_repo(
+        "git-filter-clean-smudge",
+        &[
+            ("clean", format!("{UNFOLDER} git-filter clean %f")),
+            ("smudge", format!("{UNFOLDER} git-filter smudge %f")),
+        ],
+    )?;
+    let input_path = fixture_path("labs-google_fx_tools_flow.png");
+    let bytes = input_path.read_bytes()?;
+    repo.join("image.bin").write(&bytes)?;
+    repo.join("notes.txt").write(b"not filtered\n")?;
+    git(&repo, &["add", "."]);
+    git(&repo, &["commit", "-q", "-m", "add image"]);
+
+    assert_eq!(
+        git(&repo, &["cat-file", "-p", "HEAD:notes.txt"]),
+        b"not filtered\n"
+    );
+    let blob = git(&repo, &["cat-file", "-p", "HEAD:image.bin"]);
+    assert!(is_git_pointer(&blob));
+    assert!(blob.len() < 1024);
+    let store = DirStore::new(&repo.join(".git").join("unfolder"));
+    assert_eq!(store.list()?.len(), 4);
+
+    repo.join("image.bin").delete()?;
+    git(&repo, &["checkout", "--", "image.bin"]);
+    assert_eq!(repo.join("image.bin").read_bytes()?, bytes);
+    assert_eq!(git(&repo, &["status", "--porcelain"]), b"");
+    Ok(())
+}
+
+#[test]
+fn test_git_smudge_errors_name_the_filtered_path() -> Result<()> {
+    let bytes = fixture_path("labs-google_fx_tools_flow.png").read_bytes()?;
+    let pointer = git_clean(&MemoryStore::new(), &bytes)?;
+    let store = output_folder_path("git-filter-smudge-missing-store")
+        .delete()?
+        .mkdir()?;
+    let mut child = Command::new(UNFOLDER)
+        .args([
+            "git-filter",
+            "--store",
+            &store.to_string(),
+        ])
+        .args(["smudge", "images/image.bin"])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()?;
+    child.stdin.take().unwrap().write_all(&pointer)?;
+    let output = child.wait_with_output()?;
+    assert!(!output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("smudge images/image.bin: "), "{stderr}");
+    Ok(())
+}
+
+#[test]
+fn test_git_filter_process_shares_chunks_across_versions_and_clones(
+) -> Result<()> {
+    let store_path = output_folder_path("git-filter-process-store")
+        .delete()?
+        .mkdir()?
+        .canonicalize()?;
+    let process = format!("{UNFOLDER} git-filter process --store {store_path}");
+    let repo =
+        init_repo("git-filter-process", &[("process", process.clone())])?;
+    let input_path = fixture_path("labs-google.har");
+    let mut bytes = input_path.read_bytes()?;
+    repo.join("data.bin").write(&bytes)?;
+    git(&repo, &["add", "data.bin", ".gitattributes"]);
+    git(&repo, &["commit", "-q", "-m", "v1"]);
+    let store = DirStore::new(&store_path);
+    let first = committed_index(&repo, "data.bin");
+    let chunk_count = store.list()?.len();
+
+    bytes[u16::MAX as usize * 3 + 7] ^= 0xFF;
+    repo.join("data.bin").write(&bytes)?;
+    git(&repo, &["commit", "-q", "-a", "-m", "v2"]);
+    let second = committed_index(&repo, "data.bin");
+    assert_eq!(store.list()?.len(), chunk_count + 1);
+    assert_eq!(second.chunk_count(), first.chunk_count());
+    assert_ne!(second.chunks()[3], first.chunks()[3]);
+
+    let clone = output_folder_path("git-filter-process-clone").delete()?;
+    git(
+        &Path::new("."),
+        &[
+            "clone",
+            "-q",
+            "-c",
+            &format!("filter.unfolder.process={process}"),
+            "-c",
+            "filter.unfolder.required=true",
+            &repo.to_string(),
+            &clone.to_string(),
+        ],
+    );
+    assert_eq!(clone.join("data.bin").read_bytes()?, bytes);
+
+    store.delete(&second.chunks()[3])?;
+    clone.join("data.bin").delete()?;
+    let output = run_git(&clone, &["checkout", "--", "data.bin"]);
+    assert!(!output.status.success(), "checkout should fail without the chunk");
+    assert!(
+        String::from_utf8_lossy(&output.stderr).contains(&second.chunks()[3]),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    Ok(())
+}
@@ -0,0 +1,131 @@
+use iocore::PathDateTime;
+use unfolder::{
+    dehydrate, hydrate, is_stub, ChunkStore, DirStore, Error, MemoryStore,
+    Result, Stub,
+};
+
+mod common;
+use common::{fixture_path, output_folder_path};
+
+/// [`ChunkStore`] which flips the first byte of every chunk it returns
+struct CorruptingStore(MemoryStore);
+
+impl ChunkStore for CorruptingStore {
+    fn put(&self, name: &str, bytes: &[u8]) -> Result<()> {
+        self.0.put(name, bytes)
+    }
+
+    fn get(&self, name: &str) -> Result<Vec<u8>> {
+        let mut bytes = self.0.get(name)?;
+        bytes[0] ^= 0xFF;
+        Ok(bytes)
+    }
+
+    fn has(&self, name: &str) -> Result<bool> {
+        self.0.has(name)
+    }
+
+    fn list(&self) -> Result<Vec<String>> {
+        self.0.list()
+    }
+
+    fn delete(&self, name: &str) -> Result<()> {
+        self.0.delete(name)
+    }
+
+    fn put_index(&self, bytes: &[u8]) -> Result<()> {
+        self.0.put_index(bytes)
+    }
+
+    fn get_index(&self) -> Result<Vec<u8>> {
+        self.0.get_index()
+    }
+
+    fn location(&self) -> String {
+        "corrupting".to_string()
+    }
+}
+
+#[test]
+fn test_dehydrate_and_hydrate_directory() -> Result<()> {
+    let tree = output_folder_path("hydrate-tree")
+        .delete()?
+        .mkdir()?;
+    let mut image = tree.join("images").join("flow.png");
+    let har = tree
+        .join("nested")
+        .join("deeper")
+        .join("labs-google.har");
+    let empty = tree.join("empty.txt");
+    image
+        .write(&fixture_path("labs-google_fx_tools_flow.png").read_bytes()?)?;
+    har.write(&fixture_path("labs-google.har").read_bytes()?)?;
+    empty.write(b"")?;
+    image.set_mode(0o600)?;
+    image.set_modified_time(&PathDateTime::from_timestamp(
+        1_600_000_000,
+        123_456_789,
+    ))?;
+    let store_path = tree.join(".store");
+    let store = DirStore::new(&store_path);
+
+    let dehydrated = dehydrate(&tree, &store, |_| {})?;
+    assert_eq!(dehydrated, vec![image.clone(), har.clone()]);
+    assert!(is_stub(&image)?);
+    assert!(is_stub(&har)?);
+    assert!(!is_stub(&empty)?);
+    assert!(har.file_size().as_u64() < 8192);
+    let stub = Stub::read(&image)?;
+    assert_eq!(stub.store(), store_path.to_string());
+    assert_eq!(stub.mode(), 0o600);
+    assert_eq!(stub.modified(), (1_600_000_000, 123_456_789));
+    assert!(dehydrate(&tree, &store, |_| {})?.is_empty());
+    match dehydrate(&har, &store, |_| {}) {
+        Err(Error::StubError(_)) => {},
+        other => panic!("expected StubError, got {other:?}"),
+    }
+
+    let chunks = store.list()?;
+    let hydrated = hydrate(&tree, |_| {})?;
+    assert_eq!(hydrated, vec![image.clone(), har.clone()]);
+    assert_eq!(
+        image.read_bytes()?,
+        fixture_path("labs-google_fx_tools_flow.png").read_bytes()?
+    );
+    assert_eq!(
+        har.read_bytes()?,
+        fixture_path("labs-google.har").read_bytes()?
+    );
+    assert_eq!(image.mode(), 0o600);
+    assert_eq!(
+        image.modified().unwrap().timestamp(),
+        (1_600_000_000, 123_456_789)
+    );
+    assert_eq!(store.list()?, chunks);
+    assert!(hydrate(&tree, |_| {})?.is_empty());
+    Ok(())
+}
+
+#[test]
+fn test_dehydrate_refuses_unverifiable_store() -> Result<()> {
+    let folder = output_folder_path("hydrate-refused")
+        .delete()?
+        .mkdir()?;
+    let bytes = fixture_path("deepmind.flow").read_bytes()?;
+    let path = folder.join("deepmind.flow").write(&bytes)?;
+    let store = CorruptingStore(MemoryStore::new());
+
+    match dehydrate(&path, &store, |_| {}) {
+        Err(Error::StubError(message)) =>
+            assert!(message.contains("refusing"), "{message}"),
+        other => panic!("expected StubError, got {other:?}"),
+    }
+    assert_eq!(path.read_bytes()?, bytes);
+    assert_eq!(folder.list()?, vec![path.clone()]);
+
+    match hydrate(&path, |_| {}) {
+        Err(Error::StubError(_)) => {},
+        other => panic!("expected StubError, got {other:?}"),
+    }
+    Ok(())
+}
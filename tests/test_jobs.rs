@@ -0,0 +1,132 @@
+use unfolder::{
+    fold_from_store_with_cancel, unfold_to_store_with_cancel, verify_store,
+    Action, CancelToken, ChunkLayout, ChunkNaming, ChunkStore, DirStore, Error,
+    MemoryStore, Progress, Result,
+};
+
+mod common;
+use common::{fixture_path, output_file_path, output_folder_path};
+
+fn chunk_events(action: Action, count: usize) -> Vec<Progress> {
+    let chunks = (1..=count).map(|index| Progress::Chunk {
+        index,
+        count,
+        action,
+    });
+    [Progress::Start(action)]
+        .into_iter()
+        .chain(chunks)
+        .chain([Progress::End(action)])
+        .collect()
+}
+
+#[test]
+fn test_parallel_jobs_keep_progress_and_layout_in_order() -> Result<()> {
+    let cancel = CancelToken::new();
+    let input_path = fixture_path("labs-google.har");
+    let output_path = output_folder_path("jobs-unfolded").delete()?;
+    let store = DirStore::new(&output_path);
+    let layout = ChunkLayout::new(ChunkNaming::Ordered, 1).with_span(Some(4));
+    let mut events = Vec::<Progress>::new();
+    unfold_to_store_with_cancel(
+        &input_path,
+        &store,
+        layout,
+        4,
+        &cancel,
+        |event| events.push(event),
+    )?;
+    assert_eq!(events, chunk_events(Action::Unfold, 62));
+    let index = verify_store(&store, |_| {})?;
+    assert_eq!(index.layout(), layout);
+    assert_eq!(
+        store.list()?,
+        index
+            .chunk_files()?
+            .into_keys()
+            .collect::<Vec<String>>()
+    );
+
+    let folded_path = output_file_path("jobs-folded.har").delete()?;
+    let mut events = Vec::<Progress>::new();
+    fold_from_store_with_cancel(&store, &folded_path, 4, &cancel, |event| {
+        events.push(event)
+    })?;
+    assert_eq!(events, chunk_events(Action::Fold, 62));
+    assert_eq!(folded_path.read_bytes()?, input_path.read_bytes()?);
+    Ok(())
+}
+
+#[test]
+fn test_parallel_jobs_stop_at_the_first_bad_chunk() -> Result<()> {
+    let cancel = CancelToken::new();
+    let input_path = fixture_path("labs-google.har");
+    let store = MemoryStore::new();
+    let layout = ChunkLayout::new(ChunkNaming::Hash, 0);
+    unfold_to_store_with_cancel(
+        &input_path,
+        &store,
+        layout,
+        0,
+        &cancel,
+        |_| {},
+    )?;
+    let index = store.read_index()?;
+    store.put(&index.chunk_name(40), b"tampered")?;
+
+    let folded_path = output_file_path("jobs-tampered.har").delete()?;
+    let mut events = Vec::<Progress>::new();
+    let result = fold_from_store_with_cancel(
+        &store,
+        &folded_path,
+        4,
+        &cancel,
+        |event| events.push(event),
+    );
+    match result {
+        Err(Error::CorruptedDataError(message)) =>
+            assert!(message.contains(&index.chunks()[40]), "{message}"),
+        other => panic!("expected CorruptedDataError, got {other:?}"),
+    }
+    assert_eq!(events, chunk_events(Action::Fold, 62)[..41]);
+    assert!(!folded_path.exists());
+    Ok(())
+}
+
+#[test]
+fn test_folding_into_a_file_removes_it_unless_the_whole_file_matches(
+) -> Result<()> {
+    let input_path = fixture_path("labs-google.har");
+    let store = MemoryStore::new();
+    unfold_to_store_with_cancel(
+        &input_path,
+        &store,
+        ChunkLayout::default(),
+        4,
+        &CancelToken::new(),
+        |_| {},
+    )?;
+    // every chunk is intact, so that the mismatch only shows once the
+    // whole file has been written
+    let yaml = String::from_utf8(store.get_index()?).unwrap();
+    let sha256 = hex::encode(store.read_index()?.sha256());
+    store.put_index(yaml.replace(&sha256, &"0".repeat(64)).as_bytes())?;
+
+    let folded_path = output_file_path("jobs-mismatch.har").delete()?;
+    let mut events = Vec::<Progress>::new();
+    let result = fold_from_store_with_cancel(
+        &store,
+        &folded_path,
+        4,
+        &CancelToken::new().keeping_partial_output(),
+        |event| events.push(event),
+    );
+    match result {
+        Err(Error::CorruptedDataError(message)) =>
+            assert!(message.contains(&sha256), "{message}"),
+        other => panic!("expected CorruptedDataError, got {other:?}"),
+    }
+    assert_eq!(events, chunk_events(Action::Fold, 62)[..63]);
+    assert!(!folded_path.exists());
+    Ok(())
+}
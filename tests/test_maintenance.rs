@@ -0,0 +1,127 @@
+use unfolder::{
+    gc_store, migrate_store, unfold_file, unfold_file_with_layout,
+    unfold_to_store, verify_store, ChunkLayout, ChunkNaming, ChunkStore,
+    DirStore, Error, MemoryStore, Result,
+};
+
+mod common;
+use common::{fixture_path, output_folder_path};
+
+#[test]
+fn test_verify_store_detects_corrupted_and_missing_chunks() -> Result<()> {
+    let unfolded = unfold_file(
+        &fixture_path("labs-google_fx_tools_flow.png"),
+        &output_folder_path("labs-google_fx_tools_flow.png.verify").delete()?,
+        |_| {},
+    )?;
+    let store = DirStore::new(&unfolded);
+    let mut messages = Vec::<String>::new();
+    let index = verify_store(&store, |progress| {
+        messages.push(progress.to_string());
+    })?;
+    assert_eq!(messages.first().unwrap(), "Verify start");
+    assert_eq!(messages.last().unwrap(), "Verify end");
+    assert_eq!(messages.len(), index.chunk_count() + 2);
+
+    let name = &index.chunks()[2];
+    let mut bytes = store.get(name)?;
+    bytes[0] ^= 0xFF;
+    store.put(name, &bytes)?;
+    match verify_store(&store, |_| {}) {
+        Err(Error::CorruptedDataError(message)) =>
+            assert!(message.contains(name.as_str()), "{message}"),
+        other => panic!("expected CorruptedDataError, got {other:?}"),
+    }
+
+    store.delete(name)?;
+    match verify_store(&store, |_| {}) {
+        Err(Error::InvalidIndexError(_)) => {},
+        other => panic!("expected InvalidIndexError, got {other:?}"),
+    }
+    Ok(())
+}
+
+#[test]
+fn test_gc_store_removes_unreferenced_chunks() -> Result<()> {
+    let store = MemoryStore::new();
+    match gc_store(&store, |_| {}) {
+        Err(Error::MissingIndexError(_)) => {},
+        other => panic!("expected MissingIndexError, got {other:?}"),
+    }
+    let first = unfold_to_store(
+        &fixture_path("labs-google_fx_tools_flow.png"),
+        &store,
+        |_| {},
+    )?;
+    let second = unfold_to_store(
+        &fixture_path("labs-google_fx_tools_flow-1.png"),
+        &store,
+        |_| {},
+    )?;
+    let removed = gc_store(&store, |_| {})?;
+    let mut expected = first.chunks().to_vec();
+    expected.retain(|name| !second.chunks().contains(name));
+    expected.sort();
+    assert_eq!(removed, expected);
+
+    let mut remaining = second.chunks().to_vec();
+    remaining.sort();
+    remaining.dedup();
+    assert_eq!(store.list()?, remaining);
+    verify_store(&store, |_| {})?;
+    assert_eq!(gc_store(&store, |_| {})?, Vec::<String>::new());
+    Ok(())
+}
+
+#[test]
+fn test_gc_store_leaves_other_files_alone() -> Result<()> {
+    let output_path = output_folder_path("gc-other-files").delete()?;
+    let layout = ChunkLayout::new(ChunkNaming::Ordered, 1).with_span(Some(2));
+    unfold_file_with_layout(
+        &fixture_path("labs-google_fx_tools_flow.png"),
+        &output_path,
+        layout,
+        |_| {},
+    )?;
+    let store = DirStore::new(&output_path);
+    let sha256 = "ab".repeat(32);
+    let stale = [
+        format!("ab/000009-{sha256}"),
+        format!("ab/{sha256}"),
+    ];
+    for name in &stale {
+        store.put(name, b"stale")?;
+    }
+    let others = [
+        "README".to_string(),
+        "notes.txt".to_string(),
+        sha256.clone(),
+        format!("ab/000009-{sha256}.txt"),
+        format!("notes/000009-{sha256}"),
+    ];
+    for name in &others {
+        output_path.join(name).write(b"kept")?;
+    }
+
+    let mut removed = gc_store(&store, |_| {})?;
+    removed.sort();
+    assert_eq!(removed, stale);
+    for name in &others {
+        assert_eq!(output_path.join(name).read_bytes()?, b"kept", "{name}");
+    }
+    verify_store(&store, |_| {})?;
+    Ok(())
+}
+
+#[test]
+fn test_migrate_store_between_backends() -> Result<()> {
+    let source = MemoryStore::new();
+    let index =
+        unfold_to_store(&fixture_path("deepmind.flow"), &source, |_| {})?;
+    let target =
+        DirStore::new(&output_folder_path("deepmind.flow.migrated").delete()?);
+    assert_eq!(migrate_store(&source, &target, |_| {})?, index);
+    assert_eq!(verify_store(&target, |_| {})?, index);
+    assert_eq!(target.list()?, source.list()?);
+    Ok(())
+}
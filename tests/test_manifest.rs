@@ -0,0 +1,194 @@
+use std::io::{Read, Seek, SeekFrom};
+
+use unfolder::{
+    convert_index, fold_file, gc_store, relayout_store,
+    unfold_file_with_layout, verify_store, ChunkLayout, ChunkNaming,
+    ChunkStore, DirStore, Error, FoldedReader, IndexEncoding, MerkleProof,
+    Result,
+};
+
+mod common;
+use common::{fixture_path, output_file_path, output_folder_path};
+
+#[test]
+fn test_unfold_to_hierarchical_manifest() -> Result<()> {
+    let input_path = fixture_path("labs-google.har");
+    let output_path = output_folder_path("manifest-unfolded").delete()?;
+    let layout = ChunkLayout::new(ChunkNaming::Hash, 1).with_span(Some(8));
+    unfold_file_with_layout(&input_path, &output_path, layout, |_| {})?;
+    let store = DirStore::new(&output_path);
+    let index = verify_store(&store, |_| {})?;
+    assert_eq!((index.layout(), index.chunk_count()), (layout, 62));
+
+    let yaml = output_path.join("index").read()?;
+    assert!(yaml.contains("span: '8'"), "{yaml}");
+    assert!(yaml.contains("chunks: '62'"), "{yaml}");
+    assert!(yaml.contains("subindex-00000007:"), "{yaml}");
+    assert!(!yaml.contains("subindex-00000008:"), "{yaml}");
+    assert!(!yaml.contains(&index.chunks()[0]), "{yaml}");
+    assert_eq!(
+        store.list()?,
+        index
+            .chunk_files()?
+            .into_keys()
+            .collect::<Vec<String>>()
+    );
+    assert_eq!(store.list()?.len(), 62 + 8);
+    assert!(gc_store(&store, |_| {})?.is_empty());
+
+    let folded_path = output_file_path("manifest-folded.har").delete()?;
+    fold_file(&output_path, &folded_path, |_| {})?;
+    assert_eq!(folded_path.read_bytes()?, input_path.read_bytes()?);
+
+    let flat = ChunkLayout::new(ChunkNaming::Hash, 1);
+    let relaid = relayout_store(&store, flat, |_| {})?;
+    assert_eq!(relaid.chunks(), index.chunks());
+    assert_eq!(store.list()?.len(), 62);
+    assert!(!output_path
+        .join("index")
+        .read()?
+        .contains("subindex-"));
+    let relaid = relayout_store(&store, layout, |_| {})?;
+    assert_eq!(relaid, index);
+    assert_eq!(store.list()?.len(), 62 + 8);
+    Ok(())
+}
+
+#[test]
+fn test_folded_reader_reads_only_the_subindexes_it_needs() -> Result<()> {
+    let input_path = fixture_path("labs-google.har");
+    let input = input_path.read_bytes()?;
+    let output_path = output_folder_path("manifest-reader").delete()?;
+    let layout = ChunkLayout::new(ChunkNaming::Ordered, 0).with_span(Some(4));
+    unfold_file_with_layout(&input_path, &output_path, layout, |_| {})?;
+    let store = DirStore::new(&output_path);
+
+    let mut reader = FoldedReader::open(&output_path)?;
+    let manifest = reader.manifest().unwrap().clone();
+    assert_eq!(manifest.subindexes().len(), 16);
+    assert!(reader.index().chunks().is_empty());
+    assert_eq!(reader.chunk_count(), 62);
+    store.delete(&manifest.subindex_name(0))?;
+    store.put(&manifest.subindex_name(15), b"tampered")?;
+
+    let offset = 20 * 65535 + 100;
+    reader.seek(SeekFrom::Start(offset as u64))?;
+    let mut bytes = vec![0; 3 * 65535];
+    reader.read_exact(&mut bytes)?;
+    assert_eq!(bytes, input[offset..offset + bytes.len()]);
+
+    match reader.read_chunk(0) {
+        Err(Error::MissingChunkError(_)) => {},
+        other => panic!("expected MissingChunkError, got {other:?}"),
+    }
+    match reader.read_chunk(61) {
+        Err(Error::CorruptedDataError(message)) =>
+            assert!(message.contains(&manifest.subindex_name(15)), "{message}"),
+        other => panic!("expected CorruptedDataError, got {other:?}"),
+    }
+    assert!(store.read_index().is_err());
+    Ok(())
+}
+
+#[test]
+fn test_manifest_proofs_only_read_their_subindex() -> Result<()> {
+    let input_path = fixture_path("labs-google.har");
+    let output_path = output_folder_path("manifest-proofs").delete()?;
+    let layout = ChunkLayout::new(ChunkNaming::Hash, 0).with_span(Some(8));
+    unfold_file_with_layout(&input_path, &output_path, layout, |_| {})?;
+    let store = DirStore::new(&output_path);
+    let index = store.read_index()?;
+    for idx in [0, 7, 8, 20, 55, 56, 61] {
+        assert_eq!(
+            MerkleProof::read(&store, idx)?,
+            MerkleProof::new(&index, idx)?
+        );
+    }
+
+    let reader = FoldedReader::open(&output_path)?;
+    let manifest = reader.manifest().unwrap().clone();
+    store.delete(&manifest.subindex_name(0))?;
+    let mut reader = reader.with_merkle_root(index.merkle_root());
+    assert_eq!(reader.proof(61)?, MerkleProof::new(&index, 61)?);
+    let input = input_path.read_bytes()?;
+    assert_eq!(reader.read_chunk(20)?, &input[20 * 65535..21 * 65535]);
+    assert!(MerkleProof::read(&store, 0).is_err());
+
+    let mut wrong_root = index.merkle_root().to_vec();
+    wrong_root[0] ^= 1;
+    let mut reader =
+        FoldedReader::open(&output_path)?.with_merkle_root(&wrong_root);
+    match reader.read_chunk(20) {
+        Err(Error::ProofError(_)) => {},
+        other => panic!("expected ProofError, got {other:?}"),
+    }
+
+    let odd = layout.with_span(Some(6));
+    let odd_path = output_folder_path("manifest-odd-span").delete()?;
+    match unfold_file_with_layout(&input_path, &odd_path, odd, |_| {}) {
+        Err(Error::FlattenFileOutputError(message)) =>
+            assert!(message.contains("power of two"), "{message}"),
+        other => panic!("expected FlattenFileOutputError, got {other:?}"),
+    }
+    Ok(())
+}
+
+#[test]
+fn test_relayout_and_convert_ordered_manifest() -> Result<()> {
+    let input_path = fixture_path("labs-google.har");
+    let output_path = output_folder_path("manifest-ordered").delete()?;
+    let layout = ChunkLayout::new(ChunkNaming::Ordered, 0).with_span(Some(4));
+    unfold_file_with_layout(&input_path, &output_path, layout, |_| {})?;
+    let store = DirStore::new(&output_path);
+
+    let converted = convert_index(&store, IndexEncoding::Cbor, |_| {})?;
+    assert_eq!(converted.layout().encoding, IndexEncoding::Cbor);
+    assert_eq!(verify_store(&store, |_| {})?, converted);
+
+    let fanned_out = ChunkLayout::new(ChunkNaming::Ordered, 1)
+        .with_span(Some(4))
+        .with_encoding(IndexEncoding::Cbor);
+    let relaid = relayout_store(&store, fanned_out, |_| {})?;
+    assert_eq!(verify_store(&store, |_| {})?, relaid);
+    assert_eq!(
+        store.list()?,
+        relaid
+            .chunk_files()?
+            .into_keys()
+            .collect::<Vec<String>>()
+    );
+
+    let folded_path = output_file_path("manifest-ordered.har").delete()?;
+    fold_file(&output_path, &folded_path, |_| {})?;
+    assert_eq!(folded_path.read_bytes()?, input_path.read_bytes()?);
+    Ok(())
+}
+
+#[test]
+fn test_resolving_a_forged_chunk_count_fails_without_allocating_it(
+) -> Result<()> {
+    let input_path = fixture_path("labs-google.har");
+    let output_path = output_folder_path("manifest-forged").delete()?;
+    let layout = ChunkLayout::new(ChunkNaming::Hash, 0).with_span(Some(64));
+    unfold_file_with_layout(&input_path, &output_path, layout, |_| {})?;
+    let store = DirStore::new(&output_path);
+
+    // a single sub-index, whose subtree root is the merkle root, claimed
+    // to list 2^40 chunks
+    let forged = 1usize << 40;
+    let yaml = String::from_utf8(store.get_index()?)
+        .unwrap()
+        .replace("chunks: '62'", &format!("chunks: '{forged}'"))
+        .replace("span: '64'", &format!("span: '{forged}'"))
+        .replace(
+            "size: '4004265'",
+            &format!("size: '{}'", forged as u64 * u16::MAX as u64),
+        );
+    store.put_index(yaml.as_bytes())?;
+    match verify_store(&store, |_| {}) {
+        Err(Error::InvalidIndexError(message)) =>
+            assert!(message.contains("sub-index"), "{message}"),
+        other => panic!("expected InvalidIndexError, got {other:?}"),
+    }
+    Ok(())
+}
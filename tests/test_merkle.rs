@@ -0,0 +1,94 @@
+use std::io::Read;
+
+use sha2::{Digest, Sha256};
+use unfolder::{
+    merkle_root, unfold_file, ChunkStore, DirStore, Error, FoldedReader,
+    MerkleProof, Result, UnfoldIndex,
+};
+
+mod common;
+use common::{fixture_path, output_folder_path};
+
+fn fake_chunk(idx: usize) -> (Vec<u8>, String) {
+    let bytes = format!("chunk {idx}").into_bytes();
+    let sha256 = hex::encode(Sha256::digest(&bytes));
+    (bytes, sha256)
+}
+
+#[test]
+fn test_every_chunk_is_proven_against_the_root() -> Result<()> {
+    for count in 1..=9 {
+        let (chunks, names): (Vec<_>, Vec<_>) =
+            (0..count).map(fake_chunk).unzip();
+        let index = UnfoldIndex::new(vec![0; 32], 0, names.clone());
+        let root = index.merkle_root().to_vec();
+        assert_eq!(root, merkle_root(&names));
+        for (idx, bytes) in chunks.iter().enumerate() {
+            let proof = MerkleProof::new(&index, idx)?;
+            assert_eq!((proof.chunk(), proof.chunks()), (idx, count));
+            proof.verify(bytes, &root)?;
+            let parsed = MerkleProof::parse(&proof.to_yaml()?, "yaml")?;
+            assert_eq!(parsed, proof);
+
+            match proof.verify(&chunks[(idx + 1) % count], &root) {
+                Err(Error::ProofError(_)) if count > 1 => {},
+                Ok(()) if count == 1 => {},
+                other => panic!("expected ProofError, got {other:?}"),
+            }
+        }
+    }
+
+    let (_, names): (Vec<_>, Vec<_>) = (0..5).map(fake_chunk).unzip();
+    let index = UnfoldIndex::new(vec![0; 32], 0, names.clone());
+    let yaml = MerkleProof::new(&index, 4)?.to_yaml()?;
+    let (bytes, _) = fake_chunk(4);
+    let other_root = merkle_root(&names[..4]);
+    match MerkleProof::parse(&yaml, "yaml")?.verify(&bytes, &other_root) {
+        Err(Error::ProofError(message)) =>
+            assert!(message.contains("leads to merkle root"), "{message}"),
+        other => panic!("expected ProofError, got {other:?}"),
+    }
+    let moved = yaml.replace("chunk: '4'", "chunk: '3'");
+    match MerkleProof::parse(&moved, "yaml")?.root() {
+        Err(Error::ProofError(message)) =>
+            assert!(message.starts_with("too few siblings"), "{message}"),
+        other => panic!("expected ProofError, got {other:?}"),
+    }
+    Ok(())
+}
+
+#[test]
+fn test_folded_reader_checks_chunks_against_a_trusted_root() -> Result<()> {
+    let input_path = fixture_path("labs-google.har");
+    let unfolded_path = output_folder_path("merkle-unfolded").delete()?;
+    unfold_file(&input_path, &unfolded_path, |_| {})?;
+    let store = DirStore::new(&unfolded_path);
+    let index = store.read_index()?;
+    let root = index.merkle_root().to_vec();
+
+    let mut reader =
+        FoldedReader::open(&unfolded_path)?.with_merkle_root(&root);
+    let mut bytes = Vec::new();
+    reader.read_to_end(&mut bytes)?;
+    assert_eq!(bytes, input_path.read_bytes()?);
+    let chunk = reader.read_chunk(7)?.to_vec();
+    reader.proof(7)?.verify(&chunk, &root)?;
+
+    let mut reader =
+        FoldedReader::open(&unfolded_path)?.with_merkle_root(&[0; 32]);
+    match reader.read_chunk(0) {
+        Err(Error::ProofError(message)) =>
+            assert!(message.contains(&store.location()), "{message}"),
+        other => panic!("expected ProofError, got {other:?}"),
+    }
+
+    let yaml = String::from_utf8(store.get_index()?).unwrap();
+    let tampered = yaml.replace(&hex::encode(&root), &hex::encode([0; 32]));
+    store.put_index(tampered.as_bytes())?;
+    match store.read_index() {
+        Err(Error::InvalidIndexError(message)) =>
+            assert!(message.contains("'merkle-root'"), "{message}"),
+        other => panic!("expected InvalidIndexError, got {other:?}"),
+    }
+    Ok(())
+}
@@ -0,0 +1,96 @@
+use unfolder::{
+    fold_file, relayout_store, unfold_file_with_layout,
+    unfold_to_store_with_layout, verify_store, ChunkLayout, ChunkNaming,
+    ChunkStore, DirStore, Error, MemoryStore, Result,
+};
+
+mod common;
+use common::{fixture_path, output_file_path, output_folder_path};
+
+#[test]
+fn test_unfold_with_ordered_naming() -> Result<()> {
+    let input_path = fixture_path("labs-google_fx_tools_flow.png");
+    let output_path = output_folder_path("ordered-unfolded").delete()?;
+    let layout = ChunkLayout::new(ChunkNaming::Ordered, 0);
+    unfold_file_with_layout(&input_path, &output_path, layout, |_| {})?;
+    let store = DirStore::new(&output_path);
+    let index = verify_store(&store, |_| {})?;
+    assert_eq!(index.naming(), ChunkNaming::Ordered);
+    assert_eq!(store.list()?, index.chunk_names());
+    for (idx, name) in index.chunk_names().iter().enumerate() {
+        assert_eq!(name, &format!("{:06}-{}", idx + 1, index.chunks()[idx]));
+        assert!(ChunkNaming::Ordered.accepts(name));
+        assert!(!ChunkNaming::Hash.accepts(name));
+        assert!(!ChunkNaming::Sequential.accepts(name));
+    }
+    assert!(output_path
+        .join("index")
+        .read()?
+        .contains("naming: ordered"));
+
+    let folded_path = output_file_path("ordered-folded.png").delete()?;
+    fold_file(&output_path, &folded_path, |_| {})?;
+    assert_eq!(folded_path.read_bytes()?, input_path.read_bytes()?);
+    Ok(())
+}
+
+#[test]
+fn test_index_naming_is_validated() -> Result<()> {
+    let input_path = fixture_path("labs-google_fx_tools_flow-1.png");
+    let output_path = output_folder_path("naming-mismatch").delete()?;
+    let layout = ChunkLayout::new(ChunkNaming::Sequential, 0);
+    unfold_file_with_layout(&input_path, &output_path, layout, |_| {})?;
+    let store = DirStore::new(&output_path);
+    let index = store.read_index()?;
+    store.write_index(&index.clone().with_naming(ChunkNaming::Hash))?;
+    let folded_path = output_file_path("naming-mismatch.png").delete()?;
+    match fold_file(&output_path, &folded_path, |_| {}) {
+        Err(Error::InvalidIndexError(message)) => {
+            assert!(message.contains("part-0001.bin"), "{message}");
+            assert!(message.contains("sequential naming"), "{message}");
+        },
+        other => panic!("expected InvalidIndexError, got {other:?}"),
+    }
+
+    let yaml = index.to_yaml()?;
+    for (from, to) in [
+        ("naming: sequential", "naming: shuffled"),
+        (index.chunks()[0].as_str(), "not-a-sha256"),
+    ] {
+        store.put_index(yaml.replace(from, to).as_bytes())?;
+        match store.read_index() {
+            Err(Error::InvalidIndexError(_)) => {},
+            other => panic!("expected InvalidIndexError, got {other:?}"),
+        }
+    }
+    Ok(())
+}
+
+#[test]
+fn test_relayout_replaces_stale_sequential_parts() -> Result<()> {
+    let input_path = fixture_path("labs-google_fx_tools_flow-1.png");
+    let store = MemoryStore::new();
+    let layout = ChunkLayout::new(ChunkNaming::Hash, 0);
+    unfold_to_store_with_layout(&input_path, &store, layout, |_| {})?;
+    // left behind by an earlier unfold into the same store
+    store.put("part-0001.bin", b"stale")?;
+
+    let sequential = ChunkLayout::new(ChunkNaming::Sequential, 0);
+    relayout_store(&store, sequential, |_| {})?;
+    let index = verify_store(&store, |_| {})?;
+    assert_eq!(store.list()?, index.chunk_names());
+    Ok(())
+}
+
+#[test]
+fn test_ordered_names_widen_past_a_million_chunks() {
+    let sha256 = "ab".repeat(32);
+    for (name, accepted) in [
+        (format!("000001-{sha256}"), true),
+        (format!("1000000-{sha256}"), true),
+        (format!("00001-{sha256}"), false),
+        (format!("00000a-{sha256}"), false),
+    ] {
+        assert_eq!(ChunkNaming::Ordered.accepts(&name), accepted, "{name}");
+    }
+}
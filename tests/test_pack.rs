@@ -0,0 +1,139 @@
+use std::collections::BTreeSet;
+
+use sha2::{Digest, Sha256};
+use unfolder::{
+    bundle, fold_file, pack, unbundle, unfold_file, unfold_file_with_layout,
+    unpack, verify_store, ChunkLayout, ChunkNaming, ChunkStore, DirStore,
+    Error, Frame, IndexEncoding, PackReader, PackWriter, Result,
+};
+
+mod common;
+use common::{fixture_path, output_file_path, output_folder_path};
+
+#[test]
+fn test_pack_and_unpack_stream() -> Result<()> {
+    let input_path = fixture_path("labs-google_fx_tools_flow.png");
+    let unfolded = unfold_file(
+        &input_path,
+        &output_folder_path("labs-google_fx_tools_flow.png.pack").delete()?,
+        |_| {},
+    )?;
+    let stream = pack(&unfolded, Vec::<u8>::new(), |_| {})?;
+
+    let mut reader = PackReader::new(stream.as_slice())?;
+    let (chunk_count, index) = reader.read_index()?;
+    assert_eq!(chunk_count, 4);
+    assert_eq!(index.size(), input_path.file_size().as_u64());
+    let mut names = Vec::<String>::new();
+    while let Some((name, _)) = reader.read_chunk()? {
+        names.push(name);
+    }
+    assert_eq!(names.len(), chunk_count);
+    assert_eq!(reader.read_frame()?, Frame::End);
+
+    let unpacked = output_folder_path("labs-google_fx_tools_flow.png.unpacked")
+        .delete()?;
+    let mut messages = Vec::<String>::new();
+    unpack(stream.as_slice(), &unpacked, |progress| {
+        messages.push(progress.to_string());
+    })?;
+    assert_eq!(
+        messages,
+        vec![
+            "Unpack start",
+            "Unpack chunk 1/4",
+            "Unpack chunk 2/4",
+            "Unpack chunk 3/4",
+            "Unpack chunk 4/4",
+            "Unpack end"
+        ]
+    );
+    let folded =
+        output_file_path("labs-google_fx_tools_flow.png.unpacked-folded")
+            .delete()?;
+    fold_file(&unpacked, &folded, |_| {})?;
+    assert_eq!(folded.read_bytes()?, input_path.read_bytes()?);
+    Ok(())
+}
+
+#[test]
+fn test_unpack_commits_nothing_on_corrupted_stream() -> Result<()> {
+    let unfolded = unfold_file(
+        &fixture_path("labs-google_fx_tools_flow-1.png"),
+        &output_folder_path("labs-google_fx_tools_flow-1.png.pack").delete()?,
+        |_| {},
+    )?;
+    let mut stream = pack(&unfolded, Vec::<u8>::new(), |_| {})?;
+    let last = stream.len() - 100;
+    stream[last] ^= 0xFF;
+
+    let unpacked =
+        output_folder_path("labs-google_fx_tools_flow-1.png.unpacked")
+            .delete()?;
+    match unpack(stream.as_slice(), &unpacked, |_| {}) {
+        Err(Error::CorruptedDataError(_)) => {},
+        other => panic!("expected CorruptedDataError, got {other:?}"),
+    }
+    assert!(!unpacked.exists());
+
+    let truncated = &stream[..stream.len() / 2];
+    match unpack(truncated, &unpacked, |_| {}) {
+        Err(Error::InvalidPackError(_)) => {},
+        other => panic!("expected InvalidPackError, got {other:?}"),
+    }
+    assert!(!unpacked.exists());
+    assert!(unpacked
+        .parent()
+        .unwrap()
+        .list()?
+        .iter()
+        .all(|path| !path.name().contains(".unpacking-")));
+
+    // a chunk the index does not point at is rejected, not stored
+    let store = DirStore::new(&unfolded);
+    let index = store.read_index()?;
+    let files = index.chunk_files()?;
+    let mut writer = PackWriter::new(Vec::<u8>::new())?;
+    writer.write_index(&index, files.len() + 1)?;
+    for (name, sha256) in files {
+        writer.write_chunk(&sha256, &store.get(&name)?)?;
+    }
+    writer.write_chunk(&hex::encode(Sha256::digest(b"stray")), b"stray")?;
+    let stream = writer.finish()?;
+    match unpack(stream.as_slice(), &unpacked, |_| {}) {
+        Err(Error::InvalidPackError(message)) =>
+            assert!(message.contains("not in the index"), "{message}"),
+        other => panic!("expected InvalidPackError, got {other:?}"),
+    }
+    assert!(!unpacked.exists());
+    Ok(())
+}
+
+#[test]
+fn test_pack_and_bundle_keep_the_index_encoding() -> Result<()> {
+    let input_path = fixture_path("labs-google.har");
+    for encoding in IndexEncoding::ALL {
+        let layout = ChunkLayout::new(ChunkNaming::Ordered, 1)
+            .with_span(Some(4))
+            .with_encoding(encoding);
+        let name = format!("labs-google.har.pack-{encoding}");
+        let unfolded = output_folder_path(&name).delete()?;
+        unfold_file_with_layout(&input_path, &unfolded, layout, |_| {})?;
+        let index = DirStore::new(&unfolded).read_index()?;
+
+        let stream = pack(&unfolded, Vec::<u8>::new(), |_| {})?;
+        let unpacked =
+            output_folder_path(&format!("{name}-unpacked")).delete()?;
+        unpack(stream.as_slice(), &unpacked, |_| {})?;
+        assert_eq!(verify_store(&DirStore::new(&unpacked), |_| {})?, index);
+
+        let bundle_path =
+            output_file_path(&format!("{name}.bundle")).delete()?;
+        bundle(&unfolded, &BTreeSet::new(), &bundle_path, |_| {})?;
+        let unbundled =
+            output_folder_path(&format!("{name}-unbundled")).delete()?;
+        unbundle(&bundle_path, &unbundled, |_| {})?;
+        assert_eq!(verify_store(&DirStore::new(&unbundled), |_| {})?, index);
+    }
+    Ok(())
+}
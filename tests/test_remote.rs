@@ -0,0 +1,104 @@
+use std::io::{BufRead, BufReader, Write};
+use std::net::TcpListener;
+
+use unfolder::{fold_file, pull, serve_store, unfold_file, Error, Result};
+
+mod common;
+use common::{fixture_path, output_file_path, output_folder_path};
+
+const CHUNK: usize = u16::MAX as usize;
+
+fn spawn_server(store: &iocore::Path) -> String {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let address = listener.local_addr().unwrap();
+    let store = store.clone();
+    std::thread::spawn(move || serve_store(&store, listener));
+    format!("http://{address}")
+}
+
+#[test]
+fn test_pull_only_missing_chunks_from_loopback_server() -> Result<()> {
+    let store = output_folder_path("remote-store")
+        .delete()?
+        .mkdir()?;
+    let input_path = fixture_path("labs-google.har");
+    let mut modified = input_path.read_bytes()?;
+    modified[CHUNK * 2] ^= 0xFF;
+    let modified_path =
+        output_file_path("labs-google.har.remote-v2").delete()?;
+    modified_path.write(&modified)?;
+    unfold_file(&modified_path, &store.join("labs-google.har"), |_| {})?;
+
+    let local = unfold_file(
+        &input_path,
+        &output_folder_path("labs-google.har.remote-local").delete()?,
+        |_| {},
+    )?;
+    let url = spawn_server(&store);
+    let mut messages = Vec::<String>::new();
+    pull(&format!("{url}/labs-google.har"), &local, |progress| {
+        messages.push(progress.to_string());
+    })?;
+    assert_eq!(messages, vec!["Pull start", "Pull chunk 1/1", "Pull end"]);
+
+    let folded = output_file_path("labs-google.har.remote-folded").delete()?;
+    fold_file(&local, &folded, |_| {})?;
+    assert_eq!(folded.read_bytes()?, modified);
+
+    match pull(&format!("{url}/missing"), &local, |_| {}) {
+        Err(Error::PullError(_)) => {},
+        other => panic!("expected PullError, got {other:?}"),
+    }
+    Ok(())
+}
+
+#[test]
+fn test_pull_rejects_corrupted_chunks() -> Result<()> {
+    let store = output_folder_path("remote-corrupted-store")
+        .delete()?
+        .mkdir()?;
+    let served = unfold_file(
+        &fixture_path("deepmind.flow"),
+        &store.join("deepmind.flow"),
+        |_| {},
+    )?;
+    let chunk = served
+        .list()?
+        .into_iter()
+        .find(|path| path.name() != "index")
+        .unwrap();
+    chunk.write(b"corrupted")?;
+
+    let local = output_folder_path("deepmind.flow.remote-local").delete()?;
+    let url = spawn_server(&store);
+    match pull(&format!("{url}/deepmind.flow"), &local, |_| {}) {
+        Err(Error::CorruptedDataError(_)) => {},
+        other => panic!("expected CorruptedDataError, got {other:?}"),
+    }
+    assert!(!local.join("index").exists());
+    Ok(())
+}
+
+#[test]
+fn test_pull_rejects_oversized_responses() -> Result<()> {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let address = listener.local_addr().unwrap();
+    std::thread::spawn(move || {
+        let (mut stream, _) = listener.accept().unwrap();
+        let mut reader = BufReader::new(stream.try_clone().unwrap());
+        let mut line = String::new();
+        while reader.read_line(&mut line).unwrap() > 2 {
+            line.clear();
+        }
+        let _ = stream.write_all(
+            b"HTTP/1.1 200 OK\r\nContent-Length: 2147483648\r\n\r\n",
+        );
+    });
+    let local = output_folder_path("remote-oversized-local").delete()?;
+    match pull(&format!("http://{address}/file"), &local, |_| {}) {
+        Err(Error::HttpError(message)) =>
+            assert!(message.contains("larger than"), "{message}"),
+        other => panic!("expected HttpError, got {other:?}"),
+    }
+    Ok(())
+}
@@ -0,0 +1,95 @@
+use unfolder::{
+    fold_from_store, unfold_to_store, verify_replicas, ChunkStore, DirStore,
+    Error, ReplicaStore, Result,
+};
+
+mod common;
+use common::{fixture_path, output_file_path, output_folder_path};
+
+fn open_replicas(names: &[&str]) -> Result<ReplicaStore> {
+    let replicas = names
+        .iter()
+        .map(|name| {
+            let path = output_folder_path(name).delete()?;
+            Ok(Box::new(DirStore::new(&path)) as Box<dyn ChunkStore>)
+        })
+        .collect::<Result<Vec<_>>>()?;
+    ReplicaStore::new(replicas)
+}
+
+#[test]
+fn test_fold_reads_from_healthy_replicas() -> Result<()> {
+    let input_path = fixture_path("labs-google_fx_tools_flow.png");
+    let store = open_replicas(&["replica-a", "replica-b"])?;
+    let index = unfold_to_store(&input_path, &store, |_| {})?;
+    let [a, b] = store.replicas() else {
+        panic!("expected 2 replicas");
+    };
+    for replica in [a, b] {
+        assert_eq!(replica.read_index()?, index);
+        assert_eq!(replica.list()?.len(), index.chunk_count());
+    }
+    assert_eq!(verify_replicas(&store, |_| {})?, index);
+
+    let corrupted = &index.chunks()[0];
+    a.put(corrupted, b"bit rot")?;
+    b.delete(&index.chunks()[1])?;
+    let folded_path = output_file_path("replica-folded.png").delete()?;
+    fold_from_store(&store, &folded_path, |_| {})?;
+    assert_eq!(folded_path.read_bytes()?, input_path.read_bytes()?);
+    let problems = store.problems();
+    assert_eq!(problems.len(), 1, "{problems:?}");
+    assert!(problems[0].starts_with(&format!("replica {}", a.location())));
+    assert!(problems[0].contains(corrupted.as_str()), "{problems:?}");
+
+    match verify_replicas(&store, |_| {}) {
+        Err(Error::ReplicaError(message)) => {
+            assert!(message.contains(&a.location()), "{message}");
+            assert!(message.contains(&b.location()), "{message}");
+        },
+        other => panic!("expected ReplicaError, got {other:?}"),
+    }
+    Ok(())
+}
+
+#[test]
+fn test_replicas_which_disagree_are_flagged() -> Result<()> {
+    let store = open_replicas(&["replica-c", "replica-d"])?;
+    let [c, d] = store.replicas() else {
+        panic!("expected 2 replicas");
+    };
+    let index = unfold_to_store(
+        &fixture_path("labs-google_fx_tools_flow.png"),
+        c.as_ref(),
+        |_| {},
+    )?;
+    unfold_to_store(
+        &fixture_path("labs-google_fx_tools_flow-1.png"),
+        d.as_ref(),
+        |_| {},
+    )?;
+    match verify_replicas(&store, |_| {}) {
+        Err(Error::ReplicaError(message)) => assert!(
+            message.starts_with(&format!(
+                "replica {} disagrees with replica {}",
+                d.location(),
+                c.location()
+            )),
+            "{message}"
+        ),
+        other => panic!("expected ReplicaError, got {other:?}"),
+    }
+
+    let name = &index.chunks()[2];
+    c.put(name, b"bit rot")?;
+    let folded_path = output_file_path("replica-unhealthy.png").delete()?;
+    match fold_from_store(&store, &folded_path, |_| {}) {
+        Err(Error::CorruptedDataError(message)) => {
+            assert!(message.contains(&c.location()), "{message}");
+            assert!(message.contains(&d.location()), "{message}");
+        },
+        other => panic!("expected CorruptedDataError, got {other:?}"),
+    }
+    assert!(!folded_path.exists());
+    Ok(())
+}
@@ -0,0 +1,356 @@
+#![cfg(feature = "s3")]
+use std::collections::BTreeMap;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::{Arc, Mutex};
+
+use sha2::{Digest, Sha256};
+use unfolder::{
+    fold_from_store, unfold_to_store, ChunkStore, Error, Result, S3Config,
+    S3Store,
+};
+
+mod common;
+use common::{fixture_path, output_file_path};
+
+/// in-process stand-in for an S3-compatible service keeping objects in
+/// memory. Listings return at most two keys per page, and part uploads
+/// fail while `fail_parts` is set.
+#[derive(Default)]
+struct MockS3 {
+    objects: BTreeMap<String, Vec<u8>>,
+    uploads: BTreeMap<String, BTreeMap<u32, Vec<u8>>>,
+    requests: Vec<String>,
+    fail_parts: bool,
+}
+
+impl MockS3 {
+    fn requests(&self, prefix: &str) -> usize {
+        self.requests
+            .iter()
+            .filter(|request| request.starts_with(prefix))
+            .count()
+    }
+}
+
+fn spawn_mock() -> (Arc<Mutex<MockS3>>, S3Config) {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let address = listener.local_addr().unwrap();
+    let mock = Arc::new(Mutex::new(MockS3::default()));
+    let state = mock.clone();
+    std::thread::spawn(move || {
+        for stream in listener.incoming().flatten() {
+            let state = state.clone();
+            std::thread::spawn(move || handle(stream, &state));
+        }
+    });
+    let config = S3Config {
+        endpoint: format!("http://{address}"),
+        region: "us-east-1".to_string(),
+        access_key: "test-key".to_string(),
+        secret_key: "test-secret".to_string(),
+        session_token: None,
+    };
+    (mock, config)
+}
+
+fn handle(stream: TcpStream, state: &Mutex<MockS3>) {
+    let mut reader = BufReader::new(stream.try_clone().unwrap());
+    let mut writer = stream;
+    let mut line = String::new();
+    reader.read_line(&mut line).unwrap();
+    let mut parts = line.split_whitespace();
+    let method = parts.next().unwrap_or_default().to_string();
+    let target = parts.next().unwrap_or_default().to_string();
+    let mut headers = BTreeMap::<String, String>::new();
+    loop {
+        let mut line = String::new();
+        reader.read_line(&mut line).unwrap();
+        match line.trim_end().split_once(':') {
+            Some((name, value)) => {
+                headers.insert(name.to_lowercase(), value.trim().to_string());
+            },
+            None => break,
+        }
+    }
+    let length = headers
+        .get("content-length")
+        .map(|length| length.parse::<u64>().unwrap())
+        .unwrap_or_default();
+    let mut body = Vec::<u8>::new();
+    reader
+        .take(length)
+        .read_to_end(&mut body)
+        .unwrap();
+
+    let (status, response_headers, response) =
+        respond(&mut state.lock().unwrap(), &method, &target, &headers, body);
+    write!(writer, "HTTP/1.1 {status} Mock\r\n").unwrap();
+    for (name, value) in response_headers {
+        write!(writer, "{name}: {value}\r\n").unwrap();
+    }
+    write!(writer, "Content-Length: {}\r\n", response.len()).unwrap();
+    write!(writer, "Connection: close\r\n\r\n").unwrap();
+    if method != "HEAD" {
+        writer.write_all(&response).unwrap();
+    }
+    writer.flush().unwrap();
+}
+
+fn respond(
+    mock: &mut MockS3,
+    method: &str,
+    target: &str,
+    headers: &BTreeMap<String, String>,
+    body: Vec<u8>,
+) -> (u16, Vec<(String, String)>, Vec<u8>) {
+    mock.requests.push(format!("{method} {target}"));
+    let (path, query) = target.split_once('?').unwrap_or((target, ""));
+    let query = query
+        .split('&')
+        .filter(|pair| !pair.is_empty())
+        .map(|pair| {
+            let (name, value) = pair.split_once('=').unwrap_or((pair, ""));
+            (name.to_string(), percent_decode(value))
+        })
+        .collect::<BTreeMap<String, String>>();
+    let signed = headers
+        .get("authorization")
+        .is_some_and(|authorization| {
+            authorization.starts_with("AWS4-HMAC-SHA256 Credential=test-key/")
+                && authorization.contains("/us-east-1/s3/aws4_request")
+        });
+    let payload_hash = hex::encode(Sha256::digest(&body));
+    if !signed || headers.get("x-amz-content-sha256") != Some(&payload_hash) {
+        return (
+            403,
+            Vec::new(),
+            b"<Error><Message>denied</Message></Error>".to_vec(),
+        );
+    }
+    let key = path.trim_start_matches('/').to_string();
+    let not_found = (
+        404,
+        Vec::new(),
+        b"<Error><Message>no such key</Message></Error>".to_vec(),
+    );
+    match method {
+        "PUT" if query.contains_key("uploadId") => {
+            if mock.fail_parts {
+                return (
+                    500,
+                    Vec::new(),
+                    b"<Error><Message>part failed</Message></Error>".to_vec(),
+                );
+            }
+            let etag = format!("\"{}\"", &payload_hash[..32]);
+            let number = query["partNumber"].parse::<u32>().unwrap();
+            match mock.uploads.get_mut(&query["uploadId"]) {
+                Some(parts) => {
+                    parts.insert(number, body);
+                    (200, vec![("ETag".to_string(), etag)], Vec::new())
+                },
+                None => not_found,
+            }
+        },
+        "PUT" => {
+            mock.objects.insert(key, body);
+            (200, Vec::new(), Vec::new())
+        },
+        "POST" if query.contains_key("uploads") => {
+            let upload_id = format!("upload-{}", mock.uploads.len() + 1);
+            mock.uploads
+                .insert(upload_id.clone(), BTreeMap::new());
+            let xml = format!(
+                "<InitiateMultipartUploadResult><UploadId>{upload_id}</UploadId></InitiateMultipartUploadResult>"
+            );
+            (200, Vec::new(), xml.into_bytes())
+        },
+        "POST" => match mock.uploads.remove(&query["uploadId"]) {
+            Some(parts) => {
+                let xml = String::from_utf8(body).unwrap();
+                let numbers = xml
+                    .split("<PartNumber>")
+                    .skip(1)
+                    .map(|rest| {
+                        rest.split('<')
+                            .next()
+                            .unwrap()
+                            .parse::<u32>()
+                            .unwrap()
+                    })
+                    .collect::<Vec<u32>>();
+                assert_eq!(
+                    numbers,
+                    parts.keys().copied().collect::<Vec<u32>>()
+                );
+                mock.objects
+                    .insert(key, parts.into_values().flatten().collect());
+                (200, Vec::new(), b"<CompleteMultipartUploadResult/>".to_vec())
+            },
+            None => not_found,
+        },
+        "DELETE" if query.contains_key("uploadId") => {
+            mock.uploads.remove(&query["uploadId"]);
+            (204, Vec::new(), Vec::new())
+        },
+        "DELETE" => {
+            mock.objects.remove(&key);
+            (204, Vec::new(), Vec::new())
+        },
+        "GET" if query.contains_key("list-type") => {
+            let prefix = format!("{key}/{}", query["prefix"]);
+            let after = query
+                .get("continuation-token")
+                .cloned()
+                .unwrap_or_default();
+            let keys = mock
+                .objects
+                .keys()
+                .filter(|object| {
+                    object.starts_with(&prefix) && **object > after
+                })
+                .filter(|object| {
+                    !query.contains_key("delimiter")
+                        || !object[prefix.len()..].contains('/')
+                })
+                .cloned()
+                .collect::<Vec<String>>();
+            let page = &keys[..keys.len().min(2)];
+            let mut xml = format!(
+                "<ListBucketResult><IsTruncated>{}</IsTruncated>",
+                keys.len() > 2
+            );
+            if keys.len() > 2 {
+                xml.push_str(&format!(
+                    "<NextContinuationToken>{}</NextContinuationToken>",
+                    page[1]
+                ));
+            }
+            for object in page {
+                xml.push_str(&format!(
+                    "<Contents><Key>{}</Key></Contents>",
+                    &object[key.len() + 1..]
+                ));
+            }
+            xml.push_str("</ListBucketResult>");
+            (200, Vec::new(), xml.into_bytes())
+        },
+        "GET" | "HEAD" => match mock.objects.get(&key) {
+            Some(bytes) => (200, Vec::new(), bytes.clone()),
+            None => not_found,
+        },
+        _ => (405, Vec::new(), Vec::new()),
+    }
+}
+
+fn percent_decode(value: &str) -> String {
+    let mut bytes = Vec::<u8>::new();
+    let mut rest = value.as_bytes();
+    while let Some((&byte, tail)) = rest.split_first() {
+        if byte == b'%' && tail.len() >= 2 {
+            let hex = std::str::from_utf8(&tail[..2]).unwrap();
+            bytes.push(u8::from_str_radix(hex, 16).unwrap());
+            rest = &tail[2..];
+        } else {
+            bytes.push(byte);
+            rest = tail;
+        }
+    }
+    String::from_utf8(bytes).unwrap()
+}
+
+#[test]
+fn test_s3_store_unfold_and_fold_round_trip() -> Result<()> {
+    let (mock, config) = spawn_mock();
+    let input_path = fixture_path("labs-google_fx_tools_flow.png");
+    let store = S3Store::new("s3://bucket/unfolded/flow.png", config.clone())?;
+    assert_eq!(store.location(), "s3://bucket/unfolded/flow.png");
+    let index = unfold_to_store(&input_path, &store, |_| {})?;
+    assert_eq!(store.read_index()?, index);
+
+    let mut chunks = index.chunks().to_vec();
+    chunks.sort();
+    chunks.dedup();
+    assert_eq!(store.list()?, chunks);
+    assert!(mock.lock().unwrap().requests("GET /bucket?") > 1);
+
+    let output_path =
+        output_file_path("labs-google_fx_tools_flow.png.s3").delete()?;
+    fold_from_store(&store, &output_path, |_| {})?;
+    assert_eq!(output_path.read_bytes()?, input_path.read_bytes()?);
+
+    store.delete(&chunks[0])?;
+    assert!(!store.has(&chunks[0])?);
+    match store.get(&chunks[0]) {
+        Err(Error::MissingChunkError(_)) => {},
+        other => panic!("expected MissingChunkError, got {other:?}"),
+    }
+    let empty = S3Store::new("s3://bucket/nothing", config)?;
+    match empty.get_index() {
+        Err(Error::MissingIndexError(_)) => {},
+        other => panic!("expected MissingIndexError, got {other:?}"),
+    }
+    Ok(())
+}
+
+#[test]
+fn test_s3_store_skips_existing_hash_named_chunks_only() -> Result<()> {
+    let (mock, config) = spawn_mock();
+    let input_path = fixture_path("labs-google_fx_tools_flow-1.png");
+    let store = S3Store::new("s3://bucket/flow-1.png", config.clone())?;
+    let index = unfold_to_store(&input_path, &store, |_| {})?;
+    let puts = mock.lock().unwrap().requests("PUT ");
+    assert_eq!(puts, index.chunk_count() + 1);
+
+    let again = S3Store::new("s3://bucket/flow-1.png/", config)?;
+    unfold_to_store(&input_path, &again, |_| {})?;
+    let mock = mock.lock().unwrap();
+    assert_eq!(mock.requests("PUT "), puts + 1);
+    assert_eq!(mock.requests("PUT /bucket/flow-1.png/index"), 2);
+    drop(mock);
+
+    // names which are not content-addressed are always overwritten
+    again.put("part-0001.bin", b"stale")?;
+    again.put("part-0001.bin", b"fresh")?;
+    assert_eq!(again.get("part-0001.bin")?, b"fresh");
+    Ok(())
+}
+
+#[test]
+fn test_s3_store_multipart_uploads() -> Result<()> {
+    let (mock, config) = spawn_mock();
+    let input_path = fixture_path("deepmind.flow");
+    let store = S3Store::new("s3://bucket/deepmind.flow", config)?
+        .with_multipart(64, 48);
+    let index = unfold_to_store(&input_path, &store, |_| {})?;
+    {
+        let mock = mock.lock().unwrap();
+        assert_eq!(
+            mock.requests("POST /bucket/deepmind.flow/index?uploads"),
+            1
+        );
+        assert!(
+            mock.requests("PUT /bucket/deepmind.flow/index?partNumber=") > 1
+        );
+        assert!(mock.uploads.is_empty());
+    }
+    assert_eq!(store.read_index()?, index);
+
+    mock.lock().unwrap().fail_parts = true;
+    let failing = store.clone();
+    let bytes = vec![7u8; 200];
+    let name = hex::encode(Sha256::digest(&bytes));
+    match failing.put(&name, &bytes) {
+        Err(Error::StoreError(message)) =>
+            assert!(message.contains("part failed"), "{message}"),
+        other => panic!("expected StoreError, got {other:?}"),
+    }
+    let mock = mock.lock().unwrap();
+    assert!(mock.uploads.is_empty(), "failed upload should be aborted");
+    assert_eq!(mock.requests("DELETE /bucket/deepmind.flow/"), 1);
+    assert!(!mock
+        .objects
+        .contains_key(&format!("bucket/deepmind.flow/{name}")));
+    Ok(())
+}
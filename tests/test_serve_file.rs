@@ -0,0 +1,155 @@
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::net::{TcpListener, TcpStream};
+
+use unfolder::{serve_file, unfold_file, FoldedReader, Result};
+
+mod common;
+use common::{fixture_path, output_folder_path};
+
+const CHUNK: usize = u16::MAX as usize;
+
+fn spawn_server(unfolded: &iocore::Path) -> String {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let address = listener.local_addr().unwrap().to_string();
+    let unfolded = unfolded.clone();
+    std::thread::spawn(move || serve_file(&unfolded, listener));
+    address
+}
+
+fn get(address: &str, headers: &[&str]) -> (String, Vec<String>, Vec<u8>) {
+    let mut stream = TcpStream::connect(address).unwrap();
+    write!(stream, "GET /labs-google.har HTTP/1.1\r\nHost: {address}\r\n")
+        .unwrap();
+    for header in headers {
+        write!(stream, "{header}\r\n").unwrap();
+    }
+    write!(stream, "Connection: close\r\n\r\n").unwrap();
+    let mut response = Vec::<u8>::new();
+    stream.read_to_end(&mut response).unwrap();
+    let split = response
+        .windows(4)
+        .position(|window| window == b"\r\n\r\n")
+        .unwrap();
+    let head = String::from_utf8(response[..split].to_vec()).unwrap();
+    let mut lines = head.split("\r\n").map(|line| line.to_string());
+    let status = lines.next().unwrap();
+    (status, lines.collect(), response[split + 4..].to_vec())
+}
+
+#[test]
+fn test_serve_file_with_ranges() -> Result<()> {
+    let input_path = fixture_path("labs-google.har");
+    let bytes = input_path.read_bytes()?;
+    let unfolded = unfold_file(
+        &input_path,
+        &output_folder_path("labs-google.har.serve-file").delete()?,
+        |_| {},
+    )?;
+    let address = spawn_server(&unfolded);
+
+    let (status, headers, body) = get(&address, &[]);
+    assert_eq!(status, "HTTP/1.1 200 OK");
+    assert!(headers.contains(&format!("Content-Length: {}", bytes.len())));
+    assert!(headers.contains(&"Accept-Ranges: bytes".to_string()));
+    let etag = headers
+        .iter()
+        .find_map(|header| header.strip_prefix("ETag: "))
+        .unwrap()
+        .to_string();
+    assert_eq!(etag.len(), 66);
+    assert_eq!(body, bytes);
+
+    let start = CHUNK * 3 - 10;
+    let end = CHUNK * 3 + 10;
+    let (status, headers, body) =
+        get(&address, &[&format!("Range: bytes={start}-{end}")]);
+    assert_eq!(status, "HTTP/1.1 206 Partial Content");
+    assert!(headers.contains(&format!(
+        "Content-Range: bytes {start}-{end}/{}",
+        bytes.len()
+    )));
+    assert_eq!(body, bytes[start..=end]);
+
+    let (_, _, body) = get(&address, &["Range: bytes=-100"]);
+    assert_eq!(body, bytes[bytes.len() - 100..]);
+
+    let (status, headers, _) =
+        get(&address, &[&format!("Range: bytes={}-", bytes.len())]);
+    assert_eq!(status, "HTTP/1.1 416 Range Not Satisfiable");
+    assert!(
+        headers.contains(&format!("Content-Range: bytes */{}", bytes.len()))
+    );
+
+    let (status, headers, body) =
+        get(&address, &[&format!("Range: bytes=10-{}", u64::MAX)]);
+    assert_eq!(status, "HTTP/1.1 206 Partial Content");
+    assert!(headers.contains(&format!(
+        "Content-Range: bytes 10-{}/{}",
+        bytes.len() - 1,
+        bytes.len()
+    )));
+    assert_eq!(body, bytes[10..]);
+
+    let (status, _, body) = get(&address, &[&format!("If-None-Match: {etag}")]);
+    assert_eq!(status, "HTTP/1.1 304 Not Modified");
+    assert!(body.is_empty());
+    Ok(())
+}
+
+#[test]
+fn test_folded_reader_reads_only_covering_chunks() -> Result<()> {
+    let input_path = fixture_path("policies-google-com_privacy-1.png");
+    let bytes = input_path.read_bytes()?;
+    let unfolded = unfold_file(
+        &input_path,
+        &output_folder_path("policies-google-com_privacy-1.png.folded-reader")
+            .delete()?,
+        |_| {},
+    )?;
+    let mut reader = FoldedReader::open(&unfolded)?;
+    let first = unfolded.join(&reader.index().chunks()[0]);
+    first.delete()?;
+
+    reader.seek(SeekFrom::Start((CHUNK * 7 + 3) as u64))?;
+    let mut buf = vec![0u8; CHUNK * 2];
+    reader.read_exact(&mut buf)?;
+    assert_eq!(buf, bytes[CHUNK * 7 + 3..CHUNK * 9 + 3]);
+
+    reader.seek(SeekFrom::End(-5))?;
+    let mut tail = Vec::<u8>::new();
+    reader.read_to_end(&mut tail)?;
+    assert_eq!(tail, bytes[bytes.len() - 5..]);
+
+    reader.seek(SeekFrom::Start(0))?;
+    assert!(reader.read(&mut buf).is_err());
+    Ok(())
+}
+
+#[test]
+fn test_serve_file_bounds_lines_and_connections() -> Result<()> {
+    let unfolded = unfold_file(
+        &fixture_path("deepmind.flow"),
+        &output_folder_path("deepmind.flow.serve-limits").delete()?,
+        |_| {},
+    )?;
+    let address = spawn_server(&unfolded);
+    // a header line which never ends is cut short one byte past 8KiB,
+    // rather than buffered
+    let mut stream = TcpStream::connect(&address)?;
+    write!(stream, "GET /deepmind.flow HTTP/1.1\r\nX-Padding: ")?;
+    stream.write_all(&[b'a'; 8 * 1024 + 1 - 11])?;
+    let mut response = String::new();
+    stream.read_to_string(&mut response)?;
+    assert!(response.starts_with("HTTP/1.1 400 Bad Request"), "{response}");
+    assert!(response.contains("line longer than"), "{response}");
+
+    // idle clients hold every connection the server answers at once
+    let idle = (0..64)
+        .map(|_| TcpStream::connect(&address))
+        .collect::<std::io::Result<Vec<TcpStream>>>()?;
+    let mut response = String::new();
+    TcpStream::connect(&address)?.read_to_string(&mut response)?;
+    assert!(response.starts_with("HTTP/1.1 503"), "{response}");
+    drop(idle);
+    Ok(())
+}
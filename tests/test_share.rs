@@ -0,0 +1,93 @@
+use unfolder::{
+    fold_from_shares, share_numbers, unfold_to_shares, verify_store,
+    ChunkStore, DirStore, Error, Result, ShareLabel,
+};
+
+mod common;
+use common::{fixture_path, output_file_path, output_folder_path};
+
+#[test]
+fn test_any_threshold_shares_fold_the_file() -> Result<()> {
+    let input_path = fixture_path("labs-google_fx_tools_flow.png");
+    let output_path = output_folder_path("shares-unfolded").delete()?;
+    let paths = unfold_to_shares(&input_path, &output_path, 5, 3, |_| {})?;
+    assert_eq!(paths.len(), 5);
+    let input = input_path.read_bytes()?;
+    for (idx, path) in paths.iter().enumerate() {
+        assert_eq!(path.name(), format!("share-{}", idx + 1));
+        let label = ShareLabel::read(path)?;
+        assert_eq!(label.number() as usize, idx + 1);
+        assert_eq!((label.shares(), label.threshold()), (5, 3));
+        assert_eq!(label.set(), ShareLabel::read(&paths[0])?.set());
+        let index = verify_store(&DirStore::new(path), |_| {})?;
+        assert_eq!(index.size(), input.len() as u64 + 32);
+        assert_ne!(
+            &path.join(&index.chunks()[0]).read_bytes()?,
+            &input[..65535]
+        );
+    }
+
+    for (shares, used) in [
+        (vec![4, 1, 3], "5, 2 and 4"),
+        (vec![0, 2, 4], "1, 3 and 5"),
+        (vec![4, 4, 3, 1], "5, 4 and 2"),
+    ] {
+        let share_paths = shares
+            .iter()
+            .map(|idx| paths[*idx].clone())
+            .collect::<Vec<_>>();
+        let folded_path = output_file_path("shares-folded.png").delete()?;
+        let labels = fold_from_shares(&share_paths, &folded_path, |_| {})?;
+        assert_eq!(share_numbers(&labels), used);
+        assert_eq!(folded_path.read_bytes()?, input);
+    }
+    Ok(())
+}
+
+#[test]
+fn test_fewer_shares_than_the_threshold_fail() -> Result<()> {
+    let input_path = fixture_path("labs-google_fx_tools_flow-1.png");
+    let output_path = output_folder_path("shares-fewer").delete()?;
+    let paths = unfold_to_shares(&input_path, &output_path, 4, 3, |_| {})?;
+    let folded_path = output_file_path("shares-fewer.png").delete()?;
+
+    let store = DirStore::new(&paths[1]);
+    let name = store.read_index()?.chunks()[1].clone();
+    store.put(&name, b"bit rot")?;
+    match fold_from_shares(&paths[..3], &folded_path, |_| {}) {
+        Err(Error::ShareError(message)) => {
+            assert!(
+                message.starts_with("3 of 4 shares are needed"),
+                "{message}"
+            );
+            assert!(
+                message.contains(&format!("share 2 at {}", paths[1])),
+                "{message}"
+            );
+        },
+        other => panic!("expected ShareError, got {other:?}"),
+    }
+    assert!(!folded_path.exists());
+    let labels = fold_from_shares(&paths, &folded_path, |_| {})?;
+    assert_eq!(share_numbers(&labels), "1, 3 and 4");
+    assert_eq!(folded_path.read_bytes()?, input_path.read_bytes()?);
+
+    let other_path = output_folder_path("shares-other").delete()?;
+    let others = unfold_to_shares(&input_path, &other_path, 4, 3, |_| {})?;
+    let mixed = [
+        paths[0].clone(),
+        others[2].clone(),
+        paths[3].clone(),
+    ];
+    let folded_path = output_file_path("shares-mixed.png").delete()?;
+    match fold_from_shares(&mixed, &folded_path, |_| {}) {
+        Err(Error::ShareError(message)) =>
+            assert!(message.contains("of another split"), "{message}"),
+        other => panic!("expected ShareError, got {other:?}"),
+    }
+    match unfold_to_shares(&input_path, &output_path.join("x"), 3, 4, |_| {}) {
+        Err(Error::ShareError(_)) => {},
+        other => panic!("expected ShareError, got {other:?}"),
+    }
+    Ok(())
+}
@@ -0,0 +1,116 @@
+use std::os::unix::fs::PermissionsExt;
+
+use iocore::Path;
+use unfolder::{
+    fold_signed, generate_key, read_signing_key, read_verifying_key,
+    sign_store, unfold_file, verify_signed_store, ChunkStore, DirStore, Error,
+    Result,
+};
+
+mod common;
+use common::{fixture_path, output_file_path, output_folder_path};
+
+fn keygen(name: &str) -> Result<Path> {
+    let secret_key_path = output_file_path(name).delete()?;
+    Path::new(format!("{secret_key_path}.pub").as_str()).delete()?;
+    generate_key(&secret_key_path)?;
+    Ok(secret_key_path)
+}
+
+fn expect_signature_error<T: std::fmt::Debug>(
+    result: Result<T>,
+    expected: &str,
+) {
+    match result {
+        Err(Error::SignatureError(message)) =>
+            assert!(message.contains(expected), "{message}"),
+        other => panic!("expected SignatureError, got {other:?}"),
+    }
+}
+
+#[test]
+fn test_signed_index_folds_with_trusted_key_only() -> Result<()> {
+    let secret_key_path = keygen("sign-key")?;
+    let signing_key = read_signing_key(&secret_key_path)?;
+    let public_key_path = Path::new(format!("{secret_key_path}.pub").as_str());
+    let trusted_key = read_verifying_key(&public_key_path)?;
+    assert_eq!(signing_key.verifying_key(), trusted_key);
+    let metadata = std::fs::metadata(secret_key_path.to_string())?;
+    assert_eq!(metadata.permissions().mode() & 0o777, 0o600);
+    expect_signature_error(generate_key(&secret_key_path), "already exists");
+    expect_signature_error(
+        read_verifying_key(&fixture_path("labs-google_fx_tools_flow.png")),
+        "hex encoded Ed25519 public key",
+    );
+
+    let input_path = fixture_path("labs-google_fx_tools_flow.png");
+    let unfolded_path = output_folder_path("signed-unfolded").delete()?;
+    unfold_file(&input_path, &unfolded_path, |_| {})?;
+    let store = DirStore::new(&unfolded_path);
+    let folded_path = output_file_path("signed-folded.png").delete()?;
+    expect_signature_error(
+        fold_signed(&store, &folded_path, &[trusted_key], |_| {}),
+        "the index is not signed",
+    );
+
+    let index = sign_store(&store, &signing_key)?;
+    assert_eq!(store.read_index()?, index);
+    assert_eq!(index.signature().unwrap().signer(), &trusted_key);
+    verify_signed_store(&store, &[trusted_key], |_| {})?;
+    fold_signed(&store, &folded_path, &[trusted_key], |_| {})?;
+    assert_eq!(folded_path.read_bytes()?, input_path.read_bytes()?);
+
+    let other_key = read_signing_key(&keygen("sign-other-key")?)?;
+    let folded_path = output_file_path("signed-untrusted.png").delete()?;
+    expect_signature_error(
+        fold_signed(&store, &folded_path, &[other_key.verifying_key()], |_| {}),
+        "which is not a trusted key",
+    );
+    assert!(!folded_path.exists());
+    Ok(())
+}
+
+#[test]
+fn test_tampered_index_is_refused() -> Result<()> {
+    let signing_key = read_signing_key(&keygen("sign-tamper-key")?)?;
+    let trusted_key = signing_key.verifying_key();
+    let signed_path = output_folder_path("signed-original").delete()?;
+    unfold_file(
+        &fixture_path("labs-google_fx_tools_flow.png"),
+        &signed_path,
+        |_| {},
+    )?;
+    let signed = DirStore::new(&signed_path);
+    sign_store(&signed, &signing_key)?;
+
+    // swap in the chunks and index of another file, keeping the
+    // signature of the original one
+    let tampered_path = output_folder_path("signed-tampered").delete()?;
+    unfold_file(
+        &fixture_path("labs-google_fx_tools_flow-1.png"),
+        &tampered_path,
+        |_| {},
+    )?;
+    let tampered = DirStore::new(&tampered_path);
+    let signed_yaml = String::from_utf8(signed.get_index()?).unwrap();
+    let mut yaml = String::from_utf8(tampered.get_index()?).unwrap();
+    for line in signed_yaml.lines().filter(|line| {
+        line.starts_with("signer:") || line.starts_with("signature:")
+    }) {
+        yaml.push_str(&format!("{line}\n"));
+    }
+    tampered.put_index(yaml.as_bytes())?;
+    assert!(tampered.read_index()?.signature().is_some());
+
+    let folded_path = output_file_path("signed-tampered.png").delete()?;
+    expect_signature_error(
+        fold_signed(&tampered, &folded_path, &[trusted_key], |_| {}),
+        "the index was altered after signing",
+    );
+    expect_signature_error(
+        verify_signed_store(&tampered, &[trusted_key], |_| {}),
+        &tampered.location(),
+    );
+    assert!(!folded_path.exists());
+    Ok(())
+}
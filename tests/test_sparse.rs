@@ -0,0 +1,101 @@
+use std::fs::OpenOptions;
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::os::unix::fs::MetadataExt;
+
+use unfolder::{
+    fold_file, relayout_store, unfold_file, unfold_file_with_layout,
+    verify_store, ChunkLayout, ChunkNaming, ChunkStore, DirStore, Error,
+    FoldedReader, Result,
+};
+
+mod common;
+use common::{fixture_path, output_file_path, output_folder_path};
+
+#[test]
+fn test_zero_chunks_are_recorded_and_folded_as_holes() -> Result<()> {
+    let har = fixture_path("labs-google.har").read_bytes()?;
+    let mut input = har[..3 * 65535].to_vec();
+    input.extend(vec![0; 40 * 65535]);
+    input.extend(&har[..65535]);
+    input.extend(vec![0; 65535 + 10]);
+    let input_path = output_file_path("sparse-input.bin").delete()?;
+    input_path.write(&input)?;
+
+    let unfolded_path = output_folder_path("sparse-unfolded").delete()?;
+    unfold_file(&input_path, &unfolded_path, |_| {})?;
+    let store = DirStore::new(&unfolded_path);
+    let index = verify_store(&store, |_| {})?;
+    assert_eq!(index.zero_extents(), [3..43, 44..46]);
+    assert_eq!(index.chunk_count(), 46);
+    // chunk 43 is the same as chunk 0
+    assert_eq!(store.list()?.len(), 3);
+    let yaml = unfolded_path.join("index").read()?;
+    assert!(yaml.contains("zero-extents: 3-42,44-45"), "{yaml}");
+
+    let folded_path = output_file_path("sparse-folded.bin").delete()?;
+    fold_file(&unfolded_path, &folded_path, |_| {})?;
+    assert_eq!(folded_path.read_bytes()?, input);
+    let metadata = std::fs::metadata(folded_path.to_string())?;
+    assert!(metadata.blocks() * 512 < 10 * 65535, "{metadata:?}");
+
+    let sequential = ChunkLayout::new(ChunkNaming::Sequential, 0);
+    let relaid = relayout_store(&store, sequential, |_| {})?;
+    assert!(relaid.zero_extents().is_empty());
+    assert_eq!(store.list()?.len(), 46);
+    verify_store(&store, |_| {})?;
+
+    let hashed = ChunkLayout::new(ChunkNaming::Hash, 0);
+    let unfolded_path = output_folder_path("sparse-sequential").delete()?;
+    unfold_file_with_layout(&input_path, &unfolded_path, sequential, |_| {})?;
+    let store = DirStore::new(&unfolded_path);
+    assert!(store.read_index()?.zero_extents().is_empty());
+    let index = relayout_store(&store, hashed, |_| {})?;
+    assert!(index.zero_extents().is_empty());
+    Ok(())
+}
+
+#[test]
+fn test_holes_of_sparse_files_are_not_read() -> Result<()> {
+    let har = fixture_path("labs-google.har").read_bytes()?;
+    let input_path = output_file_path("sparse-holes.bin").delete()?;
+    let mut file = OpenOptions::new()
+        .create(true)
+        .write(true)
+        .truncate(true)
+        .open(input_path.to_string())?;
+    file.write_all(&har[..65535])?;
+    file.set_len(1000 * 65535)?;
+    file.seek(SeekFrom::End(0))?;
+    file.write_all(&har[65535..2 * 65535])?;
+    drop(file);
+
+    let unfolded_path = output_folder_path("sparse-holes").delete()?;
+    unfold_file(&input_path, &unfolded_path, |_| {})?;
+    let store = DirStore::new(&unfolded_path);
+    assert_eq!(store.read_index()?.zero_extents(), vec![1..1000]);
+    assert_eq!(store.list()?.len(), 2);
+
+    let mut reader = FoldedReader::open(&unfolded_path)?;
+    reader.seek(SeekFrom::Start(500 * 65535 - 3))?;
+    let mut bytes = vec![1; 6];
+    reader.read_exact(&mut bytes)?;
+    assert_eq!(bytes, [0; 6]);
+    assert_eq!(reader.read_chunk(1000)?, &har[65535..2 * 65535]);
+
+    let yaml = unfolded_path.join("index").read()?;
+    let size = format!("size: '{}'", 1001 * 65535);
+    for (from, to, field) in [
+        ("zero-extents: 1-999", "zero-extents: 1-1001", "'zero-extents'"),
+        // would put the zero chunks past the end of the file
+        (size.as_str(), "size: '10'", "'size'"),
+    ] {
+        assert!(yaml.contains(from), "{yaml}");
+        store.put_index(yaml.replace(from, to).as_bytes())?;
+        match store.read_index() {
+            Err(Error::InvalidIndexError(message)) =>
+                assert!(message.contains(field), "{message}"),
+            other => panic!("expected InvalidIndexError, got {other:?}"),
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,79 @@
+#![cfg(feature = "sqlite")]
+use unfolder::{
+    fold_from_store, gc_store, migrate_store, unfold_file, unfold_to_store,
+    verify_store, ChunkStore, DirStore, Error, Result, SqliteStore,
+};
+
+mod common;
+use common::{fixture_path, output_file_path, output_folder_path};
+
+#[test]
+fn test_sqlite_store_unfold_verify_gc_and_fold() -> Result<()> {
+    let input_path = fixture_path("labs-google.har");
+    let database = output_file_path("labs-google.har.sqlite").delete()?;
+    let store = SqliteStore::open(&database)?;
+    assert_eq!(store.location(), format!("sqlite://{database}"));
+    match store.get_index() {
+        Err(Error::MissingIndexError(_)) => {},
+        other => panic!("expected MissingIndexError, got {other:?}"),
+    }
+    unfold_to_store(&fixture_path("deepmind.flow"), &store, |_| {})?;
+    let index = unfold_to_store(&input_path, &store, |_| {})?;
+    drop(store);
+
+    let store = SqliteStore::open(&database)?;
+    assert_eq!(store.read_index()?, index);
+    assert_eq!(gc_store(&store, |_| {})?.len(), 1);
+    let mut chunks = index.chunks().to_vec();
+    chunks.sort();
+    chunks.dedup();
+    assert_eq!(store.list()?, chunks);
+    assert_eq!(verify_store(&store, |_| {})?, index);
+
+    let output_path =
+        output_file_path("labs-google.har.sqlite-folded").delete()?;
+    fold_from_store(&store, &output_path, |_| {})?;
+    assert_eq!(output_path.read_bytes()?, input_path.read_bytes()?);
+    assert!(database.is_file());
+    Ok(())
+}
+
+#[test]
+fn test_migrate_folder_to_sqlite_and_back() -> Result<()> {
+    let input_path = fixture_path("policies-google-com_privacy.png");
+    let unfolded = unfold_file(
+        &input_path,
+        &output_folder_path("policies-google-com_privacy.png.to-sqlite")
+            .delete()?,
+        |_| {},
+    )?;
+    let database =
+        output_file_path("policies-google-com_privacy.png.sqlite").delete()?;
+    let store = SqliteStore::open(&database)?;
+    let mut messages = Vec::<String>::new();
+    let index = migrate_store(&DirStore::new(&unfolded), &store, |progress| {
+        messages.push(progress.to_string());
+    })?;
+    assert_eq!(messages.first().unwrap(), "Migrate start");
+    assert_eq!(messages.last().unwrap(), "Migrate end");
+
+    let back =
+        output_folder_path("policies-google-com_privacy.png.from-sqlite")
+            .delete()?;
+    migrate_store(&store, &DirStore::new(&back), |_| {})?;
+    assert_eq!(
+        back.join("index").read_bytes()?,
+        unfolded.join("index").read_bytes()?
+    );
+    assert_eq!(verify_store(&DirStore::new(&back), |_| {})?, index);
+
+    let empty = output_folder_path("sqlite-migrate-empty").delete()?;
+    let database = output_file_path("sqlite-migrate-empty.sqlite").delete()?;
+    let target = SqliteStore::open(&database)?;
+    match migrate_store(&DirStore::new(&empty), &target, |_| {}) {
+        Err(Error::MissingIndexError(_)) => {},
+        other => panic!("expected MissingIndexError, got {other:?}"),
+    }
+    assert!(target.list()?.is_empty());
+    Ok(())
+}
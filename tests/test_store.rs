@@ -0,0 +1,182 @@
+use std::io::Read;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use unfolder::{
+    fold_from_store, pack_store, unfold_file, unfold_to_store, unpack_to_store,
+    ChunkStore, DirStore, Error, FoldedReader, MemoryStore, Result,
+};
+
+mod common;
+use common::{fixture_path, output_file_path, output_folder_path};
+
+/// a third-party backend wrapping another store and counting writes
+struct CountingStore {
+    inner: MemoryStore,
+    puts: AtomicUsize,
+}
+
+impl ChunkStore for CountingStore {
+    fn put(&self, name: &str, bytes: &[u8]) -> Result<()> {
+        self.puts.fetch_add(1, Ordering::SeqCst);
+        self.inner.put(name, bytes)
+    }
+
+    fn get(&self, name: &str) -> Result<Vec<u8>> {
+        self.inner.get(name)
+    }
+
+    fn has(&self, name: &str) -> Result<bool> {
+        self.inner.has(name)
+    }
+
+    fn list(&self) -> Result<Vec<String>> {
+        self.inner.list()
+    }
+
+    fn delete(&self, name: &str) -> Result<()> {
+        self.inner.delete(name)
+    }
+
+    fn put_index(&self, bytes: &[u8]) -> Result<()> {
+        self.inner.put_index(bytes)
+    }
+
+    fn get_index(&self) -> Result<Vec<u8>> {
+        self.inner.get_index()
+    }
+
+    fn location(&self) -> String {
+        "counting".to_string()
+    }
+}
+
+#[test]
+fn test_memory_store_reads_lists_and_deletes() -> Result<()> {
+    let input_path = fixture_path("labs-google_fx_tools_flow.png");
+    let store = MemoryStore::new();
+    match store.get_index() {
+        Err(Error::MissingIndexError(_)) => {},
+        other => panic!("expected MissingIndexError, got {other:?}"),
+    }
+    let index = unfold_to_store(&input_path, &store, |_| {})?;
+    assert_eq!(store.read_index()?, index);
+    let mut chunks = index.chunks().to_vec();
+    chunks.sort();
+    chunks.dedup();
+    assert_eq!(store.list()?, chunks);
+
+    let mut reader = FoldedReader::from_store(store)?;
+    let mut folded = Vec::<u8>::new();
+    reader.read_to_end(&mut folded)?;
+    assert_eq!(folded, input_path.read_bytes()?);
+
+    let store = reader.store();
+    match store.get("missing") {
+        Err(Error::MissingChunkError(_)) => {},
+        other => panic!("expected MissingChunkError, got {other:?}"),
+    }
+    store.delete("missing")?;
+    store.delete(&index.chunks()[1])?;
+    assert!(!store.has(&index.chunks()[1])?);
+    let output_path =
+        output_file_path("labs-google_fx_tools_flow.png.memory-missing")
+            .delete()?;
+    match fold_from_store(store, &output_path, |_| {}) {
+        Err(Error::InvalidIndexError(_)) => {},
+        other => panic!("expected InvalidIndexError, got {other:?}"),
+    }
+    Ok(())
+}
+
+#[test]
+fn test_dir_store_matches_unfold_file_layout() -> Result<()> {
+    let input_path = fixture_path("labs-google_fx_tools_flow-1.png");
+    let unfolded = unfold_file(
+        &input_path,
+        &output_folder_path("labs-google_fx_tools_flow-1.png.unfold-file")
+            .delete()?,
+        |_| {},
+    )?;
+    let store = DirStore::new(
+        &output_folder_path("labs-google_fx_tools_flow-1.png.dir-store")
+            .delete()?,
+    );
+    unfold_to_store(&input_path, &store, |_| {})?;
+    let mut expected = unfolded
+        .list()?
+        .into_iter()
+        .map(|path| path.name())
+        .collect::<Vec<_>>();
+    let mut actual = store
+        .path()
+        .list()?
+        .into_iter()
+        .map(|path| path.name())
+        .collect::<Vec<_>>();
+    expected.sort();
+    actual.sort();
+    assert_eq!(actual, expected);
+    assert_eq!(store.get_index()?, unfolded.join("index").read_bytes()?);
+    Ok(())
+}
+
+#[test]
+fn test_dir_store_lists_only_chunks() -> Result<()> {
+    let path = output_folder_path("dir-store-listing").delete()?;
+    let store = DirStore::new(&path);
+    assert!(store.list()?.is_empty());
+    match store.get_index() {
+        Err(Error::MissingIndexError(_)) => {},
+        other => panic!("expected MissingIndexError, got {other:?}"),
+    }
+    path.join("index").mkdir()?;
+    match store.get_index() {
+        Err(Error::UnreadableIndexError(_)) => {},
+        other => panic!("expected UnreadableIndexError, got {other:?}"),
+    }
+
+    store.put("ab/cd/chunk", b"nested")?;
+    store.put("flat", b"flat")?;
+    path.join(".staging").write(b"hidden")?;
+    path.join("notes")
+        .join("chunk")
+        .write(b"not a fan-out folder")?;
+    assert_eq!(store.list()?, ["ab/cd/chunk", "flat"]);
+    assert_eq!(store.get("ab/cd/chunk")?, b"nested");
+    path.join("label").write(b"reserved")?;
+    assert_eq!(store.list()?, ["ab/cd/chunk", "flat", "label"]);
+    let reserving = store.clone().reserving("label");
+    assert_eq!(reserving.list()?, ["ab/cd/chunk", "flat"]);
+    path.join("label").delete()?;
+
+    store.delete("ab/cd/chunk")?;
+    assert!(!path.join("ab").exists());
+    assert_eq!(store.list()?, ["flat"]);
+    Ok(())
+}
+
+#[test]
+fn test_third_party_store_backend() -> Result<()> {
+    let input_path = fixture_path("labs-google_fx_tools_flow.png");
+    let source = DirStore::new(
+        &output_folder_path("labs-google_fx_tools_flow.png.counting-source")
+            .delete()?,
+    );
+    unfold_to_store(&input_path, &source, |_| {})?;
+    let stream = pack_store(&source, Vec::<u8>::new(), |_| {})?;
+
+    let store = CountingStore {
+        inner: MemoryStore::new(),
+        puts: AtomicUsize::new(0),
+    };
+    unpack_to_store(stream.as_slice(), &store, |_| {})?;
+    assert_eq!(store.puts.load(Ordering::SeqCst), 4);
+    unpack_to_store(stream.as_slice(), &store, |_| {})?;
+    assert_eq!(store.puts.load(Ordering::SeqCst), 4);
+
+    let output_path =
+        output_file_path("labs-google_fx_tools_flow.png.counting").delete()?;
+    fold_from_store(&store, &output_path, |_| {})?;
+    assert_eq!(output_path.read_bytes()?, input_path.read_bytes()?);
+    Ok(())
+}
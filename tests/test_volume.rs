@@ -0,0 +1,140 @@
+use unfolder::{
+    fold_from_volumes, unfold_to_volumes, verify_store, ChunkLayout,
+    ChunkStore, DirStore, Error, Result, VolumeLabel, VolumeSpec, VolumeStore,
+    VOLUME_NAME,
+};
+
+mod common;
+use common::{fixture_path, output_file_path, output_folder_path};
+
+#[test]
+fn test_unfold_across_volumes() -> Result<()> {
+    let input_path = fixture_path("labs-google_fx_tools_flow.png");
+    let root = output_folder_path("volumes-unfolded").delete()?;
+    let volumes = ["a:140KB", "b:70KB", "c:70KB", "d:70KB"]
+        .into_iter()
+        .map(|spec| root.join(spec).to_string().parse::<VolumeSpec>())
+        .collect::<Result<Vec<VolumeSpec>>>()?;
+    assert_eq!(volumes[1].capacity(), 70_000);
+    let index = unfold_to_volumes(
+        &input_path,
+        &volumes,
+        ChunkLayout::default(),
+        |_| {},
+    )?;
+
+    let mut chunk_counts = Vec::<usize>::new();
+    for (idx, spec) in volumes.iter().enumerate() {
+        let root = spec.path();
+        let label = VolumeLabel::read(root)?;
+        assert_eq!(label.volume().number(), idx + 1);
+        assert_eq!(label.sha256(), index.sha256());
+        assert_eq!(DirStore::new(root).read_index()?, index);
+        let written = root
+            .list()?
+            .iter()
+            .map(|path| path.file_size().as_u64())
+            .sum::<u64>();
+        assert!(written <= spec.capacity(), "{root} holds {written} bytes");
+        chunk_counts.push(
+            DirStore::new(root)
+                .reserving(VOLUME_NAME)
+                .list()?
+                .len(),
+        );
+    }
+    assert_eq!(chunk_counts, vec![2, 1, 1, 0]);
+
+    let mut roots = volumes
+        .iter()
+        .map(|spec| spec.path().clone())
+        .collect::<Vec<_>>();
+    roots.reverse();
+    let store = VolumeStore::open(&roots)?;
+    assert_eq!(verify_store(&store, |_| {})?, index);
+    let folded_path = output_file_path("volumes-folded.png").delete()?;
+    fold_from_volumes(&roots, &folded_path, |_| {})?;
+    assert_eq!(folded_path.read_bytes()?, input_path.read_bytes()?);
+    Ok(())
+}
+
+#[test]
+fn test_fold_names_the_missing_volume() -> Result<()> {
+    let input_path = fixture_path("labs-google_fx_tools_flow-1.png");
+    let root = output_folder_path("volumes-missing").delete()?;
+    let volumes = [
+        VolumeSpec::new(&root.join("a"), 100_000),
+        VolumeSpec::new(&root.join("b"), 100_000),
+        VolumeSpec::new(&root.join("c"), 100_000),
+    ];
+    let index = unfold_to_volumes(
+        &input_path,
+        &volumes,
+        ChunkLayout::default(),
+        |_| {},
+    )?;
+    let folded_path = output_file_path("volumes-missing.png").delete()?;
+
+    let roots = [root.join("c"), root.join("a")];
+    let mut store = VolumeStore::open(&roots)?;
+    let missing = store.missing_volumes();
+    assert_eq!(missing.len(), 1);
+    assert_eq!(missing[0].chunks(), 1..2);
+    let expected = format!(
+        "volume 2 of 3 (chunks 2 to 2, written to {}) was not given",
+        root.join("b")
+    );
+    match fold_from_volumes(&roots, &folded_path, |_| {}) {
+        Err(Error::VolumeError(message)) => assert_eq!(message, expected),
+        other => panic!("expected VolumeError, got {other:?}"),
+    }
+    match store.get(&index.chunks()[1]) {
+        Err(Error::MissingChunkError(message)) =>
+            assert!(message.contains("volume 2 of 3"), "{message}"),
+        other => panic!("expected MissingChunkError, got {other:?}"),
+    }
+
+    store.add(&root.join("b"))?;
+    DirStore::new(&root.join("c")).delete(&index.chunks()[2])?;
+    match store.check() {
+        Err(Error::VolumeError(message)) => {
+            assert!(message.starts_with("volume 3 of 3"), "{message}");
+            assert!(message.contains(&index.chunks()[2]), "{message}");
+        },
+        other => panic!("expected VolumeError, got {other:?}"),
+    }
+    assert!(!folded_path.exists());
+    Ok(())
+}
+
+#[test]
+fn test_volumes_must_fit() -> Result<()> {
+    let input_path = fixture_path("labs-google_fx_tools_flow-1.png");
+    let root = output_folder_path("volumes-full").delete()?;
+    let volumes = [
+        VolumeSpec::new(&root.join("a"), 100_000),
+        VolumeSpec::new(&root.join("b"), 50_000),
+    ];
+    match unfold_to_volumes(
+        &input_path,
+        &volumes,
+        ChunkLayout::default(),
+        |_| {},
+    ) {
+        Err(Error::VolumeError(message)) =>
+            assert!(message.contains("chunks 2 to 3"), "{message}"),
+        other => panic!("expected VolumeError, got {other:?}"),
+    }
+    assert!(!root.join("a").exists());
+
+    for invalid in ["no-capacity", ":1GB", "a:1XB", "a:"] {
+        match invalid.parse::<VolumeSpec>() {
+            Err(Error::VolumeError(_)) => {},
+            other => panic!("expected VolumeError, got {other:?}"),
+        }
+    }
+    let spec = "/mnt/a:1.5KiB".parse::<VolumeSpec>()?;
+    assert_eq!(spec.path().to_string(), "/mnt/a");
+    assert_eq!(spec.capacity(), 1536);
+    Ok(())
+}